@@ -0,0 +1,87 @@
+use crate::*;
+
+/// number of settlement events kept on chain, oldest ones are overwritten
+const ACTIVITY_LOG_LENGTH: u64 = 100;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum ActivityKind {
+    Sale,
+    Bid,
+    Accept,
+    Cancel,
+    Rent,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Activity {
+    pub kind: ActivityKind,
+    pub nft_contract_id: AccountId,
+    pub token_id: TokenId,
+    /// buyer, bidder or seller depending on the kind
+    pub account_id: AccountId,
+    pub ft_token_id: Option<FungibleTokenId>,
+    pub price: Option<U128>,
+    pub timestamp: U64,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// views
+
+    /// most recent activity first
+    pub fn get_recent_activity(&self, from_index: U64, limit: u64) -> Vec<Activity> {
+        let start = u64::from(from_index);
+        let end = min(start + limit, self.activity.len());
+        (start..end)
+            .filter_map(|i| self.activity.get(self.internal_activity_index(i)))
+            .collect()
+    }
+
+    /// activity still in the log for one token, most recent first
+    pub fn get_token_history(&self, nft_contract_id: AccountId, token_id: TokenId) -> Vec<Activity> {
+        (0..self.activity.len())
+            .filter_map(|i| self.activity.get(self.internal_activity_index(i)))
+            .filter(|activity| {
+                activity.nft_contract_id == nft_contract_id
+                    && activity.token_id == token_id
+            })
+            .collect()
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_record_activity(
+        &mut self,
+        kind: ActivityKind,
+        nft_contract_id: &AccountId,
+        token_id: &TokenId,
+        account_id: &AccountId,
+        ft_token_id: Option<FungibleTokenId>,
+        price: Option<U128>,
+    ) {
+        let activity = Activity {
+            kind,
+            nft_contract_id: nft_contract_id.clone(),
+            token_id: token_id.clone(),
+            account_id: account_id.clone(),
+            ft_token_id,
+            price,
+            timestamp: U64(env::block_timestamp()/1000000),
+        };
+        if self.activity.len() < ACTIVITY_LOG_LENGTH {
+            self.activity.push(&activity);
+        } else {
+            self.activity.replace(self.activity_head, &activity);
+        }
+        self.activity_head = (self.activity_head + 1) % ACTIVITY_LOG_LENGTH;
+    }
+
+    /// position in the ring buffer of the `i`-th most recent activity
+    fn internal_activity_index(&self, i: u64) -> u64 {
+        let len = self.activity.len();
+        (self.activity_head + len - 1 - i) % len
+    }
+}
@@ -0,0 +1,133 @@
+use crate::*;
+
+/// privileged maintenance, for owner and admin management see: lib.rs
+
+#[near_bindgen]
+impl Contract {
+    /// only owner or admin
+    pub fn pause(&mut self) {
+        self.assert_owner_or_manager();
+        self.paused = true;
+    }
+
+    /// only owner or admin
+    pub fn unpause(&mut self) {
+        self.assert_owner_or_manager();
+        self.paused = false;
+    }
+
+    /// only owner or admin, returns the NFT to the seller and refunds all bids
+    pub fn force_remove_sale(&mut self, nft_contract_id: AccountId, token_id: String) {
+        self.assert_owner_or_manager();
+        let sale = self.internal_remove_sale(nft_contract_id.clone(), token_id.clone());
+        MarketEvent::log_sale_delist(&sale);
+        self.refund_all_bids(&sale);
+        self.internal_record_activity(
+            ActivityKind::Cancel,
+            &nft_contract_id,
+            &token_id,
+            &env::predecessor_account_id(),
+            None,
+            None,
+        );
+        ext_contract::ext(nft_contract_id)
+            .with_attached_deposit(1)
+            .with_static_gas(GAS_FOR_NFT_TRANSFER)
+            .nft_transfer(sale.owner_id, token_id, 0, "removed by market".to_string());
+    }
+
+    /// only owner, market fees are sent to the treasury
+    pub fn set_treasury(&mut self, treasury_id: AccountId) {
+        self.assert_owner();
+        self.treasury_id = treasury_id;
+    }
+
+    /// only owner, added to the NEAR price of sales listed from now on
+    pub fn set_listing_surcharge(&mut self, listing_surcharge: U128) {
+        self.assert_owner();
+        self.listing_surcharge = listing_surcharge.0;
+    }
+
+    /// only owner, fee for featuring a listing one hour, see `boost_sale`
+    pub fn set_boost_fee_per_hour(&mut self, boost_fee_per_hour: U128) {
+        self.assert_owner();
+        self.boost_fee_per_hour = boost_fee_per_hour.0;
+    }
+
+    /// only owner, none sends surcharges to the treasury
+    pub fn set_surcharge_receiver(&mut self, surcharge_receiver_id: Option<AccountId>) {
+        self.assert_owner();
+        self.surcharge_receiver_id = surcharge_receiver_id;
+    }
+
+    /// only owner, bidding rules of sales that don't set their own
+    pub fn set_bid_defaults(&mut self, min_bid_increment_bps: u16, bid_history_length: u8) {
+        self.assert_owner();
+        assert_bid_params(Some(min_bid_increment_bps), Some(bid_history_length));
+        self.min_bid_increment_bps = min_bid_increment_bps;
+        self.bid_history_length = bid_history_length;
+    }
+
+    /// only owner, `new_owner_id` has to call `accept_ownership` to take over
+    pub fn propose_owner(&mut self, new_owner_id: Option<AccountId>) {
+        self.assert_owner();
+        self.pending_owner_id = new_owner_id;
+    }
+
+    pub fn accept_ownership(&mut self) {
+        let pending_owner_id = self.pending_owner_id.take().expect("No pending owner");
+        require!(
+            env::predecessor_account_id() == pending_owner_id,
+            "Only the proposed owner can accept"
+        );
+        self.owner_id = pending_owner_id;
+    }
+
+    /// only owner, moves up to `limit` sales left by `migrate`, returns how many are left
+    pub fn migrate_sales(&mut self, limit: u64) -> u64 {
+        self.assert_owner();
+        let contract_and_token_ids: Vec<ContractAndTokenId> =
+            self.legacy_sales.keys().take(limit as usize).collect();
+        for contract_and_token_id in contract_and_token_ids {
+            let sale = self.legacy_sales.remove(&contract_and_token_id).unwrap();
+            self.internal_migrate_sale(&contract_and_token_id, sale);
+        }
+        self.legacy_sales.len()
+    }
+
+    /// views
+
+    pub fn get_owner(&self) -> AccountId {
+        self.owner_id.clone()
+    }
+
+    pub fn get_pending_owner(&self) -> Option<AccountId> {
+        self.pending_owner_id.clone()
+    }
+
+    pub fn get_admins(&self) -> Vec<AccountId> {
+        self.admin_ids.to_vec()
+    }
+
+    pub fn get_treasury(&self) -> AccountId {
+        self.treasury_id.clone()
+    }
+
+    pub fn get_boost_fee_per_hour(&self) -> U128 {
+        U128(self.boost_fee_per_hour)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn get_listing_surcharge(&self) -> U128 {
+        U128(self.listing_surcharge)
+    }
+
+    pub fn get_surcharge_receiver(&self) -> AccountId {
+        self.surcharge_receiver_id
+            .clone()
+            .unwrap_or_else(|| self.treasury_id.clone())
+    }
+}
@@ -0,0 +1,94 @@
+use crate::*;
+
+/// NEAR credit buyers keep on the market, so they can bid and buy without attaching a deposit each time.
+/// Accounts holding a balance get their NEAR refunds (outbid bids, failed purchases) credited to it.
+
+#[near_bindgen]
+impl Contract {
+    /// credit the attached deposit to the caller's balance, returns the new balance.
+    /// Opening a balance keeps STORAGE_PER_BALANCE of the deposit, refunded when it is closed
+    #[payable]
+    pub fn deposit_balance(&mut self) -> U128 {
+        let mut deposit = env::attached_deposit();
+        let account_id = env::predecessor_account_id();
+        if !self.balances.contains_key(&account_id) {
+            require!(deposit > STORAGE_PER_BALANCE, "Requires storage deposit for the balance");
+            deposit -= STORAGE_PER_BALANCE;
+        }
+        require!(deposit > 0, "Attached deposit must be greater than 0");
+        U128(self.internal_credit_balance(&account_id, deposit))
+    }
+
+    /// withdraw `amount` of the caller's balance, everything when none.
+    /// A fully withdrawn balance is closed, along with its storage deposit, and refunds are
+    /// sent directly again
+    #[payable]
+    pub fn withdraw_balance(&mut self, amount: Option<U128>) -> U128 {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let balance = self.balances.get(&account_id).expect("No balance");
+        let amount = amount.map_or(balance, |amount| amount.0);
+        require!(amount <= balance, "Not enough balance");
+        let remaining = balance - amount;
+        let mut payout = amount;
+        if remaining == 0 {
+            self.balances.remove(&account_id);
+            payout += STORAGE_PER_BALANCE;
+        } else {
+            self.balances.insert(&account_id, &remaining);
+        }
+        if payout > 0 {
+            Promise::new(account_id).transfer(payout);
+        }
+        U128(remaining)
+    }
+
+    /// `offer` paid out of the caller's balance instead of an attached deposit
+    #[payable]
+    pub fn offer_from_balance(
+        &mut self,
+        nft_contract_id: AccountId,
+        token_id: String,
+        amount: U128,
+        expected_price: Option<U128>,
+    ) {
+        assert_one_yocto();
+        let buyer_id = env::predecessor_account_id();
+        self.internal_debit_balance(&buyer_id, amount.0);
+        self.internal_offer(nft_contract_id, token_id, buyer_id, amount.0, expected_price);
+    }
+
+    /// views
+
+    pub fn get_balance(&self, account_id: AccountId) -> U128 {
+        U128(self.balances.get(&account_id).unwrap_or(0))
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_credit_balance(&mut self, account_id: &AccountId, amount: Balance) -> Balance {
+        let balance = self.balances.get(account_id).unwrap_or(0) + amount;
+        self.balances.insert(account_id, &balance);
+        balance
+    }
+
+    pub(crate) fn internal_debit_balance(&mut self, account_id: &AccountId, amount: Balance) {
+        // an entry is only opened by `deposit_balance`, which charges its storage
+        let balance = self.balances.get(account_id).expect("No balance");
+        require!(amount <= balance, "Not enough balance");
+        // kept at 0 so refunds still land in the balance
+        self.balances.insert(account_id, &(balance - amount));
+    }
+
+    /// credit a NEAR refund to the account's balance when it has one, otherwise send it
+    pub(crate) fn internal_refund_near(&mut self, account_id: AccountId, amount: Balance) {
+        if amount == 0 {
+            return;
+        }
+        if self.balances.contains_key(&account_id) {
+            self.internal_credit_balance(&account_id, amount);
+        } else {
+            Promise::new(account_id).transfer(amount);
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use crate::*;
+
+const HOUR_MS: u64 = 60 * 60 * 1000;
+/// longest a listing can be featured for at once, a boost can be extended after
+const MAX_BOOST_HOURS: u32 = 7 * 24;
+/// featured slots, keeps `get_featured_sales` within a view's gas
+const MAX_FEATURED_SALES: u64 = 50;
+
+/// a listing featured by the market until `boosted_until`
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeaturedSale {
+    #[serde(flatten)]
+    pub sale: SaleView,
+    /// ms since epoch
+    pub boosted_until: U64,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// feature a sale of the caller for `hours`, attach `get_boost_fee_per_hour` times `hours`.
+    /// Boosting a featured sale extends it, the fee isn't refunded when the sale goes away
+    #[payable]
+    pub fn boost_sale(&mut self, nft_contract_id: AccountId, token_id: TokenId, hours: u32) -> U64 {
+        self.assert_not_paused();
+        require!(
+            hours > 0 && hours <= MAX_BOOST_HOURS,
+            format!("Hours must be between 1 and {}", MAX_BOOST_HOURS)
+        );
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let sale = self.sales.get(&contract_and_token_id).expect("No sale");
+        let owner_id = env::predecessor_account_id();
+        require!(sale.owner_id == owner_id, "Must be sale owner");
+
+        let fee = self.boost_fee_per_hour * hours as u128;
+        let deposit = env::attached_deposit();
+        require!(
+            deposit >= fee,
+            format!("Attach at least {} yN for the boost", fee)
+        );
+
+        let now = env::block_timestamp() / 1000000;
+        let boost_start = self
+            .featured_sales
+            .get(&contract_and_token_id)
+            .map_or(now, |boosted_until| boosted_until.0.max(now));
+        if boost_start == now {
+            self.internal_prune_boosts();
+            require!(
+                self.featured_sales.len() < MAX_FEATURED_SALES,
+                "No featured slot free"
+            );
+        }
+        let boosted_until = U64(boost_start + hours as u64 * HOUR_MS);
+        self.featured_sales
+            .insert(&contract_and_token_id, &boosted_until);
+
+        if fee > 0 {
+            Promise::new(self.treasury_id.clone()).transfer(fee);
+        }
+        if deposit > fee {
+            Promise::new(owner_id.clone()).transfer(deposit - fee);
+        }
+        MarketEvent::log_sale_boost(&sale, U128(fee), boosted_until);
+        boosted_until
+    }
+
+    /// remove featured sales whose boost ran out, anyone can
+    pub fn prune_featured_sales(&mut self) -> u64 {
+        self.internal_prune_boosts()
+    }
+
+    /// views
+
+    /// featured sales with an active boost, the most boosted time left first
+    pub fn get_featured_sales(&self, limit: Option<u64>) -> Vec<FeaturedSale> {
+        let now = env::block_timestamp() / 1000000;
+        let mut featured: Vec<(ContractAndTokenId, U64)> = self
+            .featured_sales
+            .iter()
+            .filter(|(_, boosted_until)| boosted_until.0 > now)
+            .collect();
+        featured.sort_by(|a, b| b.1 .0.cmp(&a.1 .0));
+        featured
+            .into_iter()
+            .filter_map(|(contract_and_token_id, boosted_until)| {
+                let sale = self.sales.get(&contract_and_token_id)?;
+                Some(FeaturedSale {
+                    sale: self.internal_sale_view(sale),
+                    boosted_until,
+                })
+            })
+            .take(limit.unwrap_or(MAX_FEATURED_SALES) as usize)
+            .collect()
+    }
+
+    /// ms since epoch the sale is featured until, None if it isn't
+    pub fn get_sale_boost(&self, nft_contract_token: ContractAndTokenId) -> Option<U64> {
+        let now = env::block_timestamp() / 1000000;
+        self.featured_sales
+            .get(&nft_contract_token)
+            .filter(|boosted_until| boosted_until.0 > now)
+    }
+}
+
+impl Contract {
+    /// drop expired boosts, returns the number removed
+    fn internal_prune_boosts(&mut self) -> u64 {
+        let now = env::block_timestamp() / 1000000;
+        let expired: Vec<ContractAndTokenId> = self
+            .featured_sales
+            .iter()
+            .filter(|(_, boosted_until)| boosted_until.0 <= now)
+            .map(|(contract_and_token_id, _)| contract_and_token_id)
+            .collect();
+        for contract_and_token_id in &expired {
+            self.featured_sales.remove(contract_and_token_id);
+        }
+        expired.len() as u64
+    }
+}
@@ -0,0 +1,149 @@
+use crate::*;
+
+/// Bulk tools for sellers with many listings. Entries are handled while the gas lasts,
+/// the ones not reached are reported back to be sent again.
+
+/// updating one sale, with its change record and event
+const GAS_FOR_BULK_ENTRY: Gas = Gas(5_000_000_000_000);
+
+/// outcome of a bulk update, by sale id
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BulkResult {
+    pub succeeded: Vec<ContractAndTokenId>,
+    pub failed: Vec<BulkFailure>,
+    /// entries the gas didn't reach
+    pub unprocessed: Vec<ContractAndTokenId>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BulkFailure {
+    pub sale_id: ContractAndTokenId,
+    pub reason: String,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// set the expiry of the caller's sales to `new_expiry`, none lists them without one.
+    /// Expired sales that weren't removed yet are listed again, auctions with bids can't be renewed
+    #[payable]
+    pub fn relist_many(
+        &mut self,
+        sale_ids: Vec<ContractAndTokenId>,
+        new_expiry: Option<U64>,
+    ) -> BulkResult {
+        assert_one_yocto();
+        if let Some(new_expiry) = new_expiry {
+            require!(
+                new_expiry.0 > env::block_timestamp() / 1000000,
+                "new_expiry must be in the future"
+            );
+        }
+        let owner_id = env::predecessor_account_id();
+        self.internal_bulk(sale_ids, |contract, sale_id| {
+            let mut sale = contract.internal_owned_sale(&owner_id, &sale_id)?;
+            if sale.is_auction && !sale.bids.is_empty() {
+                return Err("Auction has bids");
+            }
+            sale.expires_at = new_expiry;
+            contract.sales.insert(&sale_id, &sale);
+            contract.internal_record_change(&sale_id, ChangeType::ExpiryUpdated);
+            MarketEvent::log_sale_update(&sale);
+            Ok(())
+        })
+    }
+
+    /// reprice the caller's sales in `ft_token_id`, NEAR by default. Like `update_price`,
+    /// prices are what the seller gets and NEAR prices get the sale's surcharge on top
+    #[payable]
+    pub fn update_prices_bulk(
+        &mut self,
+        entries: Vec<(ContractAndTokenId, U128)>,
+        ft_token_id: Option<FungibleTokenId>,
+    ) -> BulkResult {
+        assert_one_yocto();
+        let ft_token_id = ft_token_id.unwrap_or_else(near_token_id);
+        if !self.ft_token_ids.contains(&ft_token_id) {
+            env::panic_str(&format!("Token {} not supported by this market", ft_token_id));
+        }
+        let owner_id = env::predecessor_account_id();
+        let prices: HashMap<ContractAndTokenId, U128> = entries.iter().cloned().collect();
+        let sale_ids = entries.into_iter().map(|(sale_id, _)| sale_id).collect();
+        self.internal_bulk(sale_ids, |contract, sale_id| {
+            let sale = contract.internal_owned_sale(&owner_id, &sale_id)?;
+            contract.internal_set_price(&sale_id, sale, ft_token_id.clone(), prices[&sale_id]);
+            Ok(())
+        })
+    }
+}
+
+impl Contract {
+    /// set the price of a sale in one FT and update the floor stats
+    pub(crate) fn internal_set_price(
+        &mut self,
+        contract_and_token_id: &ContractAndTokenId,
+        mut sale: Sale,
+        ft_token_id: FungibleTokenId,
+        price: U128,
+    ) {
+        let old_sale_conditions = sale.sale_conditions.clone();
+        if !old_sale_conditions.contains_key(&ft_token_id) {
+            self.internal_count_ft_listings(std::iter::once(&ft_token_id), true);
+        }
+        let price = if ft_token_id.as_str() == NEAR_TOKEN_ID {
+            U128(price.0 + sale.surcharge.0)
+        } else {
+            price
+        };
+        sale.sale_conditions.insert(ft_token_id, price);
+        self.sales.insert(contract_and_token_id, &sale);
+        self.internal_record_change(contract_and_token_id, ChangeType::PriceUpdated);
+        MarketEvent::log_sale_update(&sale);
+        self.internal_stats_on_delist(&sale.token_type, &old_sale_conditions);
+        self.internal_stats_on_list(&sale.token_type, &sale.sale_conditions);
+    }
+
+    fn internal_owned_sale(
+        &self,
+        owner_id: &AccountId,
+        sale_id: &ContractAndTokenId,
+    ) -> Result<Sale, &'static str> {
+        let sale = self.sales.get(sale_id).ok_or("No sale")?;
+        if &sale.owner_id != owner_id {
+            return Err("Must be sale owner");
+        }
+        Ok(sale)
+    }
+
+    /// apply `update` to each sale while the gas lasts, duplicates are only updated once
+    fn internal_bulk<F>(&mut self, sale_ids: Vec<ContractAndTokenId>, mut update: F) -> BulkResult
+    where
+        F: FnMut(&mut Self, ContractAndTokenId) -> Result<(), &'static str>,
+    {
+        let mut result = BulkResult {
+            succeeded: vec![],
+            failed: vec![],
+            unprocessed: vec![],
+        };
+        let mut seen = std::collections::HashSet::new();
+        for sale_id in sale_ids {
+            let out_of_gas = env::prepaid_gas() - env::used_gas() < GAS_FOR_BULK_ENTRY;
+            if !result.unprocessed.is_empty() || out_of_gas {
+                result.unprocessed.push(sale_id);
+                continue;
+            }
+            if !seen.insert(sale_id.clone()) {
+                continue;
+            }
+            match update(self, sale_id.clone()) {
+                Ok(()) => result.succeeded.push(sale_id),
+                Err(reason) => result.failed.push(BulkFailure {
+                    sale_id,
+                    reason: reason.to_string(),
+                }),
+            }
+        }
+        result
+    }
+}
@@ -0,0 +1,345 @@
+use crate::*;
+
+/// most tokens in a bundle, all of them are transferred in a single purchase
+const MAX_BUNDLE_SIZE: usize = 10;
+
+/// nft_on_approve msg for a token that is part of a bundle
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BundleMsg {
+    pub bundle: BundleArgs,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BundleArgs {
+    pub bundle_id: String,
+    pub token_ids: Vec<TokenId>,
+    /// price in NEAR for the whole bundle
+    pub price: U128,
+}
+
+/// Several tokens of one NFT contract sold together for a single NEAR price.
+/// The listing is active once the market holds every token. A sold bundle whose tokens
+/// weren't all delivered is kept for the buyer to claim the rest, see `claim_bundle_tokens`.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Bundle {
+    pub bundle_id: String,
+    pub owner_id: AccountId,
+    pub nft_contract_id: AccountId,
+    pub token_ids: Vec<TokenId>,
+    /// tokens already transferred to the market
+    pub escrowed: Vec<TokenId>,
+    pub price: U128,
+    pub active: bool,
+    pub created_at: U64,
+    /// buyer still owed the tokens left in a sold bundle
+    pub buyer_id: Option<AccountId>,
+    /// price plus market fee held for the tokens still owed to the buyer
+    pub paid: U128,
+}
+
+pub(crate) fn bundle_key(owner_id: &AccountId, bundle_id: &str) -> String {
+    format!("{}{}{}", owner_id, DELIMETER, bundle_id)
+}
+
+/// the `index`th token's part of `amount` split over `count` tokens, the last one takes the dust
+fn bundle_token_share(amount: Balance, index: usize, count: usize) -> Balance {
+    let share = amount / count as u128;
+    if index + 1 == count {
+        amount - share * (count as u128 - 1)
+    } else {
+        share
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// for add bundle see: nft_callbacks.rs
+
+    #[payable]
+    pub fn buy_bundle(&mut self, owner_id: AccountId, bundle_id: String) -> Promise {
+        self.assert_not_paused();
+        let key = bundle_key(&owner_id, &bundle_id);
+        let bundle = self.bundles.get(&key).expect("No bundle");
+        require!(bundle.active, "Bundle is not complete yet");
+        let buyer_id = env::predecessor_account_id();
+        require!(bundle.owner_id != buyer_id, "Cannot buy your own bundle.");
+        self.assert_compliant_trade(&bundle.nft_contract_id, &bundle.owner_id, &buyer_id);
+        let fee = self.internal_market_fee(&bundle.nft_contract_id, bundle.price.0);
+        let deposit = env::attached_deposit();
+        require!(deposit == bundle.price.0 + fee, "Attached deposit must be price plus market fee");
+
+        // removed while the transfers are in flight so it can't be bought twice
+        self.bundles.remove(&key);
+        update_supply(&mut self.listings_by_owner_id, &owner_id, false);
+        self.internal_transfer_bundle(bundle, buyer_id, U128(deposit))
+    }
+
+    /// deliver the tokens a bought bundle still owes the buyer, only the buyer can
+    pub fn claim_bundle_tokens(&mut self, owner_id: AccountId, bundle_id: String) -> Promise {
+        let key = bundle_key(&owner_id, &bundle_id);
+        let bundle = self.bundles.get(&key).expect("No bundle");
+        let buyer_id = env::predecessor_account_id();
+        require!(
+            bundle.buyer_id.as_ref() == Some(&buyer_id),
+            "Only the buyer can claim the bundle's tokens"
+        );
+        self.bundles.remove(&key);
+        update_supply(&mut self.listings_by_owner_id, &owner_id, false);
+        let paid = bundle.paid;
+        self.internal_transfer_bundle(bundle, buyer_id, paid)
+    }
+
+    /// self callback, returns the number of tokens the buyer received.
+    /// The purchase stands whatever happens: each delivered token pays its payout and its
+    /// part of the fee, tokens that couldn't be delivered stay in custody for the buyer
+    /// together with what was paid for them, see `claim_bundle_tokens`.
+    #[private]
+    pub fn resolve_bundle_purchase(&mut self, bundle: Bundle, buyer_id: AccountId, paid: U128) -> u64 {
+        let count = bundle.token_ids.len();
+        let fee = paid.0 - bundle.price.0;
+        let mut undelivered = vec![];
+        let mut undelivered_price = 0;
+        let mut delivered_fee = 0;
+        for (i, token_id) in bundle.token_ids.iter().enumerate() {
+            let price = bundle_token_share(bundle.price.0, i, count);
+            match env::promise_result(i as u64) {
+                PromiseResult::Successful(value) => {
+                    delivered_fee += bundle_token_share(fee, i, count);
+                    self.internal_bundle_payout(&bundle, token_id, &buyer_id, price, &value);
+                }
+                _ => {
+                    undelivered.push(token_id.clone());
+                    undelivered_price += price;
+                }
+            }
+        }
+        if delivered_fee > 0 {
+            Promise::new(self.treasury_id.clone()).transfer(delivered_fee);
+        }
+
+        let delivered = (count - undelivered.len()) as u64;
+        if !undelivered.is_empty() {
+            env::log_str(&format!("{} tokens of the bundle are left for the buyer to claim", undelivered.len()));
+            let key = bundle_key(&bundle.owner_id, &bundle.bundle_id);
+            update_supply(&mut self.listings_by_owner_id, &bundle.owner_id, true);
+            self.bundles.insert(
+                &key,
+                &Bundle {
+                    token_ids: undelivered.clone(),
+                    escrowed: undelivered,
+                    price: U128(undelivered_price),
+                    active: false,
+                    buyer_id: Some(buyer_id),
+                    paid: U128(paid.0 - bundle.price.0 + undelivered_price - delivered_fee),
+                    ..bundle
+                },
+            );
+        }
+        delivered
+    }
+
+    /// self callback, a bundle token is escrowed once the market holds it
+    #[private]
+    pub fn resolve_bundle_deposit(
+        &mut self,
+        nft_contract_id: AccountId,
+        owner_id: AccountId,
+        bundle_id: String,
+        token_id: TokenId,
+    ) -> bool {
+        let key = bundle_key(&owner_id, &bundle_id);
+        let bundle = self.bundles.get(&key).filter(|bundle| bundle.buyer_id.is_none());
+        if !matches!(env::promise_result(0), PromiseResult::Successful(_)) {
+            env::log_str(&format!("Token {} wasn't deposited to the bundle", token_id));
+            return false;
+        }
+        let mut bundle = match bundle {
+            Some(bundle) if !bundle.escrowed.contains(&token_id) => bundle,
+            // the bundle was removed or sold in the meantime
+            _ => {
+                ext_contract::ext(nft_contract_id)
+                    .with_attached_deposit(1)
+                    .with_static_gas(GAS_FOR_NFT_TRANSFER)
+                    .nft_transfer(owner_id, token_id, 0, "return to user".to_string());
+                return false;
+            }
+        };
+        bundle.escrowed.push(token_id);
+        bundle.active = bundle.escrowed.len() == bundle.token_ids.len();
+        self.bundles.insert(&key, &bundle);
+        true
+    }
+
+    /// return every escrowed token of the bundle to its owner
+    #[payable]
+    pub fn remove_bundle(&mut self, bundle_id: String) {
+        assert_one_yocto();
+        let owner_id = env::predecessor_account_id();
+        let key = bundle_key(&owner_id, &bundle_id);
+        let bundle = self.bundles.get(&key).expect("No bundle");
+        require!(bundle.buyer_id.is_none(), "Bundle was sold, its tokens are the buyer's");
+        self.bundles.remove(&key);
+        update_supply(&mut self.listings_by_owner_id, &owner_id, false);
+        for token_id in bundle.escrowed {
+            ext_contract::ext(bundle.nft_contract_id.clone())
+                .with_attached_deposit(1)
+                .with_static_gas(GAS_FOR_NFT_TRANSFER)
+                .nft_transfer(owner_id.clone(), token_id, 0, "return to user".to_string());
+        }
+    }
+
+    /// views
+
+    pub fn get_bundle(&self, owner_id: AccountId, bundle_id: String) -> Option<Bundle> {
+        self.bundles.get(&bundle_key(&owner_id, &bundle_id))
+    }
+}
+
+impl Contract {
+    /// send every token of `bundle` to `buyer_id` with its payout, settled in `resolve_bundle_purchase`
+    fn internal_transfer_bundle(&mut self, bundle: Bundle, buyer_id: AccountId, paid: U128) -> Promise {
+        let count = bundle.token_ids.len();
+        let transfers = bundle
+            .token_ids
+            .iter()
+            .enumerate()
+            .map(|(i, token_id)| {
+                ext_contract::ext(bundle.nft_contract_id.clone())
+                    .with_attached_deposit(1)
+                    .with_static_gas(GAS_FOR_NFT_TRANSFER)
+                    .nft_transfer_payout(
+                        buyer_id.clone(),
+                        token_id.clone(),
+                        0,
+                        "bundle purchase".to_string(),
+                        U128(bundle_token_share(bundle.price.0, i, count)),
+                        10,
+                    )
+            })
+            .reduce(|all, transfer| all.and(transfer))
+            .unwrap();
+        transfers.then(
+            ext_self::ext(env::current_account_id())
+                .with_static_gas(GAS_FOR_RESOLVE_BUNDLE)
+                .resolve_bundle_purchase(bundle, buyer_id, paid),
+        )
+    }
+
+    /// pay out the `price` of one delivered token, royalties capped as for single sales
+    fn internal_bundle_payout(
+        &mut self,
+        bundle: &Bundle,
+        token_id: &TokenId,
+        buyer_id: &AccountId,
+        price: Balance,
+        value: &[u8],
+    ) {
+        let owner_id = &bundle.owner_id;
+        let payout = match internal_parse_payout(value, price) {
+            Some(payout) => self.internal_cap_royalties(&bundle.nft_contract_id, owner_id, price, payout),
+            None => {
+                let mut payout = HashMap::new();
+                payout.insert(owner_id.clone(), U128(price));
+                Payout { payout }
+            }
+        };
+        let marketplace_id = env::current_account_id();
+        for (receiver_id, amount) in payout.payout {
+            let receiver_id = if receiver_id == marketplace_id {
+                owner_id.clone()
+            } else {
+                receiver_id
+            };
+            if receiver_id != *owner_id {
+                self.internal_record_royalty(
+                    &bundle.nft_contract_id,
+                    token_id,
+                    &receiver_id,
+                    &near_token_id(),
+                    amount,
+                );
+            }
+            if amount.0 > 0 {
+                Promise::new(receiver_id).transfer(amount.0);
+            }
+        }
+        MarketEvent::log_sale_purchase(
+            &bundle.nft_contract_id,
+            token_id,
+            owner_id,
+            buyer_id,
+            &near_token_id(),
+            U128(price),
+        );
+        self.internal_record_activity(
+            ActivityKind::Sale,
+            &bundle.nft_contract_id,
+            token_id,
+            buyer_id,
+            Some(near_token_id()),
+            Some(U128(price)),
+        );
+    }
+
+    /// one token of a bundle was approved, escrow it and activate the bundle once complete
+    pub(crate) fn internal_bundle_approve(
+        &mut self,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        owner_id: AccountId,
+        approval_id: u64,
+        args: BundleArgs,
+    ) {
+        let key = bundle_key(&owner_id, &args.bundle_id);
+        let mut bundle = self.bundles.get(&key).unwrap_or_else(|| {
+            require!(!args.token_ids.is_empty(), "Bundle must contain tokens");
+            require!(
+                args.token_ids.len() <= MAX_BUNDLE_SIZE,
+                format!("Bundle can have at most {} tokens", MAX_BUNDLE_SIZE)
+            );
+            let mut unique = args.token_ids.clone();
+            unique.sort();
+            unique.dedup();
+            require!(unique.len() == args.token_ids.len(), "Bundle tokens must be unique");
+            require!(args.price.0 > 0, "Bundle price must be greater than 0");
+            Bundle {
+                bundle_id: args.bundle_id.clone(),
+                owner_id: owner_id.clone(),
+                nft_contract_id: nft_contract_id.clone(),
+                token_ids: args.token_ids.clone(),
+                escrowed: vec![],
+                price: args.price,
+                active: false,
+                created_at: U64(env::block_timestamp()/1000000),
+                buyer_id: None,
+                paid: U128(0),
+            }
+        });
+        require!(bundle.buyer_id.is_none(), "Bundle was sold");
+        require!(bundle.nft_contract_id == nft_contract_id, "Bundle tokens must be from one contract");
+        require!(
+            bundle.token_ids == args.token_ids && bundle.price == args.price,
+            "Bundle args don't match the existing bundle"
+        );
+        require!(bundle.token_ids.contains(&token_id), "Token is not part of the bundle");
+        require!(!bundle.escrowed.contains(&token_id), "Token already in the bundle");
+
+        // escrowed once the transfer went through, see `resolve_bundle_deposit`
+        if self.bundles.insert(&key, &bundle).is_none() {
+            update_supply(&mut self.listings_by_owner_id, &owner_id, true);
+        }
+
+        ext_contract::ext(nft_contract_id.clone())
+            .with_attached_deposit(1)
+            .with_static_gas(GAS_FOR_NFT_TRANSFER)
+            .nft_transfer(env::current_account_id(), token_id.clone(), approval_id, "deposit to market".to_string())
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_DEPOSIT)
+                    .resolve_bundle_deposit(nft_contract_id, owner_id, args.bundle_id, token_id),
+            );
+    }
+}
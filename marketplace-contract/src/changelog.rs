@@ -0,0 +1,72 @@
+use crate::*;
+
+/// number of changes kept on chain, older ones are dropped
+const CHANGELOG_LENGTH: u64 = 1000;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeType {
+    Listed,
+    PriceUpdated,
+    AllowedBuyerUpdated,
+    Bid,
+    Removed,
+    /// a multi token listing was partly bought
+    AmountUpdated,
+    ExpiryUpdated,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Change {
+    pub seq: U64,
+    pub sale_id: ContractAndTokenId,
+    pub change_type: ChangeType,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// views
+
+    /// sequence number of the latest change, 0 when nothing changed yet
+    pub fn get_latest_change_seq(&self) -> U64 {
+        U64(self.change_seq)
+    }
+
+    /// changes after `seq`, oldest first. Panics when some of them were already dropped,
+    /// the caller then has to resync from the sale views
+    pub fn get_changes_since(&self, seq: U64, limit: Option<u64>) -> Vec<Change> {
+        let seq = u64::from(seq);
+        let oldest_seq = self.change_seq.saturating_sub(CHANGELOG_LENGTH) + 1;
+        require!(
+            seq + 1 >= oldest_seq,
+            format!("Changes before {} were dropped, resync", oldest_seq)
+        );
+        let end = min(seq + limit.unwrap_or(CHANGELOG_LENGTH), self.change_seq);
+        (seq + 1..=end)
+            .filter_map(|seq| self.changes.get(&seq))
+            .collect()
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_record_change(
+        &mut self,
+        sale_id: &ContractAndTokenId,
+        change_type: ChangeType,
+    ) {
+        self.change_seq += 1;
+        self.changes.insert(
+            &self.change_seq,
+            &Change {
+                seq: U64(self.change_seq),
+                sale_id: sale_id.clone(),
+                change_type,
+            },
+        );
+        if self.change_seq > CHANGELOG_LENGTH {
+            self.changes.remove(&(self.change_seq - CHANGELOG_LENGTH));
+        }
+    }
+}
@@ -1,5 +1,4 @@
 use crate::*;
-use near_sdk::promise_result_as_success;
 
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
 #[serde(crate = "near_sdk::serde")]
@@ -27,6 +26,27 @@ pub struct CollectionInfo {
     pub links: CollectionLinks,
 }
 
+/// Trading analytics for a token type, NEAR floor only
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Default)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CollectionStats {
+    pub volume: HashMap<FungibleTokenId, U128>,
+    pub sale_count: u64,
+    pub floor_price: Option<U128>,
+}
+
+/// Terms negotiated with an NFT contract, overriding the market defaults
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CollectionConfig {
+    /// market fee charged on top of the listed price
+    pub fee_bps: u16,
+    /// most of the seller's proceeds that can go to royalties
+    pub royalty_cap_bps: u16,
+    /// whether tokens of the contract can be listed
+    pub enabled: bool,
+}
+
 #[near_bindgen]
 impl Contract {
     /// for add sale see: nft_callbacks.rs
@@ -146,4 +166,151 @@ impl Contract {
         let contract_and_token_type = format!("{}{}{}", nft_contract, DELIMETER, token_type);
         self.collections.remove(&contract_and_token_type);
     }
+
+    /// only owner
+    pub fn set_collection_config(&mut self, nft_contract_id: AccountId, config: CollectionConfig) {
+        self.assert_owner();
+        require!(config.fee_bps <= MAX_MARKET_FEE_BPS, "fee_bps must be between 0 - 1,000");
+        require!(
+            config.royalty_cap_bps <= ONE_HUNDRED_PERCENT_IN_BPS,
+            "royalty_cap_bps must be between 0 - 10,000"
+        );
+        self.collection_configs.insert(&nft_contract_id, &config);
+        MarketEvent::log_collection_config_set(nft_contract_id, &config);
+    }
+
+    /// only owner, the collection falls back to the market defaults
+    pub fn remove_collection_config(&mut self, nft_contract_id: AccountId) {
+        self.assert_owner();
+        if self.collection_configs.remove(&nft_contract_id).is_some() {
+            MarketEvent::log_collection_config_remove(nft_contract_id);
+        }
+    }
+
+    /// views
+
+    pub fn get_collection_config(&self, nft_contract_id: AccountId) -> Option<CollectionConfig> {
+        self.collection_configs.get(&nft_contract_id)
+    }
+
+    /// market fee in basis points, config or global default
+    pub fn get_market_fee_bps(&self, nft_contract_id: AccountId) -> u16 {
+        self.internal_market_fee_bps(&nft_contract_id)
+    }
+
+    pub fn collection_stats(&self, token_type: String) -> CollectionStats {
+        self.token_type_stats.get(&token_type).unwrap_or_default()
+    }
+
+    pub fn get_floor_price(&self, token_type: String) -> Option<U128> {
+        self.token_type_stats
+            .get(&token_type)
+            .and_then(|stats| stats.floor_price)
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_market_fee_bps(&self, nft_contract_id: &AccountId) -> u16 {
+        self.collection_configs
+            .get(nft_contract_id)
+            .map_or(DEFAULT_MARKET_FEE_BPS, |config| config.fee_bps)
+    }
+
+    /// fee the buyer pays on top of `price`
+    pub(crate) fn internal_market_fee(&self, nft_contract_id: &AccountId, price: Balance) -> Balance {
+        price * self.internal_market_fee_bps(nft_contract_id) as u128 / ONE_HUNDRED_PERCENT_IN_BPS as u128
+    }
+
+    pub(crate) fn assert_collection_enabled(&self, nft_contract_id: &AccountId) {
+        if let Some(config) = self.collection_configs.get(nft_contract_id) {
+            require!(config.enabled, "Collection is disabled on this market");
+        }
+    }
+
+    /// scale down royalties above the collection's cap, the seller gets the difference
+    pub(crate) fn internal_cap_royalties(
+        &self,
+        nft_contract_id: &AccountId,
+        owner_id: &AccountId,
+        balance: Balance,
+        mut payout: Payout,
+    ) -> Payout {
+        let royalty_cap_bps = match self.collection_configs.get(nft_contract_id) {
+            Some(config) => config.royalty_cap_bps,
+            None => return payout,
+        };
+        let cap = balance * royalty_cap_bps as u128 / ONE_HUNDRED_PERCENT_IN_BPS as u128;
+        let royalties: Balance = payout
+            .payout
+            .iter()
+            .filter(|(receiver_id, _)| *receiver_id != owner_id)
+            .map(|(_, amount)| amount.0)
+            .sum();
+        if royalties <= cap {
+            return payout;
+        }
+        let total: Balance = payout.payout.values().map(|amount| amount.0).sum();
+        let mut capped_royalties = 0;
+        for (receiver_id, amount) in payout.payout.iter_mut() {
+            if receiver_id != owner_id {
+                let share_bps = amount.0 * ONE_HUNDRED_PERCENT_IN_BPS as u128 / royalties;
+                amount.0 = cap * share_bps / ONE_HUNDRED_PERCENT_IN_BPS as u128;
+                capped_royalties += amount.0;
+            }
+        }
+        payout
+            .payout
+            .insert(owner_id.clone(), U128(total - capped_royalties));
+        payout
+    }
+
+    /// a listing was added or repriced, lower the floor if it undercuts it
+    pub(crate) fn internal_stats_on_list(&mut self, token_type: &TokenType, sale_conditions: &SaleConditions) {
+        let (token_type, price) = match (token_type, sale_conditions.get(NEAR_TOKEN_ID)) {
+            (Some(token_type), Some(price)) => (token_type, price),
+            _ => return,
+        };
+        let mut stats = self.token_type_stats.get(token_type).unwrap_or_default();
+        if stats.floor_price.map_or(true, |floor| price.0 < floor.0) {
+            stats.floor_price = Some(*price);
+            self.token_type_stats.insert(token_type, &stats);
+        }
+    }
+
+    /// a listing left the market, the floor has to be recomputed if it was the cheapest one
+    pub(crate) fn internal_stats_on_delist(&mut self, token_type: &TokenType, sale_conditions: &SaleConditions) {
+        let token_type = match token_type {
+            Some(token_type) => token_type,
+            None => return,
+        };
+        let mut stats = match self.token_type_stats.get(token_type) {
+            Some(stats) => stats,
+            None => return,
+        };
+        if stats.floor_price.is_some() && stats.floor_price == sale_conditions.get(NEAR_TOKEN_ID).copied() {
+            stats.floor_price = self.internal_floor_price(token_type);
+            self.token_type_stats.insert(token_type, &stats);
+        }
+    }
+
+    pub(crate) fn internal_stats_on_sale(&mut self, token_type: &TokenType, ft_token_id: &FungibleTokenId, price: U128) {
+        let token_type = match token_type {
+            Some(token_type) => token_type,
+            None => return,
+        };
+        let mut stats = self.token_type_stats.get(token_type).unwrap_or_default();
+        let volume = stats.volume.entry(ft_token_id.clone()).or_insert(U128(0));
+        *volume = U128(volume.0 + price.0);
+        stats.sale_count += 1;
+        self.token_type_stats.insert(token_type, &stats);
+    }
+
+    fn internal_floor_price(&self, token_type: &String) -> Option<U128> {
+        let by_nft_token_type = self.by_nft_token_type.get(token_type)?;
+        by_nft_token_type
+            .iter()
+            .filter_map(|contract_and_token_id| self.sales.get(&contract_and_token_id))
+            .filter_map(|sale| sale.sale_conditions.get(NEAR_TOKEN_ID).copied())
+            .min_by_key(|price| price.0)
+    }
 }
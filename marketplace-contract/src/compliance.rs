@@ -0,0 +1,88 @@
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    /// only owner, blocked accounts can't list, bid or buy
+    pub fn block_accounts(&mut self, account_ids: Vec<AccountId>) {
+        self.assert_owner();
+        for account_id in account_ids {
+            if self.blocked_accounts.insert(&account_id) {
+                MarketEvent::log_account_block(account_id);
+            }
+        }
+    }
+
+    /// only owner
+    pub fn unblock_accounts(&mut self, account_ids: Vec<AccountId>) {
+        self.assert_owner();
+        for account_id in account_ids {
+            if self.blocked_accounts.remove(&account_id) {
+                MarketEvent::log_account_unblock(account_id);
+            }
+        }
+    }
+
+    /// only owner, tokens of a frozen contract can't be listed, bid on or bought
+    pub fn set_contract_frozen(&mut self, nft_contract_id: AccountId, frozen: bool) {
+        self.assert_owner();
+        if frozen {
+            if self.frozen_contracts.insert(&nft_contract_id) {
+                MarketEvent::log_contract_freeze(nft_contract_id);
+            }
+        } else if self.frozen_contracts.remove(&nft_contract_id) {
+            MarketEvent::log_contract_unfreeze(nft_contract_id);
+        }
+    }
+
+    /// views
+
+    pub fn is_account_blocked(&self, account_id: AccountId) -> bool {
+        self.blocked_accounts.contains(&account_id)
+    }
+
+    pub fn get_blocked_accounts(&self, from_index: U64, limit: u64) -> Vec<AccountId> {
+        let keys = self.blocked_accounts.as_vector();
+        let start = u64::from(from_index);
+        let end = min(start + limit, keys.len());
+        (start..end).filter_map(|i| keys.get(i)).collect()
+    }
+
+    pub fn is_contract_frozen(&self, nft_contract_id: AccountId) -> bool {
+        self.frozen_contracts.contains(&nft_contract_id)
+    }
+
+    pub fn get_frozen_contracts(&self, from_index: U64, limit: u64) -> Vec<AccountId> {
+        let keys = self.frozen_contracts.as_vector();
+        let start = u64::from(from_index);
+        let end = min(start + limit, keys.len());
+        (start..end).filter_map(|i| keys.get(i)).collect()
+    }
+}
+
+impl Contract {
+    pub(crate) fn assert_not_blocked(&self, account_id: &AccountId) {
+        require!(
+            !self.blocked_accounts.contains(account_id),
+            format!("{} is blocked on this market", account_id)
+        );
+    }
+
+    pub(crate) fn assert_not_frozen(&self, nft_contract_id: &AccountId) {
+        require!(
+            !self.frozen_contracts.contains(nft_contract_id),
+            "Collection is frozen on this market"
+        );
+    }
+
+    /// a trade of a token of `nft_contract_id` between `seller_id` and `buyer_id` is allowed
+    pub(crate) fn assert_compliant_trade(
+        &self,
+        nft_contract_id: &AccountId,
+        seller_id: &AccountId,
+        buyer_id: &AccountId,
+    ) {
+        self.assert_not_frozen(nft_contract_id);
+        self.assert_not_blocked(seller_id);
+        self.assert_not_blocked(buyer_id);
+    }
+}
@@ -0,0 +1,368 @@
+use crate::*;
+use near_sdk::serde_json;
+
+/// Enum that represents the data type of the EventLog.
+#[derive(Serialize, Debug)]
+#[serde(tag = "event", content = "data")]
+#[serde(rename_all = "snake_case")]
+#[serde(crate = "near_sdk::serde")]
+#[non_exhaustive]
+pub enum MarketEventKind {
+    CollectionConfigSet(Vec<CollectionConfigSetData>),
+    CollectionConfigRemove(Vec<CollectionConfigRemoveData>),
+    WashTradeFlag(Vec<WashTradeFlagData>),
+    SaleList(Vec<SaleListData>),
+    SaleUpdate(Vec<SaleListData>),
+    SaleDelist(Vec<SaleDelistData>),
+    SaleBid(Vec<SaleBidData>),
+    BidRefund(Vec<SaleBidData>),
+    SaleAccept(Vec<SaleSettleData>),
+    SalePurchase(Vec<SaleSettleData>),
+    AuctionSettle(Vec<SaleSettleData>),
+    RoyaltyPaid(Vec<RoyaltyPaidData>),
+    TokenTypeRegister(Vec<TokenTypeRegisterData>),
+    SaleBoost(Vec<SaleBoostData>),
+    TokenTypeUnregister(Vec<TokenTypeRegisterData>),
+    AccountBlock(Vec<AccountBlockData>),
+    AccountUnblock(Vec<AccountBlockData>),
+    ContractFreeze(Vec<ContractFreezeData>),
+    ContractUnfreeze(Vec<ContractFreezeData>),
+}
+
+/// Interface to capture data about an event
+///
+/// Arguments:
+/// * `standard`: name of standard e.g. nep171
+/// * `version`: e.g. 1.0.0
+/// * `event`: associate event data
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MarketEvent {
+    pub standard: String,
+    pub version: String,
+
+    // `flatten` to not have "event": {<MarketEventKind>} in the JSON, just have the contents of {<MarketEventKind>}.
+    #[serde(flatten)]
+    pub event_kind: MarketEventKind,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CollectionConfigSetData {
+    pub nft_contract_id: AccountId,
+    pub fee_bps: u16,
+    pub royalty_cap_bps: u16,
+    pub enabled: bool,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CollectionConfigRemoveData {
+    pub nft_contract_id: AccountId,
+}
+
+/// a token_type of an NFT contract registered by, or removed from, the contract's owner
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenTypeRegisterData {
+    pub nft_contract_id: AccountId,
+    pub token_type: String,
+    pub creator_id: AccountId,
+}
+
+/// an account barred from, or allowed back to, listing, bidding and buying
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AccountBlockData {
+    pub account_id: AccountId,
+}
+
+/// an NFT contract whose tokens can't, or can again, be traded on the market
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ContractFreezeData {
+    pub nft_contract_id: AccountId,
+}
+
+/// a sale repeating an earlier trade of the token between the same two accounts
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct WashTradeFlagData {
+    pub nft_contract_id: AccountId,
+    pub token_id: TokenId,
+    pub seller_id: AccountId,
+    pub buyer_id: AccountId,
+    pub ft_token_id: FungibleTokenId,
+    pub price: U128,
+}
+
+/// a listing and its prices, NEAR prices include the sale's surcharge
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SaleListData {
+    pub nft_contract_id: AccountId,
+    pub token_id: TokenId,
+    pub owner_id: AccountId,
+    pub sale_conditions: SaleConditions,
+    pub is_auction: bool,
+    pub expires_at: Option<U64>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SaleDelistData {
+    pub nft_contract_id: AccountId,
+    pub token_id: TokenId,
+    pub owner_id: AccountId,
+}
+
+/// a listing featured, or its boost extended, until `boosted_until`
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SaleBoostData {
+    pub nft_contract_id: AccountId,
+    pub token_id: TokenId,
+    pub owner_id: AccountId,
+    pub fee: U128,
+    pub boosted_until: U64,
+}
+
+/// a bid placed, or refunded when outbid or the sale ended
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SaleBidData {
+    pub nft_contract_id: AccountId,
+    pub token_id: TokenId,
+    pub bidder_id: AccountId,
+    pub ft_token_id: FungibleTokenId,
+    pub amount: U128,
+}
+
+/// a token changing hands through the market
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SaleSettleData {
+    pub nft_contract_id: AccountId,
+    pub token_id: TokenId,
+    pub seller_id: AccountId,
+    pub buyer_id: AccountId,
+    pub ft_token_id: FungibleTokenId,
+    pub price: U128,
+}
+
+/// a royalty sent to a payee out of a sale's price
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RoyaltyPaidData {
+    pub nft_contract_id: AccountId,
+    pub token_id: TokenId,
+    pub receiver_id: AccountId,
+    pub ft_token_id: FungibleTokenId,
+    pub amount: U128,
+}
+
+impl SaleBidData {
+    fn new(sale: &Sale, bid: &Bid, ft_token_id: &FungibleTokenId) -> Self {
+        SaleBidData {
+            nft_contract_id: sale.nft_contract_id.clone(),
+            token_id: sale.token_id.clone(),
+            bidder_id: bid.owner_id.clone(),
+            ft_token_id: ft_token_id.clone(),
+            amount: bid.price,
+        }
+    }
+}
+
+impl SaleListData {
+    fn new(sale: &Sale) -> Self {
+        SaleListData {
+            nft_contract_id: sale.nft_contract_id.clone(),
+            token_id: sale.token_id.clone(),
+            owner_id: sale.owner_id.clone(),
+            sale_conditions: sale.sale_conditions.clone(),
+            is_auction: sale.is_auction,
+            expires_at: sale.expires_at,
+        }
+    }
+}
+
+impl MarketEvent {
+    pub fn new(event_kind: MarketEventKind) -> Self {
+        MarketEvent {
+            standard: "marketplace".to_string(),
+            version: "1.0.0".to_string(),
+            event_kind,
+        }
+    }
+
+    pub fn to_json_string(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    pub fn to_json_event_string(&self) -> String {
+        format!("EVENT_JSON:{}", self.to_json_string())
+    }
+
+    pub fn log(&self) {
+        env::log_str(&self.to_json_event_string());
+    }
+
+    pub fn log_collection_config_set(nft_contract_id: AccountId, config: &CollectionConfig) {
+        MarketEvent::new(MarketEventKind::CollectionConfigSet(vec![CollectionConfigSetData {
+            nft_contract_id,
+            fee_bps: config.fee_bps,
+            royalty_cap_bps: config.royalty_cap_bps,
+            enabled: config.enabled,
+        }]))
+        .log();
+    }
+
+    pub fn log_wash_trade_flag(
+        sale: &Sale,
+        seller_id: &AccountId,
+        buyer_id: &AccountId,
+        ft_token_id: &FungibleTokenId,
+        price: U128,
+    ) {
+        MarketEvent::new(MarketEventKind::WashTradeFlag(vec![WashTradeFlagData {
+            nft_contract_id: sale.nft_contract_id.clone(),
+            token_id: sale.token_id.clone(),
+            seller_id: seller_id.clone(),
+            buyer_id: buyer_id.clone(),
+            ft_token_id: ft_token_id.clone(),
+            price,
+        }]))
+        .log();
+    }
+
+    pub fn log_sale_list(sale: &Sale) {
+        MarketEvent::new(MarketEventKind::SaleList(vec![SaleListData::new(sale)])).log();
+    }
+
+    pub fn log_sale_update(sale: &Sale) {
+        MarketEvent::new(MarketEventKind::SaleUpdate(vec![SaleListData::new(sale)])).log();
+    }
+
+    pub fn log_sale_boost(sale: &Sale, fee: U128, boosted_until: U64) {
+        MarketEvent::new(MarketEventKind::SaleBoost(vec![SaleBoostData {
+            nft_contract_id: sale.nft_contract_id.clone(),
+            token_id: sale.token_id.clone(),
+            owner_id: sale.owner_id.clone(),
+            fee,
+            boosted_until,
+        }]))
+        .log();
+    }
+
+    pub fn log_sale_delist(sale: &Sale) {
+        MarketEvent::new(MarketEventKind::SaleDelist(vec![SaleDelistData {
+            nft_contract_id: sale.nft_contract_id.clone(),
+            token_id: sale.token_id.clone(),
+            owner_id: sale.owner_id.clone(),
+        }]))
+        .log();
+    }
+
+    pub fn log_sale_bid(sale: &Sale, bid: &Bid, ft_token_id: &FungibleTokenId) {
+        MarketEvent::new(MarketEventKind::SaleBid(vec![SaleBidData::new(sale, bid, ft_token_id)])).log();
+    }
+
+    pub fn log_bid_refund(sale: &Sale, bid: &Bid, ft_token_id: &FungibleTokenId) {
+        MarketEvent::new(MarketEventKind::BidRefund(vec![SaleBidData::new(sale, bid, ft_token_id)])).log();
+    }
+
+    /// `SaleAccept` for an accepted offer, `AuctionSettle` when the sale was an auction
+    pub fn log_sale_accept(sale: &Sale, bid: &Bid, ft_token_id: &FungibleTokenId) {
+        let data = vec![SaleSettleData {
+            nft_contract_id: sale.nft_contract_id.clone(),
+            token_id: sale.token_id.clone(),
+            seller_id: sale.owner_id.clone(),
+            buyer_id: bid.owner_id.clone(),
+            ft_token_id: ft_token_id.clone(),
+            price: bid.price,
+        }];
+        let event_kind = if sale.is_auction {
+            MarketEventKind::AuctionSettle(data)
+        } else {
+            MarketEventKind::SaleAccept(data)
+        };
+        MarketEvent::new(event_kind).log();
+    }
+
+    pub fn log_sale_purchase(
+        nft_contract_id: &AccountId,
+        token_id: &TokenId,
+        seller_id: &AccountId,
+        buyer_id: &AccountId,
+        ft_token_id: &FungibleTokenId,
+        price: U128,
+    ) {
+        MarketEvent::new(MarketEventKind::SalePurchase(vec![SaleSettleData {
+            nft_contract_id: nft_contract_id.clone(),
+            token_id: token_id.clone(),
+            seller_id: seller_id.clone(),
+            buyer_id: buyer_id.clone(),
+            ft_token_id: ft_token_id.clone(),
+            price,
+        }]))
+        .log();
+    }
+
+    pub fn log_royalty_paid(
+        nft_contract_id: &AccountId,
+        token_id: &TokenId,
+        receiver_id: &AccountId,
+        ft_token_id: &FungibleTokenId,
+        amount: U128,
+    ) {
+        MarketEvent::new(MarketEventKind::RoyaltyPaid(vec![RoyaltyPaidData {
+            nft_contract_id: nft_contract_id.clone(),
+            token_id: token_id.clone(),
+            receiver_id: receiver_id.clone(),
+            ft_token_id: ft_token_id.clone(),
+            amount,
+        }]))
+        .log();
+    }
+
+    pub fn log_token_type_register(nft_contract_id: AccountId, token_type: String, creator_id: AccountId) {
+        MarketEvent::new(MarketEventKind::TokenTypeRegister(vec![TokenTypeRegisterData {
+            nft_contract_id,
+            token_type,
+            creator_id,
+        }]))
+        .log();
+    }
+
+    pub fn log_token_type_unregister(nft_contract_id: AccountId, token_type: String, creator_id: AccountId) {
+        MarketEvent::new(MarketEventKind::TokenTypeUnregister(vec![TokenTypeRegisterData {
+            nft_contract_id,
+            token_type,
+            creator_id,
+        }]))
+        .log();
+    }
+
+    pub fn log_account_block(account_id: AccountId) {
+        MarketEvent::new(MarketEventKind::AccountBlock(vec![AccountBlockData { account_id }])).log();
+    }
+
+    pub fn log_account_unblock(account_id: AccountId) {
+        MarketEvent::new(MarketEventKind::AccountUnblock(vec![AccountBlockData { account_id }])).log();
+    }
+
+    pub fn log_contract_freeze(nft_contract_id: AccountId) {
+        MarketEvent::new(MarketEventKind::ContractFreeze(vec![ContractFreezeData { nft_contract_id }])).log();
+    }
+
+    pub fn log_contract_unfreeze(nft_contract_id: AccountId) {
+        MarketEvent::new(MarketEventKind::ContractUnfreeze(vec![ContractFreezeData { nft_contract_id }])).log();
+    }
+
+    pub fn log_collection_config_remove(nft_contract_id: AccountId) {
+        MarketEvent::new(MarketEventKind::CollectionConfigRemove(vec![
+            CollectionConfigRemoveData { nft_contract_id },
+        ]))
+        .log();
+    }
+}
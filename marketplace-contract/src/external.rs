@@ -26,4 +26,21 @@ trait ExtContract {
         amount: U128,
         memo: Option<String>
     );
+    fn ft_transfer_call(
+        &mut self,
+        receiver_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+        msg: String,
+    );
+    fn nft_token(&self, token_id: TokenId);
+    fn owner(&self);
+    fn mt_transfer(
+        &mut self,
+        receiver_id: AccountId,
+        token_id: TokenId,
+        amount: U128,
+        approval: Option<(AccountId, u64)>,
+        memo: Option<String>,
+    );
 }
\ No newline at end of file
@@ -12,27 +12,38 @@ impl FungibleTokenReceiver for Contract {
         let PurchaseArgs {
             nft_contract_id,
             token_id,
+            expected_price,
+            expected_ft_token_id,
         } = near_sdk::serde_json::from_str(&msg).expect("Invalid PurchaseArgs");
+        self.assert_not_paused();
 
         let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
         let mut sale = self
             .sales
             .get(&contract_and_token_id)
             .expect("No sale in ft_on_transfer");
-            
-        assert_ne!(sale.owner_id, sender_id, "Cannot buy your own sale.");
+        assert_not_expired(&sale);
+
+        require!(sale.owner_id != sender_id, "Cannot buy your own sale.");
+        assert_allowed_buyer(&sale, &sender_id);
 
         let ft_token_id = env::predecessor_account_id();
         let price = *sale
             .sale_conditions
             .get(&ft_token_id)
             .expect("Not for sale in that token type");
+        // panicking here has the FT contract refund the whole amount
+        assert_expected_price(price, &ft_token_id, expected_price, expected_ft_token_id);
+
+        require!(amount.0 > 0, "Amount must be greater than 0");
 
-        assert!(amount.0 > 0, "Amount must be greater than 0");
+        if sale.is_auction {
+            require!(amount.0 >= price.0, "Bid must be at least the reserve price");
+        }
 
         if !sale.is_auction && amount == price {
             self.process_purchase(
-                nft_contract_id.into(),
+                nft_contract_id,
                 token_id,
                 ft_token_id,
                 price,
@@ -40,9 +51,6 @@ impl FungibleTokenReceiver for Contract {
                 sale.owner_id.clone()
             ).into()
         } else {
-            if sale.is_auction && price.0 > 0 {
-                assert!(amount.0 >= price.0, "Amount must be greater than reserve price");
-            }
             self.add_bid(
                 contract_and_token_id,
                 amount.0,
@@ -1,52 +1,436 @@
 use crate::*;
 
-pub(crate) fn hash_account_id(account_id: &AccountId) -> CryptoHash {
+pub(crate) fn hash_account_id(account_id: &str) -> CryptoHash {
     let mut hash = CryptoHash::default();
     hash.copy_from_slice(&env::sha256(account_id.as_bytes()));
     hash
 }
 
+pub(crate) fn near_token_id() -> FungibleTokenId {
+    NEAR_TOKEN_ID.parse().unwrap()
+}
+
+pub(crate) fn assert_not_expired(sale: &Sale) {
+    if let Some(expires_at) = sale.expires_at {
+        require!(
+            env::block_timestamp() / 1000000 < expires_at.0,
+            "Sale has expired"
+        );
+    }
+}
+
+/// a sale repriced after the buyer saw it aborts, the buyer's funds are refunded
+pub(crate) fn assert_expected_price(
+    price: U128,
+    ft_token_id: &FungibleTokenId,
+    expected_price: Option<U128>,
+    expected_ft_token_id: Option<FungibleTokenId>,
+) {
+    if let Some(expected_ft_token_id) = expected_ft_token_id {
+        require!(
+            *ft_token_id == expected_ft_token_id,
+            format!("Expected to pay in {}, not {}", expected_ft_token_id, ft_token_id)
+        );
+    }
+    if let Some(expected_price) = expected_price {
+        require!(
+            price == expected_price,
+            format!("Sale price changed to {}, expected {}", price.0, expected_price.0)
+        );
+    }
+}
+
+pub(crate) fn assert_bid_params(min_bid_increment_bps: Option<u16>, max_bids: Option<u8>) {
+    if let Some(min_bid_increment_bps) = min_bid_increment_bps {
+        require!(
+            min_bid_increment_bps <= ONE_HUNDRED_PERCENT_IN_BPS,
+            "min_bid_increment_bps must be between 0 - 10,000"
+        );
+    }
+    if let Some(max_bids) = max_bids {
+        require!(max_bids > 0, "max_bids must be greater than 0");
+    }
+}
+
+/// a seller's split has to pay every member something and add up to all of the proceeds
+pub(crate) fn assert_payout_split(payout_split: &Option<PayoutSplit>) {
+    if let Some(payout_split) = payout_split {
+        require!(
+            !payout_split.is_empty() && payout_split.len() <= MAX_PAYOUT_SPLIT,
+            format!("payout_split must have between 1 - {} accounts", MAX_PAYOUT_SPLIT)
+        );
+        require!(
+            payout_split.values().all(|&bps| bps > 0),
+            "payout_split bps must be greater than 0"
+        );
+        require!(
+            payout_split.values().map(|&bps| bps as u32).sum::<u32>() == ONE_HUNDRED_PERCENT_IN_BPS as u32,
+            "payout_split must sum to 10,000"
+        );
+    }
+}
+
+/// `amount` divided by the split, the rounding dust goes to the last account by id
+pub(crate) fn internal_split_payout(payout_split: &PayoutSplit, amount: U128) -> Vec<(AccountId, U128)> {
+    let mut account_ids: Vec<&AccountId> = payout_split.keys().collect();
+    account_ids.sort();
+    let mut left = amount.0;
+    let mut shares: Vec<(AccountId, U128)> = account_ids
+        .into_iter()
+        .map(|account_id| {
+            let share = amount.0 * payout_split[account_id] as u128 / ONE_HUNDRED_PERCENT_IN_BPS as u128;
+            left -= share;
+            (account_id.clone(), U128(share))
+        })
+        .collect();
+    if let Some((_, share)) = shares.last_mut() {
+        share.0 += left;
+    }
+    shares
+}
+
+/// the payout returned by `nft_transfer_payout`, None when it doesn't add up to `balance`
+pub(crate) fn internal_parse_payout(value: &[u8], balance: Balance) -> Option<Payout> {
+    near_sdk::serde_json::from_slice::<Payout>(value)
+        .ok()
+        .and_then(|mut payout| {
+            // transfers past the gas for 10 FT transfers are queued, see `process_queue`
+            if payout.payout.len() > 10 || payout.payout.is_empty() {
+                env::log_str("Cannot have more than 10 royalties");
+                None
+            } else {
+                let mut remainder = balance;
+                for &value in payout.payout.values() {
+                    remainder = remainder.checked_sub(value.0)?;
+                }
+                // shares rounded down leave at most 1 yocto each, e.g. 3333 + 3333 + 3333 of 10000
+                if remainder >= payout.payout.len() as u128 {
+                    return None;
+                }
+                // the dust goes to the last payee, by account id so it doesn't depend on the map's order
+                if let Some(last) = payout.payout.keys().max().cloned() {
+                    payout.payout.get_mut(&last).unwrap().0 += remainder;
+                }
+                Some(payout)
+            }
+        })
+}
+
+pub(crate) fn assert_allowed_buyer(sale: &Sale, buyer_id: &AccountId) {
+    if let Some(allowed_buyer) = sale.allowed_buyer.as_ref() {
+        require!(
+            allowed_buyer == buyer_id,
+            "This sale is private, only the allowed buyer can buy or bid"
+        );
+    }
+}
+
+/// surcharge included in a NEAR purchase, FT prices carry none
+pub(crate) fn internal_surcharge(sale: &Sale, ft_token_id: &FungibleTokenId, price: U128) -> Balance {
+    if ft_token_id.as_str() == NEAR_TOKEN_ID {
+        min(sale.surcharge.0, price.0)
+    } else {
+        0
+    }
+}
+
+/// the seller is paid on the listed price less the surcharge, anything above the listed price is the market fee
+pub(crate) fn internal_seller_balance(sale: &Sale, ft_token_id: &FungibleTokenId, price: U128) -> Balance {
+    let listed = sale
+        .sale_conditions
+        .get(ft_token_id)
+        .map_or(price.0, |listed_price| min(price.0, listed_price.0));
+    listed - min(listed, internal_surcharge(sale, ft_token_id, price))
+}
+
+/// add or remove one listing from a supply counter, dropping it at 0
+pub(crate) fn update_supply<K: BorshSerialize>(supply: &mut LookupMap<K, u64>, key: &K, listed: bool) {
+    let count = supply.get(key).unwrap_or(0);
+    let count = if listed { count + 1 } else { count.saturating_sub(1) };
+    if count == 0 {
+        supply.remove(key);
+    } else {
+        supply.insert(key, &count);
+    }
+}
+
+pub(crate) fn bid_key(
+    contract_and_token_id: &ContractAndTokenId,
+    ft_token_id: &FungibleTokenId,
+    bidder_id: &AccountId,
+) -> BidKey {
+    (contract_and_token_id.clone(), ft_token_id.clone(), bidder_id.clone())
+}
+
 impl Contract {
     pub(crate) fn assert_owner(&self) {
-        assert_eq!(
-            &env::predecessor_account_id(),
-            &self.owner_id,
+        require!(
+            env::predecessor_account_id() == self.owner_id,
             "Owner's method"
         );
     }
 
+    pub(crate) fn assert_owner_or_manager(&self) {
+        let predecessor_id = env::predecessor_account_id();
+        require!(
+            predecessor_id == self.owner_id || self.admin_ids.contains(&predecessor_id),
+            "Owner's or admin's method"
+        );
+    }
+
+    pub(crate) fn assert_not_paused(&self) {
+        require!(!self.paused, "Trading is paused");
+    }
+
     pub(crate) fn assert_manager(&self) {
-        let contains = self.admin_ids.contains(&env::predecessor_account_id());
-        assert_eq!(
-            &contains,
-            &true,
+        require!(
+            self.admin_ids.contains(&env::predecessor_account_id()),
             "Admin's method"
         );
     }
 
     /// refund the last bid of each token type, don't update sale because it's already been removed
 
-    pub(crate) fn refund_all_bids(
-        &mut self,
+    pub(crate) fn refund_all_bids(&mut self, sale: &Sale) {
+        self.internal_settle_bid_refunds(sale, &mut u32::MAX);
+    }
+
+    /// refund the last bid of each token type, once `immediate` refunds were sent the rest is queued.
+    /// Returns the number of refunds queued
+    pub(crate) fn internal_settle_bid_refunds(&mut self, sale: &Sale, immediate: &mut u32) -> u32 {
+        let contract_and_token_id = &format!("{}{}{}", sale.nft_contract_id, DELIMETER, sale.token_id);
+        let bids = &sale.bids;
+        let mut queued = 0;
+        for (bid_ft, bidders) in bids {
+            if let Some(bid) = self.internal_current_bid(contract_and_token_id, bids, bid_ft) {
+                MarketEvent::log_bid_refund(sale, &bid, bid_ft);
+                let job = SettlementJob {
+                    kind: SettlementKind::BidRefund,
+                    receiver_id: bid.owner_id,
+                    ft_token_id: bid_ft.clone(),
+                    amount: bid.price,
+                };
+                if self.internal_settle(job, immediate) {
+                    queued += 1;
+                }
+            }
+            self.internal_clear_bids(contract_and_token_id, bid_ft, bidders);
+        }
+        queued
+    }
+
+    /// give an escrowed bid back to its bidder, FT bids go back through `ft_transfer`
+    pub(crate) fn internal_refund_bid(&mut self, sale: &Sale, bid: &Bid, ft_token_id: &FungibleTokenId) {
+        MarketEvent::log_bid_refund(sale, bid, ft_token_id);
+        if ft_token_id.as_str() == NEAR_TOKEN_ID {
+            self.internal_refund_near(bid.owner_id.clone(), bid.price.0);
+        } else {
+            ext_contract::ext(ft_token_id.clone())
+                .with_attached_deposit(1)
+                .with_static_gas(GAS_FOR_FT_TRANSFER)
+                .ft_transfer(bid.owner_id.clone(), bid.price, None);
+        }
+    }
+
+    /// least a bid can be to beat `current_bid`, at least 1 more even without an increment
+    pub(crate) fn internal_min_next_bid(&self, sale: &Sale, current_bid: Balance) -> Balance {
+        let bps = sale.min_bid_increment_bps.unwrap_or(self.min_bid_increment_bps) as u128;
+        let increment = (current_bid * bps + ONE_HUNDRED_PERCENT_IN_BPS as u128 - 1)
+            / ONE_HUNDRED_PERCENT_IN_BPS as u128;
+        current_bid + increment.max(1)
+    }
+
+    /// highest (most recent) bid in a given ft_token_id
+    pub(crate) fn internal_current_bid(
+        &self,
+        contract_and_token_id: &ContractAndTokenId,
         bids: &Bids,
+        ft_token_id: &FungibleTokenId,
+    ) -> Option<Bid> {
+        let bidder_id = bids.get(ft_token_id)?.last()?;
+        self.bids
+            .get(&bid_key(contract_and_token_id, ft_token_id, bidder_id))
+            .map(|price| Bid {
+                owner_id: bidder_id.clone(),
+                price,
+            })
+    }
+
+    /// all stored bids of a sale in a given ft_token_id, oldest first
+    pub(crate) fn internal_bids(
+        &self,
+        contract_and_token_id: &ContractAndTokenId,
+        bids: &Bids,
+        ft_token_id: &FungibleTokenId,
+    ) -> Vec<Bid> {
+        bids.get(ft_token_id)
+            .map(|bidders| {
+                bidders
+                    .iter()
+                    .filter_map(|bidder_id| {
+                        self.bids
+                            .get(&bid_key(contract_and_token_id, ft_token_id, bidder_id))
+                            .map(|price| Bid {
+                                owner_id: bidder_id.clone(),
+                                price,
+                            })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn internal_clear_bids(
+        &mut self,
+        contract_and_token_id: &ContractAndTokenId,
+        ft_token_id: &FungibleTokenId,
+        bidders: &[AccountId],
     ) {
-        for (bid_ft, bid_vec) in bids {
-            let bid = &bid_vec[bid_vec.len()-1];
-            if bid_ft == "near" {
-                    Promise::new(bid.owner_id.clone()).transfer(u128::from(bid.price));
+        for bidder_id in bidders {
+            self.bids
+                .remove(&bid_key(contract_and_token_id, ft_token_id, bidder_id));
+        }
+    }
+
+    /// store a sale and add it to every index
+    pub(crate) fn internal_add_sale(&mut self, sale: Sale) {
+        let contract_and_token_id = format!("{}{}{}", sale.nft_contract_id, DELIMETER, sale.token_id);
+        self.sale_ids.insert(&contract_and_token_id);
+        self.sales.insert(&contract_and_token_id, &sale);
+        self.internal_record_change(&contract_and_token_id, ChangeType::Listed);
+
+        // extra for views
+
+        let mut by_owner_id = self.by_owner_id.get(&sale.owner_id).unwrap_or_else(|| {
+            UnorderedSet::new(
+                StorageKey::ByOwnerIdInner {
+                    account_id_hash: hash_account_id(sale.owner_id.as_str()),
+                }
+                .try_to_vec()
+                .unwrap(),
+            )
+        });
+        if by_owner_id.insert(&contract_and_token_id) {
+            update_supply(&mut self.supply_by_owner_id, &sale.owner_id, true);
+        }
+        self.by_owner_id.insert(&sale.owner_id, &by_owner_id);
+
+        let mut by_nft_contract_id = self
+            .by_nft_contract_id
+            .get(&sale.nft_contract_id)
+            .unwrap_or_else(|| {
+                UnorderedSet::new(
+                    StorageKey::ByNFTContractIdInner {
+                        account_id_hash: hash_account_id(sale.nft_contract_id.as_str()),
+                    }
+                    .try_to_vec()
+                    .unwrap(),
+                )
+            });
+        if by_nft_contract_id.insert(&sale.token_id) {
+            update_supply(&mut self.supply_by_nft_contract_id, &sale.nft_contract_id, true);
+        }
+        self.by_nft_contract_id
+            .insert(&sale.nft_contract_id, &by_nft_contract_id);
+
+        if let Some(token_type) = sale.token_type.as_ref() {
+            let mut by_nft_token_type = self
+                .by_nft_token_type
+                .get(token_type)
+                .unwrap_or_else(|| {
+                    UnorderedSet::new(
+                        StorageKey::ByNFTTokenTypeInner {
+                            token_type_hash: hash_account_id(token_type),
+                        }
+                        .try_to_vec()
+                        .unwrap(),
+                    )
+                });
+            if by_nft_token_type.insert(&contract_and_token_id) {
+                update_supply(&mut self.supply_by_nft_token_type, token_type, true);
+            }
+            self.by_nft_token_type
+                .insert(token_type, &by_nft_token_type);
+        }
+
+        self.internal_stats_on_list(&sale.token_type, &sale.sale_conditions);
+        self.internal_count_ft_listings(sale.sale_conditions.keys(), true);
+        MarketEvent::log_sale_list(&sale);
+    }
+
+    /// move a sale listed before the storage redesign to `sales`, it's already in the index sets
+    pub(crate) fn internal_migrate_sale(&mut self, contract_and_token_id: &ContractAndTokenId, old: SaleV1) {
+        let mut bids = Bids::new();
+        for (ft_token_id, old_bids) in old.bids {
+            let mut bidders: Vec<AccountId> = vec![];
+            for bid in old_bids {
+                self.bids
+                    .insert(&bid_key(contract_and_token_id, &ft_token_id, &bid.owner_id), &bid.price);
+                bidders.retain(|bidder_id| bidder_id != &bid.owner_id);
+                bidders.push(bid.owner_id);
+            }
+            bids.insert(ft_token_id, bidders);
+        }
+        let sale = Sale {
+            owner_id: old.owner_id,
+            approval_id: old.approval_id,
+            nft_contract_id: old.nft_contract_id,
+            token_id: old.token_id,
+            sale_conditions: old.sale_conditions,
+            bids,
+            created_at: old.created_at,
+            is_auction: old.is_auction,
+            token_type: old.token_type,
+            expires_at: None,
+            allowed_buyer: None,
+            surcharge: U128(0),
+            min_bid_increment_bps: None,
+            max_bids: None,
+            payout_split: None,
+        };
+        self.sale_ids.insert(contract_and_token_id);
+        self.sales.insert(contract_and_token_id, &sale);
+        update_supply(&mut self.supply_by_owner_id, &sale.owner_id, true);
+        update_supply(&mut self.supply_by_nft_contract_id, &sale.nft_contract_id, true);
+        if let Some(token_type) = sale.token_type.as_ref() {
+            update_supply(&mut self.supply_by_nft_token_type, token_type, true);
+        }
+        self.internal_stats_on_list(&sale.token_type, &sale.sale_conditions);
+        self.internal_count_ft_listings(sale.sale_conditions.keys(), true);
+    }
+
+    /// keep track of the listings priced in each ft_token_id
+    pub(crate) fn internal_count_ft_listings<'a>(
+        &mut self,
+        ft_token_ids: impl Iterator<Item = &'a FungibleTokenId>,
+        listed: bool,
+    ) {
+        for ft_token_id in ft_token_ids {
+            let count = self.ft_token_listings.get(ft_token_id).unwrap_or(0);
+            let count = if listed { count + 1 } else { count.saturating_sub(1) };
+            if count == 0 {
+                self.ft_token_listings.remove(ft_token_id);
             } else {
-                ext_contract::ft_transfer(
-                    bid.owner_id.clone(),
-                    bid.price,
-                    None,
-                    bid_ft,
-                    1,
-                    GAS_FOR_FT_TRANSFER,
-                );
+                self.ft_token_listings.insert(ft_token_id, &count);
             }
         }
     }
 
+    /// drop a listing that can no longer settle, the NFT isn't held by the market
+    pub(crate) fn internal_delist_stale_sale(&mut self, nft_contract_id: AccountId, token_id: TokenId) {
+        let sale = self.internal_remove_sale(nft_contract_id, token_id);
+        MarketEvent::log_sale_delist(&sale);
+        self.refund_all_bids(&sale);
+        self.internal_record_activity(
+            ActivityKind::Cancel,
+            &sale.nft_contract_id,
+            &sale.token_id,
+            &sale.owner_id,
+            None,
+            None,
+        );
+    }
+
     pub(crate) fn internal_remove_sale(
         &mut self,
         nft_contract_id: AccountId,
@@ -54,9 +438,14 @@ impl Contract {
     ) -> Sale {
         let contract_and_token_id = format!("{}{}{}", &nft_contract_id, DELIMETER, token_id);
         let sale = self.sales.remove(&contract_and_token_id).expect("No sale");
+        self.sale_ids.remove(&contract_and_token_id);
+        self.featured_sales.remove(&contract_and_token_id);
+        self.internal_record_change(&contract_and_token_id, ChangeType::Removed);
 
         let mut by_owner_id = self.by_owner_id.get(&sale.owner_id).expect("No sale by_owner_id");
-        by_owner_id.remove(&contract_and_token_id);
+        if by_owner_id.remove(&contract_and_token_id) {
+            update_supply(&mut self.supply_by_owner_id, &sale.owner_id, false);
+        }
         if by_owner_id.is_empty() {
             self.by_owner_id.remove(&sale.owner_id);
         } else {
@@ -67,7 +456,9 @@ impl Contract {
             .by_nft_contract_id
             .get(&nft_contract_id)
             .expect("No sale by nft_contract_id");
-        by_nft_contract_id.remove(&token_id);
+        if by_nft_contract_id.remove(&token_id) {
+            update_supply(&mut self.supply_by_nft_contract_id, &nft_contract_id, false);
+        }
         if by_nft_contract_id.is_empty() {
             self.by_nft_contract_id.remove(&nft_contract_id);
         } else {
@@ -78,7 +469,9 @@ impl Contract {
         let token_type = sale.token_type.clone();
         if let Some(token_type) = token_type {
             let mut by_nft_token_type = self.by_nft_token_type.get(&token_type).expect("No sale by nft_token_type");
-            by_nft_token_type.remove(&contract_and_token_id);
+            if by_nft_token_type.remove(&contract_and_token_id) {
+                update_supply(&mut self.supply_by_nft_token_type, &token_type, false);
+            }
             if by_nft_token_type.is_empty() {
                 self.by_nft_token_type.remove(&token_type);
             } else {
@@ -86,6 +479,9 @@ impl Contract {
             }
         }
 
+        self.internal_stats_on_delist(&sale.token_type, &sale.sale_conditions);
+        self.internal_count_ft_listings(sale.sale_conditions.keys(), false);
+
         sale
     }
 }
@@ -0,0 +1,205 @@
+use crate::*;
+use near_sdk::json_types::Base64VecU8;
+use near_sdk::{promise_result_as_success, CurveType};
+use std::convert::TryInto;
+
+/// Listing signed off chain by the seller instead of listed with `nft_approve`.
+/// The seller approves the market on the NFT contract without a msg, so no sale is stored.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LazyListing {
+    pub owner_id: AccountId,
+    pub nft_contract_id: AccountId,
+    pub token_id: TokenId,
+    /// approval id of the market on the NFT contract
+    pub approval_id: u64,
+    /// NEAR price, the market fee is paid on top of it
+    pub price: U128,
+    /// ms since epoch after which the listing can no longer be bought
+    pub expires_at: U64,
+    /// each nonce can only be bought or cancelled once
+    pub nonce: U64,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// register the key lazy listings of the caller are signed with, none removes it
+    #[payable]
+    pub fn set_listing_key(&mut self, public_key: Option<PublicKey>) {
+        assert_one_yocto();
+        let owner_id = env::predecessor_account_id();
+        match public_key {
+            Some(public_key) => {
+                require!(
+                    public_key.curve_type() == CurveType::ED25519,
+                    "Listing key must be an ed25519 key"
+                );
+                self.listing_keys.insert(&owner_id, &public_key);
+            }
+            None => {
+                self.listing_keys.remove(&owner_id);
+            }
+        }
+    }
+
+    /// make a signed listing unusable
+    #[payable]
+    pub fn cancel_lazy_listing(&mut self, nonce: U64) {
+        assert_one_yocto();
+        self.used_listing_nonces
+            .insert(&(env::predecessor_account_id(), nonce.0));
+    }
+
+    /// buy a signed listing, attach the price plus the market fee
+    #[payable]
+    pub fn buy_lazy_listing(&mut self, listing: LazyListing, signature: Base64VecU8) -> Promise {
+        self.assert_not_paused();
+        self.assert_collection_enabled(&listing.nft_contract_id);
+        let buyer_id = env::predecessor_account_id();
+        require!(listing.owner_id != buyer_id, "Cannot buy your own listing.");
+        self.assert_compliant_trade(&listing.nft_contract_id, &listing.owner_id, &buyer_id);
+        require!(
+            env::block_timestamp() / 1000000 < listing.expires_at.0,
+            "Listing has expired"
+        );
+        self.assert_listing_signature(&listing, &signature);
+        require!(
+            self.used_listing_nonces
+                .insert(&(listing.owner_id.clone(), listing.nonce.0)),
+            "Listing was already bought or cancelled"
+        );
+
+        let fee = self.internal_market_fee(&listing.nft_contract_id, listing.price.0);
+        let deposit = env::attached_deposit();
+        require!(deposit == listing.price.0 + fee, "Attached deposit must be price plus market fee");
+
+        ext_contract::ext(listing.nft_contract_id.clone())
+            .with_attached_deposit(1)
+            .with_static_gas(GAS_FOR_NFT_TRANSFER)
+            .nft_transfer_payout(
+                buyer_id.clone(),
+                listing.token_id.clone(),
+                listing.approval_id,
+                "payout from market".to_string(),
+                listing.price,
+                10,
+            )
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_ROYALTIES)
+                    .resolve_lazy_purchase(listing, buyer_id, U128(deposit)),
+            )
+    }
+
+    /// self callback, returns whether the NFT was transferred.
+    /// Nothing transferred: the buyer is refunded and the listing can be bought again.
+    #[private]
+    pub fn resolve_lazy_purchase(&mut self, listing: LazyListing, buyer_id: AccountId, paid: U128) -> bool {
+        let value = match promise_result_as_success() {
+            Some(value) => value,
+            None => {
+                self.internal_refund_near(buyer_id, paid.0);
+                self.used_listing_nonces
+                    .remove(&(listing.owner_id, listing.nonce.0));
+                return false;
+            }
+        };
+        let price = listing.price.0;
+        let marketplace_id = env::current_account_id();
+
+        let payout = near_sdk::serde_json::from_slice::<Payout>(&value)
+            .ok()
+            .filter(|payout| {
+                !payout.payout.is_empty()
+                    && payout.payout.len() <= 10
+                    && payout.payout.values().map(|amount| amount.0).sum::<u128>() <= price
+            })
+            .map(|payout| self.internal_cap_royalties(&listing.nft_contract_id, &listing.owner_id, price, payout))
+            .unwrap_or_else(|| {
+                let mut payout = HashMap::new();
+                payout.insert(listing.owner_id.clone(), listing.price);
+                Payout { payout }
+            });
+        for (receiver_id, amount) in payout.payout {
+            if receiver_id == marketplace_id {
+                Promise::new(listing.owner_id.clone()).transfer(amount.0);
+            } else {
+                if receiver_id != listing.owner_id {
+                    self.internal_record_royalty(
+                        &listing.nft_contract_id,
+                        &listing.token_id,
+                        &receiver_id,
+                        &near_token_id(),
+                        amount,
+                    );
+                }
+                Promise::new(receiver_id).transfer(amount.0);
+            }
+        }
+        let fee = paid.0 - price;
+        if fee > 0 {
+            Promise::new(self.treasury_id.clone()).transfer(fee);
+        }
+
+        MarketEvent::log_sale_purchase(
+            &listing.nft_contract_id,
+            &listing.token_id,
+            &listing.owner_id,
+            &buyer_id,
+            &near_token_id(),
+            listing.price,
+        );
+        self.internal_record_activity(
+            ActivityKind::Sale,
+            &listing.nft_contract_id,
+            &listing.token_id,
+            &buyer_id,
+            Some(near_token_id()),
+            Some(listing.price),
+        );
+        true
+    }
+
+    /// views
+
+    /// the message sellers sign: this market's account id and the listing as JSON, joined by ':'
+    pub fn lazy_listing_message(&self, listing: LazyListing) -> String {
+        format!(
+            "{}:{}",
+            env::current_account_id(),
+            near_sdk::serde_json::to_string(&listing).unwrap()
+        )
+    }
+
+    pub fn get_listing_key(&self, account_id: AccountId) -> Option<PublicKey> {
+        self.listing_keys.get(&account_id)
+    }
+
+    pub fn is_listing_nonce_used(&self, account_id: AccountId, nonce: U64) -> bool {
+        self.used_listing_nonces.contains(&(account_id, nonce.0))
+    }
+}
+
+impl Contract {
+    fn assert_listing_signature(&self, listing: &LazyListing, signature: &Base64VecU8) {
+        let public_key = self
+            .listing_keys
+            .get(&listing.owner_id)
+            .expect("Seller has no listing key");
+        let signature: [u8; 64] = signature
+            .0
+            .as_slice()
+            .try_into()
+            .unwrap_or_else(|_| env::panic_str("Signature must be 64 bytes"));
+        // the first byte of a near-sdk PublicKey is its curve type
+        let public_key: [u8; 32] = public_key.as_bytes()[1..].try_into().unwrap();
+        require!(
+            env::ed25519_verify(
+                &signature,
+                self.lazy_listing_message(listing.clone()).as_bytes(),
+                &public_key
+            ),
+            "Invalid listing signature"
+        );
+    }
+}
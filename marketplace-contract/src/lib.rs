@@ -1,10 +1,10 @@
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::{LookupMap, UnorderedMap, UnorderedSet};
-use near_sdk::json_types::{ValidAccountId, U128, U64};
+use near_sdk::collections::{LookupMap, LookupSet, UnorderedMap, UnorderedSet, Vector};
+use near_sdk::json_types::{U128, U64};
 use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::{
-    assert_one_yocto, env, ext_contract, near_bindgen, AccountId, Balance, Gas, PanicOnDefault,
-    Promise, PromiseOrValue, CryptoHash, BorshStorageKey,
+    assert_one_yocto, env, ext_contract, near_bindgen, require, AccountId, Balance, Gas,
+    PanicOnDefault, Promise, PromiseOrValue, PromiseResult, CryptoHash, BorshStorageKey, PublicKey,
 };
 use std::cmp::min;
 use std::collections::HashMap;
@@ -12,37 +12,106 @@ use std::collections::HashMap;
 use crate::external::*;
 use crate::internal::*;
 use crate::sale::*;
+use crate::activity::*;
+use crate::balance::*;
+use crate::changelog::*;
+use crate::lazy_listing::*;
+use crate::mt_listing::*;
+use crate::wash_trade::*;
+use crate::rental::*;
+use crate::boost::*;
+use crate::bulk::*;
+use crate::bundle::*;
 use crate::collection::*;
-use near_sdk::env::STORAGE_PRICE_PER_BYTE;
+use crate::event::*;
+use crate::royalty::*;
+use crate::settlement::*;
+use crate::swap::*;
+use crate::token_type::*;
 
+mod activity;
+mod admin;
+mod balance;
+mod changelog;
+mod compliance;
+mod boost;
+mod bulk;
+mod bundle;
 mod external;
 mod ft_callbacks;
 mod internal;
+mod lazy_listing;
+mod mt_listing;
 mod nft_callbacks;
+mod rental;
+mod royalty;
 mod sale;
 mod sale_views;
+mod settlement;
+mod swap;
+mod token_type;
+mod wash_trade;
 mod collection;
-
-near_sdk::setup_alloc!();
+mod event;
 
 // TODO check seller supports storage_deposit at ft_token_id they want to post sale in
 
-const GAS_FOR_FT_TRANSFER: Gas = 5_000_000_000_000;
+const GAS_FOR_FT_TRANSFER: Gas = Gas(5_000_000_000_000);
 /// greedy max Tgas for resolve_purchase
-const GAS_FOR_ROYALTIES: Gas = 115_000_000_000_000;
-const GAS_FOR_NFT_TRANSFER: Gas = 15_000_000_000_000;
+const GAS_FOR_ROYALTIES: Gas = Gas(115_000_000_000_000);
+const GAS_FOR_NFT_TRANSFER: Gas = Gas(15_000_000_000_000);
+const GAS_FOR_NFT_TOKEN: Gas = Gas(10_000_000_000_000);
+const GAS_FOR_VERIFY_SALE: Gas = Gas(30_000_000_000_000);
+/// paying out up to 10 delivered tokens of a bundle, each with its royalties
+const GAS_FOR_RESOLVE_BUNDLE: Gas = Gas(100_000_000_000_000);
+/// recording a token the market took custody of, or returning it when its listing is gone
+const GAS_FOR_RESOLVE_DEPOSIT: Gas = Gas(25_000_000_000_000);
+const GAS_FOR_NFT_OWNER: Gas = Gas(5_000_000_000_000);
+const GAS_FOR_RESOLVE_TOKEN_TYPE: Gas = Gas(10_000_000_000_000);
 const BID_HISTORY_LENGTH_DEFAULT: u8 = 1;
-const NO_DEPOSIT: Balance = 0;
+/// a new bid has to beat the current one by at least this much, unless the sale sets its own
+const DEFAULT_MIN_BID_INCREMENT_BPS: u16 = 100;
+/// yocto per byte, `env::storage_byte_cost()` isn't usable in a const
+const STORAGE_PRICE_PER_BYTE: Balance = 10_000_000_000_000_000_000;
 const STORAGE_PER_SALE: u128 = 1000 * STORAGE_PRICE_PER_BYTE;
+/// deposit held for a token_type registration, refunded when it is removed
+const STORAGE_PER_TOKEN_TYPE: u128 = 200 * STORAGE_PRICE_PER_BYTE;
+/// deposit held for an account's balance entry, refunded when the balance is closed
+const STORAGE_PER_BALANCE: u128 = 100 * STORAGE_PRICE_PER_BYTE;
+/// paid out of the seller's storage deposit to whoever cleans up an expired sale
+const EXPIRED_SALE_REWARD: u128 = 1_000_000_000_000_000_000_000;
+/// a bid this close to the end of an auction pushes the end back by AUCTION_EXTENSION_MS
+const AUCTION_EXTENSION_WINDOW_MS: u64 = 10 * 60 * 1000;
+const AUCTION_EXTENSION_MS: u64 = 10 * 60 * 1000;
+/// time the seller has to accept the winning bid before an ended auction can be removed
+const AUCTION_SETTLEMENT_PERIOD_MS: u64 = 24 * 60 * 60 * 1000;
+/// market fee charged on top of the listed price when a collection has no config
+const DEFAULT_MARKET_FEE_BPS: u16 = 100;
+const MAX_MARKET_FEE_BPS: u16 = 1_000;
+const ONE_HUNDRED_PERCENT_IN_BPS: u16 = 10_000;
+/// surcharge every listing carried before it was configurable, 2 NEAR
+const DEFAULT_LISTING_SURCHARGE: Balance = 2_000_000_000_000_000_000_000_000;
+/// a token can't be sold back to its previous seller within a day
+const DEFAULT_WASH_TRADE_WINDOW_MS: u64 = 24 * 60 * 60 * 1000;
+/// accounts a seller's proceeds can be split between, each is paid in its own transfer
+const MAX_PAYOUT_SPLIT: usize = 10;
+/// fee for featuring a listing one hour, 0.1 NEAR
+const DEFAULT_BOOST_FEE_PER_HOUR: Balance = 100_000_000_000_000_000_000_000;
 static DELIMETER: &str = "||";
+/// ft_token_id of sales and bids paid in NEAR
+static NEAR_TOKEN_ID: &str = "near";
 
 pub type SaleConditions = HashMap<FungibleTokenId, U128>;
-pub type Bids = HashMap<FungibleTokenId, Vec<Bid>>;
+/// Bidders per ft_token_id, oldest first. Bid amounts are kept in `Contract::bids`
+pub type Bids = HashMap<FungibleTokenId, Vec<AccountId>>;
 pub type TokenId = String;
 pub type TokenType = Option<String>;
 pub type FungibleTokenId = AccountId;
 pub type ContractAndTokenId = String;
 pub type ContractAndTokenType = String;
+pub type BidKey = (ContractAndTokenId, FungibleTokenId, AccountId);
+/// bps of the seller's proceeds paid to each account
+pub type PayoutSplit = HashMap<AccountId, u16>;
 
 // TODO: Capital U128
 #[derive(Serialize, Deserialize, BorshDeserialize, BorshSerialize)]
@@ -62,10 +131,93 @@ pub struct StorageBalanceBounds {
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
 pub struct Contract {
     pub owner_id: AccountId,
-    pub sales: UnorderedMap<ContractAndTokenId, Sale>,
+    pub sales: LookupMap<ContractAndTokenId, Sale>,
+    pub sale_ids: UnorderedSet<ContractAndTokenId>,
+    pub bids: LookupMap<BidKey, U128>,
     pub by_owner_id: LookupMap<AccountId, UnorderedSet<ContractAndTokenId>>,
     pub by_nft_contract_id: LookupMap<AccountId, UnorderedSet<TokenId>>,
-    pub by_nft_token_type: LookupMap<AccountId, UnorderedSet<ContractAndTokenId>>,
+    pub by_nft_token_type: LookupMap<String, UnorderedSet<ContractAndTokenId>>,
+    pub supply_by_owner_id: LookupMap<AccountId, u64>,
+    pub supply_by_nft_contract_id: LookupMap<AccountId, u64>,
+    pub supply_by_nft_token_type: LookupMap<String, u64>,
+    pub ft_token_ids: UnorderedSet<AccountId>,
+    /// number of listings priced in each ft_token_id, bids are only placed in these
+    pub ft_token_listings: LookupMap<FungibleTokenId, u64>,
+    pub admin_ids: UnorderedSet<AccountId>,
+    pub storage_deposits: LookupMap<AccountId, Balance>,
+    pub collections: UnorderedMap<ContractAndTokenType, CollectionInfo>,
+    pub token_type_stats: LookupMap<String, CollectionStats>,
+    pub collection_configs: LookupMap<AccountId, CollectionConfig>,
+    pub activity: Vector<Activity>,
+    pub activity_head: u64,
+    /// bounded log of sale changes for indexers syncing incrementally, by sequence number
+    pub changes: LookupMap<u64, Change>,
+    pub change_seq: u64,
+    /// keys sellers sign lazy listings with, and the nonces of listings bought or cancelled
+    pub listing_keys: LookupMap<AccountId, PublicKey>,
+    pub used_listing_nonces: LookupSet<(AccountId, u64)>,
+    /// NEP-245 listings by mt contract, token and owner
+    pub mt_listings: UnorderedMap<String, MtListing>,
+    /// last settled sale of each token, and how long a flip back to its seller is rejected for
+    pub last_trades: LookupMap<ContractAndTokenId, LastTrade>,
+    pub wash_trade_window_ms: u64,
+    /// tokens escrowed to be rented out, by contract and token id
+    pub rentals: LookupMap<ContractAndTokenId, Rental>,
+    pub balances: LookupMap<AccountId, Balance>,
+    /// NEAR added to the seller's price of new listings, paid to the surcharge receiver or the treasury
+    pub listing_surcharge: Balance,
+    pub surcharge_receiver_id: Option<AccountId>,
+    pub pending_owner_id: Option<AccountId>,
+    pub treasury_id: AccountId,
+    pub paused: bool,
+    pub bundles: LookupMap<String, Bundle>,
+    /// bids kept per sale and ft_token_id, unless the sale sets its own cap
+    pub bid_history_length: u8,
+    /// least a bid has to beat the current bid by, unless the sale sets its own
+    pub min_bid_increment_bps: u16,
+    pub marketplace_charge: U128,
+    /// refunds and payouts waiting for `process_queue` by queue position, the positions
+    /// still queued, and the number queued per receiver
+    pub settlement_queue: LookupMap<u64, SettlementJob>,
+    pub settlement_queue_head: u64,
+    pub settlement_queue_tail: u64,
+    pub settlement_pending: LookupMap<AccountId, u64>,
+    /// NEAR the processors of the queue are tipped from
+    pub settlement_tips: Balance,
+    /// AMM sellers' payouts are swapped on, see swap.rs
+    pub swap_contract_id: Option<AccountId>,
+    /// FT each seller wants to be paid in, by seller and the FT a sale is bought in
+    pub payout_swaps: LookupMap<(AccountId, FungibleTokenId), SwapRoute>,
+    /// royalties paid out by collection and payee, with the payees of each collection
+    /// and the collections of each payee for the views
+    pub royalties_paid: LookupMap<(AccountId, AccountId), RoyaltiesPaid>,
+    pub royalty_receivers: LookupMap<AccountId, UnorderedSet<AccountId>>,
+    pub royalty_collections: LookupMap<AccountId, UnorderedSet<AccountId>>,
+    /// token_types registered by the owner of their NFT contract, with the registrant
+    pub token_type_creators: LookupMap<ContractAndTokenType, AccountId>,
+    /// fee per hour of featuring a listing, and the featured listings with their boost's end
+    pub boost_fee_per_hour: Balance,
+    pub featured_sales: UnorderedMap<ContractAndTokenId, U64>,
+    /// accounts that can't list, bid or buy, and NFT contracts whose tokens can't be traded
+    pub blocked_accounts: UnorderedSet<AccountId>,
+    pub frozen_contracts: UnorderedSet<AccountId>,
+    /// tip per queued transfer of sales in each FT, and the FT processors are tipped from
+    pub settlement_ft_tips: LookupMap<FungibleTokenId, U128>,
+    pub settlement_ft_tip_pools: LookupMap<FungibleTokenId, Balance>,
+    /// bundles, rentals and mt listings of each account, kept in its storage deposit like sales
+    pub listings_by_owner_id: LookupMap<AccountId, u64>,
+    /// sales listed before the storage redesign, moved to `sales` with `migrate_sales`
+    pub legacy_sales: UnorderedMap<ContractAndTokenId, SaleV1>,
+}
+
+/// State layout of markets deployed before the storage redesign, see `migrate`
+#[derive(BorshDeserialize)]
+pub struct ContractV1 {
+    pub owner_id: AccountId,
+    pub sales: UnorderedMap<ContractAndTokenId, SaleV1>,
+    pub by_owner_id: LookupMap<AccountId, UnorderedSet<ContractAndTokenId>>,
+    pub by_nft_contract_id: LookupMap<AccountId, UnorderedSet<TokenId>>,
+    pub by_nft_token_type: LookupMap<String, UnorderedSet<ContractAndTokenId>>,
     pub ft_token_ids: UnorderedSet<AccountId>,
     pub admin_ids: UnorderedSet<AccountId>,
     pub storage_deposits: LookupMap<AccountId, Balance>,
@@ -88,60 +240,178 @@ pub enum StorageKey {
     ByNFTTokenTypeInner { token_type_hash: CryptoHash },
     FTTokenIds,
     StorageDeposits,
+    TokenTypeStats,
+    SaleIds,
+    Bids,
+    CollectionConfigs,
+    Activity,
+    Bundles,
+    FTTokenListings,
+    Changes,
+    ListingKeys,
+    UsedListingNonces,
+    MtListings,
+    LastTrades,
+    Rentals,
+    Balances,
+    SupplyByOwnerId,
+    SupplyByNFTContractId,
+    SupplyByNFTTokenType,
+    SettlementQueue,
+    SettlementPending,
+    PayoutSwaps,
+    RoyaltiesPaid,
+    RoyaltyReceivers,
+    RoyaltyReceiversInner { account_id_hash: CryptoHash },
+    RoyaltyCollections,
+    RoyaltyCollectionsInner { account_id_hash: CryptoHash },
+    TokenTypeCreators,
+    FeaturedSales,
+    BlockedAccounts,
+    FrozenContracts,
+    SettlementFtTips,
+    SettlementFtTipPools,
+    ListingsByOwnerId,
 }
 
 #[near_bindgen]
 impl Contract {
     #[init]
-    pub fn new(owner_id: ValidAccountId, ft_token_ids:Option<Vec<ValidAccountId>>, bid_history_length:Option<u8>) -> Self {
-        let mut this = Self {
-            owner_id: owner_id.into(),
+    pub fn new(owner_id: AccountId, ft_token_ids:Option<Vec<AccountId>>, bid_history_length:Option<u8>) -> Self {
+        let mut this = Self::from_v1(ContractV1 {
+            owner_id,
             sales: UnorderedMap::new(StorageKey::Sales),
-            collections: UnorderedMap::new(StorageKey::CollectionInfo),
-            admin_ids: UnorderedSet::new(StorageKey::AdminId),
             by_owner_id: LookupMap::new(StorageKey::ByOwnerId),
             by_nft_contract_id: LookupMap::new(StorageKey::ByNFTContractId),
             by_nft_token_type: LookupMap::new(StorageKey::ByNFTTokenType),
             ft_token_ids: UnorderedSet::new(StorageKey::FTTokenIds),
+            admin_ids: UnorderedSet::new(StorageKey::AdminId),
             storage_deposits: LookupMap::new(StorageKey::StorageDeposits),
+            collections: UnorderedMap::new(StorageKey::CollectionInfo),
             bid_history_length: bid_history_length.unwrap_or(BID_HISTORY_LENGTH_DEFAULT),
             marketplace_charge: U128(2),
-        };
+        });
         // support NEAR by default
-        this.ft_token_ids.insert(&"near".to_string());
+        this.ft_token_ids.insert(&near_token_id());
 
         if let Some(ft_token_ids) = ft_token_ids {
             for ft_token_id in ft_token_ids {
-                this.ft_token_ids.insert(ft_token_id.as_ref());
+                this.ft_token_ids.insert(&ft_token_id);
             }
         }
 
-        this.admin_ids.insert(&"testingdo3.testnet".to_string());
-
         this
     }
 
+    /// Called after deploying new code, moves the state of a market deployed before the
+    /// storage redesign to the current layout. Its sales are moved with `migrate_sales`.
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let old: ContractV1 =
+            env::state_read().unwrap_or_else(|| env::panic_str("Contract state not found"));
+        Self::from_v1(old)
+    }
+
+    /// market holding the state of `old`, with the state added since at its default
+    fn from_v1(old: ContractV1) -> Self {
+        Self {
+            owner_id: old.owner_id.clone(),
+            sales: LookupMap::new(StorageKey::Sales),
+            sale_ids: UnorderedSet::new(StorageKey::SaleIds),
+            bids: LookupMap::new(StorageKey::Bids),
+            collections: old.collections,
+            token_type_stats: LookupMap::new(StorageKey::TokenTypeStats),
+            collection_configs: LookupMap::new(StorageKey::CollectionConfigs),
+            activity: Vector::new(StorageKey::Activity),
+            activity_head: 0,
+            changes: LookupMap::new(StorageKey::Changes),
+            change_seq: 0,
+            listing_keys: LookupMap::new(StorageKey::ListingKeys),
+            used_listing_nonces: LookupSet::new(StorageKey::UsedListingNonces),
+            mt_listings: UnorderedMap::new(StorageKey::MtListings),
+            last_trades: LookupMap::new(StorageKey::LastTrades),
+            wash_trade_window_ms: DEFAULT_WASH_TRADE_WINDOW_MS,
+            rentals: LookupMap::new(StorageKey::Rentals),
+            balances: LookupMap::new(StorageKey::Balances),
+            listing_surcharge: DEFAULT_LISTING_SURCHARGE,
+            surcharge_receiver_id: None,
+            pending_owner_id: None,
+            treasury_id: old.owner_id,
+            paused: false,
+            bundles: LookupMap::new(StorageKey::Bundles),
+            admin_ids: old.admin_ids,
+            by_owner_id: old.by_owner_id,
+            by_nft_contract_id: old.by_nft_contract_id,
+            by_nft_token_type: old.by_nft_token_type,
+            supply_by_owner_id: LookupMap::new(StorageKey::SupplyByOwnerId),
+            supply_by_nft_contract_id: LookupMap::new(StorageKey::SupplyByNFTContractId),
+            supply_by_nft_token_type: LookupMap::new(StorageKey::SupplyByNFTTokenType),
+            ft_token_ids: old.ft_token_ids,
+            ft_token_listings: LookupMap::new(StorageKey::FTTokenListings),
+            storage_deposits: old.storage_deposits,
+            bid_history_length: old.bid_history_length,
+            min_bid_increment_bps: DEFAULT_MIN_BID_INCREMENT_BPS,
+            marketplace_charge: old.marketplace_charge,
+            settlement_queue: LookupMap::new(StorageKey::SettlementQueue),
+            settlement_queue_head: 0,
+            settlement_queue_tail: 0,
+            settlement_pending: LookupMap::new(StorageKey::SettlementPending),
+            settlement_tips: 0,
+            swap_contract_id: None,
+            payout_swaps: LookupMap::new(StorageKey::PayoutSwaps),
+            royalties_paid: LookupMap::new(StorageKey::RoyaltiesPaid),
+            royalty_receivers: LookupMap::new(StorageKey::RoyaltyReceivers),
+            royalty_collections: LookupMap::new(StorageKey::RoyaltyCollections),
+            token_type_creators: LookupMap::new(StorageKey::TokenTypeCreators),
+            boost_fee_per_hour: DEFAULT_BOOST_FEE_PER_HOUR,
+            featured_sales: UnorderedMap::new(StorageKey::FeaturedSales),
+            blocked_accounts: UnorderedSet::new(StorageKey::BlockedAccounts),
+            frozen_contracts: UnorderedSet::new(StorageKey::FrozenContracts),
+            settlement_ft_tips: LookupMap::new(StorageKey::SettlementFtTips),
+            settlement_ft_tip_pools: LookupMap::new(StorageKey::SettlementFtTipPools),
+            listings_by_owner_id: LookupMap::new(StorageKey::ListingsByOwnerId),
+            legacy_sales: old.sales,
+        }
+    }
+
     /// only owner 
-    pub fn add_ft_token_ids(&mut self, ft_token_ids: Vec<ValidAccountId>) -> Vec<bool> {
+    pub fn add_ft_token_ids(&mut self, ft_token_ids: Vec<AccountId>) -> Vec<bool> {
         self.assert_owner();
         let mut added = vec![];
         for ft_token_id in ft_token_ids {
-            added.push(self.ft_token_ids.insert(ft_token_id.as_ref()));
+            added.push(self.ft_token_ids.insert(&ft_token_id));
         }
         added
     }
 
+    /// only owner
+    pub fn add_ft_token_id(&mut self, ft_token_id: AccountId) -> bool {
+        self.assert_owner();
+        self.ft_token_ids.insert(&ft_token_id)
+    }
+
+    /// only owner, can't remove a token active listings or bids are in
+    pub fn remove_ft_token_id(&mut self, ft_token_id: AccountId) -> bool {
+        self.assert_owner();
+        require!(
+            self.ft_token_listings.get(&ft_token_id).unwrap_or(0) == 0,
+            "Token is used by active listings or bids"
+        );
+        self.ft_token_ids.remove(&ft_token_id)
+    }
+
     /// only owner 
-    pub fn add_admin_id(&mut self, admin_id: ValidAccountId) -> bool {
+    pub fn add_admin_id(&mut self, admin_id: AccountId) -> bool {
         self.assert_owner();
-        let added = self.admin_ids.insert(admin_id.as_ref());
+        let added = self.admin_ids.insert(&admin_id);
         added
     }
 
     /// only owner 
-    pub fn remove_admin_id(&mut self, admin_id: ValidAccountId) {
+    pub fn remove_admin_id(&mut self, admin_id: AccountId) {
         self.assert_owner();
-        self.admin_ids.remove(admin_id.as_ref());
+        self.admin_ids.remove(&admin_id);
     }
     
 
@@ -154,15 +424,12 @@ impl Contract {
     /// TODO remove token (should check if sales can complete even if owner stops supporting token type)
 
     #[payable]
-    pub fn storage_deposit(&mut self, account_id: Option<ValidAccountId>) {
-        let storage_account_id = account_id
-            .map(|a| a.into())
-            .unwrap_or_else(env::predecessor_account_id);
+    pub fn storage_deposit(&mut self, account_id: Option<AccountId>) {
+        let storage_account_id = account_id.unwrap_or_else(env::predecessor_account_id);
         let deposit = env::attached_deposit();
-        assert!(
+        require!(
             deposit >= STORAGE_PER_SALE,
-            "Requires minimum deposit of {}",
-            STORAGE_PER_SALE
+            format!("Requires minimum deposit of {}", STORAGE_PER_SALE)
         );
         let mut balance: u128 = self.storage_deposits.get(&storage_account_id).unwrap_or(0);
         balance += deposit;
@@ -175,7 +442,8 @@ impl Contract {
         let owner_id = env::predecessor_account_id();
         let mut amount = self.storage_deposits.remove(&owner_id).unwrap_or(0);
         let sales = self.by_owner_id.get(&owner_id);
-        let len = sales.map(|s| s.len()).unwrap_or_default();
+        let len = sales.map(|s| s.len()).unwrap_or_default()
+            + self.listings_by_owner_id.get(&owner_id).unwrap_or(0);
         let diff = u128::from(len) * STORAGE_PER_SALE;
         amount -= diff;
         if amount > 0 {
@@ -203,14 +471,14 @@ impl Contract {
         U128(STORAGE_PER_SALE)
     }
 
-    pub fn storage_balance_of(&self, account_id: ValidAccountId) -> U128 {
-        U128(self.storage_deposits.get(account_id.as_ref()).unwrap_or(0))
+    pub fn storage_balance_of(&self, account_id: AccountId) -> U128 {
+        U128(self.storage_deposits.get(&account_id).unwrap_or(0))
     }
 
     /// deprecated
 
-    pub fn storage_paid(&self, account_id: ValidAccountId) -> U128 {
-        U128(self.storage_deposits.get(account_id.as_ref()).unwrap_or(0))
+    pub fn storage_paid(&self, account_id: AccountId) -> U128 {
+        U128(self.storage_deposits.get(&account_id).unwrap_or(0))
     }
 
     pub fn storage_amount(&self) -> U128 {
@@ -0,0 +1,231 @@
+use crate::*;
+use near_sdk::promise_result_as_success;
+
+/// callbacks from NEP-245 multi token contracts, and quantity-aware listings of their tokens.
+/// Tokens stay with the seller, the market transfers them with its approval when bought.
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MtListing {
+    pub owner_id: AccountId,
+    pub mt_contract_id: AccountId,
+    pub token_id: TokenId,
+    pub approval_id: u64,
+    /// quantity still for sale
+    pub amount: U128,
+    /// NEAR price of one unit, the market fee is paid on top of it
+    pub price: U128,
+    pub created_at: U64,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MtSaleArgs {
+    pub price: U128,
+}
+
+pub(crate) fn mt_listing_key(mt_contract_id: &AccountId, token_id: &str, owner_id: &AccountId) -> String {
+    format!("{}{}{}{}{}", mt_contract_id, DELIMETER, token_id, DELIMETER, owner_id)
+}
+
+trait MultiTokenApprovalReceiver {
+    fn mt_on_approve(
+        &mut self,
+        token_ids: Vec<TokenId>,
+        amounts: Vec<U128>,
+        owner_id: AccountId,
+        approval_ids: Vec<u64>,
+        msg: String,
+    );
+}
+
+#[near_bindgen]
+impl MultiTokenApprovalReceiver for Contract {
+    /// lists each approved token at the msg's unit price, approving again updates the listing
+    fn mt_on_approve(
+        &mut self,
+        token_ids: Vec<TokenId>,
+        amounts: Vec<U128>,
+        owner_id: AccountId,
+        approval_ids: Vec<u64>,
+        msg: String,
+    ) {
+        let mt_contract_id = env::predecessor_account_id();
+        let signer_id = env::signer_account_id();
+        self.assert_not_paused();
+        self.assert_collection_enabled(&mt_contract_id);
+        self.assert_not_frozen(&mt_contract_id);
+        self.assert_not_blocked(&owner_id);
+        require!(
+            mt_contract_id != signer_id,
+            "mt_on_approve should only be called via cross-contract call"
+        );
+        require!(owner_id == signer_id, "owner_id should be signer_id");
+        require!(
+            token_ids.len() == amounts.len() && token_ids.len() == approval_ids.len(),
+            "token_ids, amounts and approval_ids should have the same length"
+        );
+        let MtSaleArgs { price } = near_sdk::serde_json::from_str(&msg).expect("Not valid MtSaleArgs");
+        require!(price.0 > 0, "Price must be greater than 0");
+
+        for ((token_id, amount), approval_id) in token_ids.into_iter().zip(amounts).zip(approval_ids) {
+            require!(amount.0 > 0, "Amount must be greater than 0");
+            let key = mt_listing_key(&mt_contract_id, &token_id, &owner_id);
+            let previous = self.mt_listings.insert(
+                &key,
+                &MtListing {
+                    owner_id: owner_id.clone(),
+                    mt_contract_id: mt_contract_id.clone(),
+                    token_id,
+                    approval_id,
+                    amount,
+                    price,
+                    created_at: U64(env::block_timestamp() / 1000000),
+                },
+            );
+            if previous.is_none() {
+                update_supply(&mut self.listings_by_owner_id, &owner_id, true);
+            }
+            self.internal_record_change(&key, ChangeType::Listed);
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// buy `amount` units, attach the unit price times `amount` plus the market fee
+    #[payable]
+    pub fn mt_buy(
+        &mut self,
+        mt_contract_id: AccountId,
+        token_id: TokenId,
+        owner_id: AccountId,
+        amount: U128,
+    ) -> Promise {
+        self.assert_not_paused();
+        let key = mt_listing_key(&mt_contract_id, &token_id, &owner_id);
+        let mut listing = self.mt_listings.get(&key).expect("No listing");
+        let buyer_id = env::predecessor_account_id();
+        require!(owner_id != buyer_id, "Cannot buy your own listing.");
+        self.assert_compliant_trade(&mt_contract_id, &owner_id, &buyer_id);
+        require!(
+            amount.0 > 0 && amount.0 <= listing.amount.0,
+            format!("Amount must be between 1 and {}", listing.amount.0)
+        );
+        let total = listing.price.0.checked_mul(amount.0).expect("Price overflow");
+        let fee = self.internal_market_fee(&mt_contract_id, total);
+        let deposit = env::attached_deposit();
+        require!(deposit == total + fee, "Attached deposit must be price times amount plus market fee");
+
+        // taken off the listing while the transfer is in flight so it can't be sold twice
+        listing.amount = U128(listing.amount.0 - amount.0);
+        if listing.amount.0 == 0 {
+            self.mt_listings.remove(&key);
+            update_supply(&mut self.listings_by_owner_id, &owner_id, false);
+            self.internal_record_change(&key, ChangeType::Removed);
+        } else {
+            self.mt_listings.insert(&key, &listing);
+            self.internal_record_change(&key, ChangeType::AmountUpdated);
+        }
+
+        ext_contract::ext(mt_contract_id)
+            .with_attached_deposit(1)
+            .with_static_gas(GAS_FOR_NFT_TRANSFER)
+            .mt_transfer(
+                buyer_id.clone(),
+                token_id,
+                amount,
+                Some((owner_id, listing.approval_id)),
+                Some("purchase from market".to_string()),
+            )
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_BUNDLE)
+                    .resolve_mt_purchase(listing, buyer_id, amount, U128(deposit)),
+            )
+    }
+
+    /// self callback, returns whether the tokens were transferred.
+    /// Nothing transferred: the buyer is refunded and the amount goes back on the listing.
+    #[private]
+    pub fn resolve_mt_purchase(
+        &mut self,
+        listing: MtListing,
+        buyer_id: AccountId,
+        amount: U128,
+        paid: U128,
+    ) -> bool {
+        let key = mt_listing_key(&listing.mt_contract_id, &listing.token_id, &listing.owner_id);
+        if promise_result_as_success().is_none() {
+            self.internal_refund_near(buyer_id, paid.0);
+            let mut relisted = self.mt_listings.get(&key).unwrap_or(MtListing {
+                amount: U128(0),
+                ..listing
+            });
+            relisted.amount = U128(relisted.amount.0 + amount.0);
+            if self.mt_listings.insert(&key, &relisted).is_none() {
+                update_supply(&mut self.listings_by_owner_id, &relisted.owner_id, true);
+            }
+            self.internal_record_change(&key, ChangeType::AmountUpdated);
+            return false;
+        }
+
+        let total = listing.price.0 * amount.0;
+        Promise::new(listing.owner_id.clone()).transfer(total);
+        let fee = paid.0 - total;
+        if fee > 0 {
+            Promise::new(self.treasury_id.clone()).transfer(fee);
+        }
+        MarketEvent::log_sale_purchase(
+            &listing.mt_contract_id,
+            &listing.token_id,
+            &listing.owner_id,
+            &buyer_id,
+            &near_token_id(),
+            U128(total),
+        );
+        self.internal_record_activity(
+            ActivityKind::Sale,
+            &listing.mt_contract_id,
+            &listing.token_id,
+            &buyer_id,
+            Some(near_token_id()),
+            Some(U128(total)),
+        );
+        true
+    }
+
+    #[payable]
+    pub fn mt_remove_listing(&mut self, mt_contract_id: AccountId, token_id: TokenId) {
+        assert_one_yocto();
+        let key = mt_listing_key(&mt_contract_id, &token_id, &env::predecessor_account_id());
+        self.mt_listings.remove(&key).expect("No listing");
+        update_supply(&mut self.listings_by_owner_id, &env::predecessor_account_id(), false);
+        self.internal_record_change(&key, ChangeType::Removed);
+    }
+
+    #[payable]
+    pub fn mt_update_price(&mut self, mt_contract_id: AccountId, token_id: TokenId, price: U128) {
+        assert_one_yocto();
+        require!(price.0 > 0, "Price must be greater than 0");
+        let key = mt_listing_key(&mt_contract_id, &token_id, &env::predecessor_account_id());
+        let mut listing = self.mt_listings.get(&key).expect("No listing");
+        listing.price = price;
+        self.mt_listings.insert(&key, &listing);
+        self.internal_record_change(&key, ChangeType::PriceUpdated);
+    }
+
+    /// views
+
+    pub fn get_mt_listing(&self, mt_contract_id: AccountId, token_id: TokenId, owner_id: AccountId) -> Option<MtListing> {
+        self.mt_listings.get(&mt_listing_key(&mt_contract_id, &token_id, &owner_id))
+    }
+
+    pub fn get_mt_listings(&self, from_index: U64, limit: u64) -> Vec<MtListing> {
+        self.mt_listings
+            .values()
+            .skip(from_index.0 as usize)
+            .take(limit as usize)
+            .collect()
+    }
+}
@@ -2,8 +2,6 @@ use crate::*;
 
 /// approval callbacks from NFT Contracts
 
-const INITIAL_BALANCE: Balance = 2_000_000_000_000_000_000_000_000;
-
 #[derive(Serialize, Deserialize)]
 #[serde(crate = "near_sdk::serde")]
 pub struct SaleArgs {
@@ -11,16 +9,29 @@ pub struct SaleArgs {
     pub token_type: TokenType,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub is_auction: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<U64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allowed_buyer: Option<AccountId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_bid_increment_bps: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_bids: Option<u8>,
+    /// bps of the seller's proceeds for each account, adding up to 10,000
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payout_split: Option<PayoutSplit>,
 }
 
 trait NonFungibleTokenApprovalsReceiver {
     fn nft_on_approve(
         &mut self,
         token_id: TokenId,
-        owner_id: ValidAccountId,
+        owner_id: AccountId,
         approval_id: u64,
         msg: String,
     );
+
+    fn nft_on_revoke(&mut self, token_id: TokenId, owner_id: AccountId);
 }
 
 #[near_bindgen]
@@ -30,7 +41,7 @@ impl NonFungibleTokenApprovalsReceiver for Contract {
     fn nft_on_approve(
         &mut self,
         token_id: TokenId,
-        owner_id: ValidAccountId,
+        owner_id: AccountId,
         approval_id: u64,
         msg: String,
     ) {
@@ -38,110 +49,109 @@ impl NonFungibleTokenApprovalsReceiver for Contract {
 
         let nft_contract_id = env::predecessor_account_id();
         let signer_id = env::signer_account_id();
-        assert!(vec!["dev-1695922751973-50100434093733"].contains(&nft_contract_id.as_str()), "nft_contract_id is not whitelisted");
-        assert_ne!(
-            nft_contract_id,
-            signer_id,
+        require!(vec!["dev-1695922751973-50100434093733"].contains(&nft_contract_id.as_str()), "nft_contract_id is not whitelisted");
+        self.assert_not_paused();
+        self.assert_collection_enabled(&nft_contract_id);
+        self.assert_not_frozen(&nft_contract_id);
+        self.assert_not_blocked(&owner_id);
+        require!(
+            nft_contract_id != signer_id,
             "nft_on_approve should only be called via cross-contract call"
         );
-        assert_eq!(
-            owner_id.as_ref(),
-            &signer_id,
+        require!(
+            owner_id == signer_id,
             "owner_id should be signer_id"
         );
 
-        let SaleArgs { mut sale_conditions, token_type, is_auction } =
-            near_sdk::serde_json::from_str(&msg).expect("Not valid SaleArgs");
-
-        for (ft_token_id, mut price) in sale_conditions.clone() {
-            if !self.ft_token_ids.contains(&ft_token_id) {
-                env::panic(
-                    format!("Token {} not supported by this market", ft_token_id).as_bytes(),
-                );
-            }
+        if let Ok(BundleMsg { bundle }) = near_sdk::serde_json::from_str::<BundleMsg>(&msg) {
+            self.internal_bundle_approve(nft_contract_id, token_id, owner_id, approval_id, bundle);
+            return;
+        }
 
-            price = U128(price.0 + u128::from(INITIAL_BALANCE));
-            sale_conditions.remove(&ft_token_id);
-            sale_conditions.insert(ft_token_id, price);
+        if let Ok(RentalMsg { rental }) = near_sdk::serde_json::from_str::<RentalMsg>(&msg) {
+            self.internal_rental_approve(nft_contract_id, token_id, owner_id, approval_id, rental);
+            return;
         }
 
-        let bids = HashMap::new();
+        let SaleArgs {
+            mut sale_conditions,
+            token_type,
+            is_auction,
+            expires_at,
+            allowed_buyer,
+            min_bid_increment_bps,
+            max_bids,
+            payout_split,
+        } = near_sdk::serde_json::from_str(&msg).expect("Not valid SaleArgs");
+        assert_bid_params(min_bid_increment_bps, max_bids);
+        assert_payout_split(&payout_split);
+
+        if let Some(expires_at) = expires_at {
+            require!(
+                expires_at.0 > env::block_timestamp() / 1000000,
+                "expires_at must be in the future"
+            );
+        }
 
-        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
-        self.sales.insert(
-            &contract_and_token_id,
-            &Sale {
-                owner_id: owner_id.clone().into(),
-                approval_id,
-                nft_contract_id: nft_contract_id.clone(),
-                token_id: token_id.clone(),
-                sale_conditions,
-                bids,
-                created_at: U64(env::block_timestamp()/1000000),
-                token_type: token_type.clone(),
-                is_auction: is_auction.unwrap_or(false),
-            },
-        );
+        for ft_token_id in sale_conditions.keys() {
+            if !self.ft_token_ids.contains(ft_token_id) {
+                env::panic_str(&format!("Token {} not supported by this market", ft_token_id));
+            }
+        }
+        // the buyer pays the surcharge on top of the seller's NEAR price
+        let surcharge = U128(self.listing_surcharge);
+        if let Some(price) = sale_conditions.get_mut(NEAR_TOKEN_ID) {
+            *price = U128(price.0 + surcharge.0);
+        }
 
-        // extra for views
-
-        let mut by_owner_id = self.by_owner_id.get(owner_id.as_ref()).unwrap_or_else(|| {
-            UnorderedSet::new(
-                StorageKey::ByOwnerIdInner {
-                    account_id_hash: hash_account_id(owner_id.as_ref()),
-                }
-                .try_to_vec()
-                .unwrap(),
-            )
-        });
-
-        by_owner_id.insert(&contract_and_token_id);
-        self.by_owner_id.insert(owner_id.as_ref(), &by_owner_id);
-
-        let mut by_nft_contract_id = self
-            .by_nft_contract_id
-            .get(&nft_contract_id)
-            .unwrap_or_else(|| {
-                UnorderedSet::new(
-                    StorageKey::ByNFTContractIdInner {
-                        account_id_hash: hash_account_id(&nft_contract_id),
-                    }
-                    .try_to_vec()
-                    .unwrap(),
-                )
-            });
-        by_nft_contract_id.insert(&token_id);
-        self.by_nft_contract_id
-            .insert(&nft_contract_id, &by_nft_contract_id);
-
-        if let Some(token_type) = token_type {
-            assert!(token_id.contains(&token_type), "TokenType should be substr of TokenId");
-            let mut by_nft_token_type = self
-                .by_nft_token_type
-                .get(&token_type)
-                .unwrap_or_else(|| {
-                    UnorderedSet::new(
-                        StorageKey::ByNFTTokenTypeInner {
-                            token_type_hash: hash_account_id(&token_type),
-                        }
-                        .try_to_vec()
-                        .unwrap(),
-                    )
-                });
-                by_nft_token_type.insert(&contract_and_token_id);
-            self.by_nft_token_type
-                .insert(&token_type, &by_nft_token_type);
+        if let Some(token_type) = token_type.as_ref() {
+            require!(token_id.contains(token_type), "TokenType should be substr of TokenId");
         }
+        require!(
+            self.rentals
+                .get(&format!("{}{}{}", nft_contract_id, DELIMETER, token_id))
+                .is_none(),
+            "Token is listed for rent"
+        );
 
-        let current_user = near_sdk::env::current_account_id();
-        ext_contract::nft_transfer(
-            current_user.clone(),
-            token_id,
+        let sale = Sale {
+            owner_id,
             approval_id,
-            "deposit to market".to_string(),
-            &nft_contract_id,
-            1,
-            GAS_FOR_NFT_TRANSFER,
-        );
+            nft_contract_id: nft_contract_id.clone(),
+            token_id: token_id.clone(),
+            sale_conditions,
+            bids: HashMap::new(),
+            created_at: U64(env::block_timestamp()/1000000),
+            token_type,
+            is_auction: is_auction.unwrap_or(false),
+            expires_at,
+            allowed_buyer,
+            surcharge,
+            min_bid_increment_bps,
+            max_bids,
+            payout_split,
+        };
+
+        // listed once the transfer went through, see `resolve_sale_deposit`
+        let current_user = near_sdk::env::current_account_id();
+        ext_contract::ext(nft_contract_id)
+            .with_attached_deposit(1)
+            .with_static_gas(GAS_FOR_NFT_TRANSFER)
+            .nft_transfer(current_user, token_id, approval_id, "deposit to market".to_string())
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_DEPOSIT)
+                    .resolve_sale_deposit(sale),
+            );
+    }
+
+    /// the owner revoked the market's approval, the listing can't settle anymore
+    fn nft_on_revoke(&mut self, token_id: TokenId, owner_id: AccountId) {
+        let nft_contract_id = env::predecessor_account_id();
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        if let Some(sale) = self.sales.get(&contract_and_token_id) {
+            require!(sale.owner_id == owner_id, "owner_id should be sale owner");
+            self.internal_delist_stale_sale(nft_contract_id, token_id);
+        }
     }
 }
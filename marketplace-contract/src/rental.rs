@@ -0,0 +1,219 @@
+use crate::*;
+
+const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+/// nft_on_approve msg listing a token for rent
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RentalMsg {
+    pub rental: RentalArgs,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RentalArgs {
+    /// NEAR price of one day, the market fee is paid on top of it
+    pub price_per_day: U128,
+    /// longest a single rental can last
+    pub max_days: u32,
+}
+
+/// A token held by the market to be rented out by the day.
+/// The rent is paid upfront and handed to the owner as the rental runs.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Rental {
+    pub owner_id: AccountId,
+    pub nft_contract_id: AccountId,
+    pub token_id: TokenId,
+    pub price_per_day: U128,
+    pub max_days: u32,
+    /// current or last renter
+    pub renter_id: Option<AccountId>,
+    /// ms since epoch
+    pub started_at: U64,
+    pub ends_at: U64,
+    /// rent paid for the current or last rental, and the part already sent to the owner
+    pub paid: U128,
+    pub claimed: U128,
+}
+
+impl Rental {
+    fn is_active(&self) -> bool {
+        self.renter_id.is_some() && env::block_timestamp() / 1000000 < self.ends_at.0
+    }
+
+    /// rent earned so far and not yet sent to the owner
+    fn claimable(&self) -> u128 {
+        let now = env::block_timestamp() / 1000000;
+        let duration = self.ends_at.0 - self.started_at.0;
+        if duration == 0 {
+            return 0;
+        }
+        let elapsed = min(now.saturating_sub(self.started_at.0), duration);
+        self.paid.0 * elapsed as u128 / duration as u128 - self.claimed.0
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// for listing a token for rent see: nft_callbacks.rs
+
+    /// rent a token for `days`, attach the price per day times `days` plus the market fee
+    #[payable]
+    pub fn rent(&mut self, nft_contract_id: AccountId, token_id: TokenId, days: u32) {
+        self.assert_not_paused();
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let mut rental = self.rentals.get(&contract_and_token_id).expect("No rental");
+        let renter_id = env::predecessor_account_id();
+        require!(rental.owner_id != renter_id, "Cannot rent your own token.");
+        self.assert_compliant_trade(&nft_contract_id, &rental.owner_id, &renter_id);
+        require!(!rental.is_active(), "Token is already rented");
+        require!(
+            days > 0 && days <= rental.max_days,
+            format!("Days must be between 1 and {}", rental.max_days)
+        );
+        let price = rental.price_per_day.0 * days as u128;
+        let fee = self.internal_market_fee(&nft_contract_id, price);
+        require!(
+            env::attached_deposit() == price + fee,
+            "Attached deposit must be rent plus market fee"
+        );
+
+        // whatever the last rental earned goes out before it's overwritten
+        self.internal_pay_rent(&mut rental);
+        if fee > 0 {
+            Promise::new(self.treasury_id.clone()).transfer(fee);
+        }
+        let now = env::block_timestamp() / 1000000;
+        rental.renter_id = Some(renter_id.clone());
+        rental.started_at = U64(now);
+        rental.ends_at = U64(now + days as u64 * DAY_MS);
+        rental.paid = U128(price);
+        rental.claimed = U128(0);
+        self.rentals.insert(&contract_and_token_id, &rental);
+        self.internal_record_activity(
+            ActivityKind::Rent,
+            &nft_contract_id,
+            &token_id,
+            &renter_id,
+            Some(near_token_id()),
+            Some(U128(price)),
+        );
+    }
+
+    /// send the rent earned so far to the owner, callable by anyone
+    pub fn claim_rent(&mut self, nft_contract_id: AccountId, token_id: TokenId) -> U128 {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let mut rental = self.rentals.get(&contract_and_token_id).expect("No rental");
+        let amount = self.internal_pay_rent(&mut rental);
+        self.rentals.insert(&contract_and_token_id, &rental);
+        U128(amount)
+    }
+
+    /// stop renting the token out and get it back, not while it's rented
+    #[payable]
+    pub fn remove_rental(&mut self, nft_contract_id: AccountId, token_id: TokenId) {
+        assert_one_yocto();
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let mut rental = self.rentals.get(&contract_and_token_id).expect("No rental");
+        require!(
+            rental.owner_id == env::predecessor_account_id(),
+            "Only the owner can remove the rental"
+        );
+        require!(!rental.is_active(), "Token is rented");
+        self.internal_pay_rent(&mut rental);
+        self.rentals.remove(&contract_and_token_id);
+        update_supply(&mut self.listings_by_owner_id, &rental.owner_id, false);
+        ext_contract::ext(nft_contract_id)
+            .with_attached_deposit(1)
+            .with_static_gas(GAS_FOR_NFT_TRANSFER)
+            .nft_transfer(rental.owner_id, token_id, 0, "return to user".to_string());
+    }
+
+    /// views
+
+    /// account renting the token right now
+    pub fn current_renter(&self, nft_contract_id: AccountId, token_id: TokenId) -> Option<AccountId> {
+        self.rentals
+            .get(&format!("{}{}{}", nft_contract_id, DELIMETER, token_id))
+            .filter(|rental| rental.is_active())
+            .and_then(|rental| rental.renter_id)
+    }
+
+    pub fn get_rental(&self, nft_contract_id: AccountId, token_id: TokenId) -> Option<Rental> {
+        self.rentals
+            .get(&format!("{}{}{}", nft_contract_id, DELIMETER, token_id))
+    }
+
+    /// self callback, a token is listed for rent once the market holds it
+    #[private]
+    pub fn resolve_rental_deposit(&mut self, rental: Rental) -> bool {
+        if !matches!(env::promise_result(0), PromiseResult::Successful(_)) {
+            env::log_str(&format!("Token {} wasn't deposited to the market", rental.token_id));
+            return false;
+        }
+        let contract_and_token_id =
+            format!("{}{}{}", rental.nft_contract_id, DELIMETER, rental.token_id);
+        if self.rentals.insert(&contract_and_token_id, &rental).is_none() {
+            update_supply(&mut self.listings_by_owner_id, &rental.owner_id, true);
+        }
+        true
+    }
+}
+
+impl Contract {
+    /// a token listed for rent, escrow it until the owner removes the rental
+    pub(crate) fn internal_rental_approve(
+        &mut self,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        owner_id: AccountId,
+        approval_id: u64,
+        args: RentalArgs,
+    ) {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        require!(
+            self.rentals.get(&contract_and_token_id).is_none(),
+            "Token is already listed for rent"
+        );
+        require!(
+            self.sales.get(&contract_and_token_id).is_none(),
+            "Token is listed for sale"
+        );
+        require!(args.price_per_day.0 > 0, "Price per day must be greater than 0");
+        require!(args.max_days > 0, "Max days must be greater than 0");
+        let rental = Rental {
+            owner_id,
+            nft_contract_id: nft_contract_id.clone(),
+            token_id: token_id.clone(),
+            price_per_day: args.price_per_day,
+            max_days: args.max_days,
+            renter_id: None,
+            started_at: U64(0),
+            ends_at: U64(0),
+            paid: U128(0),
+            claimed: U128(0),
+        };
+
+        // stored once the transfer went through, see `resolve_rental_deposit`
+        ext_contract::ext(nft_contract_id)
+            .with_attached_deposit(1)
+            .with_static_gas(GAS_FOR_NFT_TRANSFER)
+            .nft_transfer(env::current_account_id(), token_id, approval_id, "deposit to market".to_string())
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_DEPOSIT)
+                    .resolve_rental_deposit(rental),
+            );
+    }
+
+    fn internal_pay_rent(&self, rental: &mut Rental) -> u128 {
+        let amount = rental.claimable();
+        if amount > 0 {
+            rental.claimed = U128(rental.claimed.0 + amount);
+            Promise::new(rental.owner_id.clone()).transfer(amount);
+        }
+        amount
+    }
+}
@@ -0,0 +1,109 @@
+use crate::*;
+
+/// Royalties sent to a payee for sales of one collection, per ft_token_id they were paid in.
+/// Royalties are counted when the sale settles, queued payouts included.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RoyaltiesPaid {
+    pub nft_contract_id: AccountId,
+    pub receiver_id: AccountId,
+    pub amounts: HashMap<FungibleTokenId, U128>,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// views
+
+    /// royalties paid out for `nft_contract_id`, one entry per payee
+    pub fn royalties_paid(
+        &self,
+        nft_contract_id: AccountId,
+        from_index: Option<U64>,
+        limit: Option<u64>,
+    ) -> Vec<RoyaltiesPaid> {
+        let receiver_ids = match self.royalty_receivers.get(&nft_contract_id) {
+            Some(receiver_ids) => receiver_ids,
+            None => return vec![],
+        };
+        let keys = receiver_ids.as_vector();
+        let start = from_index.map_or(0, u64::from);
+        let end = min(start.saturating_add(limit.unwrap_or(u64::MAX)), keys.len());
+        (start..end)
+            .filter_map(|i| {
+                self.royalties_paid
+                    .get(&(nft_contract_id.clone(), keys.get(i).unwrap()))
+            })
+            .collect()
+    }
+
+    /// royalties paid out to `account_id`, one entry per collection
+    pub fn royalties_paid_to(&self, account_id: AccountId) -> Vec<RoyaltiesPaid> {
+        self.royalty_collections
+            .get(&account_id)
+            .map(|nft_contract_ids| {
+                nft_contract_ids
+                    .iter()
+                    .filter_map(|nft_contract_id| {
+                        self.royalties_paid
+                            .get(&(nft_contract_id, account_id.clone()))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+impl Contract {
+    /// add a royalty paid for a sale of `token_id` to the payee's totals and log it
+    pub(crate) fn internal_record_royalty(
+        &mut self,
+        nft_contract_id: &AccountId,
+        token_id: &TokenId,
+        receiver_id: &AccountId,
+        ft_token_id: &FungibleTokenId,
+        amount: U128,
+    ) {
+        if amount.0 == 0 {
+            return;
+        }
+        let key = (nft_contract_id.clone(), receiver_id.clone());
+        let mut paid = self.royalties_paid.get(&key).unwrap_or_else(|| {
+            self.internal_add_royalty_receiver(nft_contract_id, receiver_id);
+            RoyaltiesPaid {
+                nft_contract_id: nft_contract_id.clone(),
+                receiver_id: receiver_id.clone(),
+                amounts: HashMap::new(),
+            }
+        });
+        let total = paid.amounts.entry(ft_token_id.clone()).or_insert(U128(0));
+        total.0 = total.0.saturating_add(amount.0);
+        self.royalties_paid.insert(&key, &paid);
+        MarketEvent::log_royalty_paid(nft_contract_id, token_id, receiver_id, ft_token_id, amount);
+    }
+
+    fn internal_add_royalty_receiver(&mut self, nft_contract_id: &AccountId, receiver_id: &AccountId) {
+        let mut receiver_ids = self.royalty_receivers.get(nft_contract_id).unwrap_or_else(|| {
+            UnorderedSet::new(
+                StorageKey::RoyaltyReceiversInner {
+                    account_id_hash: hash_account_id(nft_contract_id.as_str()),
+                }
+                .try_to_vec()
+                .unwrap(),
+            )
+        });
+        receiver_ids.insert(receiver_id);
+        self.royalty_receivers.insert(nft_contract_id, &receiver_ids);
+
+        let mut nft_contract_ids = self.royalty_collections.get(receiver_id).unwrap_or_else(|| {
+            UnorderedSet::new(
+                StorageKey::RoyaltyCollectionsInner {
+                    account_id_hash: hash_account_id(receiver_id.as_str()),
+                }
+                .try_to_vec()
+                .unwrap(),
+            )
+        });
+        nft_contract_ids.insert(nft_contract_id);
+        self.royalty_collections.insert(receiver_id, &nft_contract_ids);
+    }
+}
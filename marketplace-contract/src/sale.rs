@@ -1,5 +1,13 @@
 use crate::*;
-use near_sdk::promise_result_as_success;
+use near_sdk::{promise_result_as_success, PromiseError};
+
+/// Fields of an NFT contract's `nft_token` response the market cares about
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct JsonToken {
+    pub owner_id: AccountId,
+    pub approved_account_ids: Option<HashMap<AccountId, u64>>,
+}
 
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
 #[serde(crate = "near_sdk::serde")]
@@ -13,20 +21,73 @@ pub struct Bid {
 pub struct Sale {
     pub owner_id: AccountId,
     pub approval_id: u64,
-    pub nft_contract_id: String,
+    pub nft_contract_id: AccountId,
     pub token_id: String,
     pub sale_conditions: SaleConditions,
     pub bids: Bids,
     pub created_at: U64,
     pub is_auction: bool,
     pub token_type: Option<String>,
+    /// ms since epoch after which the sale can no longer be bought
+    pub expires_at: Option<U64>,
+    /// only this account can buy or bid, for deals negotiated off the market
+    pub allowed_buyer: Option<AccountId>,
+    /// part of the NEAR price that goes to the surcharge receiver instead of the seller
+    pub surcharge: U128,
+    /// least a bid has to beat the current bid by, None uses the market default
+    pub min_bid_increment_bps: Option<u16>,
+    /// bids kept per ft_token_id, None uses the market default
+    pub max_bids: Option<u8>,
+    /// the seller's proceeds split between accounts, None pays the seller
+    pub payout_split: Option<PayoutSplit>,
+}
+
+/// Sale layout of markets deployed before the storage redesign, see `migrate_sales`
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct SaleV1 {
+    pub owner_id: AccountId,
+    pub approval_id: u64,
+    pub nft_contract_id: AccountId,
+    pub token_id: String,
+    pub sale_conditions: SaleConditions,
+    pub bids: HashMap<FungibleTokenId, Vec<Bid>>,
+    pub created_at: U64,
+    pub is_auction: bool,
+    pub token_type: Option<String>,
+}
+
+/// bidding rules in effect for a sale
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BidParams {
+    pub min_bid_increment_bps: u16,
+    pub max_bids: u8,
+    /// least the next bid in the ft_token_id can be, None when there is no bid to beat
+    pub min_next_bid: Option<U128>,
+}
+
+/// what a buyer pays for a sale in NEAR, and where it goes
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PriceBreakdown {
+    /// paid out to the seller and royalty holders
+    pub seller_price: U128,
+    pub surcharge: U128,
+    pub market_fee: U128,
+    /// deposit to attach to `offer`
+    pub total: U128,
 }
 
+/// `msg` of an `ft_transfer_call` to the market, the amount buys the sale when it matches
+/// its price in that FT, otherwise it is escrowed as a bid until outbid, cancelled or accepted
 #[derive(Serialize, Deserialize)]
 #[serde(crate = "near_sdk::serde")]
 pub struct PurchaseArgs {
-    pub nft_contract_id: ValidAccountId,
+    pub nft_contract_id: AccountId,
     pub token_id: TokenId,
+    /// price the buyer saw, the purchase aborts if the sale was repriced since
+    pub expected_price: Option<U128>,
+    pub expected_ft_token_id: Option<FungibleTokenId>,
 }
 
 #[near_bindgen]
@@ -35,67 +96,122 @@ impl Contract {
 
     /// TODO remove without redirect to wallet? panic reverts
     #[payable]
-    pub fn remove_sale(&mut self, nft_contract_id: ValidAccountId, token_id: String) {
+    pub fn remove_sale(&mut self, nft_contract_id: AccountId, token_id: String) {
         assert_one_yocto();
         let nft_contract = nft_contract_id.clone();
         let token = token_id.clone();
-        let sale = self.internal_remove_sale(nft_contract_id.into(), token_id);
+        let sale = self.internal_remove_sale(nft_contract_id, token_id);
         let owner_id = env::predecessor_account_id();
-        assert_eq!(owner_id, sale.owner_id, "Must be sale owner");
-        self.refund_all_bids(&sale.bids);
-        ext_contract::nft_transfer(
-            owner_id.clone(),
-            token,
-            0,
-            "return to user".to_string(),
-            &nft_contract,
-            1,
-            GAS_FOR_NFT_TRANSFER,
+        require!(owner_id == sale.owner_id, "Must be sale owner");
+        MarketEvent::log_sale_delist(&sale);
+        self.refund_all_bids(&sale);
+        self.internal_record_activity(
+            ActivityKind::Cancel,
+            &sale.nft_contract_id,
+            &sale.token_id,
+            &owner_id,
+            None,
+            None,
         );
+        ext_contract::ext(nft_contract)
+            .with_attached_deposit(1)
+            .with_static_gas(GAS_FOR_NFT_TRANSFER)
+            .nft_transfer(owner_id, token, 0, "return to user".to_string());
+    }
+
+    /// set or clear the only account that can buy or bid on the sale
+    #[payable]
+    pub fn update_allowed_buyer(
+        &mut self,
+        nft_contract_id: AccountId,
+        token_id: String,
+        allowed_buyer: Option<AccountId>,
+    ) {
+        assert_one_yocto();
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let mut sale = self.sales.get(&contract_and_token_id).expect("No sale");
+        require!(
+            env::predecessor_account_id() == sale.owner_id,
+            "Must be sale owner"
+        );
+        sale.allowed_buyer = allowed_buyer;
+        self.sales.insert(&contract_and_token_id, &sale);
+        self.internal_record_change(&contract_and_token_id, ChangeType::AllowedBuyerUpdated);
     }
 
     #[payable]
     pub fn update_price(
         &mut self,
-        nft_contract_id: ValidAccountId,
+        nft_contract_id: AccountId,
         token_id: String,
-        ft_token_id: ValidAccountId,
+        ft_token_id: AccountId,
         price: U128,
     ) {
         assert_one_yocto();
-        let contract_id: AccountId = nft_contract_id.into();
-        let contract_and_token_id = format!("{}{}{}", contract_id, DELIMETER, token_id);
-        let mut sale = self.sales.get(&contract_and_token_id).expect("No sale");
-        assert_eq!(
-            env::predecessor_account_id(),
-            sale.owner_id,
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let sale = self.sales.get(&contract_and_token_id).expect("No sale");
+        require!(
+            env::predecessor_account_id() == sale.owner_id,
             "Must be sale owner"
         );
-        if !self.ft_token_ids.contains(ft_token_id.as_ref()) {
-            env::panic(format!("Token {} not supported by this market", ft_token_id).as_bytes());
+        if !self.ft_token_ids.contains(&ft_token_id) {
+            env::panic_str(&format!("Token {} not supported by this market", ft_token_id));
         }
-        sale.sale_conditions.insert(ft_token_id.into(), price);
-        self.sales.insert(&contract_and_token_id, &sale);
+        self.internal_set_price(&contract_and_token_id, sale, ft_token_id, price);
     }
 
+    /// `price` is what the seller gets, in NEAR the sale's surcharge is added on top of it
+    /// `expected_price` is the NEAR price the buyer saw, the deposit is refunded if it changed since
     #[payable]
-    pub fn offer(&mut self, nft_contract_id: ValidAccountId, token_id: String) {
-        let contract_id: AccountId = nft_contract_id.into();
+    pub fn offer(
+        &mut self,
+        nft_contract_id: AccountId,
+        token_id: String,
+        expected_price: Option<U128>,
+    ) {
+        let buyer_id = env::predecessor_account_id();
+        self.internal_offer(nft_contract_id, token_id, buyer_id, env::attached_deposit(), expected_price);
+    }
+
+    /// `deposit` is either attached or drawn from the buyer's balance
+    pub(crate) fn internal_offer(
+        &mut self,
+        nft_contract_id: AccountId,
+        token_id: String,
+        buyer_id: AccountId,
+        deposit: Balance,
+        expected_price: Option<U128>,
+    ) {
+        let contract_id = nft_contract_id;
         let contract_and_token_id = format!("{}{}{}", contract_id, DELIMETER, token_id);
         let mut sale = self.sales.get(&contract_and_token_id).expect("No sale");
-        let buyer_id = env::predecessor_account_id();
-        assert_ne!(sale.owner_id, buyer_id, "Cannot bid on your own sale.");
-        let ft_token_id = "near".to_string();
+        self.assert_not_paused();
+        assert_not_expired(&sale);
+        require!(sale.owner_id != buyer_id, "Cannot bid on your own sale.");
+        assert_allowed_buyer(&sale, &buyer_id);
+        let ft_token_id = near_token_id();
         let price = sale
             .sale_conditions
             .get(&ft_token_id)
             .expect("Not for sale in NEAR")
             .0;
+        assert_expected_price(U128(price), &ft_token_id, expected_price, None);
 
-        let deposit = env::attached_deposit();
-        assert!(deposit > 0, "Attached deposit must be greater than 0");
+        require!(deposit > 0, "Deposit must be greater than 0");
 
-        if !sale.is_auction && deposit == price.checked_add(price.checked_div(100).unwrap()).unwrap() {
+        if sale.is_auction {
+            // auctions can't be bought directly, the seller accepts the highest bid
+            require!(deposit >= price, "Bid must be at least the reserve price");
+            self.add_bid(
+                contract_and_token_id,
+                deposit,
+                ft_token_id,
+                buyer_id,
+                &mut sale,
+            );
+        } else if deposit == price.checked_add(self.internal_market_fee(&contract_id, price)).unwrap()
+            || deposit == price
+        {
             self.process_purchase(
                 contract_id,
                 token_id,
@@ -105,28 +221,13 @@ impl Contract {
                 sale.owner_id.clone()
             );
         } else {
-            if sale.is_auction && price > 0 {
-                assert!(deposit <= price, "Attached deposit must be lesser than reserve price");
-            }
-
-            if deposit == price {
-                self.process_purchase(
-                    contract_id,
-                    token_id,
-                    ft_token_id,
-                    U128(deposit),
-                    buyer_id,
-                    sale.owner_id.clone()
-                );
-            } else {
-                self.add_bid(
-                    contract_and_token_id,
-                    deposit,
-                    ft_token_id,
-                    buyer_id,
-                    &mut sale,
-                );
-            }
+            self.add_bid(
+                contract_and_token_id,
+                deposit,
+                ft_token_id,
+                buyer_id,
+                &mut sale,
+            );
         }
     }
 
@@ -139,72 +240,258 @@ impl Contract {
         buyer_id: AccountId,
         sale: &mut Sale,
     ) {
+        self.assert_compliant_trade(&sale.nft_contract_id, &sale.owner_id, &buyer_id);
         // store a bid and refund any current bid lower
-        let new_bid = Bid {
-            owner_id: buyer_id,
-            price: U128(amount),
-        };
-        
-        let bids_for_token_id = sale.bids.entry(ft_token_id.clone()).or_insert_with(Vec::new);
-        
-        if !bids_for_token_id.is_empty() {
-            let current_bid = &bids_for_token_id[bids_for_token_id.len()-1];
-            assert!(
-                amount > current_bid.price.0,
-                "Can't pay less than or equal to current bid price: {}",
-                current_bid.price.0
+        if let Some(current_bid) =
+            self.internal_current_bid(&contract_and_token_id, &sale.bids, &ft_token_id)
+        {
+            let min_bid = self.internal_min_next_bid(sale, current_bid.price.0);
+            require!(
+                amount >= min_bid,
+                format!("Bid must be at least {}, the current bid plus the minimum increment", min_bid)
             );
-            if ft_token_id == "near" {
-                Promise::new(current_bid.owner_id.clone()).transfer(u128::from(current_bid.price));
-            } else {
-                ext_contract::ft_transfer(
-                    current_bid.owner_id.clone(),
-                    current_bid.price,
-                    None,
-                    &ft_token_id,
-                    1,
-                    GAS_FOR_FT_TRANSFER,
-                );
-            }
+            self.internal_refund_bid(sale, &current_bid, &ft_token_id);
         }
-        
-        bids_for_token_id.push(new_bid);
-        if bids_for_token_id.len() > self.bid_history_length as usize {
-            bids_for_token_id.remove(0);
+
+        let bidders = sale.bids.entry(ft_token_id.clone()).or_insert_with(Vec::new);
+        bidders.retain(|bidder_id| bidder_id != &buyer_id);
+        bidders.push(buyer_id.clone());
+        self.bids.insert(
+            &bid_key(&contract_and_token_id, &ft_token_id, &buyer_id),
+            &U128(amount),
+        );
+        let max_bids = sale.max_bids.unwrap_or(self.bid_history_length).max(1);
+        // the oldest bids are the lowest, all but the current one were refunded when outbid
+        while bidders.len() > max_bids as usize {
+            let expired_bidder_id = bidders.remove(0);
+            self.bids
+                .remove(&bid_key(&contract_and_token_id, &ft_token_id, &expired_bidder_id));
+        }
+
+        // anti-sniping, a late bid gives everyone else time to answer
+        if sale.is_auction {
+            if let Some(expires_at) = sale.expires_at {
+                let now = env::block_timestamp() / 1000000;
+                if expires_at.0.saturating_sub(now) < AUCTION_EXTENSION_WINDOW_MS {
+                    sale.expires_at = Some(U64(expires_at.0 + AUCTION_EXTENSION_MS));
+                }
+            }
         }
-        
+
+        MarketEvent::log_sale_bid(
+            sale,
+            &Bid {
+                owner_id: buyer_id.clone(),
+                price: U128(amount),
+            },
+            &ft_token_id,
+        );
+        self.internal_record_activity(
+            ActivityKind::Bid,
+            &sale.nft_contract_id,
+            &sale.token_id,
+            &buyer_id,
+            Some(ft_token_id.clone()),
+            Some(U128(amount)),
+        );
+        self.internal_record_change(&contract_and_token_id, ChangeType::Bid);
+
         self.sales.insert(&contract_and_token_id, &sale);
     }
 
+    /// withdraw the caller's standing offer on a sale in `ft_token_id`, the escrowed NEAR or FT
+    /// is refunded. Auction bids stay until the auction is settled
+    #[payable]
+    pub fn cancel_bid(
+        &mut self,
+        nft_contract_id: AccountId,
+        token_id: String,
+        ft_token_id: AccountId,
+    ) {
+        assert_one_yocto();
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let mut sale = self.sales.get(&contract_and_token_id).expect("No sale");
+        require!(!sale.is_auction, "Auction bids can't be cancelled");
+        let bidder_id = env::predecessor_account_id();
+        let bid = self
+            .internal_current_bid(&contract_and_token_id, &sale.bids, &ft_token_id)
+            .filter(|bid| bid.owner_id == bidder_id)
+            .expect("No bid of the caller");
+        // outbid bids were refunded already, the whole history goes with the escrowed one
+        let bidders = sale.bids.remove(&ft_token_id).unwrap();
+        self.internal_clear_bids(&contract_and_token_id, &ft_token_id, &bidders);
+        self.sales.insert(&contract_and_token_id, &sale);
+        self.internal_refund_bid(&sale, &bid, &ft_token_id);
+        self.internal_record_activity(
+            ActivityKind::Cancel,
+            &sale.nft_contract_id,
+            &sale.token_id,
+            &bidder_id,
+            Some(ft_token_id),
+            Some(bid.price),
+        );
+        self.internal_record_change(&contract_and_token_id, ChangeType::Bid);
+    }
+
+    /// the seller takes the highest bid in `ft_token_id` and is paid out in that token
     pub fn accept_offer(
         &mut self,
-        nft_contract_id: ValidAccountId,
+        nft_contract_id: AccountId,
         token_id: String,
-        ft_token_id: ValidAccountId,
+        ft_token_id: AccountId,
     ) {
-        let contract_id: AccountId = nft_contract_id.into();
+        let contract_id = nft_contract_id;
         let contract_and_token_id = format!("{}{}{}", contract_id.clone(), DELIMETER, token_id.clone());
         // remove bid before proceeding to process purchase
         let mut sale = self.sales.get(&contract_and_token_id).expect("No sale");
-        let bids_for_token_id = sale.bids.remove(ft_token_id.as_ref()).expect("No bids");
-        let bid = &bids_for_token_id[bids_for_token_id.len()-1];
+        self.assert_not_paused();
+        // an ended auction is settled by accepting its highest bid
+        if !sale.is_auction {
+            assert_not_expired(&sale);
+        }
+        let bid = self
+            .internal_current_bid(&contract_and_token_id, &sale.bids, &ft_token_id)
+            .expect("No bids");
+        let bidders = sale.bids.remove(&ft_token_id).expect("No bids");
+        self.internal_clear_bids(&contract_and_token_id, &ft_token_id, &bidders);
         let owner_id = sale.owner_id.clone();
-	assert!(
-		env::current_account_id() == owner_id.clone(),
-		"Invalid Authorization"
-	);
-    	self.sales.insert(&contract_and_token_id, &sale);
+        require!(
+            env::predecessor_account_id() == owner_id,
+            "Must be sale owner"
+        );
+        self.sales.insert(&contract_and_token_id, &sale);
+        MarketEvent::log_sale_accept(&sale, &bid, &ft_token_id);
+        self.internal_record_activity(
+            ActivityKind::Accept,
+            &contract_id,
+            &token_id,
+            &owner_id,
+            Some(ft_token_id.clone()),
+            Some(bid.price),
+        );
         // panics at `self.internal_remove_sale` and reverts above if predecessor is not sale.owner_id
         self.process_purchase(
             contract_id,
             token_id,
-            ft_token_id.into(),
+            ft_token_id,
             bid.price,
             bid.owner_id.clone(),
             owner_id,
         );
     }
 
+    /// Cross-check a listing with the NFT contract, delisting it when the market
+    /// neither holds the token nor is approved for it anymore
+    pub fn verify_sale(&mut self, nft_contract_id: AccountId, token_id: String) -> Promise {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        require!(self.sales.get(&contract_and_token_id).is_some(), "No sale");
+        ext_contract::ext(nft_contract_id.clone())
+            .with_static_gas(GAS_FOR_NFT_TOKEN)
+            .nft_token(token_id.clone())
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_VERIFY_SALE)
+                    .resolve_verify_sale(nft_contract_id, token_id),
+            )
+    }
+
+    #[private]
+    pub fn resolve_verify_sale(
+        &mut self,
+        nft_contract_id: AccountId,
+        token_id: String,
+        #[callback_result] token: Result<Option<JsonToken>, PromiseError>,
+    ) -> bool {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let sale = match self.sales.get(&contract_and_token_id) {
+            Some(sale) => sale,
+            // settled or removed in the meantime
+            None => return false,
+        };
+        let marketplace_id = env::current_account_id();
+        let valid = token
+            .ok()
+            .flatten()
+            .map_or(false, |token| {
+                token.owner_id == marketplace_id
+                    || (token.owner_id == sale.owner_id
+                        && token
+                            .approved_account_ids
+                            .and_then(|approvals| approvals.get(&marketplace_id).copied())
+                            == Some(sale.approval_id))
+            });
+        if !valid {
+            self.internal_delist_stale_sale(nft_contract_id, token_id);
+        }
+        valid
+    }
+
+    /// self callback, a sale is listed once the market holds its token
+    #[private]
+    pub fn resolve_sale_deposit(&mut self, sale: Sale) -> bool {
+        if !matches!(env::promise_result(0), PromiseResult::Successful(_)) {
+            env::log_str(&format!("Token {} wasn't deposited to the market", sale.token_id));
+            return false;
+        }
+        self.internal_add_sale(sale);
+        true
+    }
+
+    /// Check `limit` sales starting at `from_index`, in the order of `get_sales`, and remove the
+    /// expired ones, returning the NFTs to their owners and refunding bids. Removing a sale
+    /// moves the last one into its index. The caller earns a small reward per sale removed.
+    /// Ended auctions with bids are kept for AUCTION_SETTLEMENT_PERIOD_MS so the seller can accept.
+    pub fn remove_expired_sales(&mut self, from_index: Option<U64>, limit: u64) -> u64 {
+        let now = env::block_timestamp() / 1000000;
+        let keys = self.sale_ids.as_vector();
+        let start = from_index.map_or(0, u64::from);
+        let end = min(start.saturating_add(limit), keys.len());
+        let expired: Vec<ContractAndTokenId> = (start..end)
+            .filter_map(|i| keys.get(i))
+            .filter(|contract_and_token_id| {
+                self.sales
+                    .get(contract_and_token_id)
+                    .and_then(|sale| {
+                        let expires_at = sale.expires_at?.0;
+                        if sale.is_auction && !sale.bids.is_empty() {
+                            Some(expires_at + AUCTION_SETTLEMENT_PERIOD_MS)
+                        } else {
+                            Some(expires_at)
+                        }
+                    })
+                    .map_or(false, |removable_at| removable_at <= now)
+            })
+            .collect();
+
+        let mut reward = 0;
+        for contract_and_token_id in &expired {
+            let sale = self.sales.get(contract_and_token_id).unwrap();
+            let sale = self.internal_remove_sale(sale.nft_contract_id, sale.token_id);
+            MarketEvent::log_sale_delist(&sale);
+            self.refund_all_bids(&sale);
+            ext_contract::ext(sale.nft_contract_id.clone())
+                .with_attached_deposit(1)
+                .with_static_gas(GAS_FOR_NFT_TRANSFER)
+                .nft_transfer(
+                    sale.owner_id.clone(),
+                    sale.token_id.clone(),
+                    0,
+                    "sale expired".to_string(),
+                );
+            let balance = self.storage_deposits.get(&sale.owner_id).unwrap_or(0);
+            let sale_reward = min(EXPIRED_SALE_REWARD, balance);
+            if sale_reward > 0 {
+                self.storage_deposits
+                    .insert(&sale.owner_id, &(balance - sale_reward));
+                reward += sale_reward;
+            }
+        }
+        if reward > 0 {
+            Promise::new(env::predecessor_account_id()).transfer(reward);
+        }
+        expired.len() as u64
+    }
+
     #[private]
     pub fn process_purchase(
         &mut self,
@@ -215,19 +502,35 @@ impl Contract {
         buyer_id: AccountId,
         owner_id: AccountId
     ) -> Promise {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        self.assert_compliant_trade(&nft_contract_id, &owner_id, &buyer_id);
+        self.assert_not_wash_trade(&contract_and_token_id, &owner_id, &buyer_id);
         let sale = self.internal_remove_sale(nft_contract_id.clone(), token_id.clone());
+        let balance = internal_seller_balance(&sale, &ft_token_id, price);
+        // the route is fixed now, the gas for the swap is attached to resolve_purchase
+        let swap = self.internal_payout_swap(&owner_id, &ft_token_id);
+        let gas_for_resolve = if swap.is_some() {
+            GAS_FOR_ROYALTIES + GAS_FOR_SWAP_PAYOUT
+        } else {
+            GAS_FOR_ROYALTIES
+        };
 
-        ext_contract::nft_transfer(
-            buyer_id.clone(),
-            token_id,
-            sale.approval_id,
-            "payout from market".to_string(),
-            &nft_contract_id,
-            1,
-            GAS_FOR_NFT_TRANSFER,
-        );
-
-        Promise::new(owner_id.clone()).transfer(u128::from(price))
+        ext_contract::ext(nft_contract_id)
+            .with_attached_deposit(1)
+            .with_static_gas(GAS_FOR_NFT_TRANSFER)
+            .nft_transfer_payout(
+                buyer_id.clone(),
+                token_id,
+                sale.approval_id,
+                "payout from market".to_string(),
+                U128(balance),
+                10,
+            )
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(gas_for_resolve)
+                    .resolve_purchase(ft_token_id, buyer_id, sale, price, owner_id, swap),
+            )
     }
 
     /// self callback
@@ -240,83 +543,134 @@ impl Contract {
         sale: Sale,
         price: U128,
         owner_id: AccountId,
+        swap: Option<SwapRoute>,
     ) -> U128 {
         let marketplace_id = env::current_account_id();
-        // checking for payout information
-        let payout_option = promise_result_as_success().and_then(|value| {
-            // None means a bad payout from bad NFT contract
-            near_sdk::serde_json::from_slice::<Payout>(&value)
-                .ok()
-                .and_then(|payout| {
-                    // gas to do 10 FT transfers (and definitely 10 NEAR transfers)
-                    if payout.payout.len() + sale.bids.len() > 10 || payout.payout.is_empty() {
-                        env::log(format!("Cannot have more than 10 royalties and sale.bids refunds").as_bytes());
-                        None
-                    } else {
-                        // TODO off by 1 e.g. payouts are fractions of 3333 + 3333 + 3333
-                        let mut remainder = price.0;
-                        for &value in payout.payout.values() {
-                            remainder = remainder.checked_sub(value.0)?;
-                        }
-                        if remainder == 0 || remainder == 1 {
-                            Some(payout)
-                        } else {
-                            None
-                        }
-                    }
-                })
-        });
-        
-        // is payout option valid?
-        let payout = if let Some(payout_option) = payout_option {
-            payout_option
+        let balance = internal_seller_balance(&sale, &ft_token_id, price);
+
+        // the NFT never left the market: give the buyer everything back and relist
+        let value = if let Some(value) = promise_result_as_success() {
+            value
         } else {
-            if ft_token_id == "near" {
-                Promise::new(owner_id).transfer(u128::from(price));
+            if ft_token_id.as_str() == NEAR_TOKEN_ID {
+                self.internal_refund_near(buyer_id, price.0);
             }
-            // leave function and return all FTs in ft_resolve_transfer
+            self.internal_add_sale(sale);
+            // return all FTs in ft_resolve_transfer
             return price;
         };
+
+        let contract_and_token_id = format!("{}{}{}", sale.nft_contract_id, DELIMETER, sale.token_id);
+        // repeated trades between the same accounts are flagged and left out of the collection stats
+        if self.internal_record_trade(&contract_and_token_id, &owner_id, &buyer_id) {
+            MarketEvent::log_wash_trade_flag(&sale, &owner_id, &buyer_id, &ft_token_id, price);
+        } else {
+            self.internal_stats_on_sale(&sale.token_type, &ft_token_id, price);
+        }
+        MarketEvent::log_sale_purchase(
+            &sale.nft_contract_id,
+            &sale.token_id,
+            &owner_id,
+            &buyer_id,
+            &ft_token_id,
+            price,
+        );
+        self.internal_record_activity(
+            ActivityKind::Sale,
+            &sale.nft_contract_id,
+            &sale.token_id,
+            &buyer_id,
+            Some(ft_token_id.clone()),
+            Some(price),
+        );
+
+        // checking for payout information
+        let payout_option = internal_parse_payout(&value, balance);
+
         // Going to payout everyone, first return all outstanding bids (accepted offer bid was already removed)
-        self.refund_all_bids(&sale.bids);
-
-        // NEAR payouts
-        if ft_token_id == "near" {
-            for (receiver_id, amount) in payout.payout {
-                if receiver_id == marketplace_id{
-                    Promise::new(owner_id.clone()).transfer(amount.0);
-                } else {
-                    Promise::new(receiver_id).transfer(amount.0);
-                }
+        let mut immediate = MAX_IMMEDIATE_SETTLEMENTS;
+        let mut queued = self.internal_settle_bid_refunds(&sale, &mut immediate);
+
+        // the NFT was transferred but the payout is unusable, the seller gets the whole balance
+        let payout = match payout_option {
+            Some(payout) => self.internal_cap_royalties(&sale.nft_contract_id, &owner_id, balance, payout),
+            None => {
+                let mut payout = HashMap::new();
+                payout.insert(owner_id.clone(), U128(balance));
+                Payout { payout }
             }
-            // refund all FTs (won't be any)
-            price
-        } else {
-            // FT payouts
-            for (receiver_id, amount) in payout.payout {
-                if receiver_id == marketplace_id{
-                    ext_contract::ft_transfer(
-                        owner_id.clone(),
-                        amount,
-                        None,
-                        &ft_token_id,
-                        1,
-                        GAS_FOR_FT_TRANSFER,
-                    );
-                } else {
-                    ext_contract::ft_transfer(
-                        receiver_id,
-                        amount,
-                        None,
-                        &ft_token_id,
-                        1,
-                        GAS_FOR_FT_TRANSFER,
-                    );
+        };
+
+        // NEAR or FT payouts, the seller's share can be swapped for the FT they prefer
+        let mut swap = swap;
+        for (receiver_id, amount) in payout.payout {
+            let receiver_id = if receiver_id == marketplace_id {
+                owner_id.clone()
+            } else {
+                receiver_id
+            };
+            if receiver_id == owner_id {
+                // a split seller is paid out as is, the swap is for a single receiver
+                if let Some(payout_split) = sale.payout_split.as_ref() {
+                    for (receiver_id, amount) in internal_split_payout(payout_split, amount) {
+                        let job = SettlementJob {
+                            kind: SettlementKind::Payout,
+                            receiver_id,
+                            ft_token_id: ft_token_id.clone(),
+                            amount,
+                        };
+                        if self.internal_settle(job, &mut immediate) {
+                            queued += 1;
+                        }
+                    }
+                    continue;
+                }
+                if let Some(route) = swap.take() {
+                    if self.internal_swap_payout(receiver_id.clone(), ft_token_id.clone(), amount, route) {
+                        continue;
+                    }
                 }
+            } else {
+                self.internal_record_royalty(
+                    &sale.nft_contract_id,
+                    &sale.token_id,
+                    &receiver_id,
+                    &ft_token_id,
+                    amount,
+                );
+            }
+            let job = SettlementJob {
+                kind: SettlementKind::Payout,
+                receiver_id,
+                ft_token_id: ft_token_id.clone(),
+                amount,
+            };
+            if self.internal_settle(job, &mut immediate) {
+                queued += 1;
+            }
+        }
+        let surcharge = internal_surcharge(&sale, &ft_token_id, price);
+        if surcharge > 0 {
+            Promise::new(self.surcharge_receiver_id.clone().unwrap_or_else(|| self.treasury_id.clone()))
+                .transfer(surcharge);
+        }
+        // the market fee goes to the treasury, it first tips the processing of the queued transfers
+        let mut fee = price.0.saturating_sub(balance + surcharge);
+        let tips = min(fee, self.internal_settlement_tip(&ft_token_id) * queued as Balance);
+        self.internal_add_settlement_tips(&ft_token_id, tips);
+        fee -= tips;
+        if fee > 0 {
+            if ft_token_id.as_str() == NEAR_TOKEN_ID {
+                Promise::new(self.treasury_id.clone()).transfer(fee);
+            } else {
+                ext_contract::ext(ft_token_id)
+                    .with_attached_deposit(1)
+                    .with_static_gas(GAS_FOR_FT_TRANSFER)
+                    .ft_transfer(self.treasury_id.clone(), U128(fee), None);
             }
-            // keep all FTs (already transferred for payouts)
-            U128(0)
         }
+        // keep all FTs (already transferred for payouts and fee)
+        U128(0)
     }
 }
 
@@ -324,6 +678,21 @@ impl Contract {
 
 #[ext_contract(ext_self)]
 trait ExtSelf {
+    fn resolve_mt_purchase(&mut self, listing: MtListing, buyer_id: AccountId, amount: U128, paid: U128) -> bool;
+    fn resolve_lazy_purchase(&mut self, listing: LazyListing, buyer_id: AccountId, paid: U128) -> bool;
+    fn resolve_bundle_purchase(&mut self, bundle: Bundle, buyer_id: AccountId, paid: U128) -> u64;
+    fn resolve_bundle_deposit(
+        &mut self,
+        nft_contract_id: AccountId,
+        owner_id: AccountId,
+        bundle_id: String,
+        token_id: TokenId,
+    ) -> bool;
+    fn resolve_verify_sale(&mut self, nft_contract_id: AccountId, token_id: String) -> bool;
+    fn resolve_sale_deposit(&mut self, sale: Sale) -> bool;
+    fn resolve_rental_deposit(&mut self, rental: Rental) -> bool;
+    fn resolve_register_token_type(&mut self, nft_contract_id: AccountId, token_type: String, creator_id: AccountId) -> bool;
+
     fn resolve_purchase(
         &mut self,
         ft_token_id: AccountId,
@@ -331,5 +700,7 @@ trait ExtSelf {
         sale: Sale,
         price: U128,
         owner_id: AccountId,
+        swap: Option<SwapRoute>,
     ) -> Promise;
+    fn resolve_swap_payout(&mut self, seller_id: AccountId, ft_token_id: AccountId, amount: U128) -> U128;
 }
@@ -4,22 +4,33 @@ use crate::*;
 impl Contract {
 
     /// views
+
+    /// supplies are kept as counters, so they can be read for every page without loading the sets
     pub fn get_supply_sales(
         &self,
     ) -> U64 {
-        U64(self.sales.len())
+        U64(self.sale_ids.len())
+    }
+
+    pub fn get_sales(
+        &self,
+        from_index: U64,
+        limit: u64,
+    ) -> Vec<SaleView> {
+        let keys = self.sale_ids.as_vector();
+        let start = u64::from(from_index);
+        let end = min(start + limit, keys.len());
+        (start..end)
+            .filter_map(|i| self.sales.get(&keys.get(i).unwrap()))
+            .map(|sale| self.internal_sale_view(sale))
+            .collect()
     }
     
     pub fn get_supply_by_owner_id(
         &self,
         account_id: AccountId,
     ) -> U64 {
-        let by_owner_id = self.by_owner_id.get(&account_id);
-        if let Some(by_owner_id) = by_owner_id {
-            U64(by_owner_id.len())
-        } else {
-            U64(0)
-        }
+        U64(self.supply_by_owner_id.get(&account_id).unwrap_or(0))
     }
 
     pub fn get_sales_by_owner_id(
@@ -27,7 +38,7 @@ impl Contract {
         account_id: AccountId,
         from_index: U64,
         limit: u64,
-    ) -> Vec<Sale> {
+    ) -> Vec<SaleView> {
         let mut tmp = vec![];
         let by_owner_id = self.by_owner_id.get(&account_id);
         let sales = if let Some(by_owner_id) = by_owner_id {
@@ -39,7 +50,7 @@ impl Contract {
         let start = u64::from(from_index);
         let end = min(start + limit, sales.len());
         for i in start..end {
-            tmp.push(self.sales.get(&keys.get(i).unwrap()).unwrap());
+            tmp.push(self.internal_sale_view(self.sales.get(&keys.get(i).unwrap()).unwrap()));
         }
         tmp
     }
@@ -48,12 +59,7 @@ impl Contract {
         &self,
         nft_contract_id: AccountId,
     ) -> U64 {
-        let by_nft_contract_id = self.by_nft_contract_id.get(&nft_contract_id);
-        if let Some(by_nft_contract_id) = by_nft_contract_id {
-            U64(by_nft_contract_id.len())
-        } else {
-            U64(0)
-        }
+        U64(self.supply_by_nft_contract_id.get(&nft_contract_id).unwrap_or(0))
     }
 
     pub fn get_sales_by_nft_contract_id(
@@ -61,7 +67,7 @@ impl Contract {
         nft_contract_id: AccountId,
         from_index: U64,
         limit: u64,
-    ) -> Vec<Sale> {
+    ) -> Vec<SaleView> {
         let mut tmp = vec![];
         let by_nft_contract_id = self.by_nft_contract_id.get(&nft_contract_id);
         let sales = if let Some(by_nft_contract_id) = by_nft_contract_id {
@@ -73,7 +79,7 @@ impl Contract {
         let start = u64::from(from_index);
         let end = min(start + limit, sales.len());
         for i in start..end {
-            tmp.push(self.sales.get(&format!("{}{}{}", &nft_contract_id, DELIMETER, &keys.get(i).unwrap())).unwrap());
+            tmp.push(self.internal_sale_view(self.sales.get(&format!("{}{}{}", &nft_contract_id, DELIMETER, &keys.get(i).unwrap())).unwrap()));
         }
         tmp
     }
@@ -82,12 +88,14 @@ impl Contract {
         &self,
         token_type: String,
     ) -> U64 {
-        let by_nft_token_type = self.by_nft_token_type.get(&token_type);
-        if let Some(by_nft_token_type) = by_nft_token_type {
-            U64(by_nft_token_type.len())
-        } else {
-            U64(0)
-        }
+        U64(self.supply_by_nft_token_type.get(&token_type).unwrap_or(0))
+    }
+
+    pub fn get_supply_by_token_type(
+        &self,
+        token_type: String,
+    ) -> U64 {
+        self.get_supply_by_nft_token_type(token_type)
     }
 
     pub fn get_sales_by_nft_token_type(
@@ -95,7 +103,7 @@ impl Contract {
         token_type: String,
         from_index: U64,
         limit: u64,
-    ) -> Vec<Sale> {
+    ) -> Vec<SaleView> {
         let mut tmp = vec![];
         let by_nft_token_type = self.by_nft_token_type.get(&token_type);
         let sales = if let Some(by_nft_token_type) = by_nft_token_type {
@@ -107,13 +115,56 @@ impl Contract {
         let start = u64::from(from_index);
         let end = min(start + limit, sales.len());
         for i in start..end {
-            tmp.push(self.sales.get(&keys.get(i).unwrap()).unwrap());
+            tmp.push(self.internal_sale_view(self.sales.get(&keys.get(i).unwrap()).unwrap()));
         }
         tmp
     }
 
-    pub fn get_sale(&self, nft_contract_token: ContractAndTokenId) -> Option<Sale> {
-        self.sales.get(&nft_contract_token)
+    pub fn get_sale(&self, nft_contract_token: ContractAndTokenId) -> Option<SaleView> {
+        self.sales
+            .get(&nft_contract_token)
+            .map(|sale| self.internal_sale_view(sale))
+    }
+
+    /// deposit to buy a sale in NEAR, split into the seller's price, surcharge and market fee
+    pub fn get_price_breakdown(&self, nft_contract_token: ContractAndTokenId) -> Option<PriceBreakdown> {
+        let sale = self.sales.get(&nft_contract_token)?;
+        let price = *sale.sale_conditions.get(NEAR_TOKEN_ID)?;
+        let market_fee = self.internal_market_fee(&sale.nft_contract_id, price.0);
+        let surcharge = internal_surcharge(&sale, &near_token_id(), price);
+        Some(PriceBreakdown {
+            seller_price: U128(price.0 - surcharge),
+            surcharge: U128(surcharge),
+            market_fee: U128(market_fee),
+            total: U128(price.0 + market_fee),
+        })
+    }
+
+    /// current highest bid of a sale in the given ft_token_id
+    pub fn get_highest_bid(&self, nft_contract_token: ContractAndTokenId, ft_token_id: AccountId) -> Option<Bid> {
+        let sale = self.sales.get(&nft_contract_token)?;
+        self.internal_current_bid(&nft_contract_token, &sale.bids, &ft_token_id)
+    }
+
+    /// bidding rules of a sale and the least the next bid in `ft_token_id` can be
+    pub fn get_bid_params(&self, nft_contract_token: ContractAndTokenId, ft_token_id: AccountId) -> Option<BidParams> {
+        let sale = self.sales.get(&nft_contract_token)?;
+        let min_next_bid = self
+            .internal_current_bid(&nft_contract_token, &sale.bids, &ft_token_id)
+            .map(|bid| U128(self.internal_min_next_bid(&sale, bid.price.0)));
+        Some(BidParams {
+            min_bid_increment_bps: sale.min_bid_increment_bps.unwrap_or(self.min_bid_increment_bps),
+            max_bids: sale.max_bids.unwrap_or(self.bid_history_length).max(1),
+            min_next_bid,
+        })
+    }
+
+    /// bids of a sale in the given ft_token_id, oldest first
+    pub fn get_bids(&self, nft_contract_token: ContractAndTokenId, ft_token_id: AccountId) -> Vec<Bid> {
+        self.sales
+            .get(&nft_contract_token)
+            .map(|sale| self.internal_bids(&nft_contract_token, &sale.bids, &ft_token_id))
+            .unwrap_or_default()
     }
     
 }
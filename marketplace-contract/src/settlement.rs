@@ -0,0 +1,189 @@
+use crate::*;
+
+/// Transfers of a settled sale that didn't fit in the settling call's gas are queued,
+/// `process_queue` pays them out in the order they were queued for a small tip per transfer.
+
+/// at most this many refunds and payouts are sent in the call settling a sale
+pub(crate) const MAX_IMMEDIATE_SETTLEMENTS: u32 = 10;
+/// tip per queued transfer of a NEAR sale paid to whoever processes it, out of the tip pool.
+/// FT sales tip what the owner set with `set_settlement_ft_tip`
+pub(crate) const SETTLEMENT_TIP: Balance = 1_000_000_000_000_000_000_000;
+/// gas one queued transfer takes, the rest of the queue waits for the next call
+const GAS_FOR_SETTLEMENT_JOB: Gas = Gas(10_000_000_000_000);
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum SettlementKind {
+    BidRefund,
+    Payout,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SettlementJob {
+    pub kind: SettlementKind,
+    pub receiver_id: AccountId,
+    pub ft_token_id: FungibleTokenId,
+    pub amount: U128,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Send up to `limit` queued refunds and payouts, oldest first. Anyone can call it and is
+    /// tipped per transfer, in the currency of its sale, while the tip pool lasts.
+    /// Returns the number sent.
+    pub fn process_queue(&mut self, limit: u64) -> u64 {
+        let mut processed = 0;
+        let mut tips: HashMap<FungibleTokenId, Balance> = HashMap::new();
+        while processed < limit && self.settlement_queue_head < self.settlement_queue_tail {
+            if env::prepaid_gas() - env::used_gas() < GAS_FOR_SETTLEMENT_JOB {
+                break;
+            }
+            let job = self
+                .settlement_queue
+                .remove(&self.settlement_queue_head)
+                .expect("Settlement job not found");
+            self.settlement_queue_head += 1;
+            let pending = self.settlement_pending.get(&job.receiver_id).unwrap_or(1);
+            if pending > 1 {
+                self.settlement_pending.insert(&job.receiver_id, &(pending - 1));
+            } else {
+                self.settlement_pending.remove(&job.receiver_id);
+            }
+            let tip = self.internal_take_settlement_tip(&job.ft_token_id);
+            *tips.entry(job.ft_token_id.clone()).or_insert(0) += tip;
+            self.internal_execute_settlement(job);
+            processed += 1;
+        }
+        let processor_id = env::predecessor_account_id();
+        for (ft_token_id, tip) in tips {
+            if tip == 0 {
+                continue;
+            }
+            if ft_token_id.as_str() == NEAR_TOKEN_ID {
+                Promise::new(processor_id.clone()).transfer(tip);
+            } else {
+                ext_contract::ext(ft_token_id)
+                    .with_attached_deposit(1)
+                    .with_static_gas(GAS_FOR_FT_TRANSFER)
+                    .ft_transfer(processor_id.clone(), U128(tip), None);
+            }
+        }
+        processed
+    }
+
+    /// add the attached deposit to the tip pool of `process_queue`
+    #[payable]
+    pub fn fund_settlement_tips(&mut self) -> U128 {
+        let deposit = env::attached_deposit();
+        require!(deposit > 0, "Attached deposit must be greater than 0");
+        self.settlement_tips += deposit;
+        U128(self.settlement_tips)
+    }
+
+    /// only owner, tip per queued transfer of sales in `ft_token_id`, taken out of their market fee
+    pub fn set_settlement_ft_tip(&mut self, ft_token_id: FungibleTokenId, tip: U128) {
+        self.assert_owner();
+        require!(
+            ft_token_id.as_str() != NEAR_TOKEN_ID,
+            "NEAR sales tip SETTLEMENT_TIP"
+        );
+        self.settlement_ft_tips.insert(&ft_token_id, &tip);
+    }
+
+    /// views
+
+    pub fn get_settlement_queue_length(&self) -> U64 {
+        U64(self.settlement_queue_tail - self.settlement_queue_head)
+    }
+
+    /// queued transfers to `account_id`
+    pub fn get_pending_settlements(&self, account_id: AccountId) -> U64 {
+        U64(self.settlement_pending.get(&account_id).unwrap_or(0))
+    }
+
+    pub fn get_settlement_tips(&self) -> U128 {
+        U128(self.settlement_tips)
+    }
+
+    /// tip per queued transfer of sales in `ft_token_id`
+    pub fn get_settlement_ft_tip(&self, ft_token_id: FungibleTokenId) -> U128 {
+        U128(self.internal_settlement_tip(&ft_token_id))
+    }
+
+    /// FT the processors of the queue are tipped from, for sales in `ft_token_id`
+    pub fn get_settlement_ft_tip_pool(&self, ft_token_id: FungibleTokenId) -> U128 {
+        U128(self.settlement_ft_tip_pools.get(&ft_token_id).unwrap_or(0))
+    }
+}
+
+impl Contract {
+    /// send `job` now while `immediate` transfers are left, queue it otherwise.
+    /// Returns whether it was queued
+    pub(crate) fn internal_settle(&mut self, job: SettlementJob, immediate: &mut u32) -> bool {
+        if *immediate > 0 {
+            *immediate -= 1;
+            self.internal_execute_settlement(job);
+            return false;
+        }
+        let pending = self.settlement_pending.get(&job.receiver_id).unwrap_or(0);
+        self.settlement_pending.insert(&job.receiver_id, &(pending + 1));
+        self.settlement_queue.insert(&self.settlement_queue_tail, &job);
+        self.settlement_queue_tail += 1;
+        true
+    }
+
+    /// tip per queued transfer of a sale in `ft_token_id`
+    pub(crate) fn internal_settlement_tip(&self, ft_token_id: &FungibleTokenId) -> Balance {
+        if ft_token_id.as_str() == NEAR_TOKEN_ID {
+            SETTLEMENT_TIP
+        } else {
+            self.settlement_ft_tips.get(ft_token_id).map_or(0, |tip| tip.0)
+        }
+    }
+
+    pub(crate) fn internal_add_settlement_tips(
+        &mut self,
+        ft_token_id: &FungibleTokenId,
+        amount: Balance,
+    ) {
+        if amount == 0 {
+            return;
+        }
+        if ft_token_id.as_str() == NEAR_TOKEN_ID {
+            self.settlement_tips += amount;
+        } else {
+            let pool = self.settlement_ft_tip_pools.get(ft_token_id).unwrap_or(0);
+            self.settlement_ft_tip_pools.insert(ft_token_id, &(pool + amount));
+        }
+    }
+
+    /// tip for sending one queued transfer of a sale in `ft_token_id`, out of its tip pool
+    fn internal_take_settlement_tip(&mut self, ft_token_id: &FungibleTokenId) -> Balance {
+        let tip = self.internal_settlement_tip(ft_token_id);
+        if ft_token_id.as_str() == NEAR_TOKEN_ID {
+            let tip = min(self.settlement_tips, tip);
+            self.settlement_tips -= tip;
+            return tip;
+        }
+        let pool = self.settlement_ft_tip_pools.get(ft_token_id).unwrap_or(0);
+        let tip = min(pool, tip);
+        if tip > 0 {
+            self.settlement_ft_tip_pools.insert(ft_token_id, &(pool - tip));
+        }
+        tip
+    }
+
+    fn internal_execute_settlement(&mut self, job: SettlementJob) {
+        if job.ft_token_id.as_str() != NEAR_TOKEN_ID {
+            ext_contract::ext(job.ft_token_id)
+                .with_attached_deposit(1)
+                .with_static_gas(GAS_FOR_FT_TRANSFER)
+                .ft_transfer(job.receiver_id, job.amount, None);
+        } else if job.kind == SettlementKind::BidRefund {
+            self.internal_refund_near(job.receiver_id, job.amount.0);
+        } else {
+            Promise::new(job.receiver_id).transfer(job.amount.0);
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use crate::*;
+
+/// Sellers of FT sales can be paid in another FT: their share of the price is swapped on the
+/// market's AMM (ref.finance style instant swap) and the output sent to them. When the swap
+/// fails or falls short of the seller's minimum rate they are paid in the FT the buyer paid in.
+/// Royalties are always paid in the FT the buyer paid in.
+
+/// the swap on the AMM, including its transfer of the output to the seller
+const GAS_FOR_SWAP: Gas = Gas(60_000_000_000_000);
+/// pays the seller what the AMM didn't swap
+const GAS_FOR_RESOLVE_SWAP_PAYOUT: Gas = Gas(15_000_000_000_000);
+/// added to the gas of `resolve_purchase` when the seller is paid through a swap
+pub(crate) const GAS_FOR_SWAP_PAYOUT: Gas =
+    Gas(GAS_FOR_SWAP.0 + GAS_FOR_RESOLVE_SWAP_PAYOUT.0 + GAS_FOR_FT_TRANSFER.0);
+
+/// how a seller wants to be paid for sales in one FT
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SwapRoute {
+    /// FT the seller receives
+    pub ft_token_id: FungibleTokenId,
+    /// AMM pool swapping the paid FT for it
+    pub pool_id: u64,
+    /// least the seller accepts per unit paid, `min_rate / 10^min_rate_decimals` of the
+    /// received FT per smallest unit of the paid FT, both in smallest units
+    pub min_rate: U128,
+    pub min_rate_decimals: u8,
+}
+
+impl SwapRoute {
+    /// least amount out of swapping `amount_in`, None when it overflows
+    pub fn min_amount_out(&self, amount_in: Balance) -> Option<Balance> {
+        let scale = 10u128.checked_pow(self.min_rate_decimals as u32)?;
+        Some(amount_in.checked_mul(self.min_rate.0)? / scale)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// only owner, AMM seller payouts are swapped on, none turns swapped payouts off
+    pub fn set_swap_contract(&mut self, swap_contract_id: Option<AccountId>) {
+        self.assert_owner();
+        self.swap_contract_id = swap_contract_id;
+    }
+
+    /// be paid in `route.ft_token_id` for sales bought in `ft_token_id`, none clears it.
+    /// The caller has to be registered with the FT they receive
+    #[payable]
+    pub fn set_payout_swap(&mut self, ft_token_id: FungibleTokenId, route: Option<SwapRoute>) {
+        assert_one_yocto();
+        let key = (env::predecessor_account_id(), ft_token_id);
+        match route {
+            Some(route) => {
+                require!(key.1.as_str() != NEAR_TOKEN_ID, "Only FT payouts can be swapped");
+                require!(route.ft_token_id != key.1, "Route must swap to another token");
+                require!(route.min_rate.0 > 0, "Minimum rate must be greater than 0");
+                self.payout_swaps.insert(&key, &route);
+            }
+            None => {
+                self.payout_swaps.remove(&key);
+            }
+        }
+    }
+
+    /// resolves the swap of the seller's share of a sale, what wasn't swapped is paid in `ft_token_id`
+    #[private]
+    pub fn resolve_swap_payout(
+        &mut self,
+        seller_id: AccountId,
+        ft_token_id: FungibleTokenId,
+        amount: U128,
+    ) -> U128 {
+        // the FT contract returns the amount the AMM used, everything is refunded when it failed
+        let used = match env::promise_result(0) {
+            PromiseResult::Successful(value) => near_sdk::serde_json::from_slice::<U128>(&value)
+                .map(|used| min(used.0, amount.0))
+                .unwrap_or(0),
+            _ => 0,
+        };
+        let unswapped = amount.0 - used;
+        if unswapped > 0 {
+            env::log_str(&format!(
+                "Could not swap {} {} for {}, paid out unswapped",
+                unswapped, ft_token_id, seller_id
+            ));
+            ext_contract::ext(ft_token_id)
+                .with_attached_deposit(1)
+                .with_static_gas(GAS_FOR_FT_TRANSFER)
+                .ft_transfer(seller_id, U128(unswapped), None);
+        }
+        U128(unswapped)
+    }
+
+    /// views
+
+    pub fn get_swap_contract(&self) -> Option<AccountId> {
+        self.swap_contract_id.clone()
+    }
+
+    pub fn get_payout_swap(&self, account_id: AccountId, ft_token_id: FungibleTokenId) -> Option<SwapRoute> {
+        self.payout_swaps.get(&(account_id, ft_token_id))
+    }
+}
+
+impl Contract {
+    /// route the seller is paid through for a sale in `ft_token_id`, if swaps are on
+    pub(crate) fn internal_payout_swap(
+        &self,
+        seller_id: &AccountId,
+        ft_token_id: &FungibleTokenId,
+    ) -> Option<SwapRoute> {
+        self.swap_contract_id.as_ref()?;
+        if ft_token_id.as_str() == NEAR_TOKEN_ID {
+            return None;
+        }
+        self.payout_swaps.get(&(seller_id.clone(), ft_token_id.clone()))
+    }
+
+    /// swap `amount` of `ft_token_id` on the AMM for the seller's preferred FT.
+    /// Returns false when the swap can't be started, the caller pays out in `ft_token_id`
+    pub(crate) fn internal_swap_payout(
+        &mut self,
+        seller_id: AccountId,
+        ft_token_id: FungibleTokenId,
+        amount: U128,
+        route: SwapRoute,
+    ) -> bool {
+        let swap_contract_id = match self.swap_contract_id.clone() {
+            Some(swap_contract_id) => swap_contract_id,
+            None => return false,
+        };
+        let min_amount_out = match route.min_amount_out(amount.0) {
+            Some(min_amount_out) => min_amount_out,
+            None => {
+                env::log_str("Minimum swap output overflows, paying out unswapped");
+                return false;
+            }
+        };
+        let msg = near_sdk::serde_json::json!({
+            "actions": [{
+                "pool_id": route.pool_id,
+                "token_in": ft_token_id,
+                "token_out": route.ft_token_id,
+                "amount_in": amount,
+                "min_amount_out": U128(min_amount_out),
+            }],
+            "swap_out_recipient": seller_id,
+        });
+        ext_contract::ext(ft_token_id.clone())
+            .with_attached_deposit(1)
+            .with_static_gas(GAS_FOR_SWAP)
+            .ft_transfer_call(swap_contract_id, amount, None, msg.to_string())
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_SWAP_PAYOUT)
+                    .resolve_swap_payout(seller_id, ft_token_id, amount),
+            );
+        true
+    }
+}
@@ -0,0 +1,104 @@
+use crate::*;
+
+/// a sale as returned by the views, `verified` when its token_type was registered by
+/// the owner of the NFT contract
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SaleView {
+    #[serde(flatten)]
+    pub sale: Sale,
+    pub verified: bool,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Register `token_type` of `nft_contract_id` as the caller's. The registration only
+    /// goes through if the NFT contract's `owner` view returns the caller.
+    /// Attach STORAGE_PER_TOKEN_TYPE, refunded when the check fails
+    #[payable]
+    pub fn register_token_type(&mut self, nft_contract_id: AccountId, token_type: String) -> Promise {
+        let deposit = env::attached_deposit();
+        require!(deposit >= STORAGE_PER_TOKEN_TYPE, "Requires storage deposit for the token_type");
+        let creator_id = env::predecessor_account_id();
+        let contract_and_token_type = format!("{}{}{}", nft_contract_id, DELIMETER, token_type);
+        require!(
+            self.token_type_creators.get(&contract_and_token_type).is_none(),
+            "token_type already registered"
+        );
+        // keep only the storage deposit, the rest goes back right away
+        self.internal_refund_near(creator_id.clone(), deposit - STORAGE_PER_TOKEN_TYPE);
+        ext_contract::ext(nft_contract_id.clone())
+            .with_static_gas(GAS_FOR_NFT_OWNER)
+            .owner()
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_TOKEN_TYPE)
+                    .resolve_register_token_type(nft_contract_id, token_type, creator_id),
+            )
+    }
+
+    #[private]
+    pub fn resolve_register_token_type(
+        &mut self,
+        nft_contract_id: AccountId,
+        token_type: String,
+        creator_id: AccountId,
+        #[callback_result] owner_id: Result<AccountId, PromiseError>,
+    ) -> bool {
+        let contract_and_token_type = format!("{}{}{}", nft_contract_id, DELIMETER, token_type);
+        let verified = owner_id.map_or(false, |owner_id| owner_id == creator_id)
+            // registered by someone else in the meantime
+            && self.token_type_creators.get(&contract_and_token_type).is_none();
+        if !verified {
+            self.internal_refund_near(creator_id, STORAGE_PER_TOKEN_TYPE);
+            return false;
+        }
+        self.token_type_creators.insert(&contract_and_token_type, &creator_id);
+        MarketEvent::log_token_type_register(nft_contract_id, token_type, creator_id);
+        true
+    }
+
+    /// Remove a registration, by its creator or the market owner. Refunds the storage deposit
+    /// to the creator
+    pub fn unregister_token_type(&mut self, nft_contract_id: AccountId, token_type: String) {
+        let contract_and_token_type = format!("{}{}{}", nft_contract_id, DELIMETER, token_type);
+        let creator_id = self
+            .token_type_creators
+            .get(&contract_and_token_type)
+            .unwrap_or_else(|| env::panic_str("token_type not registered"));
+        let predecessor_id = env::predecessor_account_id();
+        require!(
+            predecessor_id == creator_id || predecessor_id == self.owner_id,
+            "Creator's or owner's method"
+        );
+        self.token_type_creators.remove(&contract_and_token_type);
+        self.internal_refund_near(creator_id.clone(), STORAGE_PER_TOKEN_TYPE);
+        MarketEvent::log_token_type_unregister(nft_contract_id, token_type, creator_id);
+    }
+
+    /// views
+
+    /// account that registered `token_type` of `nft_contract_id`, None if unregistered
+    pub fn get_token_type_creator(&self, nft_contract_id: AccountId, token_type: String) -> Option<AccountId> {
+        self.token_type_creators
+            .get(&format!("{}{}{}", nft_contract_id, DELIMETER, token_type))
+    }
+}
+
+impl Contract {
+    /// whether the sale's token_type is registered by the NFT contract's owner
+    pub(crate) fn internal_is_verified(&self, sale: &Sale) -> bool {
+        sale.token_type.as_ref().map_or(false, |token_type| {
+            self.token_type_creators
+                .get(&format!("{}{}{}", sale.nft_contract_id, DELIMETER, token_type))
+                .is_some()
+        })
+    }
+
+    pub(crate) fn internal_sale_view(&self, sale: Sale) -> SaleView {
+        SaleView {
+            verified: self.internal_is_verified(&sale),
+            sale,
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use crate::*;
+
+/// last settled sale of a token, to spot it being flipped between the same two accounts
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct LastTrade {
+    pub seller_id: AccountId,
+    pub buyer_id: AccountId,
+    /// ms since epoch
+    pub timestamp: u64,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// only owner, a token sold back to its previous seller within the window is rejected
+    pub fn set_wash_trade_window(&mut self, window_ms: U64) {
+        self.assert_owner();
+        self.wash_trade_window_ms = window_ms.0;
+    }
+
+    /// views
+
+    pub fn get_wash_trade_window(&self) -> U64 {
+        U64(self.wash_trade_window_ms)
+    }
+}
+
+impl Contract {
+    /// buyer and seller must be different accounts, not signed for by the same key holder,
+    /// and the token must not go straight back to whoever sold it to the seller
+    pub(crate) fn assert_not_wash_trade(
+        &self,
+        contract_and_token_id: &ContractAndTokenId,
+        owner_id: &AccountId,
+        buyer_id: &AccountId,
+    ) {
+        require!(owner_id != buyer_id, "Buyer and seller must be different accounts");
+        // whoever started the purchase can't also sign for the other side
+        let counterparty_id = if env::predecessor_account_id() == *owner_id {
+            buyer_id
+        } else {
+            owner_id
+        };
+        require!(
+            env::signer_account_id() != *counterparty_id,
+            "Buyer and seller can't share a signer"
+        );
+        if let Some(last_trade) = self.last_trades.get(contract_and_token_id) {
+            let now = env::block_timestamp() / 1000000;
+            require!(
+                !(last_trade.seller_id == *buyer_id
+                    && last_trade.buyer_id == *owner_id
+                    && now.saturating_sub(last_trade.timestamp) < self.wash_trade_window_ms),
+                "Token was sold between these accounts too recently"
+            );
+        }
+    }
+
+    /// record a settled sale, returns whether it repeats a trade between the same two accounts
+    pub(crate) fn internal_record_trade(
+        &mut self,
+        contract_and_token_id: &ContractAndTokenId,
+        seller_id: &AccountId,
+        buyer_id: &AccountId,
+    ) -> bool {
+        let suspicious = self
+            .last_trades
+            .get(contract_and_token_id)
+            .map_or(false, |last_trade| {
+                (last_trade.seller_id == *buyer_id && last_trade.buyer_id == *seller_id)
+                    || (last_trade.seller_id == *seller_id && last_trade.buyer_id == *buyer_id)
+            });
+        self.last_trades.insert(
+            contract_and_token_id,
+            &LastTrade {
+                seller_id: seller_id.clone(),
+                buyer_id: buyer_id.clone(),
+                timestamp: env::block_timestamp() / 1000000,
+            },
+        );
+        suspicious
+    }
+}
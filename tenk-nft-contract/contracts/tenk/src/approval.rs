@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+
+use crate::*;
+
+/// Clearing the approvals of one token, storage reads and writes included
+const GAS_FOR_REVOKE_STEP: Gas = Gas(parse_gas!("3 Tgas") as u64);
+
+#[near_bindgen]
+impl Contract {
+    /// Accounts approved for `token_id` with their approval ids, None if the token doesn't exist
+    pub fn nft_approvals(&self, token_id: TokenId) -> Option<HashMap<AccountId, u64>> {
+        self.tokens.owner_by_id.get(&token_id)?;
+        Some(
+            self.tokens
+                .approvals_by_id
+                .as_ref()
+                .and_then(|by_id| by_id.get(&token_id))
+                .unwrap_or_default(),
+        )
+    }
+
+    /// Revoke every approval on the caller's tokens, e.g. before burning tokens listed on
+    /// several markets. Tokens are walked from `from_index` for as long as the gas lasts and
+    /// the storage deposited for the approvals is refunded. Returns the index to continue
+    /// from, None once all of the caller's tokens are covered.
+    #[payable]
+    pub fn revoke_all_for_owner(&mut self, from_index: Option<u64>) -> Option<u64> {
+        assert_one_yocto();
+        let owner_id = env::predecessor_account_id();
+        let token_ids = self
+            .member_tokens(&owner_id)
+            .unwrap_or_else(|| env::panic_str("Account has no tokens"));
+        let token_ids = token_ids.as_vector();
+        let approvals_by_id = self
+            .tokens
+            .approvals_by_id
+            .as_mut()
+            .unwrap_or_else(|| env::panic_str("NFT does not support Approval Management"));
+
+        let mut index = from_index.unwrap_or(0);
+        let mut revoked = 0;
+        let mut storage_released: u64 = 0;
+        while index < token_ids.len() {
+            if env::prepaid_gas() - env::used_gas() < GAS_FOR_REVOKE_STEP {
+                break;
+            }
+            let token_id = token_ids.get(index).unwrap();
+            if let Some(approved_account_ids) = approvals_by_id.remove(&token_id) {
+                storage_released += approved_account_ids
+                    .keys()
+                    .map(bytes_for_approved_account_id)
+                    .sum::<u64>();
+                revoked += 1;
+            }
+            index += 1;
+        }
+        log!(
+            "Revoked the approvals of {} tokens of {}",
+            revoked,
+            owner_id
+        );
+        refund(
+            &owner_id,
+            env::storage_byte_cost() * storage_released as Balance,
+        );
+        (index < token_ids.len()).then(|| index)
+    }
+}
+
+/// Storage an approval takes, as charged by `nft_approve`
+fn bytes_for_approved_account_id(account_id: &AccountId) -> u64 {
+    account_id.as_str().len() as u64 + 4 + std::mem::size_of::<u64>() as u64
+}
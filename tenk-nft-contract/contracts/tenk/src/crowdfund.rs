@@ -0,0 +1,177 @@
+use crate::*;
+
+/// Time contributors have to fund a crowdfunded vault from the mint on
+const CROWDFUND_PERIOD_MS: TimestampMs = 30 * 24 * 60 * 60 * 1000;
+/// Most accounts contributing to one vault
+const MAX_CROWDFUND_CONTRIBUTORS: usize = 100;
+/// NEAR deposit of the raised amount, which can make the vault report its funding
+const GAS_FOR_CROWDFUND_DEPOSIT: Gas = Gas(parse_gas!("50 Tgas") as u64);
+const GAS_FOR_ON_CROWDFUND_DEPOSITED: Gas = Gas(parse_gas!("10 Tgas") as u64);
+
+#[near_bindgen]
+impl Contract {
+    /// Add to the vault of a crowdfunded token. Anything attached above what is left to raise
+    /// is refunded. Once the target is reached it is deposited into the vault and the token
+    /// becomes transferable.
+    #[payable]
+    pub fn contribute_to_vault(&mut self, token_id: TokenId) -> U128 {
+        let mut crowdfund = self
+            .crowdfund(token_id.clone())
+            .unwrap_or_else(|| env::panic_str("Token isn't crowdfunded"));
+        require!(
+            crowdfund.status == CrowdfundStatus::Open,
+            "Crowdfunding is closed"
+        );
+        let account_id = env::predecessor_account_id();
+        let mut shares = self.crowdfund_shares.get(&token_id).unwrap_or_default();
+        require!(
+            shares.contains_key(&account_id) || shares.len() < MAX_CROWDFUND_CONTRIBUTORS,
+            format!("At most {} contributors", MAX_CROWDFUND_CONTRIBUTORS)
+        );
+        let deposit = env::attached_deposit();
+        let amount = deposit.min(crowdfund.target.0 - crowdfund.raised.0);
+        require!(amount > 0, "Attach NEAR to contribute");
+        *shares.entry(account_id.clone()).or_insert(0) += amount;
+        self.crowdfund_shares.insert(&token_id, &shares);
+        crowdfund.raised = U128(crowdfund.raised.0 + amount);
+        self.crowdfund_escrow += amount;
+        refund(&account_id, deposit - amount);
+
+        if crowdfund.raised == crowdfund.target {
+            crowdfund.status = CrowdfundStatus::Depositing;
+            Promise::new(self.vault_id(&token_id))
+                .function_call(
+                    "deposit_near".to_string(),
+                    vec![],
+                    crowdfund.target.0,
+                    GAS_FOR_CROWDFUND_DEPOSIT,
+                )
+                .then(ext_self::on_crowdfund_deposited(
+                    token_id.clone(),
+                    env::current_account_id(),
+                    0,
+                    GAS_FOR_ON_CROWDFUND_DEPOSITED,
+                ));
+        }
+        self.crowdfunds.insert(&token_id, &crowdfund);
+        U128(amount)
+    }
+
+    /// Activate the token once the vault took the raised NEAR. When it didn't, the
+    /// crowdfunding failed and the NEAR, refunded by the vault, can be reclaimed.
+    #[private]
+    pub fn on_crowdfund_deposited(&mut self, token_id: TokenId) -> bool {
+        if is_promise_success(None) {
+            if let Some(crowdfund) = self.crowdfunds.remove(&token_id) {
+                self.crowdfund_escrow -= crowdfund.raised.0;
+            }
+            self.crowdfund_shares.remove(&token_id);
+            log!("Vault of token {} is crowdfunded", token_id);
+            return true;
+        }
+        log!("Could not deposit the crowdfunding of token {}", token_id);
+        self.fail_crowdfund(&token_id);
+        false
+    }
+
+    /// Take back the caller's contribution to a crowdfunding that failed. Each contributor
+    /// gets their share of what was raised, which is what they put in.
+    pub fn reclaim_contribution(&mut self, token_id: TokenId) -> U128 {
+        let mut crowdfund = self
+            .crowdfund(token_id.clone())
+            .unwrap_or_else(|| env::panic_str("Token isn't crowdfunded"));
+        require!(
+            crowdfund.status == CrowdfundStatus::Failed,
+            "Crowdfunding hasn't failed"
+        );
+        let account_id = env::predecessor_account_id();
+        let mut shares = self.crowdfund_shares.get(&token_id).unwrap_or_default();
+        let amount = shares
+            .remove(&account_id)
+            .unwrap_or_else(|| env::panic_str("Nothing to reclaim"));
+        crowdfund.raised = U128(crowdfund.raised.0 - amount);
+        self.crowdfund_escrow -= amount;
+        if shares.is_empty() && !self.tokens.owner_by_id.contains_key(&token_id) {
+            self.crowdfunds.remove(&token_id);
+            self.crowdfund_shares.remove(&token_id);
+        } else {
+            self.crowdfunds.insert(&token_id, &crowdfund);
+            self.crowdfund_shares.insert(&token_id, &shares);
+        }
+        refund(&account_id, amount);
+        U128(amount)
+    }
+
+    /// Crowdfunding of the token's vault while it isn't funded. None, means the token
+    /// wasn't crowdfunded or its vault got the raised NEAR
+    pub fn crowdfund(&self, token_id: TokenId) -> Option<Crowdfund> {
+        self.crowdfunds.get(&token_id).map(|mut crowdfund| {
+            if crowdfund.status == CrowdfundStatus::Open && current_time_ms() >= crowdfund.deadline
+            {
+                crowdfund.status = CrowdfundStatus::Failed;
+            }
+            crowdfund
+        })
+    }
+
+    /// NEAR `account_id` put into the crowdfunding of the token and can still reclaim
+    pub fn crowdfund_contribution(&self, token_id: TokenId, account_id: AccountId) -> U128 {
+        U128(
+            self.crowdfund_shares
+                .get(&token_id)
+                .and_then(|shares| shares.get(&account_id).copied())
+                .unwrap_or(0),
+        )
+    }
+}
+
+impl Contract {
+    /// Leave the vault's NEAR deposit to contributors, the vault fee on it included
+    pub(crate) fn start_crowdfund(&mut self, token_id: &TokenId, near_amount: Balance) {
+        let target = near_amount + self.vault_fee_config.fee_on(near_amount);
+        self.crowdfunds.insert(
+            token_id,
+            &Crowdfund {
+                target: U128(target),
+                raised: U128(0),
+                deadline: current_time_ms() + CROWDFUND_PERIOD_MS,
+                status: CrowdfundStatus::Open,
+            },
+        );
+    }
+
+    /// Stop taking contributions, e.g. when the token got no vault
+    pub(crate) fn fail_crowdfund(&mut self, token_id: &TokenId) {
+        if let Some(mut crowdfund) = self.crowdfunds.get(token_id) {
+            crowdfund.status = CrowdfundStatus::Failed;
+            self.crowdfunds.insert(token_id, &crowdfund);
+        }
+    }
+
+    /// A crowdfunded token can't change hands until its vault is funded or the crowdfunding failed
+    pub(crate) fn assert_not_crowdfunding(&self, token_id: &TokenId) {
+        require!(
+            !self.is_crowdfunding(token_id),
+            "Token is waiting for its crowdfunding"
+        );
+    }
+
+    /// Whether the token's crowdfunding can still succeed
+    pub(crate) fn is_crowdfunding(&self, token_id: &TokenId) -> bool {
+        self.crowdfund(token_id.clone()).map_or(false, |crowdfund| {
+            crowdfund.status != CrowdfundStatus::Failed
+        })
+    }
+
+    /// Drop a burned token's failed crowdfunding, unless contributions are left to reclaim
+    pub(crate) fn remove_crowdfund(&mut self, token_id: &TokenId) {
+        if self
+            .crowdfund_shares
+            .get(token_id)
+            .map_or(true, |shares| shares.is_empty())
+        {
+            self.crowdfunds.remove(token_id);
+            self.crowdfund_shares.remove(token_id);
+        }
+    }
+}
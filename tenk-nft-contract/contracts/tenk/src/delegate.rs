@@ -0,0 +1,126 @@
+use crate::*;
+use ed25519_dalek::{PublicKey as Ed25519PublicKey, Signature, Verifier};
+use near_sdk::CurveType;
+use std::convert::TryFrom;
+
+/// Parameters of a mint the receiver signed for a relayer to submit
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct DelegatedMint<'a> {
+    receiver_id: &'a AccountId,
+    token_deposit: &'a [TokenDeposit],
+    near_amount: U128,
+    nonce: U64,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Mint for `receiver_id` with the deposits fronted by a signer account, e.g. a relayer.
+    /// `signature` is the receiver's ed25519 signature over `delegated_mint_message`, made with
+    /// the key registered with `set_delegate_key` or, for implicit accounts, the account's own key.
+    /// `nonce` has to be the receiver's `delegate_nonce` plus one.
+    #[payable]
+    pub fn nft_mint_delegated(
+        &mut self,
+        receiver_id: AccountId,
+        token_deposit: Vec<TokenDeposit>,
+        near_amount: U128,
+        public_key: PublicKey,
+        signature: Base64VecU8,
+        nonce: U64,
+    ) -> Vec<Token> {
+        require!(
+            self.is_allowed_signer(&env::predecessor_account_id()),
+            "Method is private to signer accounts"
+        );
+        require!(
+            nonce.0 == self.delegate_nonce(receiver_id.clone()).0 + 1,
+            "Nonce was already used"
+        );
+        self.assert_delegate_key(&receiver_id, &public_key);
+        let message =
+            self.delegated_mint_message(receiver_id.clone(), token_deposit.clone(), near_amount, nonce);
+        require!(
+            verify_ed25519(&public_key, message.as_bytes(), &signature.0),
+            "Invalid signature"
+        );
+        self.delegate_nonces.insert(&receiver_id, &nonce.0);
+        self.internal_mint_one(&receiver_id, token_deposit, near_amount, None, None, None, false)
+    }
+
+    /// Register the key the caller signs delegated mints with, none removes it
+    pub fn set_delegate_key(&mut self, public_key: Option<PublicKey>) -> bool {
+        let account_id = env::predecessor_account_id();
+        match public_key {
+            Some(public_key) => {
+                require!(
+                    public_key.curve_type() == CurveType::ED25519,
+                    "Delegate key must be an ed25519 key"
+                );
+                self.delegate_keys.insert(&account_id, &public_key);
+            }
+            None => {
+                self.delegate_keys.remove(&account_id);
+            }
+        }
+        true
+    }
+
+    /// The message a receiver signs: this contract's account id and the mint as JSON, joined by ':'
+    pub fn delegated_mint_message(
+        &self,
+        receiver_id: AccountId,
+        token_deposit: Vec<TokenDeposit>,
+        near_amount: U128,
+        nonce: U64,
+    ) -> String {
+        let mint = DelegatedMint {
+            receiver_id: &receiver_id,
+            token_deposit: &token_deposit,
+            near_amount,
+            nonce,
+        };
+        format!("{}:{}", env::current_account_id(), serde_json::to_string(&mint).unwrap())
+    }
+
+    /// Nonce of the account's latest delegated mint, 0 if it has none
+    pub fn delegate_nonce(&self, account_id: AccountId) -> U64 {
+        self.delegate_nonces.get(&account_id).unwrap_or(0).into()
+    }
+
+    pub fn delegate_key(&self, account_id: AccountId) -> Option<PublicKey> {
+        self.delegate_keys.get(&account_id)
+    }
+}
+
+impl Contract {
+    /// The key has to be registered by the account, or be the key of an implicit account
+    fn assert_delegate_key(&self, account_id: &AccountId, public_key: &PublicKey) {
+        if let Some(registered) = self.delegate_keys.get(account_id) {
+            require!(&registered == public_key, "Key is not the account's delegate key");
+            return;
+        }
+        require!(
+            public_key.curve_type() == CurveType::ED25519
+                && account_id.as_str() == implicit_account_id(&public_key.as_bytes()[1..]),
+            "Account has no delegate key"
+        );
+    }
+}
+
+fn implicit_account_id(key: &[u8]) -> String {
+    key.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn verify_ed25519(public_key: &PublicKey, message: &[u8], signature: &[u8]) -> bool {
+    // the first byte of a near-sdk PublicKey is its curve type
+    let public_key = match Ed25519PublicKey::from_bytes(&public_key.as_bytes()[1..]) {
+        Ok(public_key) => public_key,
+        Err(_) => return false,
+    };
+    let signature = match Signature::try_from(signature) {
+        Ok(signature) => signature,
+        Err(_) => return false,
+    };
+    public_key.verify(message, &signature).is_ok()
+}
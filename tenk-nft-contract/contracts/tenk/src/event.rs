@@ -8,6 +8,7 @@ use serde_with::skip_serializing_none;
 #[serde(rename_all = "snake_case")]
 pub enum NearEvent {
     Nep171(Nep171Event),
+    Tenk(TenkEvent),
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -25,6 +26,7 @@ pub enum Nep171EventKind {
     NftMint(Vec<NftMintData>),
     NftTransfer(Vec<NftTransferData>),
     NftBurn(Vec<NftBurnData>),
+    ContractMetadataUpdate(Vec<ContractMetadataUpdateData>),
 }
 
 #[skip_serializing_none]
@@ -54,6 +56,107 @@ pub struct NftBurnData {
     pub memo: Option<String>,
 }
 
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ContractMetadataUpdateData {
+    pub memo: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TenkEvent {
+    pub version: String,
+    #[serde(flatten)]
+    pub event_kind: TenkEventKind,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "event", content = "data")]
+#[serde(rename_all = "snake_case")]
+pub enum TenkEventKind {
+    SaleUpdate(Vec<SaleUpdateData>),
+    ReferralReward(Vec<ReferralRewardData>),
+    VaultBeneficiaryUpdate(Vec<VaultBeneficiaryUpdateData>),
+    OwnerUpdate(Vec<OwnerUpdateData>),
+    TokenLock(Vec<TokenLockData>),
+    TokenUnlock(Vec<TokenUnlockData>),
+    MembershipTierUpdate(Vec<MembershipTierUpdateData>),
+    MembershipTierSet(Vec<MembershipTierSetData>),
+    MembershipTierRemove(Vec<MembershipTierRemoveData>),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SaleUpdateData {
+    pub field: String,
+    pub value: serde_json::Value,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReferralRewardData {
+    pub referrer_id: String,
+    pub minter_id: String,
+    pub amount: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct VaultBeneficiaryUpdateData {
+    pub token_id: String,
+    pub vault_id: String,
+    pub beneficiary_id: String,
+}
+
+/// `role` is one of "owner", "pending_owner" or "backup_owner", no account means it was cleared
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OwnerUpdateData {
+    pub role: String,
+    pub account_id: Option<String>,
+}
+
+/// `until` is in ms since epoch
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TokenLockData {
+    pub token_id: String,
+    pub locker_id: String,
+    pub until: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TokenUnlockData {
+    pub token_id: String,
+    pub locker_id: String,
+}
+
+/// No `previous_tier` means the token had none, e.g. at mint
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MembershipTierUpdateData {
+    pub token_id: String,
+    pub tier: String,
+    pub previous_tier: Option<String>,
+}
+
+/// A membership tier was added or replaced, amounts are in yocto
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MembershipTierSetData {
+    pub name: String,
+    pub rank: u8,
+    pub near_amount: String,
+    pub tokens: Vec<TierTokenData>,
+    pub media: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TierTokenData {
+    pub token_contract_id: String,
+    pub token_amount: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MembershipTierRemoveData {
+    pub name: String,
+}
+
 impl Display for NearEvent {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str(&format!("EVENT_JSON:{}", self.to_json_string()))
@@ -80,6 +183,51 @@ impl NearEvent {
         NearEvent::new_171_v1(Nep171EventKind::NftMint(data))
     }
 
+    /// `contract_metadata_update` was added in version 1.1.0 of the standard
+    pub fn contract_metadata_update(data: Vec<ContractMetadataUpdateData>) -> Self {
+        NearEvent::new_171("1.1.0".to_string(), Nep171EventKind::ContractMetadataUpdate(data))
+    }
+
+    pub fn new_tenk_v1(event_kind: TenkEventKind) -> Self {
+        NearEvent::Tenk(TenkEvent { version: "1.0.0".to_string(), event_kind })
+    }
+
+    pub fn sale_update(data: Vec<SaleUpdateData>) -> Self {
+        NearEvent::new_tenk_v1(TenkEventKind::SaleUpdate(data))
+    }
+
+    pub fn referral_reward(data: Vec<ReferralRewardData>) -> Self {
+        NearEvent::new_tenk_v1(TenkEventKind::ReferralReward(data))
+    }
+
+    pub fn vault_beneficiary_update(data: Vec<VaultBeneficiaryUpdateData>) -> Self {
+        NearEvent::new_tenk_v1(TenkEventKind::VaultBeneficiaryUpdate(data))
+    }
+
+    pub fn owner_update(data: Vec<OwnerUpdateData>) -> Self {
+        NearEvent::new_tenk_v1(TenkEventKind::OwnerUpdate(data))
+    }
+
+    pub fn token_lock(data: Vec<TokenLockData>) -> Self {
+        NearEvent::new_tenk_v1(TenkEventKind::TokenLock(data))
+    }
+
+    pub fn token_unlock(data: Vec<TokenUnlockData>) -> Self {
+        NearEvent::new_tenk_v1(TenkEventKind::TokenUnlock(data))
+    }
+
+    pub fn membership_tier_update(data: Vec<MembershipTierUpdateData>) -> Self {
+        NearEvent::new_tenk_v1(TenkEventKind::MembershipTierUpdate(data))
+    }
+
+    pub fn membership_tier_set(data: Vec<MembershipTierSetData>) -> Self {
+        NearEvent::new_tenk_v1(TenkEventKind::MembershipTierSet(data))
+    }
+
+    pub fn membership_tier_remove(data: Vec<MembershipTierRemoveData>) -> Self {
+        NearEvent::new_tenk_v1(TenkEventKind::MembershipTierRemove(data))
+    }
+
     pub(crate) fn to_json_string(&self) -> String {
         serde_json::to_string(self).unwrap()
     }
@@ -128,6 +276,61 @@ impl NearEvent {
     pub fn log_nft_burns(data: Vec<NftBurnData>) {
         NearEvent::nft_burn(data).log();
     }
+
+    pub fn log_contract_metadata_update(memo: Option<String>) {
+        NearEvent::contract_metadata_update(vec![ContractMetadataUpdateData { memo }]).log();
+    }
+
+    pub fn log_sale_update(field: &str, value: serde_json::Value) {
+        NearEvent::sale_update(vec![SaleUpdateData { field: field.to_string(), value }]).log();
+    }
+
+    pub fn log_referral_reward(referrer_id: String, minter_id: String, amount: String) {
+        NearEvent::referral_reward(vec![ReferralRewardData { referrer_id, minter_id, amount }])
+            .log();
+    }
+
+    pub fn log_vault_beneficiary_update(token_id: String, vault_id: String, beneficiary_id: String) {
+        NearEvent::vault_beneficiary_update(vec![VaultBeneficiaryUpdateData {
+            token_id,
+            vault_id,
+            beneficiary_id,
+        }])
+            .log();
+    }
+
+    pub fn log_owner_update(role: &str, account_id: Option<String>) {
+        NearEvent::owner_update(vec![OwnerUpdateData {
+            role: role.to_string(),
+            account_id,
+        }])
+            .log();
+    }
+
+    pub fn log_token_lock(token_id: String, locker_id: String, until: u64) {
+        NearEvent::token_lock(vec![TokenLockData { token_id, locker_id, until }]).log();
+    }
+
+    pub fn log_token_unlock(token_id: String, locker_id: String) {
+        NearEvent::token_unlock(vec![TokenUnlockData { token_id, locker_id }]).log();
+    }
+
+    pub fn log_membership_tier_update(token_id: String, tier: String, previous_tier: Option<String>) {
+        NearEvent::membership_tier_update(vec![MembershipTierUpdateData {
+            token_id,
+            tier,
+            previous_tier,
+        }])
+            .log();
+    }
+
+    pub fn log_membership_tier_set(data: MembershipTierSetData) {
+        NearEvent::membership_tier_set(vec![data]).log();
+    }
+
+    pub fn log_membership_tier_remove(name: String) {
+        NearEvent::membership_tier_remove(vec![MembershipTierRemoveData { name }]).log();
+    }
 }
 
 #[cfg(test)]
@@ -252,4 +455,104 @@ mod tests {
             r#"{"standard":"nep171","version":"1.0.0","event":"nft_transfer","data":[{"authorized_id":"4","old_owner_id":"alice","new_owner_id":"bob","token_ids":["2","3"],"memo":"has memo"},{"old_owner_id":"bob","new_owner_id":"alice","token_ids":["0","1"]}]}"#
         );
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn sale_update() {
+        let log = NearEvent::sale_update(vec![SaleUpdateData {
+            field: "price".to_string(),
+            value: serde_json::json!("1000"),
+        }])
+            .to_json_string();
+        assert_eq!(
+            log,
+            r#"{"standard":"tenk","version":"1.0.0","event":"sale_update","data":[{"field":"price","value":"1000"}]}"#
+        );
+    }
+
+    #[test]
+    fn referral_reward() {
+        let log = NearEvent::referral_reward(vec![ReferralRewardData {
+            referrer_id: "alice".to_string(),
+            minter_id: "bob".to_string(),
+            amount: "50".to_string(),
+        }])
+            .to_json_string();
+        assert_eq!(
+            log,
+            r#"{"standard":"tenk","version":"1.0.0","event":"referral_reward","data":[{"referrer_id":"alice","minter_id":"bob","amount":"50"}]}"#
+        );
+    }
+
+    #[test]
+    fn owner_update() {
+        let log = NearEvent::owner_update(vec![
+            OwnerUpdateData {
+                role: "pending_owner".to_string(),
+                account_id: Some("alice".to_string()),
+            },
+            OwnerUpdateData {
+                role: "backup_owner".to_string(),
+                account_id: None,
+            },
+        ])
+            .to_json_string();
+        assert_eq!(
+            log,
+            r#"{"standard":"tenk","version":"1.0.0","event":"owner_update","data":[{"role":"pending_owner","account_id":"alice"},{"role":"backup_owner"}]}"#
+        );
+    }
+
+    #[test]
+    fn token_lock() {
+        let log = NearEvent::token_lock(vec![TokenLockData {
+            token_id: "0".to_string(),
+            locker_id: "lender".to_string(),
+            until: 1_700_000_000_000,
+        }])
+            .to_json_string();
+        assert_eq!(
+            log,
+            r#"{"standard":"tenk","version":"1.0.0","event":"token_lock","data":[{"token_id":"0","locker_id":"lender","until":1700000000000}]}"#
+        );
+    }
+
+    #[test]
+    fn membership_tier_update() {
+        let log = NearEvent::membership_tier_update(vec![
+            MembershipTierUpdateData {
+                token_id: "1".to_string(),
+                tier: "Bronze".to_string(),
+                previous_tier: None,
+            },
+            MembershipTierUpdateData {
+                token_id: "2".to_string(),
+                tier: "Gold".to_string(),
+                previous_tier: Some("Silver".to_string()),
+            },
+        ])
+            .to_json_string();
+        assert_eq!(
+            log,
+            r#"{"standard":"tenk","version":"1.0.0","event":"membership_tier_update","data":[{"token_id":"1","tier":"Bronze"},{"token_id":"2","tier":"Gold","previous_tier":"Silver"}]}"#
+        );
+    }
+
+    #[test]
+    fn membership_tier_set() {
+        let log = NearEvent::membership_tier_set(vec![MembershipTierSetData {
+            name: "Gold".to_string(),
+            rank: 2,
+            near_amount: "100".to_string(),
+            tokens: vec![TierTokenData {
+                token_contract_id: "ft.near".to_string(),
+                token_amount: "50".to_string(),
+            }],
+            media: None,
+        }])
+            .to_json_string();
+        assert_eq!(
+            log,
+            r#"{"standard":"tenk","version":"1.0.0","event":"membership_tier_set","data":[{"name":"Gold","rank":2,"near_amount":"100","tokens":[{"token_contract_id":"ft.near","token_amount":"50"}]}]}"#
+        );
+    }
+}
@@ -3,21 +3,22 @@ use std::convert::TryInto;
 use linkdrop::LINKDROP_DEPOSIT;
 use near_contract_standards::non_fungible_token::{
     metadata::{NFTContractMetadata, TokenMetadata, NFT_METADATA_SPEC},
-    refund_deposit_to_account, NonFungibleToken, Token, TokenId,
+    NonFungibleToken, Token, TokenId,
 };
 use near_sdk::{assert_one_yocto, serde_json::json};
 use near_sdk::{
     borsh::{self, BorshDeserialize, BorshSerialize},
-    collections::{LazyOption, LookupMap, UnorderedSet},
+    collections::{LazyOption, LookupMap, UnorderedMap, UnorderedSet, Vector},
     env::{self},
     ext_contract,
-    json_types::{Base64VecU8, U128},
+    json_types::{Base58CryptoHash, Base64VecU8, U128, U64},
     log, near_bindgen, require,
     serde::{Deserialize, Serialize},
-    witgen, AccountId, Balance, BorshStorageKey, Gas, PanicOnDefault, Promise, PromiseOrValue,
-    PublicKey,
+    witgen, AccountId, Balance, BorshStorageKey, CryptoHash, Gas, PanicOnDefault, Promise,
+    PromiseOrValue, PublicKey,
 };
 use near_units::{parse_gas, parse_near};
+use std::collections::HashMap;
 
 /// milliseconds elapsed since the UNIX epoch
 #[witgen]
@@ -25,19 +26,41 @@ type TimestampMs = u64;
 
 pub mod event;
 pub use event::NearEvent;
+/// Event schema shared with the vault contract
+#[allow(dead_code)]
+#[path = "../../../../vault-contract/src/event.rs"]
+mod vault_event;
+use vault_event::VaultEvent;
 
+mod approval;
 pub mod linkdrop;
+mod lock;
+mod crowdfund;
+mod delegate;
+mod membership;
+mod metadata_template;
+mod mint_raffle;
 mod owner;
 pub mod payout;
+mod raffle;
+mod referral;
+mod router;
 mod standards;
+mod subscribers;
+mod treasury;
 mod types;
 mod util;
 mod views;
+mod voucher;
 
+use metadata_template::*;
+use raffle::Raffle;
 use payout::*;
+use referral::*;
 use standards::*;
+use treasury::*;
 use types::*;
-use util::{current_time_ms, is_promise_success, log_mint, refund};
+use util::{current_time_ms, is_promise_success, log_mint, refund, vault_account_id, vault_code_hash};
 
 #[derive(Serialize, Deserialize, BorshDeserialize, BorshSerialize, Clone)]
 #[serde(crate = "near_sdk::serde")]
@@ -56,8 +79,18 @@ pub struct Contract {
     pending_tokens: u32,
     /// Linkdrop fields will be removed once proxy contract is deployed
     pub accounts: LookupMap<PublicKey, bool>,
-    /// Whitelist
+    /// Balance each pending link was funded with
+    key_balances: LookupMap<PublicKey, Balance>,
+    /// Keys of pending links, for listing them
+    linkdrop_keys: UnorderedSet<PublicKey>,
+    /// Balance new links fund the claiming account with
+    linkdrop_balance: Balance,
+    /// Entries from before the presale whitelist and public allowances were split,
+    /// moved to the presale whitelist when updated or with `migrate_whitelist`
     whitelist: LookupMap<AccountId, Allowance>,
+    /// Presale tiers by name, and the tier of each member
+    tiers: UnorderedMap<String, Tier>,
+    tier_members: LookupMap<AccountId, String>,
 
     sale: Sale,
 
@@ -69,12 +102,159 @@ pub struct Contract {
     // NFT memberships
     signer_accounts: UnorderedSet<AccountId>,
     last_id: u64,
+
+    // Referral program
+    referral_config: Option<ReferralConfig>,
+    referral_earnings: LookupMap<AccountId, Balance>,
+
+    /// Per token range metadata, applied at mint and when viewing tokens
+    metadata_templates: Vector<MetadataTemplateRange>,
+
+    /// Revenue kept by the contract until the owner withdraws it
+    treasury: Treasury,
+
+    /// Fee configuration handed to every new vault
+    vault_fee_config: VaultFeeConfig,
+    /// Balance new vaults get on top of their storage
+    vault_funding_buffer: Balance,
+
+    /// Per account mint limit over time, with each account's recent mint times
+    mint_window: Option<MintWindow>,
+    recent_mints: LookupMap<AccountId, Vec<TimestampMs>>,
+
+    /// Nonce of the latest beneficiary update sent to each token's vault
+    vault_nonces: LookupMap<TokenId, u64>,
+
+    /// Tokens whose vault assets were released while the token was kept
+    redeemed: LookupMap<TokenId, bool>,
+    /// Funding state of each token's vault. None, means still pending
+    vault_status: LookupMap<TokenId, VaultStatus>,
+
+    /// Account with the same rights as the owner, e.g. for recovery
+    backup_owner: Option<AccountId>,
+    /// Proposed new owner, who has to accept before taking over
+    pending_owner: Option<AccountId>,
+
+    /// FT contracts vaults can be created with
+    accepted_tokens: UnorderedSet<AccountId>,
+
+    /// Keys accounts sign delegated mints with, and the nonce of their latest one
+    delegate_keys: LookupMap<AccountId, PublicKey>,
+    delegate_nonces: LookupMap<AccountId, u64>,
+
+    /// Fee for burning a token, none means burning is free
+    burn_fee: Option<BurnFee>,
+    /// NEAR each token's vault was created with, for bps burn fees
+    vault_near_amounts: LookupMap<TokenId, Balance>,
+
+    /// Tokens locked by their owner, e.g. as collateral
+    token_locks: LookupMap<TokenId, TokenLock>,
+
+    /// Account each token's vault was created under
+    vault_accounts: LookupMap<TokenId, AccountId>,
+
+    /// Presale whitelist, cleared after launch with `clear_presale_whitelist`
+    presale_whitelist: UnorderedMap<AccountId, Allowance>,
+    /// Public sale allowance of each account that minted, seeded from its presale allowance
+    public_allowances: LookupMap<AccountId, Allowance>,
+
+    /// Code each token's vault was deployed or last upgraded with
+    vault_codes: LookupMap<TokenId, VaultCode>,
+
+    /// Membership tiers by name, the tier of each token and the number of tokens in each
+    membership_tiers: UnorderedMap<String, MembershipTier>,
+    token_tiers: LookupMap<TokenId, String>,
+    membership_tier_supply: LookupMap<String, u64>,
+    /// Tier each token moves up to once its vault holds the tier's FTs
+    membership_upgrades: LookupMap<TokenId, String>,
+
+    /// Price oracle new vaults value their basket with, None means they don't
+    vault_oracle: Option<AccountId>,
+    /// Last valuation each token's vault reported
+    vault_valuations: LookupMap<TokenId, VaultValuation>,
+
+    /// Raffle of an oversubscribed mint, its entrants in entry order and each entry's status
+    mint_raffle: Option<MintRaffle>,
+    raffle_entrants: Vector<AccountId>,
+    raffle_entries: LookupMap<AccountId, RaffleEntryStatus>,
+    /// Entrant indexes not drawn yet, created by the first draw
+    raffle_draw: Option<Raffle>,
+    raffle_winners_drawn: u32,
+    /// ms since epoch before which a token's vault can't be released
+    vault_lockups: LookupMap<TokenId, TimestampMs>,
+    /// Media of tokens not shown with their own id's file, see `set_media_index`
+    token_media: LookupMap<TokenId, TokenMedia>,
+    /// Dual control new vaults are created with
+    vault_risk_control: Option<VaultRiskControl>,
+    /// Contracts notified of membership events, with whether they currently are
+    event_subscribers: UnorderedMap<AccountId, bool>,
+    /// Crowdfunding of vaults not funded yet, with the NEAR each account contributed
+    crowdfunds: LookupMap<TokenId, Crowdfund>,
+    crowdfund_shares: LookupMap<TokenId, HashMap<AccountId, Balance>>,
+    /// Discount of each voucher by the sha256 of its code, and the vouchers left per discount
+    vouchers: LookupMap<CryptoHash, BasisPoint>,
+    voucher_counts: UnorderedMap<BasisPoint, u64>,
+    /// NEAR set aside for the balances of new links, kept out of `withdraw`
+    linkdrop_pool: Balance,
+    /// Releases requested from the vaults of burned or redeemed tokens, see `restage_vault_release`
+    vault_releases: LookupMap<TokenId, VaultRelease>,
+    /// Tickets of raffle entries not claimed or refunded yet, kept out of `withdraw`
+    raffle_escrow: Balance,
+    /// Contributions not deposited to a vault or reclaimed yet, kept out of `withdraw`
+    crowdfund_escrow: Balance,
+    /// Balances of links not claimed yet, the sum of `key_balances`, kept out of `withdraw`
+    linkdrop_escrow: Balance,
+    /// Burned tokens whose vault release wasn't sent yet, see `continue_vault_releases`
+    release_queue: Vector<TokenId>,
+    /// Most tokens that can ever be minted, None leaves the supply open
+    max_supply: Option<u64>,
+    /// Raffle winners that claimed their mint or took their ticket back
+    raffle_winners_settled: u32,
+    /// wNEAR contract new vaults take in place of the NEAR deposit, None means they only take NEAR
+    vault_wrap_near: Option<AccountId>,
+}
+
+/// State layout of contracts deployed before referrals, see `migrate`
+#[derive(BorshDeserialize)]
+struct ContractV1 {
+    tokens: NonFungibleToken,
+    metadata: LazyOption<NFTContractMetadata>,
+    pending_tokens: u32,
+    accounts: LookupMap<PublicKey, bool>,
+    whitelist: LookupMap<AccountId, Allowance>,
+    sale: Sale,
+    admins: UnorderedSet<AccountId>,
+    media_extension: Option<String>,
+    signer_accounts: UnorderedSet<AccountId>,
+    last_id: u64,
 }
 
 const GAS_REQUIRED_FOR_LINKDROP: Gas = Gas(parse_gas!("40 Tgas") as u64);
 const GAS_REQUIRED_TO_CREATE_LINKDROP: Gas = Gas(parse_gas!("20 Tgas") as u64);
-const TECH_BACKUP_OWNER: &str = "willem.near";
+const GAS_FOR_SET_BENEFICIARY: Gas = Gas(parse_gas!("10 Tgas") as u64);
+const GAS_FOR_ON_BENEFICIARY_SET: Gas = Gas(parse_gas!("5 Tgas") as u64);
+const GAS_FOR_VAULT_UPGRADE: Gas = Gas(parse_gas!("150 Tgas") as u64);
+const GAS_FOR_VAULT_SKIM: Gas = Gas(parse_gas!("100 Tgas") as u64);
+const GAS_FOR_VAULT_RELEASE: Gas = Gas(parse_gas!("200 Tgas") as u64);
+/// Burning itself, on top of the gas handed to the vault release
+const GAS_FOR_BURN: Gas = Gas(parse_gas!("30 Tgas") as u64);
+/// The vault's `new` reports a vault without deposits to wait for as funded right away
+const GAS_FOR_VAULT_NEW: Gas = Gas(parse_gas!("25 Tgas") as u64);
+const GAS_FOR_ON_VAULT_CREATED: Gas = Gas(parse_gas!("10 Tgas") as u64);
+const GAS_FOR_ON_VAULT_UPGRADED: Gas = Gas(parse_gas!("10 Tgas") as u64);
+/// Scheduling one vault upgrade, on top of the gas handed to the vault and the callback
+const GAS_FOR_UPGRADE_STEP: Gas = Gas(parse_gas!("5 Tgas") as u64);
+/// Sending one queued vault release, on top of the gas handed to the vault
+const GAS_FOR_RELEASE_STEP: Gas = Gas(parse_gas!("5 Tgas") as u64);
+/// Topping up a vault, which forwards its fee on the NEAR added
+const GAS_FOR_VAULT_TOP_UP: Gas = Gas(parse_gas!("30 Tgas") as u64);
+const GAS_FOR_ON_VAULT_TOPPED_UP: Gas = Gas(parse_gas!("10 Tgas") as u64);
 const MAX_DATE: u64 = 8640000000000000;
+const VAULT_CODE: &[u8] = include_bytes!("../../../../wasm/liquid_nft_vault.wasm");
+/// Account record and vault state, which grows with the number of deposits
+const VAULT_STATE_BYTES: u64 = 2_000;
+/// Balance left to the vault on top of its storage, e.g. to register receivers on FT contracts
+const DEFAULT_VAULT_FUNDING_BUFFER: Balance = parse_near!("100 mN");
 // const GAS_REQUIRED_FOR_LINKDROP_CALL: Gas = Gas(5_000_000_000_000);
 
 #[ext_contract(ext_self)]
@@ -86,9 +266,28 @@ trait Linkdrop {
         gas_required: Gas,
     ) -> Promise;
 
-    fn on_send_with_callback(&mut self) -> Promise;
+    fn on_send_with_callback(&mut self, link_balance: U128) -> Promise;
 
     fn link_callback(&mut self, account_id: AccountId, mint_for_free: bool) -> Token;
+
+    fn on_vault_created(&mut self, token_id: TokenId, vault_id: AccountId, funding: U128) -> bool;
+
+    fn on_vault_upgraded(&mut self, token_id: TokenId, code_hash: String) -> bool;
+
+    fn on_beneficiary_set(&mut self, token_id: TokenId, nonce: u64) -> bool;
+
+    fn on_deposit_routed(&mut self, amount: U128) -> U128;
+
+    fn on_vault_topped_up(
+        &mut self,
+        token_id: TokenId,
+        owner_id: AccountId,
+        deposit: U128,
+        near_amount: U128,
+        tokens_required: bool,
+    ) -> bool;
+
+    fn on_crowdfund_deposited(&mut self, token_id: TokenId) -> bool;
 }
 
 #[derive(BorshSerialize, BorshStorageKey)]
@@ -102,6 +301,42 @@ enum StorageKey {
     Whitelist,
     Admins,
     SignerAccounts,
+    ReferralEarnings,
+    LinkdropKeyBalances,
+    MetadataTemplates,
+    RecentMints,
+    VaultNonces,
+    Redeemed,
+    AcceptedTokens,
+    LinkdropKeySet,
+    Tiers,
+    TierMembers,
+    VaultStatus,
+    DelegateKeys,
+    DelegateNonces,
+    VaultNearAmounts,
+    TokenLocks,
+    VaultAccounts,
+    PresaleWhitelist,
+    PublicAllowances,
+    VaultCodes,
+    MembershipTiers,
+    TokenTiers,
+    MembershipTierSupply,
+    MembershipUpgrades,
+    VaultValuations,
+    RaffleEntrants,
+    RaffleEntries,
+    RaffleDraw,
+    VaultLockups,
+    TokenMedia,
+    EventSubscribers,
+    Crowdfunds,
+    CrowdfundShares,
+    Vouchers,
+    VoucherCounts,
+    VaultReleases,
+    ReleaseQueue,
 }
 
 #[near_bindgen]
@@ -148,7 +383,7 @@ impl Contract {
                 "media extension must not start with '.'"
             );
         }
-        Self {
+        Self::from_v1(ContractV1 {
             tokens: NonFungibleToken::new(
                 StorageKey::NonFungibleToken,
                 owner_id,
@@ -165,12 +400,103 @@ impl Contract {
             media_extension,
             signer_accounts: UnorderedSet::new(StorageKey::SignerAccounts),
             last_id: 0,
+        })
+    }
+
+    /// Called after deploying new code, moves the state of a contract deployed before
+    /// referrals to the current layout. Everything added since starts at its default, its
+    /// whitelist is moved to the presale whitelist in batches with `migrate_whitelist`.
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let old: ContractV1 =
+            env::state_read().unwrap_or_else(|| env::panic_str("Contract state not found"));
+        Self::from_v1(old)
+    }
+
+    /// Contract holding the state of `old`, with the state added since at its default
+    fn from_v1(old: ContractV1) -> Self {
+        Self {
+            tokens: old.tokens,
+            metadata: old.metadata,
+            pending_tokens: old.pending_tokens,
+            accounts: old.accounts,
+            key_balances: LookupMap::new(StorageKey::LinkdropKeyBalances),
+            linkdrop_keys: UnorderedSet::new(StorageKey::LinkdropKeySet),
+            linkdrop_balance: LINKDROP_DEPOSIT,
+            whitelist: old.whitelist,
+            tiers: UnorderedMap::new(StorageKey::Tiers),
+            tier_members: LookupMap::new(StorageKey::TierMembers),
+            sale: old.sale,
+            admins: old.admins,
+            media_extension: old.media_extension,
+            signer_accounts: old.signer_accounts,
+            last_id: old.last_id,
+            referral_config: None,
+            referral_earnings: LookupMap::new(StorageKey::ReferralEarnings),
+            metadata_templates: Vector::new(StorageKey::MetadataTemplates),
+            treasury: Treasury::default(),
+            vault_fee_config: VaultFeeConfig::default(),
+            vault_funding_buffer: DEFAULT_VAULT_FUNDING_BUFFER,
+            mint_window: None,
+            recent_mints: LookupMap::new(StorageKey::RecentMints),
+            vault_nonces: LookupMap::new(StorageKey::VaultNonces),
+            redeemed: LookupMap::new(StorageKey::Redeemed),
+            vault_status: LookupMap::new(StorageKey::VaultStatus),
+            backup_owner: None,
+            pending_owner: None,
+            accepted_tokens: UnorderedSet::new(StorageKey::AcceptedTokens),
+            delegate_keys: LookupMap::new(StorageKey::DelegateKeys),
+            delegate_nonces: LookupMap::new(StorageKey::DelegateNonces),
+            burn_fee: None,
+            vault_near_amounts: LookupMap::new(StorageKey::VaultNearAmounts),
+            token_locks: LookupMap::new(StorageKey::TokenLocks),
+            vault_accounts: LookupMap::new(StorageKey::VaultAccounts),
+            presale_whitelist: UnorderedMap::new(StorageKey::PresaleWhitelist),
+            public_allowances: LookupMap::new(StorageKey::PublicAllowances),
+            vault_codes: LookupMap::new(StorageKey::VaultCodes),
+            membership_tiers: UnorderedMap::new(StorageKey::MembershipTiers),
+            token_tiers: LookupMap::new(StorageKey::TokenTiers),
+            membership_tier_supply: LookupMap::new(StorageKey::MembershipTierSupply),
+            membership_upgrades: LookupMap::new(StorageKey::MembershipUpgrades),
+            vault_oracle: None,
+            vault_valuations: LookupMap::new(StorageKey::VaultValuations),
+            mint_raffle: None,
+            raffle_entrants: Vector::new(StorageKey::RaffleEntrants),
+            raffle_entries: LookupMap::new(StorageKey::RaffleEntries),
+            raffle_draw: None,
+            raffle_winners_drawn: 0,
+            vault_lockups: LookupMap::new(StorageKey::VaultLockups),
+            token_media: LookupMap::new(StorageKey::TokenMedia),
+            vault_risk_control: None,
+            event_subscribers: UnorderedMap::new(StorageKey::EventSubscribers),
+            crowdfunds: LookupMap::new(StorageKey::Crowdfunds),
+            crowdfund_shares: LookupMap::new(StorageKey::CrowdfundShares),
+            vouchers: LookupMap::new(StorageKey::Vouchers),
+            voucher_counts: UnorderedMap::new(StorageKey::VoucherCounts),
+            linkdrop_pool: 0,
+            vault_releases: LookupMap::new(StorageKey::VaultReleases),
+            raffle_escrow: 0,
+            crowdfund_escrow: 0,
+            linkdrop_escrow: 0,
+            release_queue: Vector::new(StorageKey::ReleaseQueue),
+            max_supply: None,
+            raffle_winners_settled: 0,
+            vault_wrap_near: None,
         }
     }
 
+    /// Attach `burn_fee` for the token, or 1 yN when there is none
     #[payable]
     pub fn nft_burn(&mut self, token_id: String) {
-        assert_one_yocto();
+        self.assert_not_locked(&token_id);
+        self.assert_vault_unlocked(&token_id);
+        require!(!self.is_crowdfunding(&token_id), "Token is waiting for its crowdfunding");
+        let burn_fee = self.burn_fee(token_id.clone()).0;
+        require!(
+            env::attached_deposit() == burn_fee.max(1),
+            format!("Requires attached deposit of exactly {} yN", burn_fee.max(1))
+        );
 
         let owner_id = self
             .tokens
@@ -179,60 +505,187 @@ impl Contract {
             .unwrap_or(AccountId::new_unchecked(String::from("testnet")));
         assert_eq!(owner_id, env::predecessor_account_id(), "Token owner only");
 
-        if let Some(next_approval_id_by_id) = &mut self.tokens.next_approval_id_by_id {
-            next_approval_id_by_id.remove(&token_id);
-        }
-
-        if let Some(approvals_by_id) = &mut self.tokens.approvals_by_id {
-            approvals_by_id.remove(&token_id);
+        if let Some(release) = self.internal_burn(&owner_id, &token_id, burn_fee) {
+            self.request_vault_release(&token_id, release);
         }
+        NearEvent::log_nft_burn(owner_id.to_string(), vec![token_id], None, None);
+    }
 
-        if let Some(tokens_per_owner) = &mut self.tokens.tokens_per_owner {
-            let mut token_ids = tokens_per_owner.get(&owner_id).unwrap();
-            token_ids.remove(&token_id);
-
-            // remove the owner if there are no more tokens
-            if token_ids.is_empty() {
-                tokens_per_owner.remove(&owner_id);
+    /// Burn several tokens of the caller. Attach at least the sum of their `burn_fee`, or 1 yN,
+    /// whatever isn't charged is refunded. Tokens that can't be burned, or don't fit in the
+    /// remaining gas, are reported back and left untouched. Vault releases are queued and sent
+    /// as the gas left allows, the rest with `continue_vault_releases`.
+    #[payable]
+    pub fn nft_burn_many(&mut self, token_ids: Vec<TokenId>) -> BurnManyResult {
+        let deposit = env::attached_deposit();
+        require!(deposit >= 1, "Requires attached deposit of at least 1 yN");
+        let owner_id = env::predecessor_account_id();
+        let mut burned = vec![];
+        let mut failed = vec![];
+        let mut charged: Balance = 0;
+
+        for token_id in token_ids {
+            let reason = if self.tokens.owner_by_id.get(&token_id).as_ref() != Some(&owner_id) {
+                Some("Token owner only")
+            } else if self.is_locked(token_id.clone()) {
+                Some("Token is locked")
+            } else if self.vault_lockup_remaining(token_id.clone()) > 0 {
+                Some("Vault is locked up")
+            } else if self.is_crowdfunding(&token_id) {
+                Some("Token is waiting for its crowdfunding")
+            } else if env::prepaid_gas() - env::used_gas() < GAS_FOR_BURN {
+                Some("Not enough gas left to burn")
             } else {
-                tokens_per_owner.insert(&owner_id, &token_ids);
+                None
+            };
+            if let Some(reason) = reason {
+                failed.push(BurnFailure {
+                    token_id,
+                    reason: reason.to_string(),
+                });
+                continue;
+            }
+            let burn_fee = self.burn_fee(token_id.clone()).0;
+            charged += burn_fee;
+            require!(
+                deposit >= charged,
+                format!("Requires attached deposit of at least {} yN", charged)
+            );
+            if let Some(release) = self.internal_burn(&owner_id, &token_id, burn_fee) {
+                self.vault_releases.insert(&token_id, &release);
+                self.release_queue.push(&token_id);
             }
+            burned.push(token_id);
         }
 
-        if let Some(token_metadata_by_id) = &mut self.tokens.token_metadata_by_id {
-            token_metadata_by_id.remove(&token_id);
+        if !burned.is_empty() {
+            NearEvent::log_nft_burn(owner_id.to_string(), burned.clone(), None, None);
+        }
+        refund(&owner_id, deposit - charged.max(1));
+        let releases_queued = self.send_queued_vault_releases(u32::MAX);
+        BurnManyResult {
+            burned,
+            failed,
+            releases_queued,
         }
+    }
 
-        self.tokens.owner_by_id.remove(&token_id);
+    /// Send up to `limit` vault releases queued by `nft_burn_many`, as many as the gas allows.
+    /// Anyone can call it, returns the number of releases still queued.
+    pub fn continue_vault_releases(&mut self, limit: Option<u32>) -> u32 {
+        let left = self.send_queued_vault_releases(limit.unwrap_or(u32::MAX));
+        log!("{} vault releases left in the queue", left);
+        left
+    }
 
-        let string_account_id = token_id.clone();
+    /// Release the vault assets to the token holder and keep the token as a receipt.
+    /// A token can only be redeemed once.
+    #[payable]
+    pub fn redeem_without_burn(&mut self, token_id: TokenId) {
+        assert_one_yocto();
+        self.assert_not_locked(&token_id);
+        self.assert_vault_unlocked(&token_id);
+        self.assert_not_crowdfunding(&token_id);
 
-        NearEvent::log_nft_burn(owner_id.to_string(), vec![token_id], None, None);
+        let owner_id = self
+            .tokens
+            .owner_by_id
+            .get(&token_id)
+            .unwrap_or_else(|| env::panic_str("Token not found"));
+        assert_eq!(owner_id, env::predecessor_account_id(), "Token owner only");
+        require!(!self.is_redeemed(token_id.clone()), "Token already redeemed");
+        self.redeemed.insert(&token_id, &true);
+        self.vault_valuations.remove(&token_id);
 
-        let subaccount_id = AccountId::new_unchecked(format!(
-            "{}.{}",
-            "vault_".to_string() + string_account_id.to_string().as_str(),
-            env::current_account_id()
-        ));
+        if let Some(token_metadata_by_id) = &mut self.tokens.token_metadata_by_id {
+            if let Some(mut metadata) = token_metadata_by_id.get(&token_id) {
+                metadata.extra = Some("redeemed".to_string());
+                token_metadata_by_id.insert(&token_id, &metadata);
+            }
+        }
 
-        Promise::new(subaccount_id).function_call(
-            "release".to_string(),
-            json!({ "owner_id": owner_id }).to_string().into_bytes(),
-            0.try_into().unwrap(),
-            Gas(200_000_000_000_000),
+        let nonce = self.vault_nonces.get(&token_id).unwrap_or(0);
+        log!("Redeeming vault of token {} to {}", token_id, owner_id);
+        self.notify_subscribers(MembershipEventKind::Redeem, &token_id, &owner_id);
+
+        self.request_vault_release(
+            &token_id,
+            VaultRelease {
+                vault_id: self.vault_id(&token_id),
+                owner_id,
+                nonce,
+            },
         );
     }
 
+    /// `lockup_until` keeps the vault from being released, by burning or redeeming the token,
+    /// before that ms timestamp.
+    /// A `crowdfunded` token leaves `near_amount` to contributors, see `contribute_to_vault`,
+    /// and can't be transferred until they raised it
     #[payable]
     pub fn nft_mint_one(
         &mut self,
         token_deposit: Vec<TokenDeposit>,
         near_amount: U128,
+        strategy_contract: Option<AccountId>,
+        referrer: Option<AccountId>,
+        lockup_until: Option<TimestampMs>,
+        crowdfunded: Option<bool>,
+    ) -> Vec<Token> {
+        require!(
+            lockup_until.map_or(true, |lockup_until| lockup_until > current_time_ms()),
+            "Lockup must end in the future"
+        );
+        let predecessor_id = env::predecessor_account_id();
+        self.internal_mint_one(
+            &predecessor_id,
+            token_deposit,
+            near_amount,
+            strategy_contract,
+            referrer,
+            lockup_until,
+            crowdfunded.unwrap_or(false),
+        )
+    }
+
+    /// Mint a membership on behalf of `receiver_id`. Only approved signer accounts
+    /// (e.g. a backend relaying meta-transactions) can call this and they pay the deposit.
+    #[payable]
+    pub fn nft_mint_for(
+        &mut self,
+        receiver_id: AccountId,
+        token_deposit: Vec<TokenDeposit>,
+        near_amount: U128,
+        strategy_contract: Option<AccountId>,
     ) -> Vec<Token> {
         require!(
-            env::attached_deposit() >= parse_near!("2"),
-            "You need to deposit 2N "
+            self.is_allowed_signer(&env::predecessor_account_id()),
+            "Method is private to signer accounts"
         );
+        self.internal_mint_one(&receiver_id, token_deposit, near_amount, strategy_contract, None, None, false)
+    }
+
+    fn internal_mint_one(
+        &mut self,
+        owner_id: &AccountId,
+        token_deposit: Vec<TokenDeposit>,
+        near_amount: U128,
+        strategy_contract: Option<AccountId>,
+        referrer: Option<AccountId>,
+        lockup_until: Option<TimestampMs>,
+        crowdfunded: bool,
+    ) -> Vec<Token> {
+        require!(
+            !crowdfunded || (near_amount.0 > 0 && token_deposit.is_empty()),
+            "Only a NEAR deposit can be crowdfunded"
+        );
+
+        for deposit in &token_deposit {
+            require!(
+                self.accepted_tokens.contains(&deposit.token_contract_id),
+                format!("Token {} is not accepted", deposit.token_contract_id)
+            );
+        }
 
         let num: u16 = 1;
 
@@ -240,39 +693,107 @@ impl Contract {
             require!(num <= limit, "over mint limit");
         }
 
-        let predecessor_id = &env::predecessor_account_id();
         let signer_id = &env::signer_account_id();
 
-        let num = self.assert_can_mint(predecessor_id, signer_id, num);
-        let tokens = self.nft_mint_many_ungaurded(num, predecessor_id, false);
-        self.use_whitelist_allowance(predecessor_id, num);
+        let num = self.assert_can_mint(owner_id, signer_id, num);
+        // the vault is funded out of the deposit, on top of the token's price
+        let vault_funding = self.vault_funding().0;
+        require!(
+            env::attached_deposit() >= self.total_cost(num, owner_id).0 + vault_funding,
+            format!("You need to deposit the price plus {} yN for the vault", vault_funding)
+        );
+        let referral = self.referral_reward(referrer, owner_id, num);
+        let reserved = vault_funding + referral.as_ref().map_or(0, |(_, reward)| *reward);
+        let tokens = self.nft_mint_many_ungaurded(num, owner_id, false, reserved);
+        self.use_whitelist_allowance(owner_id, num);
+        if let Some((referrer, reward)) = referral {
+            self.pay_referral(referrer, owner_id, reward);
+        }
+
+        let token_id = self.last_id.to_string();
+        self.set_initial_tier(&token_id, near_amount.0, &token_deposit);
+        if crowdfunded {
+            self.start_crowdfund(&token_id, near_amount.0);
+        }
+        self.create_vault(
+            &token_id,
+            owner_id,
+            token_deposit,
+            near_amount,
+            strategy_contract,
+            lockup_until,
+        );
 
-        let subaccount_id = AccountId::new_unchecked(format!(
-            "{}.{}",
-            "vault_".to_string() + self.last_id.clone().to_string().as_str(),
-            env::current_account_id()
-        ));
+        tokens
+    }
 
-        let nft_contract_owner_id: AccountId = env::current_account_id();
+    /// Deploy the vault of `token_id`, funded with `vault_funding` out of the attached deposit
+    fn create_vault(
+        &mut self,
+        token_id: &str,
+        owner_id: &AccountId,
+        token_deposit: Vec<TokenDeposit>,
+        near_amount: U128,
+        strategy_contract: Option<AccountId>,
+        lockup_until: Option<TimestampMs>,
+    ) -> Promise {
+        // the holder sends the tokens to the vault once it's created
+        require!(
+            token_deposit.iter().all(|deposit| !deposit.is_deposited),
+            "Tokens are deposited to the vault after minting"
+        );
+        let subaccount_id = vault_account_id(token_id);
+        self.vault_accounts.insert(&token_id.to_string(), &subaccount_id);
+        if near_amount.0 > 0 {
+            self.vault_near_amounts.insert(&token_id.to_string(), &near_amount.0);
+        }
+        if let Some(lockup_until) = lockup_until {
+            self.vault_lockups.insert(&token_id.to_string(), &lockup_until);
+        }
 
-        Promise::new(subaccount_id)
+        let nft_contract_owner_id: AccountId = env::current_account_id();
+        let vault_fee_recipient = self
+            .vault_fee_config
+            .fee_recipient
+            .clone()
+            .unwrap_or_else(|| nft_contract_owner_id.clone());
+
+        let funding = self.vault_funding();
+        // no access key, the signer can be a relayer or an admin rather than the holder;
+        // the vault's code only changes through its `upgrade`
+        Promise::new(subaccount_id.clone())
             .create_account()
-            .add_full_access_key(env::signer_account_pk())
-            .transfer(parse_near!("2"))
-            .deploy_contract(include_bytes!("../../../../wasm/liquid_nft_vault.wasm").to_vec())
+            .transfer(funding.0)
+            .deploy_contract(VAULT_CODE.to_vec())
             .function_call(
               "new".to_string(),
               json!({ 
                   "owner_id": nft_contract_owner_id, 
-                  "token_id": self.last_id.clone().to_string(), 
+                  "token_id": token_id, 
                   "token_deposit": token_deposit, 
                   "near_amount": near_amount, 
+                  "strategy_contract": strategy_contract,
+                  "wrap_near_contract": self.vault_wrap_near,
+                  "fee_recipient": vault_fee_recipient,
+                  "fee_bps": self.vault_fee_config.fee_bps,
+                  "beneficiary_id": owner_id,
+                  "code_hash": vault_code_hash(),
+                  "oracle_contract": self.vault_oracle,
+                  "lockup_until": lockup_until.map(U64),
+                  "risk_account": self.vault_risk_control.as_ref().map(|control| &control.risk_account),
+                  "dual_control_threshold": self.vault_risk_control.as_ref().map(|control| control.threshold),
               }).to_string().into_bytes(),
               0u8.try_into().unwrap(),
-              Gas(10_000_000_000_000)
-            );
-
-        tokens
+              GAS_FOR_VAULT_NEW
+            )
+            .then(ext_self::on_vault_created(
+                token_id.to_string(),
+                subaccount_id,
+                funding,
+                env::current_account_id(),
+                0,
+                GAS_FOR_ON_VAULT_CREATED,
+            ))
     }
 
     fn nft_mint_many_ungaurded(
@@ -280,6 +801,7 @@ impl Contract {
         num: u16,
         owner_id: &AccountId,
         mint_for_free: bool,
+        reserved: Balance,
     ) -> Vec<Token> {
         let initial_storage_usage = if mint_for_free {
             0
@@ -295,13 +817,22 @@ impl Contract {
         if !mint_for_free {
             let storage_used = env::storage_usage() - initial_storage_usage;
             if let Some(royalties) = &self.sale.initial_royalties {
-                // Keep enough funds to cover storage and split the rest as royalties
+                // Keep enough funds to cover storage and reserved payouts, split the rest as royalties
                 let storage_cost = env::storage_byte_cost() * storage_used as Balance;
-                let left_over_funds = env::attached_deposit() - storage_cost;
+                let left_over_funds = env::attached_deposit() - storage_cost - reserved;
                 royalties.send_funds(left_over_funds, &self.tokens.owner_id);
             } else {
-                // Keep enough funds to cover storage and send rest to contract owner
-                refund_deposit_to_account(storage_used, self.tokens.owner_id.clone());
+                // Keep enough funds to cover storage and reserved payouts, the rest is
+                // mint revenue the owner can withdraw
+                let storage_cost = env::storage_byte_cost() * storage_used as Balance;
+                require!(
+                    env::attached_deposit() >= storage_cost + reserved,
+                    "Must attach enough to cover storage"
+                );
+                self.record_revenue(
+                    RevenueSource::Mint,
+                    env::attached_deposit() - storage_cost - reserved,
+                );
             }
         }
         // Emit mint event log
@@ -313,9 +844,13 @@ impl Contract {
 
     #[private]
     #[payable]
-    pub fn on_send_with_callback(&mut self) {
-        if !is_promise_success(None) {
+    pub fn on_send_with_callback(&mut self, link_balance: U128) {
+        if is_promise_success(None) {
+            let fee = self.link_fee(&env::signer_account_id());
+            self.record_revenue(RevenueSource::Linkdrop, fee);
+        } else {
             self.pending_tokens -= 1;
+            self.linkdrop_pool += link_balance.0;
             let amount = env::attached_deposit();
             if amount > 0 {
                 refund(&env::signer_account_id(), amount);
@@ -328,13 +863,354 @@ impl Contract {
     pub fn link_callback(&mut self, account_id: AccountId, mint_for_free: bool) -> Token {
         if is_promise_success(None) {
             self.pending_tokens -= 1;
-            self.nft_mint_many_ungaurded(1, &account_id, mint_for_free)[0].clone()
+            self.nft_mint_many_ungaurded(1, &account_id, mint_for_free, 0)[0].clone()
         } else {
             env::panic_str("Promise before Linkdrop callback failed");
         }
     }
 
+    /// Whether the vault account of `token_id` was created. When it wasn't, e.g. because the
+    /// account already exists, the token is left without a vault and the signer, who paid
+    /// for it, gets the vault funding back.
+    #[private]
+    pub fn on_vault_created(&mut self, token_id: TokenId, vault_id: AccountId, funding: U128) -> bool {
+        if is_promise_success(None) {
+            self.vault_codes.insert(
+                &token_id,
+                &VaultCode {
+                    code_hash: String::from(&vault_code_hash()),
+                    status: VaultUpgradeStatus::Done,
+                },
+            );
+            VaultEvent::log_vault_created(token_id, vault_id);
+            return true;
+        }
+        log!("Could not create vault {} for token {}", vault_id, token_id);
+        // the token could have been burned in the meantime
+        if self.vault_accounts.get(&token_id).as_ref() == Some(&vault_id) {
+            self.vault_accounts.remove(&token_id);
+            self.vault_status.insert(&token_id, &VaultStatus::NoVault);
+            self.vault_near_amounts.remove(&token_id);
+            self.membership_upgrades.remove(&token_id);
+            self.fail_crowdfund(&token_id);
+        }
+        refund(&env::signer_account_id(), funding.0);
+        false
+    }
+
+    /// Record the outcome of a vault upgrade, returns whether it went through
+    #[private]
+    pub fn on_vault_upgraded(&mut self, token_id: TokenId, code_hash: String) -> bool {
+        let upgraded = is_promise_success(None);
+        let status = if upgraded {
+            VaultUpgradeStatus::Done
+        } else {
+            log!("Upgrade of the vault of token {} failed", token_id);
+            VaultUpgradeStatus::Failed
+        };
+        // the token could have been burned in the meantime
+        if self.vault_codes.get(&token_id).is_some() {
+            self.vault_codes.insert(&token_id, &VaultCode { code_hash, status });
+        }
+        upgraded
+    }
+
+    /// Whether the vault recorded the holder sent with `nonce`. When it didn't, and no later
+    /// update was sent, the nonce goes back to the one the vault has so releases keep matching
+    /// it; `sync_vault_beneficiary` sends the holder again.
+    #[private]
+    pub fn on_beneficiary_set(&mut self, token_id: TokenId, nonce: u64) -> bool {
+        if is_promise_success(None) {
+            return true;
+        }
+        log!("Vault of token {} didn't record its holder, call sync_vault_beneficiary", token_id);
+        if self.vault_nonces.get(&token_id) == Some(nonce) {
+            self.vault_nonces.insert(&token_id, &(nonce - 1));
+        }
+        false
+    }
+
+    /// Send the token's holder to its vault again, e.g. after an update failed
+    pub fn sync_vault_beneficiary(&mut self, token_id: TokenId) {
+        let owner_id = self
+            .tokens
+            .owner_by_id
+            .get(&token_id)
+            .unwrap_or_else(|| env::panic_str("Token not found"));
+        require!(self.releases_vault(&token_id), "Token has no vault");
+        self.notify_vault_beneficiary(&token_id, &owner_id);
+    }
+
+    /// Take one FT, or the wNEAR, out of the vault of the caller's token, leaving the other
+    /// assets in it. The vault takes a deposit of it again, until then the token is pending.
+    pub fn release_vault_asset(&mut self, token_id: TokenId, token_contract_id: AccountId) -> Promise {
+        let owner_id = self.assert_partial_vault_release(&token_id);
+        Promise::new(self.vault_id(&token_id)).function_call(
+            "release_asset".to_string(),
+            json!({ "receiver_id": owner_id, "token_contract_id": token_contract_id })
+                .to_string()
+                .into_bytes(),
+            0,
+            GAS_FOR_VAULT_RELEASE,
+        )
+    }
+
+    /// Take the NEAR deposit out of the vault of the caller's token, see `release_vault_asset`
+    pub fn release_vault_near(&mut self, token_id: TokenId) -> Promise {
+        let owner_id = self.assert_partial_vault_release(&token_id);
+        Promise::new(self.vault_id(&token_id)).function_call(
+            "release_near".to_string(),
+            json!({ "receiver_id": owner_id }).to_string().into_bytes(),
+            0,
+            GAS_FOR_VAULT_RELEASE,
+        )
+    }
+
+    /// Called by a token's vault when part of it was released and it waits for deposits again
+    pub fn on_vault_unfunded(&mut self, token_id: TokenId) {
+        require!(
+            env::predecessor_account_id() == self.vault_id(&token_id),
+            "Only the token's vault can report its funding"
+        );
+        // the token could have been burned in the meantime
+        if self.tokens.owner_by_id.contains_key(&token_id) {
+            self.vault_status.insert(&token_id, &VaultStatus::Pending);
+        }
+    }
+
+    /// Called by a token's vault once every deposit is in
+    pub fn on_vault_funded(&mut self, token_id: TokenId) {
+        require!(
+            env::predecessor_account_id() == self.vault_id(&token_id),
+            "Only the token's vault can report its funding"
+        );
+        // the token could have been burned in the meantime
+        if self.tokens.owner_by_id.contains_key(&token_id) {
+            self.vault_status.insert(&token_id, &VaultStatus::Funded);
+            if let Some(tier) = self.membership_upgrades.remove(&token_id) {
+                self.set_token_tier(&token_id, tier);
+            }
+        }
+    }
+
+    /// Called by a token's vault with the valuation of its basket, see `vault_value`
+    pub fn on_vault_valued(&mut self, token_id: TokenId, valuation: VaultValuation) {
+        require!(
+            env::predecessor_account_id() == self.vault_id(&token_id),
+            "Only the token's vault can report its valuation"
+        );
+        if self.tokens.owner_by_id.contains_key(&token_id) {
+            self.vault_valuations.insert(&token_id, &valuation);
+        }
+    }
+
     // Private methods
+
+    /// Token with its vault account, funding state and redeemed flag
+    fn token_details(&self, token: Token) -> TokenDetails {
+        let vault_status = self
+            .vault_status
+            .get(&token.token_id)
+            .unwrap_or(VaultStatus::Pending);
+        TokenDetails {
+            vault_account_id: (vault_status != VaultStatus::NoVault)
+                .then(|| self.vault_id(&token.token_id)),
+            vault_status,
+            redeemed: self.is_redeemed(token.token_id.clone()),
+            token,
+        }
+    }
+
+    /// Remove a token, record its burn fee and release its vault to `owner_id`
+    /// Returns the release to request from the token's vault, if it has one to release
+    fn internal_burn(
+        &mut self,
+        owner_id: &AccountId,
+        token_id: &TokenId,
+        burn_fee: Balance,
+    ) -> Option<VaultRelease> {
+        if let Some(next_approval_id_by_id) = &mut self.tokens.next_approval_id_by_id {
+            next_approval_id_by_id.remove(token_id);
+        }
+
+        if let Some(approvals_by_id) = &mut self.tokens.approvals_by_id {
+            approvals_by_id.remove(token_id);
+        }
+
+        if let Some(tokens_per_owner) = &mut self.tokens.tokens_per_owner {
+            let mut token_ids = tokens_per_owner.get(owner_id).unwrap();
+            token_ids.remove(token_id);
+
+            // remove the owner if there are no more tokens
+            if token_ids.is_empty() {
+                tokens_per_owner.remove(owner_id);
+            } else {
+                tokens_per_owner.insert(owner_id, &token_ids);
+            }
+        }
+
+        if let Some(token_metadata_by_id) = &mut self.tokens.token_metadata_by_id {
+            token_metadata_by_id.remove(token_id);
+        }
+
+        self.tokens.owner_by_id.remove(token_id);
+
+        let subaccount_id = self.vault_id(token_id);
+        self.vault_accounts.remove(token_id);
+        let nonce = self.vault_nonces.remove(token_id).unwrap_or(0);
+        // a redeemed token's vault is already gone
+        let redeemed = self.redeemed.remove(token_id).unwrap_or(false);
+        let vault_status = self.vault_status.remove(token_id);
+        self.vault_near_amounts.remove(token_id);
+        self.vault_codes.remove(token_id);
+        self.token_locks.remove(token_id);
+        self.remove_token_tier(token_id);
+        self.membership_upgrades.remove(token_id);
+        self.vault_valuations.remove(token_id);
+        self.vault_lockups.remove(token_id);
+        self.token_media.remove(token_id);
+        self.remove_crowdfund(token_id);
+        if burn_fee > 0 {
+            self.record_revenue(RevenueSource::BurnFees, burn_fee);
+        }
+        self.notify_subscribers(MembershipEventKind::Burn, token_id, owner_id);
+
+        (!redeemed && vault_status != Some(VaultStatus::NoVault)).then(|| VaultRelease {
+            vault_id: subaccount_id,
+            owner_id: owner_id.clone(),
+            nonce,
+        })
+    }
+
+    /// Ask the vault to release to the holder of a token that was burned or redeemed,
+    /// remembering the request so it can be sent again
+    pub(crate) fn request_vault_release(
+        &mut self,
+        token_id: &TokenId,
+        release: VaultRelease,
+    ) -> Promise {
+        self.vault_releases.insert(token_id, &release);
+        Promise::new(release.vault_id).function_call(
+            "release".to_string(),
+            json!({ "owner_id": release.owner_id, "nonce": release.nonce })
+                .to_string()
+                .into_bytes(),
+            0,
+            GAS_FOR_VAULT_RELEASE,
+        )
+    }
+
+    /// Send queued vault releases while the gas allows, returns the number still queued
+    fn send_queued_vault_releases(&mut self, limit: u32) -> u32 {
+        let mut sent = 0;
+        while sent < limit
+            && env::prepaid_gas() - env::used_gas() >= GAS_FOR_VAULT_RELEASE + GAS_FOR_RELEASE_STEP
+        {
+            let token_id = match self.release_queue.pop() {
+                Some(token_id) => token_id,
+                None => break,
+            };
+            if let Some(release) = self.vault_releases.get(&token_id) {
+                self.request_vault_release(&token_id, release);
+            }
+            sent += 1;
+        }
+        self.release_queue.len() as u32
+    }
+
+    fn assert_vault_unlocked(&self, token_id: &TokenId) {
+        require!(
+            self.vault_lockup_remaining(token_id.clone()) == 0,
+            "Vault is locked up"
+        );
+    }
+
+    /// Only the holder of an unlocked token takes assets out of its vault, returns the holder
+    fn assert_partial_vault_release(&self, token_id: &TokenId) -> AccountId {
+        self.assert_not_locked(token_id);
+        let owner_id = self
+            .tokens
+            .owner_by_id
+            .get(token_id)
+            .unwrap_or_else(|| env::panic_str("Token not found"));
+        require!(owner_id == env::predecessor_account_id(), "Token owner only");
+        require!(self.releases_vault(token_id), "Token has no vault");
+        self.assert_vault_unlocked(token_id);
+        owner_id
+    }
+
+    /// Whether burning `token_id` releases a vault
+    fn releases_vault(&self, token_id: &TokenId) -> bool {
+        !self.is_redeemed(token_id.clone()) && self.vault_status.get(token_id) != Some(VaultStatus::NoVault)
+    }
+
+    /// Send `code` to the vault of `token_id`, its status is pending until the vault confirmed it
+    fn internal_upgrade_vault(&mut self, token_id: &TokenId, code: Base64VecU8, code_hash: String) -> Promise {
+        self.vault_codes.insert(
+            token_id,
+            &VaultCode {
+                code_hash: code_hash.clone(),
+                status: VaultUpgradeStatus::Pending,
+            },
+        );
+        Promise::new(self.vault_id(token_id))
+            .function_call(
+                "upgrade".to_string(),
+                json!({ "code": code }).to_string().into_bytes(),
+                0,
+                GAS_FOR_VAULT_UPGRADE,
+            )
+            .then(ext_self::on_vault_upgraded(
+                token_id.clone(),
+                code_hash,
+                env::current_account_id(),
+                0,
+                GAS_FOR_ON_VAULT_UPGRADED,
+            ))
+    }
+
+    /// Account of the vault of `token_id`. Vaults created before the registry are under
+    /// the derived name
+    pub(crate) fn vault_id(&self, token_id: &str) -> AccountId {
+        self.vault_accounts
+            .get(&token_id.to_string())
+            .unwrap_or_else(|| vault_account_id(token_id))
+    }
+
+    /// Token set of an account, straight from `tokens_per_owner` without enumerating it
+    fn member_tokens(&self, account_id: &AccountId) -> Option<UnorderedSet<TokenId>> {
+        self.tokens.tokens_per_owner.as_ref()?.get(account_id)
+    }
+
+    /// Let the token's vault know who would receive its assets on burn
+    fn notify_vault_beneficiary(&mut self, token_id: &TokenId, beneficiary_id: &AccountId) {
+        if self.is_redeemed(token_id.clone()) {
+            return;
+        }
+        let vault_id = self.vault_id(token_id);
+        let nonce = self.vault_nonces.get(token_id).unwrap_or(0) + 1;
+        self.vault_nonces.insert(token_id, &nonce);
+        NearEvent::log_vault_beneficiary_update(
+            token_id.to_string(),
+            vault_id.to_string(),
+            beneficiary_id.to_string(),
+        );
+        Promise::new(vault_id)
+            .function_call(
+                "set_beneficiary".to_string(),
+                json!({ "beneficiary_id": beneficiary_id, "nonce": nonce }).to_string().into_bytes(),
+                0,
+                GAS_FOR_SET_BENEFICIARY,
+            )
+            .then(ext_self::on_beneficiary_set(
+                token_id.clone(),
+                nonce,
+                env::current_account_id(),
+                0,
+                GAS_FOR_ON_BENEFICIARY_SET,
+            ));
+    }
+
     fn assert_deposit(&self, num: u16, account_id: &AccountId) {
         require!(
             env::attached_deposit() >= self.total_cost(num, account_id).0,
@@ -347,14 +1223,26 @@ impl Contract {
         // Check quantity
         // Owner can mint for free
         if !self.is_owner(account_id) {
-            let allowance = match self.get_status() {
+            let allowance = match self.sale_status() {
                 Status::SoldOut => env::panic_str("No NFTs left to mint"),
                 Status::Closed => env::panic_str("Contract currently closed"),
-                Status::Presale => self.get_whitelist_allowance(account_id).left(),
-                Status::Open => self.get_or_add_whitelist_allowance(account_id, num),
+                Status::Presale => {
+                    self.assert_tier_active(account_id);
+                    self.get_whitelist_allowance(account_id).left()
+                }
+                Status::Open => self.get_or_add_public_allowance(account_id, num),
             };
             num = u16::min(allowance, num);
             require!(num > 0, "Account has no more allowance left");
+            if let Some(remaining) = self.mints_remaining_in_window(account_id) {
+                num = u16::min(remaining, num);
+                require!(num > 0, "Mint limit reached for this time window");
+                self.record_recent_mints(account_id, num);
+            }
+        }
+        if let Some(left) = self.tokens_left() {
+            require!(left > 0, "No NFTs left to mint");
+            num = u16::min(num, left.min(u16::MAX as u64) as u16);
         }
         self.assert_deposit(num, account_id);
         num
@@ -369,7 +1257,7 @@ impl Contract {
     }
 
     fn is_owner(&self, minter: &AccountId) -> bool {
-        minter.as_str() == self.tokens.owner_id.as_str() || minter.as_str() == TECH_BACKUP_OWNER
+        minter == &self.tokens.owner_id || self.backup_owner.as_ref() == Some(minter)
     }
 
     fn assert_owner_or_admin(&self) {
@@ -404,13 +1292,13 @@ impl Contract {
         self.admins.contains(account_id)
     }
 
-    fn full_link_price(&self, minter: &AccountId) -> u128 {
-        LINKDROP_DEPOSIT
-            + if self.is_owner(minter) {
-                parse_near!("0 mN")
-            } else {
-                parse_near!("8 mN")
-            }
+    /// Fee for creating a link, its balance comes out of the linkdrop pool
+    fn link_fee(&self, minter: &AccountId) -> u128 {
+        if self.is_owner(minter) {
+            parse_near!("0 mN")
+        } else {
+            parse_near!("8 mN")
+        }
     }
 
     fn draw_and_mint(&mut self, token_owner_id: AccountId, refund: Option<AccountId>) -> Token {
@@ -426,67 +1314,137 @@ impl Contract {
         refund_id: Option<AccountId>,
     ) -> Token {
         let token_metadata = Some(self.create_metadata(&token_id));
+        self.notify_subscribers(MembershipEventKind::Mint, &token_id, &token_owner_id);
         self.tokens
             .internal_mint_with_refund(token_id, token_owner_id, token_metadata, refund_id)
     }
 
     fn create_metadata(&mut self, token_id: &String) -> TokenMetadata {
         let title = Some(token_id.to_string());
-        let media = Some(format!("1.png"));
+        let media = Some(self.media_path(token_id));
         let issued_at = Some(env::block_timestamp().to_string());
 
-        TokenMetadata {
+        let mut metadata = TokenMetadata {
             title,
             media,
             issued_at,
             reference: None,      // URL to an off-chain JSON file with more info.
             description: None,    // free-form description
-            media_hash: None, // Base64-encoded sha256 hash of content referenced by the `media` field. Required if `media` is included.
+            media_hash: self.media_hash(token_id), // Base64-encoded sha256 hash of content referenced by the `media` field. Required if `media` is included.
             copies: None, // number of copies of this set of metadata in existence when token was minted.
             expires_at: None, // ISO 8601 datetime when token expires
             starts_at: None, // ISO 8601 datetime when token starts being valid
             updated_at: None, // ISO 8601 datetime when token was last updated
             extra: None, // anything extra the NFT wants to store on-chain. Can be stringified JSON.
             reference_hash: None, // Base64-encoded sha256 hash of JSON from reference field. Required if `reference` is included.
+        };
+        if let Some(template) = self.metadata_template_for(token_id) {
+            template.apply(token_id, &mut metadata);
+        }
+        metadata
+    }
+
+    /// Mint times of `account_id` still inside the current window
+    fn recent_mints_in_window(&self, account_id: &AccountId, window: &MintWindow) -> Vec<TimestampMs> {
+        let window_start = current_time_ms().saturating_sub(window.window_ms);
+        self.recent_mints
+            .get(account_id)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|minted_at| *minted_at > window_start)
+            .collect()
+    }
+
+    fn record_recent_mints(&mut self, account_id: &AccountId, num: u16) {
+        if let Some(window) = self.mint_window.clone() {
+            let mut recent_mints = self.recent_mints_in_window(account_id, &window);
+            let now = current_time_ms();
+            recent_mints.extend((0..num).map(|_| now));
+            self.recent_mints.insert(account_id, &recent_mints);
         }
     }
 
     fn use_whitelist_allowance(&mut self, account_id: &AccountId, num: u16) {
         if self.has_allowance() && !self.is_owner(account_id) {
-            let mut allowance = self.get_whitelist_allowance(account_id);
-            allowance.use_num(num);
-            self.whitelist.insert(account_id, &allowance);
+            if self.is_presale() {
+                let mut allowance = self.get_whitelist_allowance(account_id);
+                allowance.use_num(num);
+                self.insert_presale_allowance(account_id, &allowance);
+            } else {
+                let mut allowance = self
+                    .public_allowance(account_id)
+                    .unwrap_or_else(|| env::panic_str("Account has no public allowance"));
+                allowance.use_num(num);
+                self.public_allowances.insert(account_id, &allowance);
+            }
         }
     }
 
     fn get_whitelist_allowance(&self, account_id: &AccountId) -> Allowance {
-        self.whitelist
-            .get(account_id)
+        self.presale_allowance(account_id)
             .unwrap_or_else(|| panic!("Account not on whitelist"))
     }
 
-    fn get_or_add_whitelist_allowance(&mut self, account_id: &AccountId, num: u16) -> u16 {
+    fn get_or_add_public_allowance(&mut self, account_id: &AccountId, num: u16) -> u16 {
         // return num if allowance isn't set
         self.sale.allowance.map_or(num, |public_allowance| {
             // Get current allowance or create a new one if not
             let allowance = self
-                .whitelist
-                .get(account_id)
+                .public_allowance(account_id)
                 .unwrap_or_else(|| Allowance::new(public_allowance))
                 .raise_max(public_allowance);
-            self.whitelist.insert(account_id, &allowance);
+            self.public_allowances.insert(account_id, &allowance);
             allowance.left()
         })
     }
+
+    /// Presale whitelist entry of an account, including one from before the split
+    fn presale_allowance(&self, account_id: &AccountId) -> Option<Allowance> {
+        self.presale_whitelist
+            .get(account_id)
+            .or_else(|| self.whitelist.get(account_id))
+    }
+
+    /// Public sale allowance of an account, what it minted in the presale counts
+    /// until it mints in the public sale
+    fn public_allowance(&self, account_id: &AccountId) -> Option<Allowance> {
+        self.public_allowances
+            .get(account_id)
+            .or_else(|| self.presale_allowance(account_id))
+    }
+
+    fn insert_presale_allowance(&mut self, account_id: &AccountId, allowance: &Allowance) {
+        self.presale_whitelist.insert(account_id, allowance);
+        self.whitelist.remove(account_id);
+    }
+    /// Tier `account_id` is a member of, if the tier still exists
+    fn tier_of(&self, account_id: &AccountId) -> Option<Tier> {
+        self.tier_members
+            .get(account_id)
+            .and_then(|name| self.tiers.get(&name))
+    }
+
+    fn assert_tier_active(&self, account_id: &AccountId) {
+        if let Some(tier) = self.tier_of(account_id) {
+            require!(
+                tier.is_active(current_time_ms()),
+                format!("Tier {} can't mint at this time", tier.name)
+            );
+        }
+    }
+
     fn has_allowance(&self) -> bool {
         self.sale.allowance.is_some() || self.is_presale()
     }
 
     fn is_presale(&self) -> bool {
-        matches!(self.get_status(), Status::Presale)
+        matches!(self.sale_status(), Status::Presale)
     }
 
-    fn get_status(&self) -> Status {
+    fn sale_status(&self) -> Status {
+        if self.tokens_left() == Some(0) {
+            return Status::SoldOut;
+        }
         let current_time = current_time_ms();
         match (self.sale.presale_start, self.sale.public_sale_start) {
             (_, Some(public)) if public < current_time => Status::Open,
@@ -495,9 +1453,29 @@ impl Contract {
         }
     }
 
-    fn price(&self) -> u128 {
-        match self.get_status() {
-            Status::Presale | Status::Closed => self.sale.presale_price.unwrap_or(self.sale.price),
+    /// Tokens that can still be minted, besides those pending in links or held back for raffle
+    /// winners. None when the supply is open
+    fn tokens_left(&self) -> Option<u64> {
+        let max_supply = self.max_supply?;
+        Some(max_supply.saturating_sub(self.minted_or_reserved()))
+    }
+
+    fn minted_or_reserved(&self) -> u64 {
+        self.last_id + self.pending_tokens as u64 + self.raffle_reserved_supply()
+    }
+
+    fn assert_supply_left(&self, num: u64) {
+        require!(
+            self.tokens_left().map_or(true, |left| left >= num),
+            "No NFTs left to mint"
+        );
+    }
+
+    fn price(&self, minter: &AccountId) -> u128 {
+        match self.sale_status() {
+            Status::Presale | Status::Closed => self
+                .tier_of(minter)
+                .map_or_else(|| self.sale.presale_price.unwrap_or(self.sale.price), |tier| tier.price),
             Status::Open | Status::SoldOut => self.sale.price,
         }
         .into()
@@ -1,13 +1,15 @@
 use crate::*;
 use near_sdk::{
-    env, ext_contract, json_types::U128, log, near_bindgen, AccountId, Balance, Gas, Promise,
-    PublicKey,
+    env, ext_contract,
+    json_types::{U128, U64},
+    log, near_bindgen, AccountId, Balance, Gas, Promise, PublicKey,
 };
 use near_units::parse_near;
 
 /// 0.064311394105062020653824 N
 pub(crate) const ACCESS_KEY_ALLOWANCE: u128 = parse_near!("0 N");
 
+/// Default, and minimum, balance a claimed link funds the receiving account with
 pub(crate) const LINKDROP_DEPOSIT: u128 = parse_near!("0.02 N");
 /// can take 0.5 of access key since gas required is 6.6 times what was actually used
 const ON_CREATE_ACCOUNT_GAS: Gas = Gas(30_000_000_000_000);
@@ -16,10 +18,46 @@ const NO_DEPOSIT: Balance = 0;
 /// Gas attached to the callback from account creation.
 pub const ON_CREATE_ACCOUNT_CALLBACK_GAS: Gas = Gas(10_000_000_000_000);
 
+/// Registration of a link's key, for claim pages to validate a link before claiming
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[witgen]
+pub struct KeyInfo {
+    pub registered: bool,
+    pub mint_for_free: bool,
+    /// Balance the claiming account is funded with
+    pub balance: U128,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[witgen]
+pub struct LinkdropKey {
+    pub public_key: PublicKey,
+    pub mint_for_free: bool,
+    pub balance: U128,
+}
+
+/// Breakdown of what funding one link costs
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[witgen]
+pub struct DropCost {
+    /// Balance the claiming account is funded with, out of the linkdrop pool
+    pub link_balance: U128,
+    /// Fee kept by the contract for creating the link
+    pub link_fee: U128,
+    /// Price of the token minted on claim
+    pub token_price: U128,
+    /// Storage of the token minted on claim
+    pub token_storage: U128,
+    pub total: U128,
+}
+
 #[ext_contract(ext_linkdrop)]
 trait ExtLinkdrop {
     fn create_account(&mut self, new_account_id: AccountId, new_public_key: PublicKey) -> Promise;
-    fn on_create_and_claim(&mut self, mint_for_free: bool) -> bool;
+    fn on_create_and_claim(&mut self, mint_for_free: bool, balance: U128) -> bool;
 }
 
 #[near_bindgen]
@@ -31,9 +69,9 @@ impl Contract {
     #[private]
     pub fn claim(&mut self, account_id: AccountId) -> Promise {
         // require!(false, "Cannot claim at this time try again later");
-        let (mint_for_free, deletion_promise) = self.delete_current_access_key();
+        let (mint_for_free, balance, deletion_promise) = self.delete_current_access_key();
         deletion_promise
-            .then(Promise::new(account_id.clone()).transfer(LINKDROP_DEPOSIT))
+            .then(Promise::new(account_id.clone()).transfer(balance))
             .then(ext_self::link_callback(
                 account_id.clone(),
                 mint_for_free,
@@ -43,13 +81,14 @@ impl Contract {
             ))
             .then(ext_linkdrop::on_create_and_claim(
                 mint_for_free,
+                balance.into(),
                 env::current_account_id(),
                 NO_DEPOSIT,
                 ON_CREATE_ACCOUNT_CALLBACK_GAS,
             ))
     }
 
-    /// Create new account and and claim tokens to it.
+    /// Create new account, funded with the link's balance, and claim tokens to it.
     #[private]
     pub fn create_account_and_claim(
         &mut self,
@@ -57,9 +96,9 @@ impl Contract {
         new_public_key: PublicKey,
     ) -> Promise {
         // require!(false, "Cannot claim at this time try again later");
-        let (mint_for_free, deletion_promise) = self.delete_current_access_key();
+        let (mint_for_free, balance, deletion_promise) = self.delete_current_access_key();
         deletion_promise
-            .and(self.create_account(new_account_id.clone(), new_public_key))
+            .and(self.create_account(new_account_id.clone(), new_public_key, balance))
             .then(ext_self::link_callback(
                 new_account_id.clone(),
                 mint_for_free,
@@ -69,26 +108,72 @@ impl Contract {
             ))
             .then(ext_linkdrop::on_create_and_claim(
                 mint_for_free,
+                balance.into(),
                 env::current_account_id(),
                 NO_DEPOSIT,
                 ON_CREATE_ACCOUNT_CALLBACK_GAS,
             ))
     }
 
-    /// Returns the balance associated with given key.
+    /// Returns the balance new links fund the claiming account with.
     #[allow(unused_variables)]
     pub fn get_key_balance(&self) -> U128 {
-        LINKDROP_DEPOSIT.into()
+        self.linkdrop_balance.into()
+    }
+
+    /// NEAR left to fund the balances of new links
+    pub fn linkdrop_pool_balance(&self) -> U128 {
+        self.linkdrop_pool.into()
+    }
+
+    /// Whether a key belongs to a pending link, and how it will be claimed
+    pub fn check_key(&self, public_key: PublicKey) -> KeyInfo {
+        let mint_for_free = self.accounts.get(&public_key);
+        KeyInfo {
+            registered: mint_for_free.is_some(),
+            mint_for_free: mint_for_free.unwrap_or(false),
+            balance: self.key_balance(&public_key).into(),
+        }
     }
 
-    pub fn check_key(&self, public_key: PublicKey) -> bool {
-        self.accounts.contains_key(&public_key)
+    /// Keys of pending links
+    pub fn get_linkdrop_keys(&self, from_index: Option<U64>, limit: Option<u64>) -> Vec<LinkdropKey> {
+        let start = from_index.map_or(0, u64::from);
+        let limit = limit.unwrap_or_else(|| self.linkdrop_keys.len());
+        self.linkdrop_keys
+            .iter()
+            .skip(start as usize)
+            .take(limit as usize)
+            .map(|public_key| LinkdropKey {
+                mint_for_free: self.accounts.get(&public_key).unwrap_or(false),
+                balance: self.key_balance(&public_key).into(),
+                public_key,
+            })
+            .collect()
+    }
+
+    /// What funding a link costs `minter`, see `cost_of_linkdrop` for the total
+    pub fn drop_cost(&self, minter: &AccountId) -> DropCost {
+        DropCost {
+            link_balance: self.linkdrop_balance.into(),
+            link_fee: self.link_fee(minter).into(),
+            token_price: self.total_cost(1, minter),
+            token_storage: self.token_storage_cost(),
+            total: self.cost_of_linkdrop(minter),
+        }
     }
 
+    /// A failed claim returns the link's balance to the pool, the link is then created again
+    /// when the pool can fund it
     #[private]
-    pub fn on_create_and_claim(&mut self, mint_for_free: bool) {
+    pub fn on_create_and_claim(&mut self, mint_for_free: bool, balance: U128) {
         if !is_promise_success(None) {
-            self.send(env::signer_account_pk(), mint_for_free);
+            self.linkdrop_pool += balance.0;
+            if self.linkdrop_pool >= self.linkdrop_balance {
+                self.send(env::signer_account_pk(), mint_for_free);
+            } else {
+                log!("Linkdrop pool can't fund the link again");
+            }
         }
     }
 
@@ -109,13 +194,18 @@ impl Contract {
     pub(crate) fn send(&mut self, public_key: PublicKey, mint_for_free: bool) -> Promise {
         self.add_key(public_key, mint_for_free)
     }
-    fn create_account(&self, new_account_id: AccountId, new_public_key: PublicKey) -> Promise {
+    fn create_account(
+        &self,
+        new_account_id: AccountId,
+        new_public_key: PublicKey,
+        balance: Balance,
+    ) -> Promise {
         log!("creating account for {}", &new_account_id);
         ext_linkdrop::create_account(
             new_account_id,
             new_public_key,
             self.get_linkdrop_contract(),
-            LINKDROP_DEPOSIT,
+            balance,
             ON_CREATE_ACCOUNT_GAS,
         )
     }
@@ -125,6 +215,14 @@ impl Contract {
         if self.accounts.insert(&key, &mint_for_free).is_some() {
             env::panic_str("key already added");
         }
+        require!(
+            self.linkdrop_pool >= self.linkdrop_balance,
+            "Linkdrop pool can't fund the link"
+        );
+        self.linkdrop_pool -= self.linkdrop_balance;
+        self.key_balances.insert(&key, &self.linkdrop_balance);
+        self.linkdrop_escrow += self.linkdrop_balance;
+        self.linkdrop_keys.insert(&key);
         Promise::new(env::current_account_id()).add_access_key(
             key,
             ACCESS_KEY_ALLOWANCE,
@@ -133,12 +231,31 @@ impl Contract {
         )
     }
 
-    fn delete_current_access_key(&mut self) -> (bool, Promise) {
+    /// Links created before balances were configurable were funded with the default
+    fn key_balance(&self, key: &PublicKey) -> Balance {
+        if self.accounts.contains_key(key) {
+            self.key_balances.get(key).unwrap_or(LINKDROP_DEPOSIT)
+        } else {
+            0
+        }
+    }
+
+    fn delete_current_access_key(&mut self) -> (bool, Balance, Promise) {
         let key = env::signer_account_pk();
         let mint_for_free = self.accounts.remove(&key);
         require!(mint_for_free.is_some(), "Can't use a full access key.");
+        // Links created before balances were configurable were funded with the default
+        let balance = match self.key_balances.remove(&key) {
+            Some(balance) => {
+                self.linkdrop_escrow -= balance;
+                balance
+            }
+            None => LINKDROP_DEPOSIT,
+        };
+        self.linkdrop_keys.remove(&key);
         (
             mint_for_free.unwrap(),
+            balance,
             Promise::new(env::current_account_id()).delete_key(key),
         )
     }
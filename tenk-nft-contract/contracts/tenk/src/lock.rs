@@ -0,0 +1,65 @@
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    /// Lock the caller's token for `locker`, e.g. a lending protocol holding it as collateral.
+    /// A locked token can't be transferred, burned or redeemed until the locker unlocks it
+    /// or `until` has passed.
+    #[payable]
+    pub fn lock(&mut self, token_id: TokenId, locker: AccountId, until: TimestampMs) -> bool {
+        assert_one_yocto();
+        let owner_id = self
+            .tokens
+            .owner_by_id
+            .get(&token_id)
+            .unwrap_or_else(|| env::panic_str("Token not found"));
+        require!(owner_id == env::predecessor_account_id(), "Token owner only");
+        require!(!self.is_locked(token_id.clone()), "Token is already locked");
+        require!(until > current_time_ms(), "Lock must end in the future");
+        self.token_locks.insert(
+            &token_id,
+            &TokenLock {
+                locker: locker.clone(),
+                until,
+            },
+        );
+        NearEvent::log_token_lock(token_id, locker.to_string(), until);
+        true
+    }
+
+    /// Release a lock before it expires, only the locker can
+    pub fn unlock(&mut self, token_id: TokenId) -> bool {
+        let lock = self
+            .token_locks
+            .get(&token_id)
+            .unwrap_or_else(|| env::panic_str("Token is not locked"));
+        require!(
+            lock.locker == env::predecessor_account_id(),
+            "Only the locker can unlock"
+        );
+        self.token_locks.remove(&token_id);
+        NearEvent::log_token_unlock(token_id, lock.locker.to_string());
+        true
+    }
+
+    pub fn is_locked(&self, token_id: TokenId) -> bool {
+        self.active_lock(&token_id).is_some()
+    }
+
+    /// The token's lock while it's in force
+    pub fn token_lock(&self, token_id: TokenId) -> Option<TokenLock> {
+        self.active_lock(&token_id)
+    }
+}
+
+impl Contract {
+    fn active_lock(&self, token_id: &TokenId) -> Option<TokenLock> {
+        self.token_locks
+            .get(token_id)
+            .filter(|lock| lock.until > current_time_ms())
+    }
+
+    pub(crate) fn assert_not_locked(&self, token_id: &TokenId) {
+        require!(self.active_lock(token_id).is_none(), "Token is locked");
+    }
+}
@@ -0,0 +1,242 @@
+use crate::*;
+use crate::event::{MembershipTierSetData, TierTokenData};
+
+#[near_bindgen]
+impl Contract {
+    /// Add or replace a membership tier. Tokens already in the tier keep it when its
+    /// requirements change, new tokens get the highest tier their vault holds once funded.
+    /// @allow ["::owner"]
+    pub fn set_membership_tier(&mut self, tier: MembershipTier) -> bool {
+        self.assert_owner();
+        tier.validate();
+        for token in &tier.tokens {
+            require!(
+                self.accepted_tokens.contains(&token.token_contract_id),
+                format!("Token {} is not accepted", token.token_contract_id)
+            );
+        }
+        require!(
+            self.membership_tiers
+                .values()
+                .all(|other| other.name == tier.name || other.rank != tier.rank),
+            "Another tier has this rank"
+        );
+        NearEvent::log_membership_tier_set(MembershipTierSetData {
+            name: tier.name.clone(),
+            rank: tier.rank,
+            near_amount: tier.near_amount.0.to_string(),
+            tokens: tier
+                .tokens
+                .iter()
+                .map(|token| TierTokenData {
+                    token_contract_id: token.token_contract_id.to_string(),
+                    token_amount: token.token_amount.0.to_string(),
+                })
+                .collect(),
+            media: tier.media.clone(),
+        });
+        self.membership_tiers.insert(&tier.name, &tier);
+        true
+    }
+
+    /// Remove a membership tier no token is in
+    /// @allow ["::owner"]
+    pub fn remove_membership_tier(&mut self, name: String) -> bool {
+        self.assert_owner();
+        require!(
+            self.membership_tier_supply.get(&name).unwrap_or(0) == 0,
+            "Tier still has tokens"
+        );
+        let removed = self.membership_tiers.remove(&name).is_some();
+        if removed {
+            NearEvent::log_membership_tier_remove(name);
+        }
+        removed
+    }
+
+    /// Move the caller's token up to `tier`. Attach the NEAR the tier adds to the vault plus the
+    /// vault's fee on it, see `membership_upgrade_cost` and the vault's `get_fee_quote`. When the
+    /// tier adds FTs, they are deposited into the vault afterwards and the token moves up once
+    /// the vault is funded. The deposit is refunded if the vault refuses the top up.
+    #[payable]
+    pub fn upgrade_membership(&mut self, token_id: TokenId, tier: String) -> Promise {
+        self.assert_not_locked(&token_id);
+        let owner_id = self
+            .tokens
+            .owner_by_id
+            .get(&token_id)
+            .unwrap_or_else(|| env::panic_str("Token not found"));
+        require!(owner_id == env::predecessor_account_id(), "Token owner only");
+        require!(self.releases_vault(&token_id), "Token has no vault");
+        require!(
+            self.membership_upgrades.get(&token_id).is_none(),
+            "Membership upgrade already in progress"
+        );
+        let cost = self.membership_upgrade_cost(token_id.clone(), tier.clone());
+        self.membership_upgrades.insert(&token_id, &tier);
+
+        let deposit = env::attached_deposit();
+        let tokens_required = !cost.tokens.is_empty();
+        let token_deposit: Vec<TokenDeposit> = cost
+            .tokens
+            .into_iter()
+            .map(|token| TokenDeposit {
+                token_contract_id: token.token_contract_id,
+                token_amount: token.token_amount,
+                is_deposited: false,
+            })
+            .collect();
+        Promise::new(self.vault_id(&token_id))
+            .function_call(
+                "top_up".to_string(),
+                json!({ "near_amount": cost.near_amount, "token_deposit": token_deposit })
+                    .to_string()
+                    .into_bytes(),
+                deposit,
+                GAS_FOR_VAULT_TOP_UP,
+            )
+            .then(ext_self::on_vault_topped_up(
+                token_id,
+                owner_id,
+                U128(deposit),
+                cost.near_amount,
+                tokens_required,
+                env::current_account_id(),
+                0,
+                GAS_FOR_ON_VAULT_TOPPED_UP,
+            ))
+    }
+
+    /// Move the token up right away when the tier adds no FTs, otherwise wait for the vault
+    /// to be funded. A top up the vault refused gives the deposit back.
+    #[private]
+    pub fn on_vault_topped_up(
+        &mut self,
+        token_id: TokenId,
+        owner_id: AccountId,
+        deposit: U128,
+        near_amount: U128,
+        tokens_required: bool,
+    ) -> bool {
+        if !is_promise_success(None) {
+            log!("Could not top up the vault of token {}", token_id);
+            self.membership_upgrades.remove(&token_id);
+            refund(&owner_id, deposit.0);
+            return false;
+        }
+        if near_amount.0 > 0 {
+            let vault_near_amount = self.vault_near_amounts.get(&token_id).unwrap_or(0);
+            self.vault_near_amounts
+                .insert(&token_id, &(vault_near_amount + near_amount.0));
+        }
+        if tokens_required {
+            self.vault_status.insert(&token_id, &VaultStatus::Pending);
+        } else if let Some(tier) = self.membership_upgrades.remove(&token_id) {
+            self.set_token_tier(&token_id, tier);
+        }
+        true
+    }
+
+    /// Membership tiers, lowest rank first
+    pub fn membership_tiers(&self) -> Vec<MembershipTier> {
+        let mut tiers = self.membership_tiers.values().collect::<Vec<_>>();
+        tiers.sort_by_key(|tier| tier.rank);
+        tiers
+    }
+
+    /// Membership tier of a token. None, means it isn't in any tier
+    pub fn token_membership_tier(&self, token_id: TokenId) -> Option<MembershipTier> {
+        self.membership_tier_of(&token_id)
+    }
+
+    /// Tier the token moves into once its vault holds the tier's assets, at mint or on upgrade
+    pub fn pending_membership_upgrade(&self, token_id: TokenId) -> Option<String> {
+        self.membership_upgrades.get(&token_id)
+    }
+
+    /// What moving the token up to `tier` adds to its vault
+    pub fn membership_upgrade_cost(&self, token_id: TokenId, tier: String) -> MembershipUpgradeCost {
+        let target = self
+            .membership_tiers
+            .get(&tier)
+            .unwrap_or_else(|| env::panic_str("Tier not found"));
+        let current = self.membership_tier_of(&token_id);
+        if let Some(current) = current.as_ref() {
+            require!(target.rank > current.rank, "Can only upgrade to a higher tier");
+        }
+        target.requirements_over(current.as_ref())
+    }
+
+    /// Number of tokens in each membership tier, lowest rank first
+    pub fn membership_tier_distribution(&self) -> Vec<TierSupply> {
+        self.membership_tiers()
+            .into_iter()
+            .map(|tier| TierSupply {
+                supply: self.membership_tier_supply.get(&tier.name).unwrap_or(0),
+                name: tier.name,
+                rank: tier.rank,
+            })
+            .collect()
+    }
+}
+
+impl Contract {
+    /// Tier of `token_id`, if the tier still exists
+    pub(crate) fn membership_tier_of(&self, token_id: &TokenId) -> Option<MembershipTier> {
+        self.token_tiers
+            .get(token_id)
+            .and_then(|name| self.membership_tiers.get(&name))
+    }
+
+    /// Highest tier a vault holding `near_amount` and the deposited `token_deposit` is in
+    pub(crate) fn membership_tier_held(
+        &self,
+        near_amount: Balance,
+        token_deposit: &[TokenDeposit],
+    ) -> Option<MembershipTier> {
+        self.membership_tiers
+            .values()
+            .filter(|tier| tier.is_held_by(near_amount, token_deposit))
+            .max_by_key(|tier| tier.rank)
+    }
+
+    /// Put a new token in the highest tier its vault holds once every deposit is in,
+    /// it moves into it when the vault reports being funded, see `on_vault_funded`
+    pub(crate) fn set_initial_tier(
+        &mut self,
+        token_id: &TokenId,
+        near_amount: Balance,
+        token_deposit: &[TokenDeposit],
+    ) {
+        let funded: Vec<TokenDeposit> = token_deposit
+            .iter()
+            .map(|deposit| TokenDeposit {
+                is_deposited: true,
+                ..deposit.clone()
+            })
+            .collect();
+        if let Some(tier) = self.membership_tier_held(near_amount, &funded) {
+            self.membership_upgrades.insert(token_id, &tier.name);
+        }
+    }
+
+    pub(crate) fn set_token_tier(&mut self, token_id: &TokenId, tier: String) {
+        let previous_tier = self.remove_token_tier(token_id);
+        let supply = self.membership_tier_supply.get(&tier).unwrap_or(0);
+        self.membership_tier_supply.insert(&tier, &(supply + 1));
+        self.token_tiers.insert(token_id, &tier);
+        NearEvent::log_membership_tier_update(token_id.clone(), tier, previous_tier);
+    }
+
+    /// Take the token out of its tier, returns the tier it was in
+    pub(crate) fn remove_token_tier(&mut self, token_id: &TokenId) -> Option<String> {
+        let tier = self.token_tiers.remove(token_id)?;
+        let supply = self.membership_tier_supply.get(&tier).unwrap_or(0);
+        if supply > 1 {
+            self.membership_tier_supply.insert(&tier, &(supply - 1));
+        } else {
+            self.membership_tier_supply.remove(&tier);
+        }
+        Some(tier)
+    }
+}
@@ -0,0 +1,173 @@
+use crate::*;
+
+/// Placeholder replaced with the token id when a template is rendered
+const ID_PLACEHOLDER: &str = "{id}";
+
+/// Metadata fields for a range of tokens, e.g. `"media": "gold/{id}.png"`
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[witgen]
+pub struct MetadataTemplate {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub media: Option<String>,
+    pub reference: Option<String>,
+}
+
+/// Template applied to token ids `range_start..=range_end`
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[witgen]
+pub struct MetadataTemplateRange {
+    pub range_start: u64,
+    pub range_end: u64,
+    pub template: MetadataTemplate,
+}
+
+/// Media file a token is shown with instead of the one of its id
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct TokenMedia {
+    pub index: u64,
+    pub media_hash: Option<Base64VecU8>,
+}
+
+impl MetadataTemplate {
+    fn render(field: &Option<String>, token_id: &str) -> Option<String> {
+        field
+            .as_ref()
+            .map(|value| value.replace(ID_PLACEHOLDER, token_id))
+    }
+
+    /// Overwrite the fields of `metadata` this template sets
+    pub(crate) fn apply(&self, token_id: &str, metadata: &mut TokenMetadata) {
+        if let Some(title) = Self::render(&self.title, token_id) {
+            metadata.title = Some(title);
+        }
+        if let Some(description) = Self::render(&self.description, token_id) {
+            metadata.description = Some(description);
+        }
+        if let Some(media) = Self::render(&self.media, token_id) {
+            metadata.media = Some(media);
+            metadata.media_hash = None;
+        }
+        if let Some(reference) = Self::render(&self.reference, token_id) {
+            metadata.reference = Some(reference);
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Set the metadata template for tokens `range_start..=range_end`.
+    /// Later templates take precedence over earlier overlapping ones.
+    /// @allow ["::admins", "::owner"]
+    pub fn set_metadata_template(
+        &mut self,
+        range_start: u64,
+        range_end: u64,
+        template: MetadataTemplate,
+    ) -> bool {
+        self.assert_owner_or_admin();
+        require!(range_start <= range_end, "range_start must not exceed range_end");
+        self.metadata_templates.push(&MetadataTemplateRange {
+            range_start,
+            range_end,
+            template,
+        });
+        true
+    }
+
+    /// Remove the template at `index`, see `metadata_templates`
+    /// @allow ["::admins", "::owner"]
+    pub fn remove_metadata_template(&mut self, index: u64) -> bool {
+        self.assert_owner_or_admin();
+        require!(index < self.metadata_templates.len(), "No template at index");
+        // keep order so precedence of the remaining templates is unchanged
+        let mut templates = self.metadata_templates.to_vec();
+        templates.remove(index as usize);
+        self.metadata_templates.clear();
+        templates
+            .iter()
+            .for_each(|template| self.metadata_templates.push(template));
+        true
+    }
+
+    /// All templates, in order of increasing precedence
+    pub fn metadata_templates(&self) -> Vec<MetadataTemplateRange> {
+        self.metadata_templates.to_vec()
+    }
+
+    /// Show `token_id` with media file `index` instead of the one of its id, e.g. to assign
+    /// shuffled media after a reveal or correct a token. `media_hash` is the base64 sha256
+    /// of the file. Templates setting `media` still take precedence.
+    /// @allow ["::owner"]
+    pub fn set_media_index(
+        &mut self,
+        token_id: TokenId,
+        index: u64,
+        media_hash: Option<Base64VecU8>,
+    ) -> bool {
+        self.assert_owner();
+        require!(
+            self.tokens.owner_by_id.get(&token_id).is_some(),
+            "Token not found"
+        );
+        self.token_media
+            .insert(&token_id, &TokenMedia { index, media_hash });
+        let media = self.media_path(&token_id);
+        let media_hash = self.media_hash(&token_id);
+        let template = self.metadata_template_for(&token_id);
+        if let Some(token_metadata_by_id) = &mut self.tokens.token_metadata_by_id {
+            if let Some(mut metadata) = token_metadata_by_id.get(&token_id) {
+                metadata.media = Some(media);
+                metadata.media_hash = media_hash;
+                if let Some(template) = template {
+                    template.apply(&token_id, &mut metadata);
+                }
+                token_metadata_by_id.insert(&token_id, &metadata);
+            }
+        }
+        true
+    }
+
+    /// Index of the media file shown for `token_id`, its id unless set with `set_media_index`
+    pub fn media_index(&self, token_id: TokenId) -> Option<u64> {
+        self.token_media_index(&token_id)
+    }
+}
+
+impl Contract {
+    fn token_media_index(&self, token_id: &str) -> Option<u64> {
+        self.token_media
+            .get(&token_id.to_string())
+            .map(|media| media.index)
+            .or_else(|| token_id.parse().ok())
+    }
+
+    /// `{media index}.{media_extension}`, the token id stands in for ids that aren't numbers
+    pub(crate) fn media_path(&self, token_id: &str) -> String {
+        let index = self
+            .token_media_index(token_id)
+            .map_or_else(|| token_id.to_string(), |index| index.to_string());
+        format!(
+            "{}.{}",
+            index,
+            self.media_extension.as_deref().unwrap_or("png")
+        )
+    }
+
+    pub(crate) fn media_hash(&self, token_id: &str) -> Option<Base64VecU8> {
+        self.token_media
+            .get(&token_id.to_string())
+            .and_then(|media| media.media_hash)
+    }
+
+    pub(crate) fn metadata_template_for(&self, token_id: &str) -> Option<MetadataTemplate> {
+        let id: u64 = token_id.parse().ok()?;
+        self.metadata_templates
+            .iter()
+            .filter(|range| range.range_start <= id && id <= range.range_end)
+            .last()
+            .map(|range| range.template)
+    }
+}
@@ -0,0 +1,223 @@
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    /// Raffle `raffle.winners` mints when demand exceeds supply. Accounts enter during the
+    /// window by escrowing the ticket price, winners are drawn once it closed. Their tokens
+    /// are held back from the supply until claimed or refunded. A contract holds one raffle.
+    /// @allow ["::owner"]
+    pub fn start_mint_raffle(&mut self, raffle: MintRaffle) -> bool {
+        self.assert_owner();
+        require!(self.mint_raffle.is_none(), "A mint raffle was already held");
+        require!(raffle.winners > 0, "Raffle needs at least one winner");
+        self.assert_supply_left(raffle.winners as u64);
+        require!(
+            raffle.starts_at < raffle.ends_at,
+            "Raffle must end after it starts"
+        );
+        require!(
+            raffle.ticket_price.0 >= self.vault_funding().0,
+            "Ticket price must cover the vault funding"
+        );
+        NearEvent::log_sale_update("mint_raffle", json!(raffle));
+        self.mint_raffle = Some(raffle);
+        true
+    }
+
+    /// Enter the raffle, attach the ticket price. It stays in escrow until the entry is
+    /// claimed or refunded, anything attached above it is returned.
+    #[payable]
+    pub fn enter_mint_raffle(&mut self) -> bool {
+        let raffle = self.current_raffle();
+        let now = current_time_ms();
+        require!(
+            raffle.starts_at <= now && now < raffle.ends_at,
+            "Raffle is not taking entries"
+        );
+        let account_id = env::predecessor_account_id();
+        require!(
+            self.raffle_entries.get(&account_id).is_none(),
+            "Account already entered"
+        );
+        let deposit = env::attached_deposit();
+        require!(
+            deposit >= raffle.ticket_price.0,
+            format!("Attach the ticket price of {} yN", raffle.ticket_price.0)
+        );
+        self.raffle_entries
+            .insert(&account_id, &RaffleEntryStatus::Entered);
+        self.raffle_entrants.push(&account_id);
+        self.raffle_escrow += raffle.ticket_price.0;
+        refund(&account_id, deposit - raffle.ticket_price.0);
+        true
+    }
+
+    /// Draw up to `limit` winners once entries closed, returns the number still to draw.
+    /// Winners are picked with the block's random seed, large raffles take several calls.
+    /// @allow ["::owner"]
+    pub fn draw_mint_raffle(&mut self, limit: u32) -> u32 {
+        self.assert_owner();
+        let raffle = self.current_raffle();
+        require!(
+            current_time_ms() >= raffle.ends_at,
+            "Raffle is still taking entries"
+        );
+        let mut draw = self
+            .raffle_draw
+            .take()
+            .unwrap_or_else(|| Raffle::new(StorageKey::RaffleDraw, self.raffle_entrants.len()));
+        let mut left = self.raffle_winners_left(&raffle);
+        for _ in 0..limit.min(left) {
+            let winner_id = self.raffle_entrants.get(draw.draw()).unwrap();
+            self.raffle_entries
+                .insert(&winner_id, &RaffleEntryStatus::Won);
+            self.raffle_winners_drawn += 1;
+            left -= 1;
+        }
+        self.raffle_draw = Some(draw);
+        log!(
+            "{} raffle winners drawn, {} left",
+            self.raffle_winners_drawn,
+            left
+        );
+        left
+    }
+
+    /// Mint the token the caller's entry won, paid for by the ticket. The vault is created for
+    /// `token_deposit` and `near_amount` like for `nft_mint_one`, they are deposited to it after.
+    pub fn claim_raffle_mint(
+        &mut self,
+        token_deposit: Vec<TokenDeposit>,
+        near_amount: U128,
+        strategy_contract: Option<AccountId>,
+    ) -> Token {
+        let raffle = self.current_raffle();
+        let owner_id = env::predecessor_account_id();
+        require!(
+            self.raffle_entries.get(&owner_id) == Some(RaffleEntryStatus::Won),
+            "No winning entry to claim"
+        );
+        for deposit in &token_deposit {
+            require!(
+                self.accepted_tokens.contains(&deposit.token_contract_id),
+                format!("Token {} is not accepted", deposit.token_contract_id)
+            );
+        }
+        self.raffle_entries
+            .insert(&owner_id, &RaffleEntryStatus::Claimed);
+        self.raffle_escrow -= raffle.ticket_price.0;
+        self.raffle_winners_settled += 1;
+
+        let initial_storage_usage = env::storage_usage();
+        let token = self.draw_and_mint(owner_id.clone(), None);
+        self.set_initial_tier(&token.token_id, near_amount.0, &token_deposit);
+        self.create_vault(
+            &token.token_id,
+            &owner_id,
+            token_deposit,
+            near_amount,
+            strategy_contract,
+            None,
+        );
+        let storage_cost =
+            env::storage_byte_cost() * (env::storage_usage() - initial_storage_usage) as Balance;
+        let costs = storage_cost + self.vault_funding().0;
+        require!(
+            raffle.ticket_price.0 >= costs,
+            "Ticket price doesn't cover the token's storage and vault"
+        );
+        self.record_revenue(RevenueSource::Mint, raffle.ticket_price.0 - costs);
+        log_mint(&owner_id, &[token.clone()]);
+        token
+    }
+
+    /// Refund the ticket of an entry that wasn't drawn, once every winner was. A winner
+    /// can take its ticket back too, when its mint can't be claimed.
+    pub fn withdraw_raffle_refund(&mut self) -> U128 {
+        let raffle = self.current_raffle();
+        let account_id = env::predecessor_account_id();
+        let status = self.raffle_entry(account_id.clone());
+        require!(
+            matches!(status, Some(RaffleEntryStatus::Lost | RaffleEntryStatus::Won)),
+            "No entry to refund"
+        );
+        if status == Some(RaffleEntryStatus::Won) {
+            self.raffle_winners_settled += 1;
+        }
+        self.raffle_entries
+            .insert(&account_id, &RaffleEntryStatus::Refunded);
+        self.raffle_escrow -= raffle.ticket_price.0;
+        refund(&account_id, raffle.ticket_price.0);
+        raffle.ticket_price
+    }
+
+    /// The mint raffle with its draw progress, None if none was started
+    pub fn mint_raffle(&self) -> Option<MintRaffleInfo> {
+        let raffle = self.mint_raffle.clone()?;
+        Some(MintRaffleInfo {
+            entries: self.raffle_entrants.len(),
+            winners_drawn: self.raffle_winners_drawn,
+            drawn: self.is_raffle_drawn(&raffle),
+            raffle,
+        })
+    }
+
+    /// Status of an account's entry, None if it didn't enter
+    pub fn raffle_entry(&self, account_id: AccountId) -> Option<RaffleEntryStatus> {
+        let status = self.raffle_entries.get(&account_id)?;
+        let drawn = self
+            .mint_raffle
+            .as_ref()
+            .map_or(false, |raffle| self.is_raffle_drawn(raffle));
+        Some(match status {
+            RaffleEntryStatus::Entered if drawn => RaffleEntryStatus::Lost,
+            status => status,
+        })
+    }
+
+    /// Entries in entry order with their status, paginated like `nft_tokens`
+    pub fn raffle_entries(&self, from_index: Option<u64>, limit: Option<u64>) -> Vec<RaffleEntry> {
+        self.raffle_entrants
+            .iter()
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit.map_or(usize::MAX, |limit| limit as usize))
+            .map(|account_id| RaffleEntry {
+                status: self.raffle_entry(account_id.clone()).unwrap(),
+                account_id,
+            })
+            .collect()
+    }
+}
+
+impl Contract {
+    fn current_raffle(&self) -> MintRaffle {
+        self.mint_raffle
+            .clone()
+            .unwrap_or_else(|| env::panic_str("No mint raffle"))
+    }
+
+    /// Winners still to draw, every entrant wins when there are fewer than `raffle.winners`
+    fn raffle_winners_left(&self, raffle: &MintRaffle) -> u32 {
+        let winners = (raffle.winners as u64).min(self.raffle_entrants.len()) as u32;
+        winners - self.raffle_winners_drawn
+    }
+
+    /// Tokens held back for raffle winners, drawn or not, that didn't claim or take their
+    /// ticket back yet. Once entries closed there are no more winners than entrants
+    pub(crate) fn raffle_reserved_supply(&self) -> u64 {
+        let raffle = match self.mint_raffle.as_ref() {
+            Some(raffle) => raffle,
+            None => return 0,
+        };
+        let winners = if current_time_ms() >= raffle.ends_at {
+            (raffle.winners as u64).min(self.raffle_entrants.len())
+        } else {
+            raffle.winners as u64
+        };
+        winners.saturating_sub(self.raffle_winners_settled as u64)
+    }
+
+    fn is_raffle_drawn(&self, raffle: &MintRaffle) -> bool {
+        current_time_ms() >= raffle.ends_at && self.raffle_winners_left(raffle) == 0
+    }
+}
@@ -4,21 +4,50 @@ use crate::*;
 impl Contract {
     // Owner private methods
 
-    /// @allow ["::admins", "::owner"]
-    pub fn transfer_ownership(&mut self, new_owner: AccountId) -> bool {
+    /// Propose a new owner, who then has to call `accept_ownership`.
+    /// Submit with no `new_owner` to cancel a proposal.
+    /// @allow ["::owner"]
+    pub fn propose_owner(&mut self, new_owner: Option<AccountId>) -> bool {
         self.assert_owner();
+        NearEvent::log_owner_update("pending_owner", new_owner.as_ref().map(ToString::to_string));
+        self.pending_owner = new_owner;
+        true
+    }
+
+    /// Complete an ownership transfer proposed with `propose_owner`
+    pub fn accept_ownership(&mut self) -> bool {
+        let new_owner = env::predecessor_account_id();
+        require!(
+            self.pending_owner.as_ref() == Some(&new_owner),
+            "Only the proposed owner can accept ownership"
+        );
         env::log_str(&format!(
             "{} transfers ownership to {}",
             self.tokens.owner_id, new_owner
         ));
+        NearEvent::log_owner_update("owner", Some(new_owner.to_string()));
+        self.pending_owner = None;
         self.tokens.owner_id = new_owner;
         true
     }
 
+    /// Set or remove (with no argument) the backup owner. Only the owner itself can.
+    /// @allow ["::owner"]
+    pub fn update_backup_owner(&mut self, backup_owner: Option<AccountId>) -> bool {
+        require!(
+            env::signer_account_id() == self.tokens.owner_id,
+            "Method is private to owner"
+        );
+        NearEvent::log_owner_update("backup_owner", backup_owner.as_ref().map(ToString::to_string));
+        self.backup_owner = backup_owner;
+        true
+    }
+
     /// @allow ["::admins", "::owner"]
     pub fn update_initial_royalties(&mut self, initial_royalties: Royalties) -> bool {
         self.assert_owner_or_admin();
         initial_royalties.validate();
+        NearEvent::log_sale_update("initial_royalties", json!(initial_royalties));
         self.sale.initial_royalties = Some(initial_royalties);
         true
     }
@@ -27,6 +56,7 @@ impl Contract {
     pub fn update_royalties(&mut self, royalties: Royalties) -> bool {
         self.assert_owner_or_admin();
         royalties.validate();
+        NearEvent::log_sale_update("royalties", json!(royalties));
         self.sale.royalties = Some(royalties);
         true
     }
@@ -38,6 +68,46 @@ impl Contract {
     pub fn update_allowance(&mut self, allowance: Option<u16>) -> bool {
         self.assert_owner_or_admin();
         self.sale.allowance = allowance;
+        NearEvent::log_sale_update("allowance", json!(allowance));
+        true
+    }
+
+    /// Max number of tokens that can be minted in one transaction.
+    /// When a limit isn't provided, it is unlimited.
+    /// @allow ["::admins", "::owner"]
+    pub fn update_mint_rate_limit(&mut self, mint_rate_limit: Option<u16>) -> bool {
+        self.assert_owner_or_admin();
+        self.sale.mint_rate_limit = mint_rate_limit;
+        self.sale.validate();
+        NearEvent::log_sale_update("mint_rate_limit", json!(mint_rate_limit));
+        true
+    }
+
+    /// Cap the number of tokens that can ever be minted, the sale is sold out once they were.
+    /// When a max supply isn't provided, it is unlimited. It can't be set below the tokens
+    /// minted, pending in links and held back for raffle winners.
+    /// @allow ["::owner"]
+    pub fn update_max_supply(&mut self, max_supply: Option<u64>) -> bool {
+        self.assert_owner();
+        require!(
+            max_supply.map_or(true, |max_supply| max_supply >= self.minted_or_reserved()),
+            "Max supply is below the tokens already minted or reserved"
+        );
+        self.max_supply = max_supply;
+        NearEvent::log_sale_update("max_supply", json!(max_supply));
+        true
+    }
+
+    /// Limit how many tokens one account can mint within a sliding time window.
+    /// When a window isn't provided, it is unlimited.
+    /// @allow ["::admins", "::owner"]
+    pub fn update_mint_window(&mut self, mint_window: Option<MintWindow>) -> bool {
+        self.assert_owner_or_admin();
+        if let Some(window) = mint_window.as_ref() {
+            window.validate();
+        }
+        NearEvent::log_sale_update("mint_window", json!(mint_window));
+        self.mint_window = mint_window;
         true
     }
 
@@ -47,10 +117,44 @@ impl Contract {
         let mut metadata = self.metadata.get().unwrap();
         log!("New URI: {}", &uri);
         metadata.base_uri = Some(uri);
-        self.metadata.set(&metadata);
+        self.set_contract_metadata(metadata, "base_uri");
+        true
+    }
+
+    /// Replace the contract metadata, e.g. to rebrand the collection.
+    /// @allow ["::owner"]
+    pub fn update_contract_metadata(&mut self, metadata: NFTContractMetadata) -> bool {
+        self.assert_owner();
+        self.set_contract_metadata(metadata, "metadata");
         true
     }
 
+    /// Point token media to another IPFS gateway. None, means media links are used as is
+    /// @allow ["::owner"]
+    pub fn update_base_uri(&mut self, base_uri: Option<String>) -> bool {
+        self.assert_owner();
+        let mut metadata = self.metadata.get().unwrap();
+        metadata.base_uri = base_uri;
+        self.set_contract_metadata(metadata, "base_uri");
+        true
+    }
+
+    /// Data URL of the collection's icon
+    /// @allow ["::owner"]
+    pub fn update_icon(&mut self, icon: Option<String>) -> bool {
+        self.assert_owner();
+        let mut metadata = self.metadata.get().unwrap();
+        metadata.icon = icon;
+        self.set_contract_metadata(metadata, "icon");
+        true
+    }
+
+    fn set_contract_metadata(&mut self, metadata: NFTContractMetadata, updated: &str) {
+        metadata.assert_valid();
+        self.metadata.set(&metadata);
+        NearEvent::log_contract_metadata_update(Some(updated.to_string()));
+    }
+
     /// Add whitelist accounts at a given max allowance
     /// @allow ["::admins", "::owner"]
     pub fn add_whitelist_accounts(
@@ -63,11 +167,10 @@ impl Contract {
         let max_allowance = max_allowance.unwrap_or_else(|| self.sale.allowance.unwrap_or(0));
         accounts.iter().for_each(|account_id| {
             let allowance = self
-                .whitelist
-                .get(account_id)
+                .presale_allowance(account_id)
                 .unwrap_or_else(|| Allowance::new(max_allowance))
                 .raise_max(max_allowance);
-            self.whitelist.insert(account_id, &allowance);
+            self.insert_presale_allowance(account_id, &allowance);
         });
         true
     }
@@ -76,9 +179,103 @@ impl Contract {
     /// @allow ["::admins", "::owner"]
     pub fn remove_whitelist_account(&mut self, account_id: AccountId) -> Option<u16> {
         self.assert_owner_or_admin();
-        self.whitelist.remove(&account_id).as_ref().map(Allowance::left)
+        self.tier_members.remove(&account_id);
+        let legacy = self.whitelist.remove(&account_id);
+        self.presale_whitelist
+            .remove(&account_id)
+            .or(legacy)
+            .as_ref()
+            .map(Allowance::left)
+    }
+
+    /// Remove up to `limit` accounts from the presale whitelist, along with their tier.
+    /// Public sale allowances are kept. Returns the number of accounts still on it.
+    /// @allow ["::owner"]
+    pub fn clear_presale_whitelist(&mut self, limit: u64) -> u64 {
+        self.assert_owner();
+        let accounts: Vec<AccountId> = self.presale_whitelist.keys().take(limit as usize).collect();
+        for account_id in &accounts {
+            self.presale_whitelist.remove(account_id);
+            self.tier_members.remove(account_id);
+        }
+        self.presale_whitelist.len()
+    }
+
+    /// Second step of `migrate`, moves whitelist entries from before the split to the
+    /// presale whitelist so `clear_presale_whitelist` can reach them. The old whitelist
+    /// can't be listed, the accounts are taken from the contract's whitelisting calls.
+    /// @allow ["::owner"]
+    pub fn migrate_whitelist(&mut self, accounts: Vec<AccountId>) -> bool {
+        self.assert_owner();
+        accounts.iter().for_each(|account_id| {
+            if let Some(allowance) = self.whitelist.remove(account_id) {
+                if self.presale_whitelist.get(account_id).is_none() {
+                    self.presale_whitelist.insert(account_id, &allowance);
+                }
+            }
+        });
+        true
+    }
+
+    /// Import entries exported with `export_whitelist`, e.g. on a new deployment. An account
+    /// already on the whitelist is overwritten, so importing a batch twice changes nothing.
+    /// Returns the number of accounts now on the presale whitelist.
+    /// @allow ["::owner"]
+    pub fn import_whitelist(&mut self, entries: Vec<WhitelistEntry>) -> u64 {
+        self.assert_owner();
+        for entry in entries {
+            if let Some(tier) = &entry.tier {
+                require!(self.tiers.get(tier).is_some(), format!("Tier {} not found", tier));
+                self.tier_members.insert(&entry.account_id, tier);
+            } else {
+                self.tier_members.remove(&entry.account_id);
+            }
+            self.insert_presale_allowance(
+                &entry.account_id,
+                &Allowance::with_used(entry.max, entry.used),
+            );
+        }
+        let imported = self.presale_whitelist.len();
+        log!("{} accounts on the presale whitelist", imported);
+        imported
+    }
+
+    /// Add or replace a presale tier
+    /// @allow ["::admins", "::owner"]
+    pub fn set_tier(&mut self, tier: Tier) -> bool {
+        self.assert_owner_or_admin();
+        tier.validate();
+        NearEvent::log_sale_update("tier", json!(tier));
+        self.tiers.insert(&tier.name, &tier);
+        true
     }
 
+    /// Remove a presale tier, its members fall back to the presale price
+    /// @allow ["::admins", "::owner"]
+    pub fn remove_tier(&mut self, name: String) -> bool {
+        self.assert_owner_or_admin();
+        self.tiers.remove(&name).is_some()
+    }
+
+    /// Whitelist accounts as members of a tier, at the tier's allowance
+    /// @allow ["::admins", "::owner"]
+    pub fn add_tier_accounts(&mut self, tier: String, accounts: Vec<AccountId>) -> bool {
+        self.assert_owner_or_admin();
+        let max_allowance = self
+            .tiers
+            .get(&tier)
+            .unwrap_or_else(|| env::panic_str("Tier not found"))
+            .allowance;
+        accounts.iter().for_each(|account_id| {
+            let allowance = self
+                .presale_allowance(account_id)
+                .unwrap_or_else(|| Allowance::new(max_allowance))
+                .raise_max(max_allowance);
+            self.insert_presale_allowance(account_id, &allowance);
+            self.tier_members.insert(account_id, &tier);
+        });
+        true
+    }
 
     /// Increases allowance for whitelist accounts
     /// @allow ["::admins", "::owner"]
@@ -89,9 +286,9 @@ impl Contract {
     ) -> bool {
         self.assert_owner_or_admin();
         accounts.iter().for_each(|account_id| {
-            if let Some(mut allowance) = self.whitelist.get(account_id) {
+            if let Some(mut allowance) = self.presale_allowance(account_id) {
                 allowance.increase_max(allowance_increase);
-                self.whitelist.insert(account_id, &allowance);
+                self.insert_presale_allowance(account_id, &allowance);
             } else {
                 log!("Account {} is not in whitelist", account_id);
             }
@@ -150,10 +347,92 @@ impl Contract {
         true
     }
 
+    /// Allow an account to mint on behalf of users with `nft_mint_for`.
+    /// @allow ["::admins", "::owner"]
     pub fn add_signer_account(&mut self, account_id: AccountId) -> bool {
-      self.assert_owner_or_admin();
-      self.signer_accounts.insert(&account_id);
-      true
+        self.assert_owner_or_admin();
+        self.signer_accounts.insert(&account_id);
+        true
+    }
+
+    /// Revoke an account's permission to mint on behalf of users.
+    /// @allow ["::admins", "::owner"]
+    pub fn remove_signer_account(&mut self, account_id: AccountId) -> bool {
+        self.assert_owner_or_admin();
+        self.signer_accounts.remove(&account_id)
+    }
+
+    /// Deploy new code to a token's vault, migrating its state.
+    /// @allow ["::owner"]
+    pub fn upgrade_vault(&mut self, token_id: TokenId, code: Base64VecU8) -> Promise {
+        self.assert_owner();
+        let hash: CryptoHash = env::sha256(&code.0).try_into().unwrap();
+        let code_hash = String::from(&Base58CryptoHash::from(hash));
+        self.internal_upgrade_vault(&token_id, code, code_hash)
+    }
+
+    /// Upgrade the vaults of tokens `from_id` onwards to the vault code this contract deploys,
+    /// as many as the gas allows and at most `limit`. Vaults already running or being upgraded
+    /// to it are skipped, so a run can be repeated. Returns the id to continue from, None once
+    /// every token was reached.
+    /// @allow ["::owner"]
+    pub fn upgrade_vaults(&mut self, from_id: u64, limit: u64) -> Option<u64> {
+        self.assert_owner();
+        let code_hash = String::from(&vault_code_hash());
+        let gas_per_vault = GAS_FOR_VAULT_UPGRADE + GAS_FOR_ON_VAULT_UPGRADED + GAS_FOR_UPGRADE_STEP;
+        let end = self.last_id.min(from_id.saturating_add(limit).saturating_sub(1));
+        let mut id = from_id.max(1);
+        while id <= end {
+            if env::prepaid_gas() - env::used_gas() < gas_per_vault {
+                log!("Out of gas, continue from token {}", id);
+                return Some(id);
+            }
+            let token_id = id.to_string();
+            let up_to_date = self.vault_codes.get(&token_id).map_or(false, |vault_code| {
+                vault_code.code_hash == code_hash && vault_code.status != VaultUpgradeStatus::Failed
+            });
+            let has_vault =
+                self.tokens.owner_by_id.contains_key(&token_id) && self.releases_vault(&token_id);
+            if has_vault && !up_to_date {
+                let code = Base64VecU8(VAULT_CODE.to_vec());
+                self.internal_upgrade_vault(&token_id, code, code_hash.clone());
+            }
+            id += 1;
+        }
+        (id <= self.last_id).then(|| id)
+    }
+
+    /// Send FTs stuck in a token's vault, above what it holds for its deposits, to `to`.
+    /// @allow ["::owner"]
+    pub fn skim_vault(
+        &mut self,
+        token_id: TokenId,
+        token_contract_id: AccountId,
+        to: AccountId,
+    ) -> Promise {
+        self.assert_owner();
+        Promise::new(self.vault_id(&token_id)).function_call(
+            "skim".to_string(),
+            json!({ "token_contract_id": token_contract_id, "to": to })
+                .to_string()
+                .into_bytes(),
+            0,
+            GAS_FOR_VAULT_SKIM,
+        )
+    }
+
+    /// Accept an FT contract in the deposits of new vaults.
+    /// @allow ["::admins", "::owner"]
+    pub fn add_accepted_token(&mut self, token_contract_id: AccountId) -> bool {
+        self.assert_owner_or_admin();
+        self.accepted_tokens.insert(&token_contract_id)
+    }
+
+    /// Stop accepting an FT contract, existing vaults are not affected.
+    /// @allow ["::admins", "::owner"]
+    pub fn remove_accepted_token(&mut self, token_contract_id: AccountId) -> bool {
+        self.assert_owner_or_admin();
+        self.accepted_tokens.remove(&token_contract_id)
     }
 
     /// Update public sale price.
@@ -162,6 +441,7 @@ impl Contract {
     pub fn update_price(&mut self, price: U128) -> bool {
         self.assert_owner_or_admin();
         self.sale.price = price;
+        NearEvent::log_sale_update("price", json!(price));
         true
     }
 
@@ -171,6 +451,7 @@ impl Contract {
     pub fn update_presale_price(&mut self, presale_price: Option<U128>) -> bool {
         self.assert_owner_or_admin();
         self.sale.presale_price = presale_price;
+        NearEvent::log_sale_update("presale_price", json!(presale_price));
         true
     }
 
@@ -180,6 +461,8 @@ impl Contract {
     pub fn update_presale_start(&mut self, presale_start: TimestampMs) -> bool {
         self.assert_owner_or_admin();
         self.sale.presale_start = Some(presale_start);
+        self.sale.validate();
+        NearEvent::log_sale_update("presale_start", json!(presale_start));
         true
     }
 
@@ -189,9 +472,174 @@ impl Contract {
     pub fn update_public_sale_start(&mut self, public_sale_start: TimestampMs) -> bool {
         self.assert_owner_or_admin();
         self.sale.public_sale_start = Some(public_sale_start);
+        self.sale.validate();
+        NearEvent::log_sale_update("public_sale_start", json!(public_sale_start));
         true
     }
 
+    /// Update the balance new links fund the claiming account with.
+    /// Must be at least the default 0.02N; links already created keep their balance.
+    /// @allow ["::admins", "::owner"]
+    pub fn update_linkdrop_balance(&mut self, balance: U128) -> bool {
+        self.assert_owner_or_admin();
+        require!(
+            balance.0 >= LINKDROP_DEPOSIT,
+            "linkdrop balance must cover account creation"
+        );
+        self.linkdrop_balance = balance.0;
+        true
+    }
+
+    /// Add the attached NEAR to the pool funding the balances of new links
+    #[payable]
+    pub fn fund_linkdrop_pool(&mut self) -> U128 {
+        let amount = env::attached_deposit();
+        require!(amount > 0, "Attach NEAR to fund the linkdrop pool");
+        self.linkdrop_pool += amount;
+        NearEvent::log_sale_update("linkdrop_pool", json!(U128(self.linkdrop_pool)));
+        self.linkdrop_pool.into()
+    }
+
+    /// Take NEAR out of the linkdrop pool, sent to the owner. Links already created keep
+    /// their balance.
+    /// @allow ["::owner"]
+    pub fn withdraw_linkdrop_pool(&mut self, amount: U128) -> Promise {
+        self.assert_owner();
+        require!(amount.0 > 0, "amount must be positive");
+        require!(
+            amount.0 <= self.linkdrop_pool,
+            "amount exceeds the linkdrop pool"
+        );
+        self.linkdrop_pool -= amount.0;
+        NearEvent::log_sale_update("linkdrop_pool", json!(U128(self.linkdrop_pool)));
+        Promise::new(self.tokens.owner_id.clone()).transfer(amount.0)
+    }
+
+    /// Balance new vaults get on top of the storage for their code and state.
+    /// @allow ["::owner"]
+    pub fn update_vault_funding_buffer(&mut self, buffer: U128) -> bool {
+        self.assert_owner();
+        NearEvent::log_sale_update("vault_funding_buffer", json!(buffer));
+        self.vault_funding_buffer = buffer.0;
+        true
+    }
+
+    /// Fee for burning a token, flat or a share of its vault's NEAR. None, makes burning free
+    /// @allow ["::owner"]
+    pub fn update_burn_fee(&mut self, burn_fee: Option<BurnFee>) -> bool {
+        self.assert_owner();
+        if let Some(burn_fee) = &burn_fee {
+            burn_fee.validate();
+        }
+        NearEvent::log_sale_update("burn_fee", json!(burn_fee));
+        self.burn_fee = burn_fee;
+        true
+    }
+
+    /// Update the fee charged by vaults created from now on, existing vaults keep theirs.
+    /// @allow ["::owner"]
+    pub fn update_vault_fee_config(&mut self, vault_fee_config: VaultFeeConfig) -> bool {
+        self.assert_owner();
+        vault_fee_config.validate();
+        NearEvent::log_sale_update("vault_fee_config", json!(vault_fee_config));
+        self.vault_fee_config = vault_fee_config;
+        true
+    }
+
+    /// Set the price oracle vaults created from now on value their basket with, existing
+    /// vaults keep theirs. None turns valuation off for new vaults.
+    /// @allow ["::owner"]
+    pub fn set_vault_oracle(&mut self, oracle_contract: Option<AccountId>) -> bool {
+        self.assert_owner();
+        NearEvent::log_sale_update("vault_oracle", json!(oracle_contract));
+        self.vault_oracle = oracle_contract;
+        true
+    }
+
+    /// Set the wNEAR contract vaults created from now on take in place of their NEAR deposit,
+    /// existing vaults keep theirs. None makes new vaults only take NEAR.
+    /// @allow ["::owner"]
+    pub fn set_vault_wrap_near(&mut self, wrap_near_contract: Option<AccountId>) -> bool {
+        self.assert_owner();
+        NearEvent::log_sale_update("vault_wrap_near", json!(wrap_near_contract));
+        self.vault_wrap_near = wrap_near_contract;
+        true
+    }
+
+    /// Make releases of vaults created from now on worth `threshold` or more wait for the
+    /// approval of `risk_account`, existing vaults keep theirs. None turns it off for new vaults.
+    /// @allow ["::owner"]
+    pub fn set_vault_risk_control(&mut self, risk_control: Option<VaultRiskControl>) -> bool {
+        self.assert_owner();
+        NearEvent::log_sale_update("vault_risk_control", json!(risk_control));
+        self.vault_risk_control = risk_control;
+        true
+    }
+
+    /// Send the release requested when `token_id` was burned or redeemed to its vault again,
+    /// for dual-control releases whose pending record expired before the risk account
+    /// approved it. Only tokens that were burned or redeemed have a release to send.
+    /// @allow ["::owner"]
+    pub fn restage_vault_release(&mut self, token_id: TokenId) -> Promise {
+        self.assert_owner();
+        let release = self
+            .vault_releases
+            .get(&token_id)
+            .unwrap_or_else(|| env::panic_str("Token wasn't burned or redeemed"));
+        self.request_vault_release(&token_id, release)
+    }
+
+    /// Mint one token to each receiver for free, e.g. for partnerships and promos.
+    /// The attached deposit covers storage and, with `create_vault`, `vault_funding` per NEAR-only vault.
+    /// @allow ["::admins", "::owner"]
+    #[payable]
+    pub fn nft_gift(&mut self, receivers: Vec<AccountId>, create_vault: Option<bool>) -> Vec<Token> {
+        self.assert_owner_or_admin();
+        require!(!receivers.is_empty(), "No receivers to gift to");
+        self.assert_supply_left(receivers.len() as u64);
+        let create_vault = create_vault.unwrap_or(false);
+        let initial_storage_usage = env::storage_usage();
+
+        let tokens: Vec<Token> = receivers
+            .iter()
+            .map(|receiver_id| {
+                let token = self.draw_and_mint(receiver_id.clone(), None);
+                if create_vault {
+                    self.create_vault(&token.token_id, receiver_id, vec![], U128(0), None, None);
+                } else {
+                    self.vault_status.insert(&token.token_id, &VaultStatus::NoVault);
+                }
+                token
+            })
+            .collect();
+
+        let storage_cost =
+            env::storage_byte_cost() * (env::storage_usage() - initial_storage_usage) as Balance;
+        let vault_cost = if create_vault {
+            self.vault_funding().0 * tokens.len() as Balance
+        } else {
+            0
+        };
+        let deposit = env::attached_deposit();
+        require!(
+            deposit >= storage_cost + vault_cost,
+            "Must attach enough to cover storage and vaults"
+        );
+        refund(&env::predecessor_account_id(), deposit - storage_cost - vault_cost);
+
+        NearEvent::log_nft_mints(
+            tokens
+                .iter()
+                .map(|token| event::NftMintData {
+                    owner_id: token.owner_id.to_string(),
+                    token_ids: vec![token.token_id.clone()],
+                    memo: Some("gift".to_string()),
+                })
+                .collect(),
+        );
+        tokens
+    }
+
     #[payable]
     /// Create a pending token that can be claimed with corresponding private key
     /// @allow ["::admins", "::owner"]
@@ -202,6 +650,7 @@ impl Contract {
         let signer = &env::signer_account_id();
         self.assert_can_mint(account, signer, 1);
         let total_cost = self.cost_of_linkdrop(account).0;
+        let link_balance = self.linkdrop_balance;
         self.pending_tokens += 1;
         let mint_for_free = self.is_owner(account);
         self.use_whitelist_allowance(account, 1);
@@ -209,6 +658,7 @@ impl Contract {
         refund(account, deposit - total_cost);
         self.send(public_key, mint_for_free)
             .then(ext_self::on_send_with_callback(
+                link_balance.into(),
                 env::current_account_id(),
                 total_cost,
                 GAS_REQUIRED_TO_CREATE_LINKDROP,
@@ -85,13 +85,40 @@ impl Payouts for Contract {
     }
 }
 
+#[near_bindgen]
+impl Contract {
+    /// Move the royalty share of `old_account` to `new_account`, in both royalties and initial royalties.
+    /// Callable by the payee itself or the owner. Shares are kept as they are.
+    pub fn update_royalty_account(&mut self, old_account: AccountId, new_account: AccountId) -> bool {
+        require!(
+            env::predecessor_account_id() == old_account || self.signer_is_owner(),
+            "Only the payee or the owner can update a royalty account"
+        );
+        let mut updated = false;
+        if let Some(royalties) = self.sale.royalties.as_mut() {
+            if royalties.replace_account(&old_account, &new_account) {
+                NearEvent::log_sale_update("royalties", json!(royalties));
+                updated = true;
+            }
+        }
+        if let Some(initial_royalties) = self.sale.initial_royalties.as_mut() {
+            if initial_royalties.replace_account(&old_account, &new_account) {
+                NearEvent::log_sale_update("initial_royalties", json!(initial_royalties));
+                updated = true;
+            }
+        }
+        require!(updated, "Account is not a royalty payee");
+        true
+    }
+}
+
 #[near_sdk::witgen]
-type BasisPoint = u16;
+pub type BasisPoint = u16;
 
-const ONE_HUNDRED_PERCENT_IN_BPS: BasisPoint = 10_000;
+pub(crate) const ONE_HUNDRED_PERCENT_IN_BPS: BasisPoint = 10_000;
 
 #[near_sdk::witgen]
-#[derive(BorshSerialize, BorshDeserialize, Deserialize, Serialize, Default)]
+#[derive(BorshSerialize, BorshDeserialize, Deserialize, Serialize, Default, Clone)]
 #[serde(crate = "near_sdk::serde")]
 pub struct Royalties {
     pub accounts: HashMap<AccountId, BasisPoint>,
@@ -121,6 +148,18 @@ impl Royalties {
             "total percent of each royalty split must equal 10,000"
         )
     }
+    /// Give the share of `old_account` to `new_account`, merged with its own share if it has one.
+    /// Returns false if `old_account` has no share
+    pub(crate) fn replace_account(&mut self, old_account: &AccountId, new_account: &AccountId) -> bool {
+        let share = match self.accounts.remove(old_account) {
+            Some(share) => share,
+            None => return false,
+        };
+        *self.accounts.entry(new_account.clone()).or_insert(0) += share;
+        self.validate();
+        true
+    }
+
     pub(crate) fn create_payout(&self, balance: Balance, owner_id: &AccountId) -> Payout {
         let royalty_payment = apply_percent(self.percent, balance);
         let mut payout = Payout {
@@ -147,7 +186,7 @@ impl Royalties {
     }
 }
 
-fn apply_percent(percent: BasisPoint, int: u128) -> u128 {
+pub(crate) fn apply_percent(percent: BasisPoint, int: u128) -> u128 {
     int * percent as u128 / 10_000u128
 }
 
@@ -0,0 +1,79 @@
+use crate::*;
+
+/// Share of the mint price paid out to whoever referred the minter
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[witgen]
+pub struct ReferralConfig {
+    /// Share of the mint price in basis points
+    pub bps: BasisPoint,
+    /// Most a referrer can earn from a single mint. None, means uncapped
+    pub max_reward: Option<U128>,
+}
+
+impl ReferralConfig {
+    pub fn validate(&self) {
+        require!(
+            self.bps <= ONE_HUNDRED_PERCENT_IN_BPS,
+            "referral bps must be between 0 - 10,000"
+        );
+    }
+
+    fn reward(&self, price: Balance) -> Balance {
+        let reward = apply_percent(self.bps, price);
+        self.max_reward
+            .map_or(reward, |max_reward| u128::min(reward, max_reward.0))
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Set or disable (with no argument) the referral program
+    /// @allow ["::owner"]
+    pub fn update_referral_config(&mut self, referral_config: Option<ReferralConfig>) -> bool {
+        self.assert_owner();
+        if let Some(config) = referral_config.as_ref() {
+            config.validate();
+        }
+        self.referral_config = referral_config;
+        true
+    }
+
+    pub fn referral_config(&self) -> Option<ReferralConfig> {
+        self.referral_config.clone()
+    }
+
+    /// Total yoctoNEAR paid out to an account for referring minters
+    pub fn referral_earnings(&self, account_id: AccountId) -> U128 {
+        self.referral_earnings.get(&account_id).unwrap_or(0).into()
+    }
+}
+
+impl Contract {
+    /// Reward owed to `referrer` for `minter` buying `num` tokens, if any
+    pub(crate) fn referral_reward(
+        &self,
+        referrer: Option<AccountId>,
+        minter: &AccountId,
+        num: u16,
+    ) -> Option<(AccountId, Balance)> {
+        let referrer = referrer.filter(|referrer| referrer != minter)?;
+        let config = self.referral_config.as_ref()?;
+        let reward = config.reward(self.total_cost(num, minter).0);
+        if reward == 0 {
+            return None;
+        }
+        Some((referrer, reward))
+    }
+
+    pub(crate) fn pay_referral(&mut self, referrer: AccountId, minter: &AccountId, reward: Balance) {
+        let earnings = self.referral_earnings.get(&referrer).unwrap_or(0) + reward;
+        self.referral_earnings.insert(&referrer, &earnings);
+        NearEvent::log_referral_reward(
+            referrer.to_string(),
+            minter.to_string(),
+            reward.to_string(),
+        );
+        Promise::new(referrer).transfer(reward);
+    }
+}
@@ -0,0 +1,78 @@
+use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+use near_sdk::PromiseResult;
+
+use crate::*;
+
+/// Handing an FT deposit to a vault, which forwards its fee and may report being funded
+const GAS_FOR_ROUTED_DEPOSIT: Gas = Gas(parse_gas!("100 Tgas") as u64);
+const GAS_FOR_ON_DEPOSIT_ROUTED: Gas = Gas(parse_gas!("10 Tgas") as u64);
+
+/// `msg` of an `ft_transfer_call` to this contract, the tokens go to the vault of `token_id`.
+/// `fund_for` is passed on to the vault for sponsored deposits
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct RouteMsg {
+    token_id: TokenId,
+    fund_for: Option<AccountId>,
+}
+
+#[near_bindgen]
+impl FungibleTokenReceiver for Contract {
+    /// Deposit into a token's vault without looking up its account: the tokens are sent on to
+    /// the vault of `token_id` in `msg`, on behalf of `sender_id`. Whatever the vault doesn't
+    /// take is returned to the sender.
+    fn ft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        amount: U128,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        let token_contract_id = env::predecessor_account_id();
+        require!(
+            self.accepted_tokens.contains(&token_contract_id),
+            format!("Token {} is not accepted", token_contract_id)
+        );
+        let RouteMsg { token_id, fund_for } = near_sdk::serde_json::from_str(&msg)
+            .unwrap_or_else(|_| env::panic_str("msg must name the token_id of the vault"));
+        require!(
+            self.tokens.owner_by_id.contains_key(&token_id),
+            "Token not found"
+        );
+        require!(self.releases_vault(&token_id), "Token has no vault");
+
+        Promise::new(token_contract_id)
+            .function_call(
+                "ft_transfer_call".to_string(),
+                json!({
+                    "receiver_id": self.vault_id(&token_id),
+                    "amount": amount,
+                    "msg": json!({ "depositor": sender_id, "fund_for": fund_for }).to_string(),
+                })
+                .to_string()
+                .into_bytes(),
+                1,
+                GAS_FOR_ROUTED_DEPOSIT,
+            )
+            .then(ext_self::on_deposit_routed(
+                amount,
+                env::current_account_id(),
+                0,
+                GAS_FOR_ON_DEPOSIT_ROUTED,
+            ))
+            .into()
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Amount the vault didn't take, handed back to the FT contract to refund the sender
+    #[private]
+    pub fn on_deposit_routed(&mut self, amount: U128) -> U128 {
+        let used = match env::promise_result(0) {
+            PromiseResult::Successful(value) => near_sdk::serde_json::from_slice::<U128>(&value)
+                .map_or(0, |used| used.0.min(amount.0)),
+            _ => 0,
+        };
+        U128(amount.0 - used)
+    }
+}
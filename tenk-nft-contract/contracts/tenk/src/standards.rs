@@ -39,8 +39,12 @@ impl NonFungibleTokenCore for Contract {
         approval_id: Option<u64>,
         memo: Option<String>,
     ) {
+        self.assert_not_locked(&token_id);
+        self.assert_not_crowdfunding(&token_id);
         self.tokens
-            .nft_transfer(receiver_id, token_id, approval_id, memo)
+            .nft_transfer(receiver_id.clone(), token_id.clone(), approval_id, memo);
+        self.notify_vault_beneficiary(&token_id, &receiver_id);
+        self.notify_subscribers(MembershipEventKind::Transfer, &token_id, &receiver_id);
     }
 
     /// Transfer token and call a method on a receiver contract. A successful
@@ -85,6 +89,8 @@ impl NonFungibleTokenCore for Contract {
         memo: Option<String>,
         msg: String,
     ) -> PromiseOrValue<bool> {
+        self.assert_not_locked(&token_id);
+        self.assert_not_crowdfunding(&token_id);
         self.tokens
             .nft_transfer_call(receiver_id, token_id, approval_id, memo, msg)
     }
@@ -100,21 +106,17 @@ impl NonFungibleTokenCore for Contract {
 
         let metadata = self.metadata.get().unwrap();
 
-        let token_metadata = TokenMetadata {
+        let mut token_metadata = TokenMetadata {
             title: Some(format!(
                 "{} #{}",
                 self.metadata.get().unwrap().name,
                 token_id.to_string()
             )),
-            media: Some(format!(
-                "{}.{}",
-                "1",
-                self.media_extension.as_ref().unwrap_or(&"png".to_string())
-            )),
+            media: Some(self.media_path(&token_id)),
             issued_at: None, // ISO 8601 datetime when token was issued or minted
             reference: metadata.reference,
             description: None,    // free-form description
-            media_hash: None, // Base64-encoded sha256 hash of content referenced by the `media` field. Required if `media` is included.
+            media_hash: self.media_hash(&token_id), // Base64-encoded sha256 hash of content referenced by the `media` field. Required if `media` is included.
             copies: None, // number of copies of this set of metadata in existence when token was minted.
             expires_at: None, // ISO 8601 datetime when token expires
             starts_at: None, // ISO 8601 datetime when token starts being valid
@@ -122,6 +124,13 @@ impl NonFungibleTokenCore for Contract {
             extra: None, // anything extra the NFT wants to store on-chain. Can be stringified JSON.
             reference_hash: None, // Base64-encoded sha256 hash of JSON from reference field. Required if `reference` is included.
         };
+        if let Some(template) = self.metadata_template_for(&token_id) {
+            template.apply(&token_id, &mut token_metadata);
+        }
+        if let Some(media) = self.membership_tier_of(&token_id).and_then(|tier| tier.media) {
+            token_metadata.media = Some(media);
+            token_metadata.media_hash = None;
+        }
 
         Some(Token {
             token_id,
@@ -142,12 +151,17 @@ impl NonFungibleTokenResolver for Contract {
         token_id: TokenId,
         approved_account_ids: Option<std::collections::HashMap<AccountId, u64>>,
     ) -> bool {
-        self.tokens.nft_resolve_transfer(
+        let transferred = self.tokens.nft_resolve_transfer(
             previous_owner_id,
-            receiver_id,
-            token_id,
+            receiver_id.clone(),
+            token_id.clone(),
             approved_account_ids,
-        )
+        );
+        if transferred {
+            self.notify_vault_beneficiary(&token_id, &receiver_id);
+            self.notify_subscribers(MembershipEventKind::Transfer, &token_id, &receiver_id);
+        }
+        transferred
     }
 }
 
@@ -182,6 +196,9 @@ impl NonFungibleTokenApproval for Contract {
         account_id: AccountId,
         msg: Option<String>,
     ) -> Option<Promise> {
+        // a locked token can't be transferred, so it can't be listed either
+        self.assert_not_locked(&token_id);
+        self.assert_not_crowdfunding(&token_id);
         self.tokens.nft_approve(token_id, account_id, msg)
     }
 
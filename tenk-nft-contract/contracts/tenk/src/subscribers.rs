@@ -0,0 +1,88 @@
+use crate::*;
+
+/// Most partner contracts notified of membership events
+const MAX_EVENT_SUBSCRIBERS: u64 = 5;
+/// Attached to each notification, subscribers can't make it fail the calling transaction
+const GAS_FOR_MEMBERSHIP_EVENT: Gas = Gas(parse_gas!("5 Tgas") as u64);
+/// Left for the rest of the call, notifications that would cut into it are skipped
+const GAS_RESERVED_AFTER_EVENTS: Gas = Gas(parse_gas!("20 Tgas") as u64);
+
+#[near_bindgen]
+impl Contract {
+    /// Notify `contract_id` of mints, burns, transfers and redemptions through its
+    /// `on_membership_event(kind, token_id, account_id)`
+    /// @allow ["::owner"]
+    pub fn add_event_subscriber(&mut self, contract_id: AccountId) -> bool {
+        self.assert_owner();
+        require!(
+            self.event_subscribers.get(&contract_id).is_some()
+                || self.event_subscribers.len() < MAX_EVENT_SUBSCRIBERS,
+            format!("At most {} subscribers", MAX_EVENT_SUBSCRIBERS)
+        );
+        self.event_subscribers.insert(&contract_id, &true);
+        true
+    }
+
+    /// @allow ["::owner"]
+    pub fn remove_event_subscriber(&mut self, contract_id: AccountId) -> bool {
+        self.assert_owner();
+        self.event_subscribers.remove(&contract_id).is_some()
+    }
+
+    /// Pause or resume the notifications of a subscriber
+    /// @allow ["::owner"]
+    pub fn set_event_subscriber_enabled(&mut self, contract_id: AccountId, enabled: bool) -> bool {
+        self.assert_owner();
+        require!(
+            self.event_subscribers.get(&contract_id).is_some(),
+            "Not a subscriber"
+        );
+        self.event_subscribers.insert(&contract_id, &enabled);
+        true
+    }
+
+    pub fn event_subscribers(&self) -> Vec<EventSubscriber> {
+        self.event_subscribers
+            .iter()
+            .map(|(contract_id, enabled)| EventSubscriber {
+                contract_id,
+                enabled,
+            })
+            .collect()
+    }
+}
+
+impl Contract {
+    /// Fire and forget `on_membership_event` on every enabled subscriber
+    pub(crate) fn notify_subscribers(
+        &self,
+        kind: MembershipEventKind,
+        token_id: &TokenId,
+        account_id: &AccountId,
+    ) {
+        let args = json!({
+            "kind": kind,
+            "token_id": token_id,
+            "account_id": account_id,
+        })
+        .to_string()
+        .into_bytes();
+        for (contract_id, enabled) in self.event_subscribers.iter() {
+            if !enabled {
+                continue;
+            }
+            if env::prepaid_gas() - env::used_gas()
+                < GAS_FOR_MEMBERSHIP_EVENT + GAS_RESERVED_AFTER_EVENTS
+            {
+                log!("Out of gas to notify {} of token {}", contract_id, token_id);
+                continue;
+            }
+            Promise::new(contract_id).function_call(
+                "on_membership_event".to_string(),
+                args.clone(),
+                0,
+                GAS_FOR_MEMBERSHIP_EVENT,
+            );
+        }
+    }
+}
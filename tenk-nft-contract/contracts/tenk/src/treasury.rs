@@ -0,0 +1,97 @@
+use crate::*;
+
+/// Where funds kept by the contract came from
+pub(crate) enum RevenueSource {
+    Mint,
+    Linkdrop,
+    VaultFees,
+    BurnFees,
+}
+
+/// Running totals of the funds the contract kept for the owner
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub struct Treasury {
+    mint: Balance,
+    linkdrop: Balance,
+    vault_fees: Balance,
+    burn_fees: Balance,
+    withdrawn: Balance,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+#[witgen]
+pub struct RevenueReport {
+    /// Mint proceeds kept by the contract
+    pub mint: U128,
+    /// Fees taken when creating linkdrops
+    pub linkdrop: U128,
+    /// Fees forwarded by vaults
+    pub vault_fees: U128,
+    /// Fees paid for burning tokens
+    pub burn_fees: U128,
+    /// Total already withdrawn by the owner
+    pub withdrawn: U128,
+    /// Amount that can be withdrawn without dipping into storage or escrowed NEAR
+    pub available: U128,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Withdraw accumulated funds to `to`, keeping enough to cover the contract's storage
+    /// and the NEAR it holds for links, raffle tickets and crowdfundings.
+    /// @allow ["::owner"]
+    pub fn withdraw(&mut self, amount: U128, to: AccountId) -> Promise {
+        self.assert_owner();
+        require!(amount.0 > 0, "amount must be positive");
+        require!(
+            amount.0 <= self.withdrawable_balance(),
+            "amount exceeds available balance"
+        );
+        self.treasury.withdrawn += amount.0;
+        log!("Withdrawing {} to {}", amount.0, to);
+        Promise::new(to).transfer(amount.0)
+    }
+
+    /// Called by a token's vault when it forwards its collected fees
+    #[payable]
+    pub fn record_vault_fee(&mut self, token_id: TokenId) {
+        require!(
+            env::predecessor_account_id() == self.vault_id(&token_id),
+            "Only the token's vault can record fees"
+        );
+        self.record_revenue(RevenueSource::VaultFees, env::attached_deposit());
+    }
+
+    /// Revenue kept by the contract, per source
+    pub fn revenue_report(&self) -> RevenueReport {
+        RevenueReport {
+            mint: self.treasury.mint.into(),
+            linkdrop: self.treasury.linkdrop.into(),
+            vault_fees: self.treasury.vault_fees.into(),
+            burn_fees: self.treasury.burn_fees.into(),
+            withdrawn: self.treasury.withdrawn.into(),
+            available: self.withdrawable_balance().into(),
+        }
+    }
+}
+
+impl Contract {
+    pub(crate) fn record_revenue(&mut self, source: RevenueSource, amount: Balance) {
+        match source {
+            RevenueSource::Mint => self.treasury.mint += amount,
+            RevenueSource::Linkdrop => self.treasury.linkdrop += amount,
+            RevenueSource::VaultFees => self.treasury.vault_fees += amount,
+            RevenueSource::BurnFees => self.treasury.burn_fees += amount,
+        }
+    }
+
+    fn withdrawable_balance(&self) -> Balance {
+        let storage_cost = env::storage_byte_cost() * env::storage_usage() as Balance;
+        let escrow = self.linkdrop_pool
+            + self.linkdrop_escrow
+            + self.raffle_escrow
+            + self.crowdfund_escrow;
+        env::account_balance().saturating_sub(storage_cost + escrow)
+    }
+}
@@ -50,6 +50,12 @@ impl Allowance {
     pub fn new(max: u16) -> Self {
         Self { max, used: 0 }
     }
+
+    /// Allowance with `used` of `max` already minted, e.g. when imported
+    pub fn with_used(max: u16, used: u16) -> Self {
+        require!(used <= max, "Allowance used is more than its max");
+        Self { max, used }
+    }
     pub fn left(&self) -> u16 {
         self.max - self.used
     }
@@ -66,9 +72,39 @@ impl Allowance {
         self.max = u16::max(self.max, new_max);
         self
     }
+
+    pub fn info(&self) -> AllowanceInfo {
+        AllowanceInfo {
+            max: self.max,
+            used: self.used,
+            left: self.left(),
+        }
+    }
+}
+
+/// How many tokens an account may mint, and has minted, during the sale
+#[witgen]
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AllowanceInfo {
+    pub max: u16,
+    pub used: u16,
+    pub left: u16,
+}
+
+/// Presale whitelist entry as exported by `export_whitelist` and imported by `import_whitelist`
+#[witgen]
+#[derive(Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct WhitelistEntry {
+    pub account_id: AccountId,
+    pub max: u16,
+    pub used: u16,
+    /// Presale tier the account is a member of
+    pub tier: Option<String>,
 }
 
-#[derive(Deserialize, Serialize, BorshSerialize, BorshDeserialize)]
+#[derive(Deserialize, Serialize, BorshSerialize, BorshDeserialize, Clone)]
 #[serde(crate = "near_sdk::serde")]
 #[witgen]
 pub struct Sale {
@@ -106,8 +142,251 @@ impl Sale {
         if let Some(r) = self.initial_royalties.as_ref() {
             r.validate()
         }
+        if let (Some(presale_start), Some(public_sale_start)) =
+            (self.presale_start, self.public_sale_start)
+        {
+            require!(
+                presale_start <= public_sale_start,
+                "presale must start before the public sale"
+            );
+        }
+        if let Some(limit) = self.mint_rate_limit {
+            require!(limit > 0, "mint rate limit must be greater than 0");
+        }
+    }
+}
+
+/// Most a vault can charge on deposits, 10%
+pub const MAX_VAULT_FEE_BPS: BasisPoint = 1_000;
+
+/// Fee every new vault charges on deposits and who receives it
+#[derive(Deserialize, Serialize, BorshSerialize, BorshDeserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[witgen]
+pub struct VaultFeeConfig {
+    /// Receiver of the fees. None, means this contract
+    pub fee_recipient: Option<AccountId>,
+    pub fee_bps: BasisPoint,
+}
+
+impl Default for VaultFeeConfig {
+    fn default() -> Self {
+        Self {
+            fee_recipient: None,
+            fee_bps: 100,
+        }
+    }
+}
+
+impl VaultFeeConfig {
+    /// Fee a vault charges on depositing `amount`, rounded up like the vault does
+    pub fn fee_on(&self, amount: Balance) -> Balance {
+        (amount * self.fee_bps as Balance + 9_999) / 10_000
+    }
+
+    pub fn validate(&self) {
+        require!(
+            self.fee_bps <= MAX_VAULT_FEE_BPS,
+            "vault fee bps must be between 0 - 1,000"
+        );
+    }
+}
+
+/// Fee charged for burning a token whose vault gets released, kept by the contract
+#[derive(Deserialize, Serialize, BorshSerialize, BorshDeserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[witgen]
+pub enum BurnFee {
+    /// Flat amount of NEAR
+    Flat(YoctoNEAR),
+    /// Share of the NEAR the token's vault was created with
+    Bps(BasisPoint),
+}
+
+impl BurnFee {
+    pub fn validate(&self) {
+        if let BurnFee::Bps(bps) = self {
+            require!(
+                *bps <= ONE_HUNDRED_PERCENT_IN_BPS,
+                "burn fee bps must be between 0 - 10,000"
+            );
+        }
+    }
+
+    pub fn amount(&self, vault_near_amount: Balance) -> Balance {
+        match self {
+            BurnFee::Flat(amount) => amount.0,
+            BurnFee::Bps(bps) => vault_near_amount * *bps as Balance / ONE_HUNDRED_PERCENT_IN_BPS as Balance,
+        }
+    }
+}
+
+/// Lock keeping a token in place, e.g. while it's collateral
+#[derive(Deserialize, Serialize, BorshSerialize, BorshDeserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[witgen]
+pub struct TokenLock {
+    /// Account that can unlock the token early
+    pub locker: AccountId,
+    /// The lock ends at this time
+    pub until: TimestampMs,
+}
+
+/// Sliding window limiting how many tokens one account can mint over time
+#[derive(Deserialize, Serialize, BorshSerialize, BorshDeserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[witgen]
+pub struct MintWindow {
+    /// Most tokens an account can mint within the window
+    pub max_mints: u16,
+    /// Length of the window in ms
+    pub window_ms: TimestampMs,
+}
+
+impl MintWindow {
+    pub fn validate(&self) {
+        require!(self.max_mints > 0, "max mints must be greater than 0");
+        require!(self.window_ms > 0, "window must be greater than 0");
+    }
+}
+
+/// Presale tier, e.g. OG or WL, with its own price, allowance and minting window
+#[derive(Deserialize, Serialize, BorshSerialize, BorshDeserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[witgen]
+pub struct Tier {
+    pub name: String,
+    pub price: YoctoNEAR,
+    /// Tokens each member can mint during the presale
+    pub allowance: u16,
+    /// Members can't mint before this time. None, means from the start of the presale
+    pub start: Option<TimestampMs>,
+    /// Members can't mint from this time on. None, means until the public sale
+    pub end: Option<TimestampMs>,
+}
+
+impl Tier {
+    pub fn validate(&self) {
+        require!(!self.name.is_empty(), "tier name can't be empty");
+        require!(self.allowance > 0, "tier allowance must be greater than 0");
+        if let (Some(start), Some(end)) = (self.start, self.end) {
+            require!(start < end, "tier must start before it ends");
+        }
+    }
+
+    pub fn is_active(&self, now: TimestampMs) -> bool {
+        self.start.map_or(true, |start| start <= now) && self.end.map_or(true, |end| now < end)
     }
 }
+
+/// FT a membership tier requires in the vault
+#[derive(Deserialize, Serialize, BorshSerialize, BorshDeserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[witgen]
+pub struct TierToken {
+    pub token_contract_id: AccountId,
+    pub token_amount: U128,
+}
+
+/// Membership tier, e.g. Bronze, Silver or Gold, defined by what the token's vault holds
+#[derive(Deserialize, Serialize, BorshSerialize, BorshDeserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[witgen]
+pub struct MembershipTier {
+    pub name: String,
+    /// Tokens can only be upgraded to a tier of higher rank
+    pub rank: u8,
+    /// NEAR the vault holds
+    pub near_amount: U128,
+    /// FTs the vault holds
+    pub tokens: Vec<TierToken>,
+    /// Artwork of the tier's tokens, replaces the token's media
+    pub media: Option<String>,
+}
+
+impl MembershipTier {
+    pub fn validate(&self) {
+        require!(!self.name.is_empty(), "tier name can't be empty");
+        for (i, token) in self.tokens.iter().enumerate() {
+            require!(token.token_amount.0 > 0, "tier token amount must be greater than 0");
+            require!(
+                self.tokens[..i]
+                    .iter()
+                    .all(|other| other.token_contract_id != token.token_contract_id),
+                "tier tokens must be distinct"
+            );
+        }
+    }
+
+    fn token_amount(&self, token_contract_id: &AccountId) -> u128 {
+        self.tokens
+            .iter()
+            .filter(|token| &token.token_contract_id == token_contract_id)
+            .map(|token| token.token_amount.0)
+            .sum()
+    }
+
+    /// Whether a vault holding `near_amount` and the deposited `token_deposit` holds what the
+    /// tier requires, deposits still to be made don't count
+    pub fn is_held_by(&self, near_amount: u128, token_deposit: &[TokenDeposit]) -> bool {
+        near_amount >= self.near_amount.0
+            && self.tokens.iter().all(|token| {
+                token_deposit
+                    .iter()
+                    .filter(|deposit| {
+                        deposit.is_deposited && deposit.token_contract_id == token.token_contract_id
+                    })
+                    .map(|deposit| deposit.token_amount.0)
+                    .sum::<u128>()
+                    >= token.token_amount.0
+            })
+    }
+
+    /// NEAR and FTs the vault of a token in `from` is missing to hold this tier
+    pub fn requirements_over(&self, from: Option<&MembershipTier>) -> MembershipUpgradeCost {
+        let held = |token_contract_id: &AccountId| from.map_or(0, |from| from.token_amount(token_contract_id));
+        MembershipUpgradeCost {
+            near_amount: U128(
+                self.near_amount
+                    .0
+                    .saturating_sub(from.map_or(0, |from| from.near_amount.0)),
+            ),
+            tokens: self
+                .tokens
+                .iter()
+                .filter_map(|token| {
+                    let missing = token.token_amount.0.saturating_sub(held(&token.token_contract_id));
+                    (missing > 0).then(|| TierToken {
+                        token_contract_id: token.token_contract_id.clone(),
+                        token_amount: U128(missing),
+                    })
+                })
+                .collect(),
+        }
+    }
+}
+
+/// What moving a token up to a membership tier adds to its vault, vault fees excluded
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[witgen]
+pub struct MembershipUpgradeCost {
+    /// NEAR to attach to `upgrade_membership`, plus the vault's fee on it
+    pub near_amount: U128,
+    /// FTs to deposit into the vault once the upgrade call went through
+    pub tokens: Vec<TierToken>,
+}
+
+/// Number of tokens in a membership tier
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[witgen]
+pub struct TierSupply {
+    pub name: String,
+    pub rank: u8,
+    pub supply: u64,
+}
+
 /// Current state of contract
 #[witgen]
 #[derive(Serialize)]
@@ -123,6 +402,159 @@ pub enum Status {
     SoldOut,
 }
 
+/// Deposit minting costs an account, broken down
+#[witgen]
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MintCost {
+    pub price: YoctoNEAR,
+    /// Balance the new vaults are created with
+    pub vault_funding: YoctoNEAR,
+    pub storage: YoctoNEAR,
+    /// Fee of the link, when minting through a linkdrop. Its balance comes out of the linkdrop pool
+    pub linkdrop_fee: YoctoNEAR,
+    /// Deposit to attach
+    pub total: YoctoNEAR,
+}
+
+/// Outcome of `nft_burn_many`
+#[witgen]
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BurnManyResult {
+    pub burned: Vec<TokenId>,
+    /// Tokens left as they were, with why
+    pub failed: Vec<BurnFailure>,
+    /// Vault releases that didn't fit in the gas, sent with `continue_vault_releases`
+    pub releases_queued: u32,
+}
+
+#[witgen]
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BurnFailure {
+    pub token_id: TokenId,
+    pub reason: String,
+}
+
+/// Membership of an account, as checked by partner dapps
+#[witgen]
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MembershipInfo {
+    /// One of the account's tokens, the first in its token set
+    pub token_id: TokenId,
+    /// ms since epoch, None if the token has no mint time recorded
+    pub minted_at: Option<TimestampMs>,
+    pub redeemed: bool,
+    /// Whether the token is locked, e.g. as collateral
+    pub locked: bool,
+    pub vault_status: VaultStatus,
+    /// Number of tokens the account holds
+    pub token_count: u64,
+}
+
+/// Upgrade state of a token's vault
+#[witgen]
+#[derive(Serialize, BorshSerialize, BorshDeserialize, Clone, Copy, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum VaultUpgradeStatus {
+    /// Upgrade call in flight
+    Pending,
+    /// The vault runs the code
+    Done,
+    /// The upgrade failed, the vault still runs its previous code
+    Failed,
+}
+
+/// Release requested from a token's vault when the token was burned or redeemed
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct VaultRelease {
+    pub vault_id: AccountId,
+    pub owner_id: AccountId,
+    pub nonce: u64,
+}
+
+/// Code a token's vault was last deployed or upgraded with
+#[witgen]
+#[derive(Serialize, BorshSerialize, BorshDeserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VaultCode {
+    /// base58 sha256 of the code
+    pub code_hash: String,
+    pub status: VaultUpgradeStatus,
+}
+
+/// Value of a token's vault at its price oracle's prices, as last reported by the vault
+#[witgen]
+#[derive(Deserialize, Serialize, BorshSerialize, BorshDeserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VaultValuation {
+    /// USD with 6 decimals
+    pub value_usd: U128,
+    pub value_near: YoctoNEAR,
+    /// ms since epoch the prices were reported at
+    pub priced_at: U64,
+    /// held assets the oracle has no price for, left out of the value
+    pub unpriced: Vec<AccountId>,
+}
+
+/// What happened to a membership, sent to event subscribers
+#[witgen]
+#[derive(Serialize, Clone, Copy)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum MembershipEventKind {
+    Mint,
+    Burn,
+    Transfer,
+    Redeem,
+}
+
+#[witgen]
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct EventSubscriber {
+    pub contract_id: AccountId,
+    pub enabled: bool,
+}
+
+/// Account co-signing the release of vaults worth `threshold` or more
+#[witgen]
+#[derive(Deserialize, Serialize, BorshSerialize, BorshDeserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VaultRiskControl {
+    pub risk_account: AccountId,
+    /// NEAR-equivalent basket value from which releases need both authorizations
+    pub threshold: YoctoNEAR,
+}
+
+/// Funding state of a token's vault, as reported by the vault itself
+#[witgen]
+#[derive(Serialize, BorshSerialize, BorshDeserialize, Clone, Copy, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum VaultStatus {
+    /// Waiting for its deposits
+    Pending,
+    /// Every deposit is in
+    Funded,
+    /// Token was minted without a vault
+    NoVault,
+}
+
+/// Token with its vault joined in, for rendering liquid NFT balances in one call
+#[witgen]
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenDetails {
+    pub token: Token,
+    /// None, means the token has no vault
+    pub vault_account_id: Option<AccountId>,
+    pub vault_status: VaultStatus,
+    /// Whether the vault assets were released with `redeem_without_burn`
+    pub redeemed: bool,
+}
+
 /// Information about the current sale from user perspective
 #[allow(dead_code)]
 #[witgen]
@@ -152,6 +584,54 @@ pub struct SaleInfo {
     pub price: U128,
 }
 
+/// Raffle for an oversubscribed mint, see `start_mint_raffle`
+#[witgen]
+#[derive(Deserialize, Serialize, BorshSerialize, BorshDeserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MintRaffle {
+    /// NEAR escrowed per entry, pays for the token, its storage and its vault
+    pub ticket_price: YoctoNEAR,
+    /// Window entries are taken in
+    pub starts_at: TimestampMs,
+    pub ends_at: TimestampMs,
+    /// Number of tokens raffled off
+    pub winners: u32,
+}
+
+/// Mint raffle with its entries and draw progress
+#[witgen]
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MintRaffleInfo {
+    pub raffle: MintRaffle,
+    pub entries: u64,
+    pub winners_drawn: u32,
+    /// Whether every winner was drawn, losing entries can be refunded from then on
+    pub drawn: bool,
+}
+
+#[witgen]
+#[derive(Serialize, BorshSerialize, BorshDeserialize, Clone, Copy, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum RaffleEntryStatus {
+    /// Waiting for the draw
+    Entered,
+    /// Drawn, the mint can be claimed, or the ticket refunded if it can't be
+    Won,
+    /// Not drawn, the ticket can be refunded
+    Lost,
+    Claimed,
+    Refunded,
+}
+
+#[witgen]
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RaffleEntry {
+    pub account_id: AccountId,
+    pub status: RaffleEntryStatus,
+}
+
 #[cfg(not(target_arch = "wasm32"))]
 #[cfg(test)]
 mod tests {
@@ -172,3 +652,37 @@ mod tests {
         }
     }
 }
+
+/// Crowdfunding of a token's vault, see `contribute_to_vault`
+#[witgen]
+#[derive(Serialize, BorshSerialize, BorshDeserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Crowdfund {
+    /// NEAR to raise: the vault's NEAR deposit plus the vault fee on it
+    pub target: YoctoNEAR,
+    pub raised: YoctoNEAR,
+    /// Crowdfunding fails if the target isn't raised by then
+    pub deadline: TimestampMs,
+    pub status: CrowdfundStatus,
+}
+
+#[witgen]
+#[derive(Serialize, BorshSerialize, BorshDeserialize, Clone, Copy, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum CrowdfundStatus {
+    /// Taking contributions
+    Open,
+    /// Target raised, waiting for the vault to take it
+    Depositing,
+    /// Deadline passed or the vault refused the deposit, contributions can be reclaimed
+    Failed,
+}
+
+/// Vouchers not redeemed yet with a discount, see `add_vouchers`
+#[witgen]
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VoucherCount {
+    pub discount_bps: BasisPoint,
+    pub count: u64,
+}
@@ -1,5 +1,7 @@
 use near_contract_standards::non_fungible_token::{events::NftMint, Token};
-use near_sdk::{env, AccountId, Promise, PromiseResult};
+use near_sdk::json_types::Base58CryptoHash;
+use near_sdk::{env, require, AccountId, CryptoHash, Promise, PromiseResult};
+use std::convert::TryInto;
 
 use crate::TimestampMs;
 pub fn is_promise_success(num_of_promises: Option<u64>) -> bool {
@@ -32,6 +34,40 @@ pub fn refund(account_id: &AccountId, amount: u128) -> Option<Promise> {
     None
 }
 
+/// Longest account id the protocol accepts
+const MAX_ACCOUNT_ID_LEN: usize = 64;
+/// Shortest hashed vault name prefix, "v" and 8 hex chars
+const MIN_HASHED_PREFIX_LEN: usize = 9;
+
+/// Account the vault of `token_id` is created under, `vault_{id}.{contract}`.
+/// When that gets too long for the parent account, the prefix is a truncated hash of the
+/// token id instead. The created name is kept in the vault registry, so this is only
+/// derived once per token.
+pub fn vault_account_id(token_id: &str) -> AccountId {
+    let parent_id = env::current_account_id();
+    let name = format!("vault_{}.{}", token_id, parent_id);
+    if name.len() <= MAX_ACCOUNT_ID_LEN {
+        return AccountId::new_unchecked(name);
+    }
+    let prefix_len = MAX_ACCOUNT_ID_LEN.saturating_sub(parent_id.as_str().len() + 1);
+    require!(
+        prefix_len >= MIN_HASHED_PREFIX_LEN,
+        "Contract account id is too long for vault subaccounts"
+    );
+    let hash: String = env::sha256(token_id.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    let prefix_len = prefix_len.min(hash.len() + 1);
+    AccountId::new_unchecked(format!("v{}.{}", &hash[..prefix_len - 1], parent_id))
+}
+
+/// Hash of the code vaults are deployed with, so integrations can check a vault runs it
+pub fn vault_code_hash() -> Base58CryptoHash {
+    let hash: CryptoHash = env::sha256(crate::VAULT_CODE).try_into().unwrap();
+    hash.into()
+}
+
 pub fn current_time_ms() -> TimestampMs {
     env::block_timestamp() / 1_000_000
 }
@@ -7,20 +7,39 @@ impl Contract {
         self.tokens.owner_id.clone()
     }
 
+    /// Account proposed as the next owner, if any
+    pub fn pending_owner(&self) -> Option<AccountId> {
+        self.pending_owner.clone()
+    }
+
+    /// Account with the same rights as the owner, if any
+    pub fn backup_owner(&self) -> Option<AccountId> {
+        self.backup_owner.clone()
+    }
+
     /// Current set of admins
     pub fn admins(&self) -> Vec<AccountId> {
         self.admins.to_vec()
     }
 
+    /// FT contracts accepted in vault deposits
+    pub fn accepted_tokens(&self) -> Vec<AccountId> {
+        self.accepted_tokens.to_vec()
+    }
+
+    /// Current set of accounts allowed to mint on behalf of users
+    pub fn signer_accounts(&self) -> Vec<AccountId> {
+        self.signer_accounts.to_vec()
+    }
+
     /// Check whether an account is allowed to mint during the presale
     pub fn whitelisted(&self, account_id: &AccountId) -> bool {
-        self.whitelist.contains_key(account_id)
+        self.presale_allowance(account_id).is_some()
     }
 
-    /// Cost of NFT + fees for linkdrop
+    /// Cost of NFT + fees for linkdrop, the link's balance is paid by the linkdrop pool
     pub fn cost_of_linkdrop(&self, minter: &AccountId) -> U128 {
-        (self.full_link_price(minter) + self.total_cost(1, minter).0 + self.token_storage_cost().0)
-            .into()
+        (self.link_fee(minter) + self.total_cost(1, minter).0 + self.token_storage_cost().0).into()
     }
 
     pub fn total_cost(&self, num: u16, minter: &AccountId) -> U128 {
@@ -32,11 +51,48 @@ impl Contract {
         if self.is_owner(minter) {
             0
         } else {
-            self.price()
+            self.price(minter)
         }
         .into()
     }
 
+    /// What minting `num` tokens costs `account_id`, to attach the exact deposit.
+    /// Tokens minted through a linkdrop get no vault but pay for the link
+    pub fn estimate_mint_cost(
+        &self,
+        num: u16,
+        account_id: AccountId,
+        linkdrop: Option<bool>,
+    ) -> MintCost {
+        let num = num as Balance;
+        let price = self.total_cost(num as u16, &account_id).0;
+        let storage = num * self.token_storage_cost().0;
+        let (vault_funding, linkdrop_fee) = if linkdrop.unwrap_or(false) {
+            (0, num * self.link_fee(&account_id))
+        } else {
+            (num * self.vault_funding().0, 0)
+        };
+        MintCost {
+            price: price.into(),
+            vault_funding: vault_funding.into(),
+            storage: storage.into(),
+            linkdrop_fee: linkdrop_fee.into(),
+            total: (price + storage + vault_funding + linkdrop_fee).into(),
+        }
+    }
+
+    /// Balance each new vault is created with: storage for its code and state plus a buffer
+    pub fn vault_funding(&self) -> U128 {
+        (env::storage_byte_cost() * (VAULT_CODE.len() as u64 + VAULT_STATE_BYTES) as Balance
+            + self.vault_funding_buffer)
+            .into()
+    }
+
+    /// Gas to attach to `nft_burn`, including the release of the token's vault
+    pub fn estimate_burn_gas(&self) -> U64 {
+        (GAS_FOR_BURN.0 + GAS_FOR_VAULT_RELEASE.0).into()
+    }
+
     /// Current cost in NEAR to store one NFT
     pub fn token_storage_cost(&self) -> U128 {
         (env::storage_byte_cost() * self.tokens.extra_storage_in_bytes_per_token as Balance).into()
@@ -49,15 +105,11 @@ impl Contract {
 
     /// How many tokens an account is still allowed to mint. None, means unlimited
     pub fn remaining_allowance(&self, account_id: &AccountId) -> Option<u16> {
-        let allowance = if self.is_presale() {
-            0
-        } else if let Some(allowance) = self.sale.allowance {
-            allowance
-        } else {
-            return None;
-        };
-        self.whitelist
-            .get(account_id)
+        if self.is_presale() {
+            return self.presale_allowance(account_id).map(|a| a.left());
+        }
+        let allowance = self.sale.allowance?;
+        self.public_allowance(account_id)
             .map(|a| a.raise_max(allowance).left())
     }
 
@@ -65,4 +117,189 @@ impl Contract {
     pub fn mint_rate_limit(&self) -> Option<u16> {
         self.sale.mint_rate_limit
     }
+
+    /// Per account limit on mints over a sliding time window. None, means unlimited
+    pub fn mint_window(&self) -> Option<MintWindow> {
+        self.mint_window.clone()
+    }
+
+    /// Tokens `account_id` can still mint in the current window. None, means unlimited
+    pub fn mints_remaining_in_window(&self, account_id: &AccountId) -> Option<u16> {
+        let window = self.mint_window.as_ref()?;
+        let minted = self.recent_mints_in_window(account_id, window).len() as u16;
+        Some(window.max_mints.saturating_sub(minted))
+    }
+
+    /// Whether the token's vault assets were released with `redeem_without_burn`
+    pub fn is_redeemed(&self, token_id: TokenId) -> bool {
+        self.redeemed.get(&token_id).unwrap_or(false)
+    }
+
+    /// Code hash the token's vault runs and the status of its last upgrade
+    pub fn vault_code(&self, token_id: TokenId) -> Option<VaultCode> {
+        self.vault_codes.get(&token_id)
+    }
+
+    /// Value of the token's vault as last reported by the vault, refreshed with the
+    /// vault's `update_valuation`. None until the vault was valued once
+    pub fn vault_value(&self, token_id: TokenId) -> Option<VaultValuation> {
+        self.vault_valuations.get(&token_id)
+    }
+
+    /// ms left until the token's vault can be released, 0 once its lockup is over or without one
+    pub fn vault_lockup_remaining(&self, token_id: TokenId) -> TimestampMs {
+        self.vault_lockups
+            .get(&token_id)
+            .map_or(0, |lockup_until| lockup_until.saturating_sub(current_time_ms()))
+    }
+
+    /// Dual control new vaults are created with
+    pub fn vault_risk_control(&self) -> Option<VaultRiskControl> {
+        self.vault_risk_control.clone()
+    }
+
+    /// Price oracle new vaults are valued with
+    pub fn vault_oracle(&self) -> Option<AccountId> {
+        self.vault_oracle.clone()
+    }
+
+    /// wNEAR contract new vaults take in place of their NEAR deposit
+    pub fn vault_wrap_near(&self) -> Option<AccountId> {
+        self.vault_wrap_near.clone()
+    }
+
+    /// Tokens with their vault joined in, paginated like `nft_tokens`
+    pub fn nft_tokens_detailed(
+        &self,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<TokenDetails> {
+        self.nft_tokens(from_index, limit)
+            .into_iter()
+            .map(|token| self.token_details(token))
+            .collect()
+    }
+
+    /// Tokens of an account with their vault joined in, paginated like `nft_tokens_for_owner`
+    pub fn nft_tokens_for_owner_detailed(
+        &self,
+        account_id: AccountId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<TokenDetails> {
+        self.nft_tokens_for_owner(account_id, from_index, limit)
+            .into_iter()
+            .map(|token| self.token_details(token))
+            .collect()
+    }
+
+    /// Whether the account holds at least one token
+    pub fn is_member(&self, account_id: AccountId) -> bool {
+        self.member_tokens(&account_id).map_or(false, |token_ids| !token_ids.is_empty())
+    }
+
+    /// `is_member` for each account, in the same order
+    pub fn are_members(&self, accounts: Vec<AccountId>) -> Vec<bool> {
+        accounts
+            .into_iter()
+            .map(|account_id| self.is_member(account_id))
+            .collect()
+    }
+
+    /// One of the account's tokens with its state, None if the account isn't a member
+    pub fn membership_info(&self, account_id: AccountId) -> Option<MembershipInfo> {
+        let token_ids = self.member_tokens(&account_id)?;
+        let token_id = token_ids.iter().next()?;
+        let minted_at = self
+            .tokens
+            .token_metadata_by_id
+            .as_ref()
+            .and_then(|by_id| by_id.get(&token_id))
+            .and_then(|metadata| metadata.issued_at)
+            .and_then(|issued_at| issued_at.parse::<u64>().ok())
+            .map(|issued_at| issued_at / 1_000_000);
+        Some(MembershipInfo {
+            minted_at,
+            redeemed: self.is_redeemed(token_id.clone()),
+            locked: self.is_locked(token_id.clone()),
+            vault_status: self.vault_status.get(&token_id).unwrap_or(VaultStatus::Pending),
+            token_count: token_ids.len(),
+            token_id,
+        })
+    }
+
+    /// Fee new vaults charge on deposits and who receives it
+    pub fn vault_fee_config(&self) -> VaultFeeConfig {
+        self.vault_fee_config.clone()
+    }
+
+    /// Fee for burning a token, none means burning is free
+    pub fn burn_fee_config(&self) -> Option<BurnFee> {
+        self.burn_fee.clone()
+    }
+
+    /// Fee to attach to `nft_burn` for the token, 0 when its vault won't be released
+    pub fn burn_fee(&self, token_id: TokenId) -> U128 {
+        match &self.burn_fee {
+            Some(burn_fee) if self.releases_vault(&token_id) => burn_fee
+                .amount(self.vault_near_amounts.get(&token_id).unwrap_or(0))
+                .into(),
+            _ => 0.into(),
+        }
+    }
+
+    /// Full sale configuration: prices, start times, allowance, limits and royalties
+    pub fn get_sale_info(&self) -> Sale {
+        self.sale.clone()
+    }
+
+    /// Same as `get_sale_info`
+    pub fn get_sale(&self) -> Sale {
+        self.get_sale_info()
+    }
+
+    /// Current state of the sale: "Closed", "Presale", "Open" or "SoldOut"
+    pub fn get_status(&self) -> Status {
+        self.sale_status()
+    }
+
+    /// Presale tiers
+    pub fn tiers(&self) -> Vec<Tier> {
+        self.tiers.values().collect()
+    }
+
+    /// Tier an account is a member of. None, means it isn't in any tier
+    pub fn get_account_tier(&self, account_id: AccountId) -> Option<Tier> {
+        self.tier_of(&account_id)
+    }
+
+    /// Allowance of an account on the whitelist. None, means the account isn't on it
+    pub fn get_allowance_info(&self, account_id: AccountId) -> Option<AllowanceInfo> {
+        self.presale_allowance(&account_id).map(|allowance| allowance.info())
+    }
+
+    /// Presale whitelist entries with their tier, paginated like `nft_tokens`. Entries from
+    /// before the presale whitelist was split out are only included after `migrate_whitelist`
+    pub fn export_whitelist(&self, from_index: Option<u64>, limit: Option<u64>) -> Vec<WhitelistEntry> {
+        self.presale_whitelist
+            .iter()
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit.map_or(usize::MAX, |limit| limit as usize))
+            .map(|(account_id, allowance)| {
+                let info = allowance.info();
+                WhitelistEntry {
+                    tier: self.tier_members.get(&account_id),
+                    account_id,
+                    max: info.max,
+                    used: info.used,
+                }
+            })
+            .collect()
+    }
+
+    /// Public sale allowance of an account. None, means it has neither minted in the
+    /// public sale nor is on the whitelist
+    pub fn get_public_allowance_info(&self, account_id: AccountId) -> Option<AllowanceInfo> {
+        self.public_allowance(&account_id).map(|allowance| allowance.info())
+    }
 }
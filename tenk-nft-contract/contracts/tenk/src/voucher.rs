@@ -0,0 +1,130 @@
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    /// Add vouchers by the sha256 of their code, redeeming one takes `discount_bps` off the
+    /// mint price, 10,000 makes the mint free. Hashes already added are skipped.
+    /// @allow ["::owner"]
+    pub fn add_vouchers(&mut self, hashes: Vec<Base58CryptoHash>, discount_bps: BasisPoint) -> u32 {
+        self.assert_owner();
+        require!(
+            discount_bps > 0 && discount_bps <= ONE_HUNDRED_PERCENT_IN_BPS,
+            "discount bps must be between 1 - 10,000"
+        );
+        let mut added = 0;
+        for hash in hashes {
+            let hash: CryptoHash = hash.into();
+            if self.vouchers.get(&hash).is_none() {
+                self.vouchers.insert(&hash, &discount_bps);
+                added += 1;
+            }
+        }
+        self.update_voucher_count(discount_bps, added as i64);
+        NearEvent::log_sale_update(
+            "vouchers",
+            json!({ "added": added, "discount_bps": discount_bps }),
+        );
+        added
+    }
+
+    /// Remove vouchers that weren't redeemed yet
+    /// @allow ["::owner"]
+    pub fn remove_vouchers(&mut self, hashes: Vec<Base58CryptoHash>) -> u32 {
+        self.assert_owner();
+        let mut removed = 0;
+        for hash in hashes {
+            if let Some(discount_bps) = self.vouchers.remove(&hash.into()) {
+                self.update_voucher_count(discount_bps, -1);
+                removed += 1;
+            }
+        }
+        removed
+    }
+
+    /// Mint a token with the voucher whose sha256 is that of `code`, the voucher can't be
+    /// used again. Attach the discounted price plus the token's storage and `vault_funding`,
+    /// anything above is refunded. Vouchers don't need an allowance but the sale has to be
+    /// on and not sold out. The vault is created like for `nft_mint_one`.
+    #[payable]
+    pub fn redeem_voucher(
+        &mut self,
+        code: String,
+        token_deposit: Vec<TokenDeposit>,
+        near_amount: U128,
+        strategy_contract: Option<AccountId>,
+    ) -> Token {
+        match self.sale_status() {
+            Status::SoldOut => env::panic_str("No NFTs left to mint"),
+            Status::Closed => env::panic_str("Contract currently closed"),
+            Status::Presale | Status::Open => {}
+        }
+        let hash: CryptoHash = env::sha256(code.as_bytes()).try_into().unwrap();
+        let discount_bps = self
+            .vouchers
+            .remove(&hash)
+            .unwrap_or_else(|| env::panic_str("Invalid voucher code"));
+        self.update_voucher_count(discount_bps, -1);
+        for deposit in &token_deposit {
+            require!(
+                self.accepted_tokens.contains(&deposit.token_contract_id),
+                format!("Token {} is not accepted", deposit.token_contract_id)
+            );
+        }
+
+        let owner_id = env::predecessor_account_id();
+        let price = apply_percent(
+            ONE_HUNDRED_PERCENT_IN_BPS - discount_bps,
+            self.price(&owner_id),
+        );
+        let initial_storage_usage = env::storage_usage();
+        let token = self.draw_and_mint(owner_id.clone(), None);
+        self.set_initial_tier(&token.token_id, near_amount.0, &token_deposit);
+        self.create_vault(
+            &token.token_id,
+            &owner_id,
+            token_deposit,
+            near_amount,
+            strategy_contract,
+            None,
+        );
+        let storage_cost =
+            env::storage_byte_cost() * (env::storage_usage() - initial_storage_usage) as Balance;
+        let cost = price + storage_cost + self.vault_funding().0;
+        let deposit = env::attached_deposit();
+        require!(
+            deposit >= cost,
+            format!("Attach at least {} yN to redeem the voucher", cost)
+        );
+        self.record_revenue(RevenueSource::Mint, price);
+        refund(&owner_id, deposit - cost);
+        log_mint(&owner_id, &[token.clone()]);
+        token
+    }
+
+    /// Whether a voucher with this sha256 can still be redeemed
+    pub fn voucher_exists(&self, hash: Base58CryptoHash) -> bool {
+        self.vouchers.get(&hash.into()).is_some()
+    }
+
+    /// Vouchers not redeemed yet, counted per discount
+    pub fn voucher_counts(&self) -> Vec<VoucherCount> {
+        self.voucher_counts
+            .iter()
+            .map(|(discount_bps, count)| VoucherCount {
+                discount_bps,
+                count,
+            })
+            .collect()
+    }
+}
+
+impl Contract {
+    fn update_voucher_count(&mut self, discount_bps: BasisPoint, change: i64) {
+        let count = (self.voucher_counts.get(&discount_bps).unwrap_or(0) as i64 + change) as u64;
+        if count == 0 {
+            self.voucher_counts.remove(&discount_bps);
+        } else {
+            self.voucher_counts.insert(&discount_bps, &count);
+        }
+    }
+}
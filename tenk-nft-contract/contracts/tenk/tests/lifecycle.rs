@@ -0,0 +1,265 @@
+//! Full membership lifecycle on a sandbox: mint, vault deploy, FT and NEAR deposits,
+//! transfer, burn and release.
+//!
+//! The tenk contract embeds the vault code, build it first with `vault-contract/build.sh`.
+//! Run with `cargo test -p tenk --test lifecycle`.
+use near_workspaces::network::Sandbox;
+use near_workspaces::types::{Gas, NearToken};
+use near_workspaces::{Account, AccountId, Contract, Worker};
+use serde_json::{json, Value};
+
+const FT_DEPOSIT: u128 = 1_000;
+const MAX_GAS: Gas = Gas::from_tgas(300);
+const ONE_YOCTO: NearToken = NearToken::from_yoctonear(1);
+
+struct Setup {
+    worker: Worker<Sandbox>,
+    tenk: Contract,
+    ft: Contract,
+    alice: Account,
+    bob: Account,
+}
+
+async fn setup() -> anyhow::Result<Setup> {
+    let worker = near_workspaces::sandbox().await?;
+    let tenk_wasm = near_workspaces::compile_project("./").await?;
+    let ft_wasm = near_workspaces::compile_project("./tests/mock-ft").await?;
+    let owner = worker.root_account()?;
+
+    // vaults are subaccounts of the tenk contract, so it needs a name of its own
+    let tenk = owner
+        .create_subaccount("tenk")
+        .initial_balance(NearToken::from_near(50))
+        .transact()
+        .await?
+        .into_result()?
+        .deploy(&tenk_wasm)
+        .await?
+        .into_result()?;
+    tenk.call("new_default_meta")
+        .args_json(json!({ "owner_id": owner.id() }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    let alice = create_user(&owner, "alice").await?;
+    let bob = create_user(&owner, "bob").await?;
+
+    let ft = owner
+        .create_subaccount("ft")
+        .initial_balance(NearToken::from_near(10))
+        .transact()
+        .await?
+        .into_result()?
+        .deploy(&ft_wasm)
+        .await?
+        .into_result()?;
+    ft.call("new")
+        .args_json(json!({ "owner_id": alice.id(), "total_supply": "1000000" }))
+        .transact()
+        .await?
+        .into_result()?;
+    // the tenk contract receives the vault fees, bob the released tokens
+    for account_id in [tenk.id(), bob.id()] {
+        storage_deposit(&alice, &ft, account_id).await?;
+    }
+
+    owner
+        .call(tenk.id(), "add_accepted_token")
+        .args_json(json!({ "token_contract_id": ft.id() }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    Ok(Setup {
+        worker,
+        tenk,
+        ft,
+        alice,
+        bob,
+    })
+}
+
+async fn create_user(owner: &Account, name: &str) -> anyhow::Result<Account> {
+    Ok(owner
+        .create_subaccount(name)
+        .initial_balance(NearToken::from_near(30))
+        .transact()
+        .await?
+        .into_result()?)
+}
+
+async fn storage_deposit(payer: &Account, ft: &Contract, account_id: &AccountId) -> anyhow::Result<()> {
+    payer
+        .call(ft.id(), "storage_deposit")
+        .args_json(json!({ "account_id": account_id }))
+        .deposit(NearToken::from_millinear(125))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+async fn ft_balance(ft: &Contract, account_id: &AccountId) -> anyhow::Result<u128> {
+    let balance: String = ft
+        .view("ft_balance_of")
+        .args_json(json!({ "account_id": account_id }))
+        .await?
+        .json()?;
+    Ok(balance.parse()?)
+}
+
+async fn view(worker: &Worker<Sandbox>, account_id: &AccountId, method: &str, args: Value) -> anyhow::Result<Value> {
+    Ok(worker.view(account_id, method).args_json(args).await?.json()?)
+}
+
+fn amount(value: &Value) -> u128 {
+    value.as_str().unwrap().parse().unwrap()
+}
+
+#[tokio::test]
+async fn mint_fund_transfer_burn_release() -> anyhow::Result<()> {
+    let Setup {
+        worker,
+        tenk,
+        ft,
+        alice,
+        bob,
+    } = setup().await?;
+
+    // mint, which deploys the token's vault
+    let cost = view(
+        &worker,
+        tenk.id(),
+        "estimate_mint_cost",
+        json!({ "num": 1, "account_id": alice.id() }),
+    )
+    .await?;
+    alice
+        .call(tenk.id(), "nft_mint_one")
+        .args_json(json!({
+            "token_deposit": [{
+                "token_contract_id": ft.id(),
+                "token_amount": FT_DEPOSIT.to_string(),
+                "is_deposited": false,
+            }],
+            "near_amount": NearToken::from_near(1).as_yoctonear().to_string(),
+        }))
+        .deposit(NearToken::from_yoctonear(amount(&cost["total"])))
+        .gas(MAX_GAS)
+        .transact()
+        .await?
+        .into_result()?;
+
+    let vault_id: AccountId = format!("vault_1.{}", tenk.id()).parse()?;
+    let status = view(&worker, &vault_id, "get_status", json!({})).await?;
+    assert_eq!(status["fully_funded"], false);
+    assert_eq!(status["token_holder"], json!(alice.id()));
+
+    // FT deposit, fee included
+    storage_deposit(&alice, &ft, &vault_id).await?;
+    let required = amount(&status["tokens"][0]["required"]);
+    alice
+        .call(ft.id(), "ft_transfer_call")
+        .args_json(json!({
+            "receiver_id": vault_id,
+            "amount": required.to_string(),
+            "msg": "",
+        }))
+        .deposit(ONE_YOCTO)
+        .gas(MAX_GAS)
+        .transact()
+        .await?
+        .into_result()?;
+    assert_eq!(ft_balance(&ft, &vault_id).await?, FT_DEPOSIT);
+
+    // NEAR deposit, fee included
+    alice
+        .call(&vault_id, "deposit_near")
+        .deposit(NearToken::from_yoctonear(amount(&status["near_remaining"])))
+        .gas(MAX_GAS)
+        .transact()
+        .await?
+        .into_result()?;
+    let status = view(&worker, &vault_id, "get_status", json!({})).await?;
+    assert_eq!(status["fully_funded"], true);
+
+    let tokens = view(&worker, tenk.id(), "nft_tokens_detailed", json!({})).await?;
+    assert_eq!(tokens[0]["vault_status"], "Funded");
+
+    // transfer, the vault follows the new holder
+    alice
+        .call(tenk.id(), "nft_transfer")
+        .args_json(json!({ "receiver_id": bob.id(), "token_id": "1" }))
+        .deposit(ONE_YOCTO)
+        .gas(MAX_GAS)
+        .transact()
+        .await?
+        .into_result()?;
+    let info = view(&worker, &vault_id, "get_info", json!({})).await?;
+    assert_eq!(info["beneficiary_id"], json!(bob.id()));
+
+    // burn, the vault releases everything to bob and deletes itself
+    let bob_near = bob.view_account().await?.balance;
+    let burn_fee = view(&worker, tenk.id(), "burn_fee", json!({ "token_id": "1" })).await?;
+    bob.call(tenk.id(), "nft_burn")
+        .args_json(json!({ "token_id": "1" }))
+        .deposit(NearToken::from_yoctonear(amount(&burn_fee).max(1)))
+        .gas(MAX_GAS)
+        .transact()
+        .await?
+        .into_result()?;
+
+    assert_eq!(ft_balance(&ft, bob.id()).await?, FT_DEPOSIT);
+    assert!(bob.view_account().await?.balance > bob_near);
+    assert!(worker.view_account(&vault_id).await.is_err());
+    let tokens = view(&worker, tenk.id(), "nft_tokens_for_owner", json!({ "account_id": bob.id() })).await?;
+    assert_eq!(tokens, json!([]));
+    Ok(())
+}
+
+#[tokio::test]
+async fn unknown_tokens_are_refunded_by_the_vault() -> anyhow::Result<()> {
+    let Setup {
+        worker,
+        tenk,
+        ft,
+        alice,
+        bob: _,
+    } = setup().await?;
+
+    let cost = view(
+        &worker,
+        tenk.id(),
+        "estimate_mint_cost",
+        json!({ "num": 1, "account_id": alice.id() }),
+    )
+    .await?;
+    alice
+        .call(tenk.id(), "nft_mint_one")
+        .args_json(json!({ "token_deposit": [], "near_amount": "0" }))
+        .deposit(NearToken::from_yoctonear(amount(&cost["total"])))
+        .gas(MAX_GAS)
+        .transact()
+        .await?
+        .into_result()?;
+
+    // a vault without deposits to wait for reports being funded when it's created
+    let tokens = view(&worker, tenk.id(), "nft_tokens_detailed", json!({})).await?;
+    assert_eq!(tokens[0]["vault_status"], "Funded");
+
+    let vault_id: AccountId = format!("vault_1.{}", tenk.id()).parse()?;
+    storage_deposit(&alice, &ft, &vault_id).await?;
+    let before = ft_balance(&ft, alice.id()).await?;
+    alice
+        .call(ft.id(), "ft_transfer_call")
+        .args_json(json!({ "receiver_id": vault_id, "amount": "500", "msg": "" }))
+        .deposit(ONE_YOCTO)
+        .gas(MAX_GAS)
+        .transact()
+        .await?
+        .into_result()?;
+    assert_eq!(ft_balance(&ft, alice.id()).await?, before);
+    assert_eq!(ft_balance(&ft, &vault_id).await?, 0);
+    Ok(())
+}
@@ -0,0 +1,169 @@
+//! NEP-297 events of the vault lifecycle, `EVENT_JSON:{"standard":"liquid_nft_vault",...}`.
+//!
+//! Only depends on what every near-sdk version provides, the tenk contract includes this
+//! file as is to log the events it originates under the same standard.
+use near_sdk::json_types::U128;
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json::{self, Value};
+use near_sdk::{env, AccountId};
+
+pub const VAULT_EVENT_STANDARD: &str = "liquid_nft_vault";
+pub const VAULT_EVENT_VERSION: &str = "1.0.0";
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct VaultEventLog<'a> {
+    standard: &'a str,
+    version: &'a str,
+    #[serde(flatten)]
+    event: VaultEvent,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(tag = "event", content = "data")]
+#[serde(rename_all = "snake_case")]
+pub enum VaultEvent {
+    /// Logged by the NFT contract once the vault account exists
+    VaultCreated(Vec<VaultCreatedData>),
+    DepositReceived(Vec<DepositReceivedData>),
+    FeeCollected(Vec<FeeCollectedData>),
+    FundingCompleted(Vec<FundingCompletedData>),
+    ReleaseRequested(Vec<ReleaseRequestedData>),
+    /// Logged right before the vault is deleted, with the exact payout of every asset
+    VaultReleased(Vec<VaultReleasedData>),
+    /// Every move of the vault between lifecycle stages
+    StatusChanged(Vec<StatusChangedData>),
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VaultCreatedData {
+    pub token_id: String,
+    pub vault_id: AccountId,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DepositReceivedData {
+    pub token_id: String,
+    /// None, means native NEAR
+    pub token_contract_id: Option<AccountId>,
+    pub depositor: AccountId,
+    /// Amount kept in the vault, the fee is logged separately
+    pub amount: U128,
+    pub funded_for: Option<AccountId>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeeCollectedData {
+    pub token_id: String,
+    /// None, means native NEAR
+    pub token_contract_id: Option<AccountId>,
+    pub fee_recipient: AccountId,
+    pub amount: U128,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FundingCompletedData {
+    pub token_id: String,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ReleaseRequestedData {
+    pub token_id: String,
+    pub owner_id: AccountId,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VaultReleasedData {
+    pub token_id: String,
+    pub beneficiary_id: AccountId,
+    /// Receipt of every released asset, as returned by the vault's `get_release_receipt`
+    pub assets: Value,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StatusChangedData {
+    pub token_id: String,
+    pub from: String,
+    pub to: String,
+}
+
+impl VaultEvent {
+    pub fn log(self) {
+        let log = VaultEventLog {
+            standard: VAULT_EVENT_STANDARD,
+            version: VAULT_EVENT_VERSION,
+            event: self,
+        };
+        env::log_str(&format!("EVENT_JSON:{}", serde_json::to_string(&log).unwrap()));
+    }
+
+    pub fn log_vault_created(token_id: String, vault_id: AccountId) {
+        VaultEvent::VaultCreated(vec![VaultCreatedData { token_id, vault_id }]).log();
+    }
+
+    pub fn log_deposit_received(
+        token_id: String,
+        token_contract_id: Option<AccountId>,
+        depositor: AccountId,
+        amount: U128,
+        funded_for: Option<AccountId>,
+    ) {
+        VaultEvent::DepositReceived(vec![DepositReceivedData {
+            token_id,
+            token_contract_id,
+            depositor,
+            amount,
+            funded_for,
+        }])
+        .log();
+    }
+
+    pub fn log_fee_collected(
+        token_id: String,
+        token_contract_id: Option<AccountId>,
+        fee_recipient: AccountId,
+        amount: U128,
+    ) {
+        VaultEvent::FeeCollected(vec![FeeCollectedData {
+            token_id,
+            token_contract_id,
+            fee_recipient,
+            amount,
+        }])
+        .log();
+    }
+
+    pub fn log_funding_completed(token_id: String) {
+        VaultEvent::FundingCompleted(vec![FundingCompletedData { token_id }]).log();
+    }
+
+    pub fn log_release_requested(token_id: String, owner_id: AccountId) {
+        VaultEvent::ReleaseRequested(vec![ReleaseRequestedData { token_id, owner_id }]).log();
+    }
+
+    pub fn log_status_changed(token_id: String, from: &str, to: &str) {
+        VaultEvent::StatusChanged(vec![StatusChangedData {
+            token_id,
+            from: from.to_string(),
+            to: to.to_string(),
+        }])
+        .log();
+    }
+
+    pub fn log_vault_released(token_id: String, beneficiary_id: AccountId, assets: Value) {
+        VaultEvent::VaultReleased(vec![VaultReleasedData {
+            token_id,
+            beneficiary_id,
+            assets,
+        }])
+        .log();
+    }
+}
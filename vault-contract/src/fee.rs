@@ -0,0 +1,125 @@
+//! Deposit fee math, in basis points.
+//!
+//! Rounding rules:
+//! - the fee on an amount is rounded up, dust is never left uncharged
+//! - the held amount is exactly the configured amount, rounding never eats into it
+//! - an FT deposit has to be exactly `amount + fee`, there is no tolerance for dust,
+//!   NEAR deposits above it get the change back
+
+pub const ONE_HUNDRED_PERCENT_IN_BPS: u128 = 10_000;
+
+/// Fee charged on top of `amount`, rounded up
+pub fn fee_for(amount: u128, fee_bps: u16) -> u128 {
+    let numerator = amount
+        .checked_mul(fee_bps as u128)
+        .expect("Fee computation overflow");
+    numerator / ONE_HUNDRED_PERCENT_IN_BPS
+        + if numerator % ONE_HUNDRED_PERCENT_IN_BPS == 0 { 0 } else { 1 }
+}
+
+/// What a depositor has to send for `amount` to be held by the vault
+pub fn amount_with_fee(amount: u128, fee_bps: u16) -> u128 {
+    amount
+        .checked_add(fee_for(amount, fee_bps))
+        .expect("Fee computation overflow")
+}
+
+/// How a deposit is divided: `principal` is held for release, `fee` goes to the fee recipient
+#[derive(Debug, PartialEq)]
+pub struct DepositSplit {
+    pub principal: u128,
+    pub fee: u128,
+}
+
+/// Split a deposit meant to fund `amount`, None unless it is exactly `amount + fee`
+pub fn split_deposit(deposit: u128, amount: u128, fee_bps: u16) -> Option<DepositSplit> {
+    let fee = fee_for(amount, fee_bps);
+    if amount.checked_add(fee)? != deposit {
+        return None;
+    }
+    Some(DepositSplit {
+        principal: amount,
+        fee,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exact_fee() {
+        assert_eq!(fee_for(10_000, 100), 100);
+        assert_eq!(amount_with_fee(10_000, 100), 10_100);
+    }
+
+    #[test]
+    fn fee_rounds_up() {
+        assert_eq!(fee_for(1, 100), 1);
+        assert_eq!(fee_for(10_001, 100), 101);
+        assert_eq!(amount_with_fee(199, 50), 200);
+    }
+
+    #[test]
+    fn zero_fee() {
+        assert_eq!(fee_for(12_345, 0), 0);
+        assert_eq!(amount_with_fee(12_345, 0), 12_345);
+        assert_eq!(fee_for(0, 1_000), 0);
+    }
+
+    #[test]
+    fn large_amounts() {
+        // 1M NEAR in yocto at the 10% cap
+        let amount = 1_000_000 * 10u128.pow(24);
+        assert_eq!(fee_for(amount, 1_000), amount / 10);
+        assert_eq!(amount_with_fee(amount, 1_000), amount + amount / 10);
+    }
+
+    #[test]
+    fn split_exact_deposit() {
+        assert_eq!(
+            split_deposit(10_100, 10_000, 100),
+            Some(DepositSplit { principal: 10_000, fee: 100 })
+        );
+        assert_eq!(
+            split_deposit(12_345, 12_345, 0),
+            Some(DepositSplit { principal: 12_345, fee: 0 })
+        );
+    }
+
+    #[test]
+    fn split_rejects_inexact_deposit() {
+        assert_eq!(split_deposit(10_099, 10_000, 100), None);
+        assert_eq!(split_deposit(10_101, 10_000, 100), None);
+        // the old flow's fee of amount / 100 is short by one for odd amounts
+        assert_eq!(split_deposit(10_001 + 100, 10_001, 100), None);
+        assert_eq!(split_deposit(0, 1, 100), None);
+    }
+
+    #[test]
+    fn split_odd_amounts() {
+        for fee_bps in [0u16, 1, 3, 50, 99, 100, 333, 999, 1_000] {
+            for amount in (0u128..2_000).chain([10u128.pow(24) - 1, 10u128.pow(24) + 1, 7 * 10u128.pow(23) + 13]) {
+                let deposit = amount_with_fee(amount, fee_bps);
+                let split = split_deposit(deposit, amount, fee_bps).unwrap();
+                // the principal is held exactly and the whole deposit is accounted for
+                assert_eq!(split.principal, amount);
+                assert_eq!(split.principal + split.fee, deposit);
+                // the fee is never below the exact bps share, and at most one yocto above it
+                let exact = amount * fee_bps as u128;
+                assert!(split.fee * ONE_HUNDRED_PERCENT_IN_BPS >= exact);
+                assert!(split.fee * ONE_HUNDRED_PERCENT_IN_BPS < exact + ONE_HUNDRED_PERCENT_IN_BPS);
+                if deposit > 0 {
+                    assert_eq!(split_deposit(deposit - 1, amount, fee_bps), None);
+                }
+                assert_eq!(split_deposit(deposit + 1, amount, fee_bps), None);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Fee computation overflow")]
+    fn overflow() {
+        fee_for(u128::MAX, 2);
+    }
+}
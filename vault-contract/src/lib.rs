@@ -1,19 +1,303 @@
 use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::json_types::U128;
+use near_sdk::json_types::{Base58CryptoHash, Base64VecU8, U128, U64};
 use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::serde_json::json;
 use near_sdk::{
-    env, near_bindgen, require, AccountId, Balance, Gas, PanicOnDefault, Promise, PromiseOrValue,
+    env, ext_contract, near_bindgen, require, AccountId, Balance, Gas, PanicOnDefault, Promise,
+    PromiseOrValue, PromiseResult,
 };
 
+mod event;
+mod fee;
+mod valuation;
+
+use event::VaultEvent;
+use valuation::PriceData;
+
+const GAS_FOR_FT_TRANSFER: Gas = Gas(60_000_000_000_000);
+const GAS_FOR_FT_TRANSFER_CALL: Gas = Gas(60_000_000_000_000);
+const GAS_FOR_STRATEGY_WITHDRAW: Gas = Gas(80_000_000_000_000);
+const GAS_FOR_STRATEGY_CALLBACK: Gas = Gas(20_000_000_000_000);
+const GAS_FOR_RELEASE_CALLBACK: Gas = Gas(100_000_000_000_000);
+const GAS_FOR_RECORD_FEE: Gas = Gas(10_000_000_000_000);
+const GAS_FOR_STORAGE_BALANCE_OF: Gas = Gas(5_000_000_000_000);
+const GAS_FOR_FT_BALANCE_OF: Gas = Gas(5_000_000_000_000);
+const GAS_FOR_STORAGE_DEPOSIT: Gas = Gas(10_000_000_000_000);
+/// Continuing a release relies on the unused gas shared out to callbacks on top of this
+const GAS_FOR_TOKEN_RELEASED_CALLBACK: Gas = Gas(5_000_000_000_000);
+const GAS_FOR_STORAGE_CHECKED_CALLBACK: Gas = Gas(85_000_000_000_000);
+/// Checking the FT balances left before deletion relies on the unused gas on top of this
+const GAS_FOR_RELEASE_COMPLETE_CALLBACK: Gas = Gas(10_000_000_000_000);
+/// Sweeping the FT balances left relies on the unused gas on top of this
+const GAS_FOR_RESIDUALS_CHECKED_CALLBACK: Gas = Gas(10_000_000_000_000);
+const GAS_FOR_RESIDUALS_SWEPT_CALLBACK: Gas = Gas(10_000_000_000_000);
+/// Storage check, registration, transfer and callbacks of one asset in a release
+const GAS_FOR_RELEASE_STEP: Gas = Gas(100_000_000_000_000);
+/// Most FTs one release invocation sends, the gas left usually stops it earlier
+const MAX_RELEASE_BATCH: u32 = 10;
+const GAS_FOR_MIGRATE: Gas = Gas(20_000_000_000_000);
+const GAS_FOR_FUNDED_NOTIFY: Gas = Gas(10_000_000_000_000);
+const GAS_FOR_SKIM_CALLBACK: Gas = Gas(70_000_000_000_000);
+const GAS_FOR_FEE_FORWARDED_CALLBACK: Gas = Gas(5_000_000_000_000);
+const GAS_FOR_GET_PRICE_DATA: Gas = Gas(10_000_000_000_000);
+// reads the prices and reports the valuation to the NFT contract
+const GAS_FOR_PRICE_DATA_CALLBACK: Gas = Gas(15_000_000_000_000);
+const GAS_FOR_VALUATION_NOTIFY: Gas = Gas(5_000_000_000_000);
+/// Covers registering the receiver on an FT contract, paid from the vault's own balance
+const STORAGE_DEPOSIT_RESERVE: Balance = 1_250_000_000_000_000_000_000;
+const DEFAULT_FEE_BPS: u16 = 100;
+/// Deposit fees are capped at 10%
+const MAX_FEE_BPS: u16 = 1_000;
+/// How long the first authorization of a dual-control release waits for the second one
+const PENDING_RELEASE_TTL_MS: u64 = 24 * 60 * 60 * 1000;
+
+#[ext_contract(ext_self)]
+trait SelfCallbacks {
+    fn on_strategy_deposit(&mut self, token_contract_ids: Vec<AccountId>);
+    fn on_strategy_withdraw(&mut self, owner_id: AccountId) -> PromiseOrValue<bool>;
+    fn on_storage_checked(
+        &mut self,
+        receiver_id: AccountId,
+        token_contract_id: AccountId,
+        amount: U128,
+        batch_left: Option<u32>,
+    ) -> Promise;
+    fn on_token_released(&mut self, asset: ReleasedAsset, batch_left: Option<u32>) -> Promise;
+    fn on_release_complete(&mut self) -> PromiseOrValue<bool>;
+    fn on_residuals_checked(&mut self, token_contract_ids: Vec<AccountId>) -> PromiseOrValue<bool>;
+    fn on_residuals_swept(&mut self, residuals: Vec<ReleasedAsset>) -> bool;
+    fn on_skim_balance(&mut self, token_contract_id: AccountId, to: AccountId) -> Promise;
+    fn on_fee_forwarded(&mut self, index: Option<u32>) -> bool;
+    fn on_price_data(&mut self) -> Option<Valuation>;
+}
+
 #[derive(Serialize, Deserialize)]
 #[serde(crate = "near_sdk::serde")]
 pub struct AssetArgs {
     token_id: String,
     near_amount: U128,
     near_deposited: bool,
+    near_depositor: Option<AccountId>,
+    near_funded_for: Option<AccountId>,
     token_deposit: Vec<TokenDeposit>,
+    strategy_contract: Option<AccountId>,
+    strategy_deposited: bool,
+    wrap_near_contract: Option<AccountId>,
+    near_wrapped: bool,
+    beneficiary_id: Option<AccountId>,
+    status: VaultState,
+    cancelled: bool,
+    release_requested: bool,
+    /// Whether release transfers are still in flight
+    release_pending: bool,
+    /// Nonce of the release request being processed
+    release_nonce: Option<u64>,
+}
+
+/// Who funded one asset of the vault, and for whom
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Funding {
+    /// None, means native NEAR
+    token_contract_id: Option<AccountId>,
+    amount: U128,
+    depositor: AccountId,
+    funded_for: Option<AccountId>,
+}
+
+/// `msg` of an FT deposit made by a sponsor on behalf of the token holder
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct FundingMsg {
+    fund_for: AccountId,
+}
+
+/// `msg` of an FT deposit the NFT contract routed to the vault for `depositor`
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct RoutedMsg {
+    depositor: AccountId,
+    fund_for: Option<AccountId>,
+}
+
+/// One asset sent out by a release
+#[derive(Serialize, Deserialize, BorshDeserialize, BorshSerialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ReleasedAsset {
+    /// None, means native NEAR
+    token_contract_id: Option<AccountId>,
+    receiver_id: AccountId,
+    amount: U128,
+    /// Vault's balance on the FT contract right before the transfer
+    balance: Option<U128>,
+}
+
+/// What a release paid out, kept until the vault is deleted
+#[derive(Serialize, Deserialize, BorshDeserialize, BorshSerialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ReleaseReceipt {
+    beneficiary_id: AccountId,
+    assets: Vec<ReleasedAsset>,
+}
+
+/// Release of a high-value vault waiting for its second authorization, see `approve_release`
+#[derive(Serialize, Deserialize, BorshDeserialize, BorshSerialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PendingRelease {
+    owner_id: AccountId,
+    nonce: Option<u64>,
+    /// Authorized by the NFT contract
+    nft_approved: bool,
+    /// Authorized by the risk account
+    risk_approved: bool,
+    /// ms since epoch the record lapses at, both authorizations are needed again after it
+    expires_at: U64,
+}
+
+/// Lifecycle of a vault. Deposits move it between the funding stages, a release or
+/// cancellation ends it and it is deleted right after becoming Released
+#[derive(Serialize, Deserialize, BorshDeserialize, BorshSerialize, Clone, Copy, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum VaultState {
+    Created,
+    PartiallyFunded,
+    Funded,
+    /// Assets are being sent to the token holder
+    Releasing,
+    /// Deposits of a vault that was never fully funded are going back to their depositors
+    Cancelled,
+    Released,
+}
+
+impl VaultState {
+    fn as_str(self) -> &'static str {
+        match self {
+            VaultState::Created => "created",
+            VaultState::PartiallyFunded => "partially_funded",
+            VaultState::Funded => "funded",
+            VaultState::Releasing => "releasing",
+            VaultState::Cancelled => "cancelled",
+            VaultState::Released => "released",
+        }
+    }
+
+    fn takes_deposits(self) -> bool {
+        matches!(self, VaultState::Created | VaultState::PartiallyFunded)
+    }
+
+    fn can_become(self, to: VaultState) -> bool {
+        use VaultState::*;
+        match (self, to) {
+            (Created, PartiallyFunded | Funded | Releasing | Cancelled) => true,
+            (PartiallyFunded, Created | Funded | Releasing | Cancelled) => true,
+            // a top up adds deposits to wait for
+            (Funded, Created | PartiallyFunded | Releasing) => true,
+            (Releasing | Cancelled, Released) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Who has to co-sign releases and from which basket value on
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DualControl {
+    risk_account: Option<AccountId>,
+    /// yoctoNEAR, None when every release goes through on the NFT contract's request alone
+    threshold: Option<U128>,
+    /// Whether a release currently needs both authorizations
+    required: bool,
+}
+
+/// Progress of a release, assets are sent in batches until none is left
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ReleaseProgress {
+    receiver_id: AccountId,
+    /// assets still held, NEAR included
+    assets_left: u32,
+    /// held FTs skipped in this pass because their transfer failed
+    cursor: u32,
+    /// whether transfers are still in flight
+    pending: bool,
+}
+
+/// What the vault belongs to, for integrations that can't trust the account name alone
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VaultMetadata {
+    nft_contract_id: AccountId,
+    token_id: String,
+    /// ms since epoch, None for vaults created before it was recorded
+    created_at: Option<U64>,
+    /// sha256 of the deployed code, None when the deployer didn't provide it
+    code_hash: Option<Base58CryptoHash>,
+    /// ms since epoch before which the assets can't be released, None without a lockup
+    lockup_until: Option<U64>,
+    /// Account that initialized the vault, None for vaults created before it was recorded
+    created_by: Option<AccountId>,
+}
+
+/// Fee charged on top of every deposit and who receives it
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeeInfo {
+    fee_recipient: AccountId,
+    fee_bps: u16,
+}
+
+/// Exact figures of a deposit, see the rounding rules in `fee`
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeeQuote {
+    /// amount held by the vault
+    amount: U128,
+    fee: U128,
+    /// amount the depositor has to send
+    total: U128,
+}
+
+/// Value of the held assets at the oracle's prices, see `valuation`
+#[derive(Serialize, Deserialize, BorshDeserialize, BorshSerialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Valuation {
+    /// USD with `valuation::USD_DECIMALS` decimals
+    value_usd: U128,
+    /// yoctoNEAR at the oracle's NEAR price
+    value_near: U128,
+    /// ms since epoch the prices were reported at
+    priced_at: U64,
+    /// held assets the oracle has no price for, they are left out of the value
+    unpriced: Vec<AccountId>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenStatus {
+    token_contract_id: AccountId,
+    /// amount the depositor has to send, fee included
+    required: U128,
+    /// amount held for the token holder
+    deposited: U128,
+    fee_collected: U128,
+}
+
+/// Funding progress of the vault, as shown to the token holder
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VaultStatus {
+    token_id: String,
+    near_required: U128,
+    near_deposited: U128,
+    /// NEAR still to attach to `deposit_near`, fee included
+    near_remaining: U128,
+    near_fee_collected: U128,
+    tokens: Vec<TokenStatus>,
+    fully_funded: bool,
+    release_requested: bool,
+    token_holder: Option<AccountId>,
 }
 
 #[derive(Serialize, Deserialize, BorshDeserialize, BorshSerialize, Clone)]
@@ -22,6 +306,14 @@ pub struct TokenDeposit {
     token_contract_id: AccountId,
     token_amount: U128,
     is_deposited: bool,
+    /// Account that sent the tokens, refunded if the vault is cancelled
+    depositor: Option<AccountId>,
+    /// Member a sponsor sent the tokens for, see `fund_for`
+    funded_for: Option<AccountId>,
+    /// Whether the fee of the deposit is still being forwarded, no other deposit
+    /// of the token is taken until it is resolved
+    #[serde(default)]
+    fee_pending: bool,
 }
 
 #[near_bindgen]
@@ -31,7 +323,80 @@ pub struct Contract {
     token_id: String,
     near_amount: U128,
     near_deposited: bool,
+    /// Account that sent the NEAR deposit, refunded if the vault is cancelled
+    near_depositor: Option<AccountId>,
+    /// Member a sponsor sent the NEAR deposit for
+    near_funded_for: Option<AccountId>,
     token_deposit: Vec<TokenDeposit>,
+    /// Optional DeFi contract the funded basket is put to work in
+    strategy_contract: Option<AccountId>,
+    /// Whether the FT balances currently sit in the strategy contract
+    strategy_deposited: bool,
+    /// wNEAR contract whose tokens can stand in for the NEAR deposit. None, means the
+    /// NEAR deposit is only taken natively
+    wrap_near_contract: Option<AccountId>,
+    /// Whether the NEAR deposit is held as wNEAR
+    near_wrapped: bool,
+    /// Current holder of the membership token, kept up to date by the NFT contract
+    beneficiary_id: Option<AccountId>,
+    /// Nonce of the last beneficiary update, older updates are ignored
+    beneficiary_nonce: u64,
+    /// Where the vault is in its lifecycle, only changed through `transition`
+    status: VaultState,
+    /// Nonce the release was requested with, the same request sent again is a no-op
+    release_nonce: Option<u64>,
+    /// Account the assets are being released to, kept until every transfer went through
+    release_receiver: Option<AccountId>,
+    /// Whether release transfers are still in flight
+    release_pending: bool,
+    /// Payouts of the current release
+    release_receipt: Option<ReleaseReceipt>,
+    /// Receiver of the deposit fees
+    fee_recipient: AccountId,
+    fee_bps: u16,
+    /// ms since epoch the vault was created at, None for vaults created before it was recorded
+    created_at: Option<u64>,
+    /// sha256 of the code the vault runs, as provided by the NFT contract
+    code_hash: Option<Base58CryptoHash>,
+    /// Held FTs skipped in the current release pass because their transfer failed,
+    /// the next batch starts after them
+    release_cursor: u32,
+    /// Whether the fee of the wNEAR deposit is still being forwarded
+    near_fee_pending: bool,
+    /// Price oracle the basket is valued with, None when valuation is off
+    oracle_contract: Option<AccountId>,
+    /// Last valuation of the basket, refreshed by `update_valuation`
+    valuation: Option<Valuation>,
+    /// ms since epoch before which the assets can't be released, None without a lockup
+    lockup_until: Option<u64>,
+    /// Account co-signing releases of baskets worth `dual_control_threshold` or more
+    risk_account: Option<AccountId>,
+    dual_control_threshold: Option<U128>,
+    /// Release authorized by one side only, waiting for the other
+    pending_release: Option<PendingRelease>,
+    /// Account that initialized the vault, the NFT contract. None for vaults created
+    /// before it was recorded
+    created_by: Option<AccountId>,
+    /// FT amounts the strategy contract took, as reported back by `ft_transfer_call`
+    strategy_balances: Vec<(AccountId, U128)>,
+}
+
+/// State layout of vaults created before they could be upgraded, see `migrate_baseline`
+#[derive(BorshDeserialize)]
+struct ContractV1 {
+    owner_id: AccountId,
+    token_id: String,
+    near_amount: U128,
+    near_deposited: bool,
+    token_deposit: Vec<TokenDepositV1>,
+}
+
+#[derive(BorshDeserialize)]
+struct TokenDepositV1 {
+    token_contract_id: AccountId,
+    token_amount: U128,
+    #[allow(dead_code)]
+    is_deposited: bool,
 }
 
 #[near_bindgen]
@@ -41,25 +406,207 @@ impl Contract {
         owner_id: AccountId,
         token_id: String,
         near_amount: U128,
-        token_deposit: Vec<TokenDeposit>,
+        mut token_deposit: Vec<TokenDeposit>,
+        strategy_contract: Option<AccountId>,
+        wrap_near_contract: Option<AccountId>,
+        fee_recipient: Option<AccountId>,
+        fee_bps: Option<u16>,
+        beneficiary_id: Option<AccountId>,
+        code_hash: Option<Base58CryptoHash>,
+        oracle_contract: Option<AccountId>,
+        lockup_until: Option<U64>,
+        risk_account: Option<AccountId>,
+        dual_control_threshold: Option<U128>,
     ) -> Self {
         require!(!env::state_exists(), "Already initialized");
+        // a vault only holds assets for a token of the contract that created it
+        require!(
+            env::predecessor_account_id() == owner_id,
+            "Only the NFT contract can create its vault"
+        );
+        let fee_bps = fee_bps.unwrap_or(DEFAULT_FEE_BPS);
+        require!(fee_bps <= MAX_FEE_BPS, "fee_bps must be between 0 - 1,000");
 
-        for token in &token_deposit {
+        for (i, token) in token_deposit.iter().enumerate() {
+            // a deposit could otherwise be matched against two requirements
+            require!(
+                token_deposit[..i]
+                    .iter()
+                    .all(|other| other.token_contract_id != token.token_contract_id),
+                "Duplicate token contract"
+            );
+            require!(
+                near_amount.0 == 0
+                    || wrap_near_contract.as_ref() != Some(&token.token_contract_id),
+                "wNEAR stands in for the NEAR deposit"
+            );
+        }
+        for token in &mut token_deposit {
             require!(
                 env::is_valid_account_id(token.token_contract_id.as_bytes()),
                 "Not valid token contract id"
             );
             require!(token.token_amount > U128(0), "Cannot wrap 0 token");
-            require!(token.is_deposited == true, "is_deposit must be true");
+            // declared tokens are sent with `ft_transfer_call` once the vault exists
+            require!(!token.is_deposited, "Tokens are deposited after the vault is created");
+            token.depositor = None;
+            token.funded_for = None;
+            token.fee_pending = false;
         }
 
-        Self {
+        let mut vault = Self {
+            fee_recipient: fee_recipient.unwrap_or_else(|| owner_id.clone()),
+            fee_bps,
             owner_id,
             token_id,
             near_amount,
             near_deposited: false,
+            near_depositor: None,
+            near_funded_for: None,
             token_deposit,
+            strategy_contract,
+            strategy_deposited: false,
+            wrap_near_contract,
+            near_wrapped: false,
+            beneficiary_id,
+            beneficiary_nonce: 0,
+            status: VaultState::Created,
+            release_nonce: None,
+            release_receiver: None,
+            release_pending: false,
+            release_receipt: None,
+            created_at: Some(env::block_timestamp_ms()),
+            code_hash,
+            release_cursor: 0,
+            near_fee_pending: false,
+            oracle_contract,
+            valuation: None,
+            lockup_until: lockup_until.map(|lockup_until| lockup_until.0),
+            risk_account,
+            dual_control_threshold,
+            pending_release: None,
+            created_by: Some(env::predecessor_account_id()),
+            strategy_balances: vec![],
+        };
+        // a vault without assets to wait for is funded from the start
+        vault.status = vault.funding_status();
+        if vault.status == VaultState::Funded {
+            vault.notify_funded();
+        }
+        vault
+    }
+
+    /// Deploy new vault code and migrate the state, only the NFT contract can upgrade
+    pub fn upgrade(&mut self, code: Base64VecU8) -> Promise {
+        assert_eq!(env::predecessor_account_id(), self.owner_id, "Unauthorized");
+        let code_hash: [u8; 32] = env::sha256(&code.0).try_into().unwrap();
+        let code_hash = Base58CryptoHash::from(code_hash);
+        Promise::new(env::current_account_id())
+            .deploy_contract(code.into())
+            .function_call(
+                "migrate".to_string(),
+                json!({ "code_hash": code_hash }).to_string().into_bytes(),
+                0,
+                GAS_FOR_MIGRATE,
+            )
+    }
+
+    /// Called right after `upgrade`
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate(code_hash: Option<Base58CryptoHash>) -> Self {
+        let mut vault: Self =
+            env::state_read().unwrap_or_else(|| env::panic_str("Vault state not found"));
+        vault.code_hash = code_hash.or(vault.code_hash);
+        vault
+    }
+
+    /// Called with the creator's key after deploying this code to a vault created before
+    /// vaults could be upgraded. Those vaults required every FT to be marked deposited at
+    /// creation and so bounced every FT deposit, their FTs are taken again from scratch.
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate_baseline() -> Self {
+        let old: ContractV1 =
+            env::state_read().unwrap_or_else(|| env::panic_str("Vault state not found"));
+        let mut vault = Self {
+            fee_recipient: old.owner_id.clone(),
+            fee_bps: DEFAULT_FEE_BPS,
+            owner_id: old.owner_id,
+            token_id: old.token_id,
+            near_amount: old.near_amount,
+            near_deposited: old.near_deposited,
+            near_depositor: None,
+            near_funded_for: None,
+            token_deposit: old
+                .token_deposit
+                .into_iter()
+                .map(|token| TokenDeposit {
+                    token_contract_id: token.token_contract_id,
+                    token_amount: token.token_amount,
+                    is_deposited: false,
+                    depositor: None,
+                    funded_for: None,
+                    fee_pending: false,
+                })
+                .collect(),
+            strategy_contract: None,
+            strategy_deposited: false,
+            wrap_near_contract: None,
+            near_wrapped: false,
+            beneficiary_id: None,
+            beneficiary_nonce: 0,
+            status: VaultState::Created,
+            release_nonce: None,
+            release_receiver: None,
+            release_pending: false,
+            release_receipt: None,
+            created_at: None,
+            code_hash: None,
+            release_cursor: 0,
+            near_fee_pending: false,
+            oracle_contract: None,
+            valuation: None,
+            lockup_until: None,
+            risk_account: None,
+            dual_control_threshold: None,
+            pending_release: None,
+            created_by: None,
+            strategy_balances: vec![],
+        };
+        vault.status = vault.funding_status();
+        vault
+    }
+
+    /// Whether this vault holds the assets of `token_id` of `nft_contract`
+    pub fn verify(&self, token_id: String, nft_contract: AccountId) -> bool {
+        self.token_id == token_id && self.owner_id == nft_contract
+    }
+
+    pub fn vault_metadata(&self) -> VaultMetadata {
+        VaultMetadata {
+            nft_contract_id: self.owner_id.clone(),
+            token_id: self.token_id.clone(),
+            created_at: self.created_at.map(U64),
+            code_hash: self.code_hash,
+            lockup_until: self.lockup_until.map(U64),
+            created_by: self.created_by.clone(),
+        }
+    }
+
+    pub fn get_fee_info(&self) -> FeeInfo {
+        FeeInfo {
+            fee_recipient: self.fee_recipient.clone(),
+            fee_bps: self.fee_bps,
+        }
+    }
+
+    /// What depositing `amount` costs with this vault's fee
+    pub fn get_fee_quote(&self, amount: U128) -> FeeQuote {
+        FeeQuote {
+            amount,
+            fee: U128(self.fee_for(amount)),
+            total: U128(self.amount_with_fee(amount)),
         }
     }
 
@@ -68,63 +615,1415 @@ impl Contract {
             token_id: String::from(self.token_id.clone()),
             near_amount: self.near_amount,
             near_deposited: self.near_deposited,
+            near_depositor: self.near_depositor.clone(),
+            near_funded_for: self.near_funded_for.clone(),
             token_deposit: self.token_deposit.clone(),
+            strategy_contract: self.strategy_contract.clone(),
+            strategy_deposited: self.strategy_deposited,
+            wrap_near_contract: self.wrap_near_contract.clone(),
+            near_wrapped: self.near_wrapped,
+            beneficiary_id: self.beneficiary_id.clone(),
+            status: self.status,
+            cancelled: self.status == VaultState::Cancelled,
+            release_requested: self.is_releasing(),
+            release_pending: self.release_pending,
+            release_nonce: self.release_nonce,
+        }
+    }
+
+    pub fn get_status(&self) -> VaultStatus {
+        let near_fee = self.fee_for(self.near_amount);
+        let (near_deposited, near_remaining, near_fee_collected) = if self.near_deposited {
+            (self.near_amount, U128(0), U128(near_fee))
+        } else {
+            (U128(0), U128(self.near_amount_with_fee()), U128(0))
+        };
+        VaultStatus {
+            token_id: self.token_id.clone(),
+            near_required: self.near_amount,
+            near_deposited,
+            near_remaining,
+            near_fee_collected,
+            tokens: self
+                .token_deposit
+                .iter()
+                .map(|token| TokenStatus {
+                    token_contract_id: token.token_contract_id.clone(),
+                    required: U128(self.required_token_amount(token.token_amount)),
+                    deposited: if token.is_deposited { token.token_amount } else { U128(0) },
+                    fee_collected: if token.is_deposited {
+                        U128(self.fee_for(token.token_amount))
+                    } else {
+                        U128(0)
+                    },
+                })
+                .collect(),
+            fully_funded: self.is_fully_funded(),
+            release_requested: self.is_releasing(),
+            token_holder: self.beneficiary_id.clone(),
+        }
+    }
+
+    /// Value the held assets at the oracle's current prices. Anyone can call it, e.g. a keeper,
+    /// the result is cached for `get_valuation` and reported to the NFT contract.
+    pub fn update_valuation(&mut self) -> Promise {
+        let oracle_contract = self
+            .oracle_contract
+            .clone()
+            .unwrap_or_else(|| env::panic_str("Vault has no price oracle"));
+        let mut asset_ids: Vec<AccountId> = self
+            .held_assets()
+            .into_iter()
+            .map(|(token_contract_id, _)| token_contract_id)
+            .collect();
+        // NEAR is priced as wNEAR
+        let wrap_near_contract = self
+            .wrap_near_contract
+            .clone()
+            .unwrap_or_else(|| env::panic_str("Vault has no wNEAR contract to price NEAR with"));
+        if !asset_ids.contains(&wrap_near_contract) {
+            asset_ids.push(wrap_near_contract);
+        }
+        Promise::new(oracle_contract)
+            .function_call(
+                "get_price_data".to_string(),
+                json!({ "asset_ids": asset_ids }).to_string().into_bytes(),
+                0,
+                GAS_FOR_GET_PRICE_DATA,
+            )
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_PRICE_DATA_CALLBACK)
+                    .on_price_data(),
+            )
+    }
+
+    /// Value the assets held now with the oracle's prices, the cached valuation is kept
+    /// when the oracle couldn't be read or has no usable NEAR price
+    #[private]
+    pub fn on_price_data(&mut self) -> Option<Valuation> {
+        let price_data = match env::promise_result(0) {
+            PromiseResult::Successful(value) => {
+                near_sdk::serde_json::from_slice::<PriceData>(&value).ok()
+            }
+            _ => None,
+        };
+        let price_data = match price_data {
+            Some(price_data) => price_data,
+            None => {
+                env::log_str("Could not read the oracle prices");
+                return None;
+            }
+        };
+        let near_price = match self
+            .wrap_near_contract
+            .as_ref()
+            .and_then(|wrap_near_contract| price_data.price_of(wrap_near_contract))
+        {
+            Some(near_price) => near_price,
+            None => {
+                env::log_str("Oracle has no NEAR price");
+                return None;
+            }
+        };
+        let mut value_usd: u128 = 0;
+        let mut unpriced = vec![];
+        for (token_contract_id, amount) in self.held_assets() {
+            match price_data
+                .price_of(&token_contract_id)
+                .and_then(|price| valuation::usd_value(amount, price))
+                .and_then(|value| value_usd.checked_add(value))
+            {
+                Some(total) => value_usd = total,
+                None => unpriced.push(token_contract_id),
+            }
+        }
+        let value_near = match valuation::near_value(value_usd, near_price) {
+            Some(value_near) => value_near,
+            None => {
+                env::log_str("Could not convert the basket value to NEAR");
+                return None;
+            }
+        };
+        let valuation = Valuation {
+            value_usd: U128(value_usd),
+            value_near: U128(value_near),
+            priced_at: U64(price_data.timestamp.0 / 1_000_000),
+            unpriced,
+        };
+        self.valuation = Some(valuation.clone());
+        Promise::new(self.owner_id.clone()).function_call(
+            "on_vault_valued".to_string(),
+            json!({ "token_id": self.token_id, "valuation": valuation })
+                .to_string()
+                .into_bytes(),
+            0,
+            GAS_FOR_VALUATION_NOTIFY,
+        );
+        Some(valuation)
+    }
+
+    /// Last valuation of the basket, None until `update_valuation` succeeded once
+    pub fn get_valuation(&self) -> Option<Valuation> {
+        self.valuation.clone()
+    }
+
+    pub fn get_oracle_contract(&self) -> Option<AccountId> {
+        self.oracle_contract.clone()
+    }
+
+    /// ms left until the assets can be released, 0 once the lockup is over or without one
+    pub fn get_lockup_remaining(&self) -> U64 {
+        let now = env::block_timestamp_ms();
+        U64(self.lockup_until.map_or(0, |lockup_until| lockup_until.saturating_sub(now)))
+    }
+
+    pub fn get_dual_control(&self) -> DualControl {
+        DualControl {
+            risk_account: self.risk_account.clone(),
+            threshold: self.dual_control_threshold,
+            required: self.requires_dual_control(),
         }
     }
 
-    pub fn release(&mut self, owner_id: AccountId) {
+    /// Release waiting for its second authorization, None once it expired
+    pub fn get_pending_release(&self) -> Option<PendingRelease> {
+        self.pending_release
+            .clone()
+            .filter(|pending| pending.expires_at.0 > env::block_timestamp_ms())
+    }
+
+    /// Change who co-signs releases and from which value on, only the risk account can.
+    /// Clearing the risk account turns dual control off for good
+    pub fn set_dual_control(&mut self, risk_account: Option<AccountId>, threshold: Option<U128>) {
+        require!(
+            self.risk_account.as_ref() == Some(&env::predecessor_account_id()),
+            "Unauthorized"
+        );
+        self.risk_account = risk_account;
+        self.dual_control_threshold = threshold;
+        self.pending_release = None;
+    }
+
+    /// Called by the NFT contract whenever the membership token changes hands
+    pub fn set_beneficiary(&mut self, beneficiary_id: AccountId, nonce: Option<u64>) {
         assert_eq!(env::predecessor_account_id(), self.owner_id, "Unauthorized");
+        if let Some(nonce) = nonce {
+            if self.beneficiary_id.is_some() && nonce <= self.beneficiary_nonce {
+                env::log_str("Ignoring stale beneficiary update");
+                return;
+            }
+            self.beneficiary_nonce = nonce;
+        }
+        self.beneficiary_id = Some(beneficiary_id);
+    }
 
-        if self.near_deposited {
-            Promise::new(owner_id.clone()).transfer(u128::from(self.near_amount));
-            self.near_deposited = false;
+    /// Once a beneficiary is recorded, assets can only be released to it and with
+    /// the nonce of its latest update. Requesting the same release again returns false
+    /// without paying out twice. Baskets worth `dual_control_threshold` or more are only
+    /// released once the risk account approved it too, until then this returns false
+    pub fn release(&mut self, owner_id: AccountId, nonce: Option<u64>) -> PromiseOrValue<bool> {
+        assert_eq!(env::predecessor_account_id(), self.owner_id, "Unauthorized");
+        if self.is_releasing() {
+            // the NFT contract retrying the same request must not pay out twice,
+            // assets a release could not transfer are sent with `retry_release`
+            require!(
+                self.status == VaultState::Releasing && self.release_nonce == nonce,
+                "Vault is being released"
+            );
+            env::log_str("Release already requested, the vault is kept until every asset left it");
+            return PromiseOrValue::Value(false);
+        }
+        self.assert_unlocked();
+        self.assert_release_to(&owner_id, nonce);
+        if self.requires_dual_control() && !self.stage_release(owner_id.clone(), nonce, true) {
+            return PromiseOrValue::Value(false);
+        }
+        self.internal_start_release(owner_id, nonce)
+    }
+
+    /// Co-sign the release of a vault worth `dual_control_threshold` or more, only the risk
+    /// account can. Either side can authorize first, the release starts once both authorized
+    /// the same receiver and nonce within PENDING_RELEASE_TTL_MS
+    pub fn approve_release(&mut self, owner_id: AccountId, nonce: Option<u64>) -> PromiseOrValue<bool> {
+        require!(
+            self.risk_account.as_ref() == Some(&env::predecessor_account_id()),
+            "Unauthorized"
+        );
+        require!(!self.is_releasing(), "Vault is being released");
+        self.assert_unlocked();
+        self.assert_release_to(&owner_id, nonce);
+        if !self.stage_release(owner_id.clone(), nonce, false) {
+            return PromiseOrValue::Value(false);
         }
+        self.internal_start_release(owner_id, nonce)
+    }
 
-        for token in self.token_deposit.clone() {
-            if token.is_deposited {
-                Promise::new(token.token_contract_id).function_call(
-                    "ft_transfer".to_string(),
-                    json!({
-                      "receiver_id": owner_id.clone(), "amount": token.token_amount
-                    })
+    /// Book what the strategy contract took of each FT, `ft_transfer_call` resolves to the
+    /// amount it used and the rest came back to the vault
+    #[private]
+    pub fn on_strategy_deposit(&mut self, token_contract_ids: Vec<AccountId>) {
+        self.strategy_balances = token_contract_ids
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, token_contract_id)| {
+                let used = match env::promise_result(i as u64) {
+                    PromiseResult::Successful(value) => {
+                        near_sdk::serde_json::from_slice::<U128>(&value).ok()
+                    }
+                    _ => None,
+                }?;
+                (used.0 > 0).then(|| (token_contract_id, used))
+            })
+            .collect();
+        self.strategy_deposited = !self.strategy_balances.is_empty();
+        if !self.strategy_deposited {
+            env::log_str("Strategy deposit failed, funds stay in the vault");
+        }
+    }
+
+    /// Book what the strategy contract sent back, its `withdraw` returns the amount of each
+    /// FT requested. The release goes on once nothing is left in the strategy, otherwise
+    /// `continue_release` asks for the rest again.
+    #[private]
+    pub fn on_strategy_withdraw(&mut self, owner_id: AccountId) -> PromiseOrValue<bool> {
+        let returned: Vec<U128> = match env::promise_result(0) {
+            PromiseResult::Successful(value) => {
+                near_sdk::serde_json::from_slice(&value).unwrap_or_default()
+            }
+            _ => vec![],
+        };
+        for ((_, balance), returned) in self.strategy_balances.iter_mut().zip(returned) {
+            balance.0 = balance.0.saturating_sub(returned.0);
+        }
+        self.strategy_balances.retain(|(_, balance)| balance.0 > 0);
+        self.strategy_deposited = !self.strategy_balances.is_empty();
+        if self.strategy_deposited {
+            env::log_str("Strategy withdraw incomplete, assets are left in the strategy contract");
+            return PromiseOrValue::Value(false);
+        }
+        self.internal_release(owner_id, MAX_RELEASE_BATCH).into()
+    }
+
+    /// Give the deposits of a vault that never got fully funded back to their depositors,
+    /// then delete the vault in favour of the token holder. Callable by the NFT contract or the holder.
+    pub fn cancel(&mut self) -> Promise {
+        let holder_id = self
+            .beneficiary_id
+            .clone()
+            .unwrap_or_else(|| env::panic_str("No beneficiary recorded"));
+        let predecessor_id = env::predecessor_account_id();
+        require!(
+            predecessor_id == self.owner_id || predecessor_id == holder_id,
+            "Unauthorized"
+        );
+        require!(!self.is_fully_funded(), "Vault is fully funded, release it instead");
+        require!(!self.is_releasing(), "Vault is being released");
+        require!(!self.release_pending, "Release transfers still in flight");
+        self.transition(VaultState::Cancelled);
+        self.internal_release(holder_id, MAX_RELEASE_BATCH)
+    }
+
+    /// Send one FT, or wNEAR, to the token holder, leaving the other assets in the vault.
+    /// Only the NFT contract can call it, for `receiver_id` who has to be the recorded holder.
+    /// The vault then takes a deposit of the asset again.
+    pub fn release_asset(&mut self, receiver_id: AccountId, token_contract_id: AccountId) -> Promise {
+        self.assert_partial_release(&receiver_id);
+        let amount = if self.is_wrap_near(&token_contract_id) && self.near_wrapped {
+            require!(self.near_deposited, "Asset already released");
+            self.near_amount
+        } else {
+            self.token_deposit
+                .iter()
+                .find(|token| token.token_contract_id == token_contract_id && token.is_deposited)
+                .unwrap_or_else(|| env::panic_str("Asset not held by the vault"))
+                .token_amount
+        };
+        self.release_receiver = Some(receiver_id.clone());
+        self.release_pending = true;
+        self.internal_release_fungible(&receiver_id, token_contract_id, amount, None)
+    }
+
+    /// Send the NEAR deposit to the token holder, leaving the other assets in the vault.
+    /// Only the NFT contract can call it, for `receiver_id` who has to be the recorded holder.
+    pub fn release_near(&mut self, receiver_id: AccountId) -> Promise {
+        if self.near_wrapped {
+            return self.release_asset(receiver_id, self.wrap_near_contract());
+        }
+        self.assert_partial_release(&receiver_id);
+        require!(self.near_deposited, "Asset already released");
+        self.near_deposited = false;
+        self.release_receiver = Some(receiver_id.clone());
+        self.release_pending = true;
+        Promise::new(receiver_id)
+            .transfer(u128::from(self.near_amount))
+            .then(self.on_release_complete_callback())
+    }
+
+    /// Send the next `limit` assets of a started release, by default as many as the gas allows.
+    /// The vault is deleted once the last asset left it and no FT balance remains.
+    pub fn continue_release(&mut self, limit: Option<u32>) -> Promise {
+        let receiver_id = self
+            .release_receiver
+            .clone()
+            .unwrap_or_else(|| env::panic_str("No release in progress"));
+        require!(self.is_releasing(), "No release in progress");
+        require!(!self.release_pending, "Release transfers still in flight");
+        if self.strategy_deposited {
+            return self.withdraw_from_strategy(receiver_id);
+        }
+        let limit = limit.unwrap_or(MAX_RELEASE_BATCH).min(MAX_RELEASE_BATCH);
+        require!(limit > 0, "limit must be greater than 0");
+        self.internal_release(receiver_id, limit)
+    }
+
+    /// Try again to send the assets a previous release could not transfer
+    pub fn retry_release(&mut self) -> Promise {
+        self.continue_release(None)
+    }
+
+    /// How far the release got, None when no release was requested
+    pub fn get_release_progress(&self) -> Option<ReleaseProgress> {
+        let receiver_id = self.release_receiver.clone().filter(|_| self.is_releasing())?;
+        Some(ReleaseProgress {
+            assets_left: self.held_fungible_assets(&receiver_id).len() as u32
+                + if self.near_deposited && !self.near_wrapped { 1 } else { 0 },
+            cursor: self.release_cursor,
+            pending: self.release_pending,
+            receiver_id,
+        })
+    }
+
+    /// Send FTs stuck in the vault, e.g. from before unexpected transfers were bounced, to `to`.
+    /// Only the balance above what the vault holds for its deposits is sent. Only the NFT contract can skim.
+    pub fn skim(&mut self, token_contract_id: AccountId, to: AccountId) -> Promise {
+        assert_eq!(env::predecessor_account_id(), self.owner_id, "Unauthorized");
+        require!(!self.release_pending, "Release transfers still in flight");
+        Promise::new(token_contract_id.clone())
+            .function_call(
+                "ft_balance_of".to_string(),
+                json!({ "account_id": env::current_account_id() })
                     .to_string()
                     .into_bytes(),
-                    1.try_into().unwrap(),
-                    Gas(60_000_000_000_000),
-                );
+                0,
+                GAS_FOR_FT_BALANCE_OF,
+            )
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_SKIM_CALLBACK)
+                    .on_skim_balance(token_contract_id, to),
+            )
+    }
+
+    #[private]
+    pub fn on_skim_balance(
+        &mut self,
+        token_contract_id: AccountId,
+        to: AccountId,
+        #[callback_unwrap] balance: U128,
+    ) -> Promise {
+        let surplus = balance.0.saturating_sub(self.held_amount(&token_contract_id));
+        require!(surplus > 0, "Nothing to skim");
+        Promise::new(token_contract_id).function_call(
+            "ft_transfer".to_string(),
+            json!({ "receiver_id": to, "amount": U128(surplus) })
+                .to_string()
+                .into_bytes(),
+            1,
+            GAS_FOR_FT_TRANSFER,
+        )
+    }
+
+    /// Clear the in-flight flag of a deposit's fee. A fee that could not be sent stays
+    /// in the vault as surplus, the NFT contract can `skim` it.
+    #[private]
+    pub fn on_fee_forwarded(&mut self, index: Option<u32>) -> bool {
+        let forwarded = is_promise_success();
+        let (token_contract_id, amount) = match index {
+            Some(index) => {
+                let token = &mut self.token_deposit[index as usize];
+                token.fee_pending = false;
+                (token.token_contract_id.clone(), token.token_amount)
+            }
+            None => {
+                self.near_fee_pending = false;
+                (self.wrap_near_contract(), self.near_amount)
+            }
+        };
+        if forwarded {
+            self.log_fee_collected(Some(token_contract_id), self.fee_for(amount));
+        } else {
+            env::log_str(&format!("Fee transfer of {} failed, it stays in the vault", token_contract_id));
+        }
+        forwarded
+    }
+
+    /// Register the receiver on the FT contract when it isn't yet, then transfer
+    #[private]
+    pub fn on_storage_checked(
+        &mut self,
+        receiver_id: AccountId,
+        token_contract_id: AccountId,
+        amount: U128,
+        batch_left: Option<u32>,
+    ) -> Promise {
+        let registered = match env::promise_result(0) {
+            PromiseResult::Successful(value) => {
+                near_sdk::serde_json::from_slice::<Option<near_sdk::serde_json::Value>>(&value)
+                    .map_or(false, |balance| balance.is_some())
+            }
+            _ => false,
+        };
+        let balance = match env::promise_result(1) {
+            PromiseResult::Successful(value) => near_sdk::serde_json::from_slice::<U128>(&value).ok(),
+            _ => None,
+        };
+        let asset = ReleasedAsset {
+            token_contract_id: Some(token_contract_id.clone()),
+            receiver_id: receiver_id.clone(),
+            amount,
+            balance,
+        };
+        let transfer = Promise::new(token_contract_id.clone()).function_call(
+            "ft_transfer".to_string(),
+            json!({ "receiver_id": receiver_id.clone(), "amount": amount })
+                .to_string()
+                .into_bytes(),
+            1,
+            GAS_FOR_FT_TRANSFER,
+        );
+        let transfer = if registered {
+            transfer
+        } else {
+            Promise::new(token_contract_id.clone())
+                .function_call(
+                    "storage_deposit".to_string(),
+                    json!({ "account_id": receiver_id, "registration_only": true })
+                        .to_string()
+                        .into_bytes(),
+                    STORAGE_DEPOSIT_RESERVE,
+                    GAS_FOR_STORAGE_DEPOSIT,
+                )
+                .then(transfer)
+        };
+        transfer.then(
+            ext_self::ext(env::current_account_id())
+                .with_static_gas(GAS_FOR_TOKEN_RELEASED_CALLBACK)
+                .on_token_released(asset, batch_left),
+        )
+    }
+
+    /// Mark the asset released once its transfer went through, then move on to the next one.
+    /// `batch_left` is how many more assets this invocation may send, None, means a single
+    /// asset was released. Assets that could not be transferred move the release cursor past
+    /// them and are left for `continue_release`.
+    #[private]
+    pub fn on_token_released(&mut self, asset: ReleasedAsset, batch_left: Option<u32>) -> Promise {
+        let token_contract_id = asset.token_contract_id.clone().unwrap();
+        let released = is_promise_success();
+        if !released {
+            env::log_str(&format!("Release of {} failed", token_contract_id));
+        } else {
+            if self.is_wrap_near(&token_contract_id) && self.near_wrapped {
+                self.near_deposited = false;
+                self.near_wrapped = false;
+            } else if let Some(token) = self
+                .token_deposit
+                .iter_mut()
+                .find(|token| token.token_contract_id == token_contract_id && token.is_deposited)
+            {
+                token.is_deposited = false;
+            }
+            self.record_released(asset);
+        }
+        let batch_left = match batch_left {
+            Some(batch_left) => batch_left,
+            None => return self.on_release_complete_callback(),
+        };
+        if !released {
+            self.release_cursor += 1;
+        }
+        if env::prepaid_gas() - env::used_gas() < GAS_FOR_RELEASE_STEP + GAS_FOR_RELEASE_COMPLETE_CALLBACK {
+            env::log_str("Out of gas for the next asset, the rest is left for continue_release");
+            return self.on_release_complete_callback();
+        }
+        self.internal_release_next(batch_left)
+    }
+
+    /// Once every asset left the vault, check the balances it still has on the FT contracts
+    /// it knows of, otherwise keep it for `continue_release`
+    #[private]
+    pub fn on_release_complete(&mut self) -> PromiseOrValue<bool> {
+        self.release_pending = false;
+        // a partial release keeps the vault, it waits for deposits of what left it
+        if !self.is_releasing() {
+            self.release_receiver = None;
+            let was_funded = self.status == VaultState::Funded;
+            self.sync_funding_status();
+            if was_funded && self.status != VaultState::Funded {
+                self.notify_unfunded();
+            }
+            return PromiseOrValue::Value(false);
+        }
+        if self.near_deposited || self.token_deposit.iter().any(|token| token.is_deposited) {
+            env::log_str("Assets remain in the vault, it is kept until continue_release sent them");
+            return PromiseOrValue::Value(false);
+        }
+        let token_contract_ids = self.known_token_contracts();
+        let balances = token_contract_ids
+            .iter()
+            .map(|token_contract_id| {
+                Promise::new(token_contract_id.clone()).function_call(
+                    "ft_balance_of".to_string(),
+                    json!({ "account_id": env::current_account_id() })
+                        .to_string()
+                        .into_bytes(),
+                    0,
+                    GAS_FOR_FT_BALANCE_OF,
+                )
+            })
+            .reduce(|all, balance| all.and(balance));
+        match balances {
+            Some(balances) => {
+                self.release_pending = true;
+                balances
+                    .then(
+                        ext_self::ext(env::current_account_id())
+                            .with_static_gas(GAS_FOR_RESIDUALS_CHECKED_CALLBACK)
+                            .on_residuals_checked(token_contract_ids),
+                    )
+                    .into()
+            }
+            None => PromiseOrValue::Value(self.internal_delete_vault()),
+        }
+    }
+
+    /// Sweep the FTs that arrived outside the deposits to the release receiver. Deleting the
+    /// vault would destroy them, so it is only deleted once every balance is known to be zero.
+    #[private]
+    pub fn on_residuals_checked(&mut self, token_contract_ids: Vec<AccountId>) -> PromiseOrValue<bool> {
+        self.release_pending = false;
+        let receiver_id = self.release_receiver.clone().unwrap();
+        let mut residuals = vec![];
+        let mut unknown = vec![];
+        for (i, token_contract_id) in token_contract_ids.into_iter().enumerate() {
+            let balance = match env::promise_result(i as u64) {
+                PromiseResult::Successful(value) => near_sdk::serde_json::from_slice::<U128>(&value).ok(),
+                _ => None,
+            };
+            match balance {
+                Some(U128(0)) => {}
+                Some(balance) => residuals.push(ReleasedAsset {
+                    token_contract_id: Some(token_contract_id),
+                    receiver_id: receiver_id.clone(),
+                    amount: balance,
+                    balance: Some(balance),
+                }),
+                None => unknown.push(token_contract_id.to_string()),
+            }
+        }
+        if !unknown.is_empty() {
+            env::log_str(&format!(
+                "Could not read the balance on {}, the vault is kept until continue_release checked it",
+                unknown.join(", ")
+            ));
+        }
+        let sweeps = residuals
+            .iter()
+            .map(|asset| {
+                let token_contract_id = asset.token_contract_id.clone().unwrap();
+                Promise::new(token_contract_id.clone())
+                    .function_call(
+                        "storage_deposit".to_string(),
+                        json!({ "account_id": receiver_id, "registration_only": true })
+                            .to_string()
+                            .into_bytes(),
+                        STORAGE_DEPOSIT_RESERVE,
+                        GAS_FOR_STORAGE_DEPOSIT,
+                    )
+                    .then(Promise::new(token_contract_id).function_call(
+                        "ft_transfer".to_string(),
+                        json!({ "receiver_id": receiver_id, "amount": asset.amount })
+                            .to_string()
+                            .into_bytes(),
+                        1,
+                        GAS_FOR_FT_TRANSFER,
+                    ))
+            })
+            .reduce(|all, sweep| all.and(sweep));
+        match sweeps {
+            Some(sweeps) => {
+                self.release_pending = true;
+                sweeps
+                    .then(
+                        ext_self::ext(env::current_account_id())
+                            .with_static_gas(GAS_FOR_RESIDUALS_SWEPT_CALLBACK)
+                            .on_residuals_swept(residuals),
+                    )
+                    .into()
+            }
+            None if unknown.is_empty() => PromiseOrValue::Value(self.internal_delete_vault()),
+            None => PromiseOrValue::Value(false),
+        }
+    }
+
+    /// Delete the vault when every residual balance was swept, otherwise report what remains
+    #[private]
+    pub fn on_residuals_swept(&mut self, residuals: Vec<ReleasedAsset>) -> bool {
+        self.release_pending = false;
+        let mut remaining = vec![];
+        for (i, asset) in residuals.into_iter().enumerate() {
+            if matches!(env::promise_result(i as u64), PromiseResult::Successful(_)) {
+                self.record_released(asset);
+            } else {
+                remaining.push(format!(
+                    "{} {}",
+                    asset.amount.0,
+                    asset.token_contract_id.unwrap()
+                ));
             }
         }
+        if !remaining.is_empty() {
+            env::log_str(&format!(
+                "Could not sweep {}, the vault is kept until continue_release sent them",
+                remaining.join(", ")
+            ));
+            return false;
+        }
+        self.internal_delete_vault()
+    }
+
+    /// Payouts of the release in progress, None when no full release was requested
+    pub fn get_release_receipt(&self) -> Option<ReleaseReceipt> {
+        self.release_receipt.clone()
+    }
 
-        Promise::new(env::current_account_id()).delete_account(owner_id);
+    /// Raise what the vault holds, e.g. when the token moves up a membership tier. Attach
+    /// `near_amount` plus the fee on it, it is added to the NEAR deposit right away.
+    /// `token_deposit` are further FT deposits the vault waits for. Only the NFT contract can top up.
+    #[payable]
+    pub fn top_up(&mut self, near_amount: U128, token_deposit: Vec<TokenDeposit>) {
+        assert_eq!(env::predecessor_account_id(), self.owner_id, "Unauthorized");
+        require!(!self.is_releasing(), "Vault is being released");
+        require!(!self.release_pending, "Release transfers still in flight");
+        require!(
+            !self.strategy_deposited,
+            "Assets are deposited in the strategy contract"
+        );
+        if near_amount.0 > 0 {
+            require!(
+                self.near_amount.0 == 0 || (self.near_deposited && !self.near_wrapped),
+                "The current NEAR deposit has to be made in NEAR first"
+            );
+            let split = fee::split_deposit(env::attached_deposit(), near_amount.0, self.fee_bps)
+                .unwrap_or_else(|| env::panic_str("Attached deposit must be the amount plus the fee"));
+            self.send_near_fee(split.fee);
+            VaultEvent::log_deposit_received(
+                self.token_id.clone(),
+                None,
+                env::signer_account_id(),
+                U128(split.principal),
+                None,
+            );
+            self.near_amount = U128(self.near_amount.0 + split.principal);
+            self.near_deposited = true;
+            if self.near_depositor.is_none() {
+                self.near_depositor = Some(env::signer_account_id());
+            }
+        } else {
+            require!(env::attached_deposit() == 0, "Nothing to top up with the deposit");
+        }
+        for token in token_deposit {
+            require!(token.token_amount > U128(0), "Cannot wrap 0 token");
+            // like in `new`, a deposit could otherwise be matched against two requirements.
+            // A token already deposited can be required again, e.g. by a higher tier
+            require!(
+                self.token_deposit
+                    .iter()
+                    .all(|other| other.token_contract_id != token.token_contract_id || other.is_deposited),
+                "Duplicate token contract"
+            );
+            require!(
+                self.near_amount.0 == 0 || !self.is_wrap_near(&token.token_contract_id),
+                "wNEAR stands in for the NEAR deposit"
+            );
+            self.token_deposit.push(TokenDeposit {
+                token_contract_id: token.token_contract_id,
+                token_amount: token.token_amount,
+                is_deposited: false,
+                depositor: None,
+                funded_for: None,
+                fee_pending: false,
+            });
+        }
+        // new deposits to wait for take a funded vault back to partially funded
+        self.sync_funding_status();
     }
 
+    /// Attach at least `get_status`'s NEAR still required, anything above it is refunded
     #[payable]
     pub fn deposit_near(&mut self) {
+        self.internal_deposit_near(None);
+    }
+
+    /// Make the NEAR deposit on behalf of `beneficiary`, e.g. a DAO funding a member's vault.
+    /// Panics unless `beneficiary` holds the token, a cancelled vault refunds the caller.
+    /// FTs are funded the same way with `{"fund_for": "<beneficiary>"}` as `ft_transfer_call` msg.
+    #[payable]
+    pub fn fund_for(&mut self, beneficiary: AccountId) {
+        require!(
+            self.beneficiary_id.as_ref() == Some(&beneficiary),
+            "Beneficiary doesn't hold the token of this vault"
+        );
+        self.internal_deposit_near(Some(beneficiary));
+    }
+
+    /// Who funded each deposited asset, and for whom
+    pub fn get_funding(&self) -> Vec<Funding> {
+        let near = self
+            .near_depositor
+            .clone()
+            .filter(|_| self.near_deposited)
+            .map(|depositor| Funding {
+                token_contract_id: None,
+                amount: self.near_amount,
+                depositor,
+                funded_for: self.near_funded_for.clone(),
+            });
+        near.into_iter()
+            .chain(self.token_deposit.iter().filter_map(|token| {
+                let depositor = token.depositor.clone().filter(|_| token.is_deposited)?;
+                Some(Funding {
+                    token_contract_id: Some(token.token_contract_id.clone()),
+                    amount: token.token_amount,
+                    depositor,
+                    funded_for: token.funded_for.clone(),
+                })
+            }))
+            .collect()
+    }
+}
+
+impl Contract {
+    fn internal_deposit_near(&mut self, funded_for: Option<AccountId>) {
+        require!(self.status.takes_deposits(), "Vault doesn't take deposits");
         require!(
-            self.near_amount != U128(0)
-                && !self.near_deposited
-                && u128::from(self.near_amount)
-                    .checked_div(100)
-                    .unwrap()
-                    .checked_add(u128::from(self.near_amount))
-                    .unwrap()
-                    == env::attached_deposit(),
+            self.near_amount != U128(0) && !self.near_deposited,
             "Can not accept Near Deposit"
         );
-        Promise::new(self.owner_id.clone())
-            .transfer(u128::from(self.near_amount).checked_div(100).unwrap());
+        let required = self.near_amount_with_fee();
+        let attached = env::attached_deposit();
+        require!(
+            attached >= required,
+            format!("Attach at least {} yN for the NEAR deposit", required)
+        );
+        // the principal stays in the vault for release, only the fee leaves it
+        let split = fee::split_deposit(required, self.near_amount.0, self.fee_bps).unwrap();
+        self.send_near_fee(split.fee);
+        if attached > required {
+            Promise::new(env::predecessor_account_id()).transfer(attached - required);
+        }
+        VaultEvent::log_deposit_received(
+            self.token_id.clone(),
+            None,
+            env::predecessor_account_id(),
+            U128(split.principal),
+            funded_for.clone(),
+        );
         self.near_deposited = true;
+        self.near_depositor = Some(env::predecessor_account_id());
+        self.near_funded_for = funded_for;
+        self.on_funding_update();
     }
-}
 
-#[near_bindgen]
-impl FungibleTokenReceiver for Contract {
-    /// Called by fungible token contract after `ft_transfer_call` was initiated by
-    /// `sender_id` of the given `amount` with the transfer message given in `msg` field.
-    /// The `amount` of tokens were already transferred to this contract account and ready to be used.
-    ///
-    /// The method must return the amount of tokens that are *not* used/accepted by this contract from the transferred
-    /// amount. Examples:
+    /// Send every held asset to `owner_id`, or back to its depositor when the vault was
+    /// cancelled, registering the receiver on FT contracts first.
+    /// At most `limit` FTs are sent, the vault is deleted once all transfers succeeded.
+    fn internal_release(&mut self, owner_id: AccountId, limit: u32) -> Promise {
+        self.release_receiver = Some(owner_id.clone());
+        self.release_pending = true;
+        if self.release_receipt.is_none() {
+            self.release_receipt = Some(ReleaseReceipt {
+                beneficiary_id: owner_id.clone(),
+                assets: vec![],
+            });
+        }
+
+        if self.near_deposited && !self.near_wrapped {
+            let near_receiver_id = self.asset_receiver(&self.near_depositor, &owner_id);
+            Promise::new(near_receiver_id.clone()).transfer(u128::from(self.near_amount));
+            self.near_deposited = false;
+            self.record_released(ReleasedAsset {
+                token_contract_id: None,
+                receiver_id: near_receiver_id,
+                amount: self.near_amount,
+                balance: None,
+            });
+        }
+
+        self.internal_release_next(limit)
+    }
+
+    /// Release the FTs one after the other, each confirmed by `on_token_released` before
+    /// the next is sent, until `batch_left` of them were sent. Held assets before the release
+    /// cursor already failed in this pass and are skipped, once the pass is through they are
+    /// tried again by the next invocation.
+    fn internal_release_next(&mut self, batch_left: u32) -> Promise {
+        if batch_left == 0 {
+            return self.on_release_complete_callback();
+        }
+        let owner_id = self.release_receiver.clone().unwrap();
+        match self
+            .held_fungible_assets(&owner_id)
+            .into_iter()
+            .nth(self.release_cursor as usize)
+        {
+            Some((receiver_id, token_contract_id, amount)) => self.internal_release_fungible(
+                &receiver_id,
+                token_contract_id,
+                amount,
+                Some(batch_left - 1),
+            ),
+            None => {
+                self.release_cursor = 0;
+                self.on_release_complete_callback()
+            }
+        }
+    }
+
+    /// FTs, and wNEAR, still held by the vault in release order, with who receives each
+    fn held_fungible_assets(&self, owner_id: &AccountId) -> Vec<(AccountId, AccountId, U128)> {
+        let mut assets: Vec<(AccountId, AccountId, U128)> = self
+            .token_deposit
+            .iter()
+            .filter(|token| token.is_deposited)
+            .map(|token| {
+                (
+                    self.asset_receiver(&token.depositor, owner_id),
+                    token.token_contract_id.clone(),
+                    token.token_amount,
+                )
+            })
+            .collect();
+        if self.near_deposited && self.near_wrapped {
+            assets.push((
+                self.asset_receiver(&self.near_depositor, owner_id),
+                self.wrap_near_contract(),
+                self.near_amount,
+            ));
+        }
+        assets
+    }
+
+    /// Check the receiver's storage on the FT contract, `on_storage_checked` does the transfer
+    fn internal_release_fungible(
+        &self,
+        receiver_id: &AccountId,
+        token_contract_id: AccountId,
+        amount: U128,
+        batch_left: Option<u32>,
+    ) -> Promise {
+        Promise::new(token_contract_id.clone())
+            .function_call(
+                "storage_balance_of".to_string(),
+                json!({ "account_id": receiver_id }).to_string().into_bytes(),
+                0,
+                GAS_FOR_STORAGE_BALANCE_OF,
+            )
+            // snapshot of what the vault holds, for the release receipt
+            .and(Promise::new(token_contract_id.clone()).function_call(
+                "ft_balance_of".to_string(),
+                json!({ "account_id": env::current_account_id() })
+                    .to_string()
+                    .into_bytes(),
+                0,
+                GAS_FOR_FT_BALANCE_OF,
+            ))
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_STORAGE_CHECKED_CALLBACK)
+                    .on_storage_checked(receiver_id.clone(), token_contract_id, amount, batch_left),
+            )
+    }
+
+    /// FT contracts the vault could hold a balance on, wNEAR included when NEAR was required
+    fn known_token_contracts(&self) -> Vec<AccountId> {
+        let mut token_contract_ids: Vec<AccountId> = self
+            .token_deposit
+            .iter()
+            .map(|token| token.token_contract_id.clone())
+            .collect();
+        if self.near_amount.0 > 0 {
+            token_contract_ids.extend(self.wrap_near_contract.clone());
+        }
+        token_contract_ids.sort();
+        token_contract_ids.dedup();
+        token_contract_ids
+    }
+
+    /// Delete the vault in favour of the release receiver, every known balance was checked empty
+    fn internal_delete_vault(&mut self) -> bool {
+        let receiver_id = self.release_receiver.take().unwrap();
+        self.transition(VaultState::Released);
+        if let Some(receipt) = self.release_receipt.take() {
+            VaultEvent::log_vault_released(
+                self.token_id.clone(),
+                receipt.beneficiary_id,
+                json!(receipt.assets),
+            );
+        }
+        Promise::new(env::current_account_id()).delete_account(receiver_id);
+        true
+    }
+
+    /// Add an asset to the receipt of a full release, partial releases have none
+    fn record_released(&mut self, asset: ReleasedAsset) {
+        if let Some(receipt) = self.release_receipt.as_mut() {
+            receipt.assets.push(asset);
+        }
+    }
+
+    /// Assets go to the token holder, unless the vault was cancelled and the depositor is known
+    fn asset_receiver(&self, depositor: &Option<AccountId>, owner_id: &AccountId) -> AccountId {
+        match depositor {
+            Some(depositor) if self.status == VaultState::Cancelled => depositor.clone(),
+            _ => owner_id.clone(),
+        }
+    }
+
+    fn on_release_complete_callback(&self) -> Promise {
+        ext_self::ext(env::current_account_id())
+            .with_static_gas(GAS_FOR_RELEASE_COMPLETE_CALLBACK)
+            .on_release_complete()
+    }
+
+    /// Start a release both sides agreed to, or the NFT contract alone below the threshold
+    fn internal_start_release(&mut self, owner_id: AccountId, nonce: Option<u64>) -> PromiseOrValue<bool> {
+        self.pending_release = None;
+        self.transition(VaultState::Releasing);
+        self.release_nonce = nonce;
+        VaultEvent::log_release_requested(self.token_id.clone(), owner_id.clone());
+
+        // Funds put to work in a strategy have to come back before they can be released
+        if self.strategy_deposited {
+            return self.withdraw_from_strategy(owner_id).into();
+        }
+
+        self.internal_release(owner_id, MAX_RELEASE_BATCH).into()
+    }
+
+    /// Ask the strategy contract for the FTs it took, the release goes on once they are back
+    fn withdraw_from_strategy(&self, owner_id: AccountId) -> Promise {
+        let strategy_contract = self
+            .strategy_contract
+            .clone()
+            .unwrap_or_else(|| env::panic_str("No strategy contract"));
+        let tokens: Vec<TokenDeposit> = self
+            .strategy_balances
+            .iter()
+            .filter_map(|(token_contract_id, balance)| {
+                let token = self
+                    .token_deposit
+                    .iter()
+                    .find(|token| token.token_contract_id == *token_contract_id)?;
+                Some(TokenDeposit {
+                    token_amount: *balance,
+                    ..token.clone()
+                })
+            })
+            .collect();
+        Promise::new(strategy_contract)
+            .function_call(
+                "withdraw".to_string(),
+                json!({ "tokens": tokens }).to_string().into_bytes(),
+                0,
+                GAS_FOR_STRATEGY_WITHDRAW,
+            )
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RELEASE_CALLBACK)
+                    .on_strategy_withdraw(owner_id),
+            )
+    }
+
+    /// Once a beneficiary is recorded, assets can only be released to it with its latest nonce
+    fn assert_release_to(&self, owner_id: &AccountId, nonce: Option<u64>) {
+        if let Some(beneficiary_id) = self.beneficiary_id.as_ref() {
+            require!(owner_id == beneficiary_id, "Can only release to the token holder");
+            require!(
+                nonce == Some(self.beneficiary_nonce),
+                "Beneficiary nonce does not match"
+            );
+        }
+    }
+
+    /// NEAR-equivalent value of the basket, the NEAR deposit when it was never valued
+    fn basket_value_near(&self) -> u128 {
+        let valued = self
+            .valuation
+            .as_ref()
+            .map_or(0, |valuation| valuation.value_near.0);
+        valued.max(self.near_amount.0)
+    }
+
+    fn requires_dual_control(&self) -> bool {
+        self.risk_account.is_some()
+            && self
+                .dual_control_threshold
+                .map_or(false, |threshold| self.basket_value_near() >= threshold.0)
+    }
+
+    /// Record one side's authorization of a release, true once both sides authorized it.
+    /// An expired record, or one for another receiver or nonce, is started over
+    fn stage_release(&mut self, owner_id: AccountId, nonce: Option<u64>, from_nft_contract: bool) -> bool {
+        let now = env::block_timestamp_ms();
+        let mut pending = self
+            .pending_release
+            .take()
+            .filter(|pending| {
+                pending.expires_at.0 > now && pending.owner_id == owner_id && pending.nonce == nonce
+            })
+            .unwrap_or(PendingRelease {
+                owner_id,
+                nonce,
+                nft_approved: false,
+                risk_approved: false,
+                expires_at: U64(now + PENDING_RELEASE_TTL_MS),
+            });
+        if from_nft_contract {
+            pending.nft_approved = true;
+        } else {
+            pending.risk_approved = true;
+        }
+        if pending.nft_approved && pending.risk_approved {
+            return true;
+        }
+        env::log_str(&format!(
+            "Release staged, waiting for the {} to authorize it",
+            if pending.nft_approved { "risk account" } else { "NFT contract" }
+        ));
+        self.pending_release = Some(pending);
+        false
+    }
+
+    /// Partial releases are requested by the NFT contract for the recorded token holder,
+    /// one at a time
+    fn assert_partial_release(&self, receiver_id: &AccountId) {
+        assert_eq!(env::predecessor_account_id(), self.owner_id, "Unauthorized");
+        require!(
+            self.beneficiary_id.as_ref() == Some(receiver_id),
+            "Only the token holder can receive part of the vault"
+        );
+        require!(!self.is_releasing(), "Vault is being released");
+        require!(!self.release_pending, "Release transfers still in flight");
+        require!(
+            !self.strategy_deposited,
+            "Assets are deposited in the strategy contract"
+        );
+        self.assert_unlocked();
+        require!(
+            !self.requires_dual_control(),
+            "Vault needs a dual-control release, release it whole"
+        );
+    }
+
+    fn assert_unlocked(&self) {
+        require!(
+            self.lockup_until.map_or(true, |lockup_until| env::block_timestamp_ms() >= lockup_until),
+            "Vault is locked up"
+        );
+    }
+
+    fn fee_for(&self, amount: U128) -> u128 {
+        fee::fee_for(amount.0, self.fee_bps)
+    }
+
+    fn amount_with_fee(&self, amount: U128) -> u128 {
+        fee::amount_with_fee(amount.0, self.fee_bps)
+    }
+
+    fn near_amount_with_fee(&self) -> u128 {
+        self.amount_with_fee(self.near_amount)
+    }
+
+    /// FT amount `ft_on_transfer` expects for a token deposit, fee included
+    fn required_token_amount(&self, token_amount: U128) -> u128 {
+        self.amount_with_fee(token_amount)
+    }
+
+    /// The NFT contract keeps track of the fees it receives, other recipients get a plain transfer
+    fn send_near_fee(&self, fee: u128) {
+        if fee == 0 {
+            return;
+        }
+        self.log_fee_collected(None, fee);
+        if self.fee_recipient == self.owner_id {
+            Promise::new(self.owner_id.clone()).function_call(
+                "record_vault_fee".to_string(),
+                json!({ "token_id": self.token_id }).to_string().into_bytes(),
+                fee,
+                GAS_FOR_RECORD_FEE,
+            );
+        } else {
+            Promise::new(self.fee_recipient.clone()).transfer(fee);
+        }
+    }
+
+    fn log_fee_collected(&self, token_contract_id: Option<AccountId>, fee: u128) {
+        if fee > 0 {
+            VaultEvent::log_fee_collected(
+                self.token_id.clone(),
+                token_contract_id,
+                self.fee_recipient.clone(),
+                U128(fee),
+            );
+        }
+    }
+
+    /// Send the fee of an FT deposit to the fee recipient, `on_fee_forwarded` resolves it.
+    /// `index` is the deposit in `token_deposit`, None, means the wNEAR deposit.
+    /// Returns whether a fee is in flight.
+    fn forward_token_fee(&self, token_contract_id: AccountId, fee: u128, index: Option<u32>) -> bool {
+        if fee == 0 {
+            return false;
+        }
+        Promise::new(token_contract_id)
+            .function_call(
+                "ft_transfer".to_string(),
+                json!({
+                  "receiver_id": self.fee_recipient.clone(),
+                  "amount": U128(fee),
+                })
+                .to_string()
+                .into_bytes(),
+                1,
+                GAS_FOR_FT_TRANSFER,
+            )
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_FEE_FORWARDED_CALLBACK)
+                    .on_fee_forwarded(index),
+            );
+        true
+    }
+
+    /// wNEAR sent through `ft_transfer_call` satisfies the NEAR deposit while it is still open
+    fn deposit_wrapped_near(
+        &mut self,
+        sender_id: AccountId,
+        amount: U128,
+        funded_for: Option<AccountId>,
+    ) -> PromiseOrValue<U128> {
+        let split = match fee::split_deposit(amount.0, self.near_amount.0, self.fee_bps) {
+            Some(split) => split,
+            None => return PromiseOrValue::Value(amount),
+        };
+        self.near_deposited = true;
+        self.near_depositor = Some(sender_id.clone());
+        self.near_funded_for = funded_for.clone();
+        self.near_wrapped = true;
+        self.near_fee_pending = self.forward_token_fee(self.wrap_near_contract(), split.fee, None);
+        VaultEvent::log_deposit_received(
+            self.token_id.clone(),
+            Some(self.wrap_near_contract()),
+            sender_id,
+            U128(split.principal),
+            funded_for,
+        );
+        self.on_funding_update();
+        PromiseOrValue::Value(U128(0))
+    }
+
+    fn is_wrap_near(&self, token_contract_id: &AccountId) -> bool {
+        self.wrap_near_contract.as_ref() == Some(token_contract_id)
+    }
+
+    /// wNEAR contract of a vault holding its NEAR deposit as wNEAR
+    fn wrap_near_contract(&self) -> AccountId {
+        self.wrap_near_contract
+            .clone()
+            .unwrap_or_else(|| env::panic_str("Vault has no wNEAR contract"))
+    }
+
+    /// Balance of an FT the vault keeps for its deposits, FTs in the strategy contract aren't held here
+    fn held_amount(&self, token_contract_id: &AccountId) -> u128 {
+        // fees still being forwarded aren't surplus either
+        let near = if self.is_wrap_near(token_contract_id)
+            && self.near_wrapped
+            && self.near_deposited
+        {
+            self.near_amount.0
+                + if self.near_fee_pending { self.fee_for(self.near_amount) } else { 0 }
+        } else {
+            0
+        };
+        let pending_fees = self
+            .token_deposit
+            .iter()
+            .filter(|token| token.token_contract_id == *token_contract_id && token.fee_pending)
+            .map(|token| self.fee_for(token.token_amount))
+            .sum::<u128>();
+        let in_strategy = self
+            .strategy_balances
+            .iter()
+            .filter(|(strategy_token_id, _)| strategy_token_id == token_contract_id)
+            .map(|(_, balance)| balance.0)
+            .sum::<u128>();
+        near + pending_fees
+            + self
+                .token_deposit
+                .iter()
+                .filter(|token| token.token_contract_id == *token_contract_id && token.is_deposited)
+                .map(|token| token.token_amount.0)
+                .sum::<u128>()
+                .saturating_sub(in_strategy)
+    }
+
+    /// Assets held for the token holder and their amounts, the NEAR deposit as wNEAR
+    fn held_assets(&self) -> Vec<(AccountId, u128)> {
+        let mut assets: Vec<(AccountId, u128)> = vec![];
+        let deposits = self
+            .token_deposit
+            .iter()
+            .filter(|token| token.is_deposited)
+            .map(|token| (token.token_contract_id.clone(), token.token_amount.0));
+        let near = self
+            .wrap_near_contract
+            .clone()
+            .filter(|_| self.near_deposited && self.near_amount.0 > 0)
+            .map(|wrap_near_contract| (wrap_near_contract, self.near_amount.0));
+        for (token_contract_id, amount) in near.into_iter().chain(deposits) {
+            match assets.iter_mut().find(|(id, _)| *id == token_contract_id) {
+                Some((_, held)) => *held += amount,
+                None => assets.push((token_contract_id, amount)),
+            }
+        }
+        assets
+    }
+
+    /// Funding stage the deposits put the vault in
+    fn funding_status(&self) -> VaultState {
+        if self.is_fully_funded() {
+            VaultState::Funded
+        } else if self.near_deposited || self.token_deposit.iter().any(|token| token.is_deposited) {
+            VaultState::PartiallyFunded
+        } else {
+            VaultState::Created
+        }
+    }
+
+    /// Move between funding stages after deposits and top ups
+    fn sync_funding_status(&mut self) {
+        let status = self.funding_status();
+        if self.status.takes_deposits() || self.status == VaultState::Funded {
+            if status != self.status {
+                self.transition(status);
+            }
+        }
+    }
+
+    fn is_releasing(&self) -> bool {
+        matches!(self.status, VaultState::Releasing | VaultState::Cancelled)
+    }
+
+    /// Change the status, panicking on transitions the lifecycle doesn't allow
+    fn transition(&mut self, to: VaultState) {
+        let from = self.status;
+        require!(
+            from.can_become(to),
+            format!("Vault can't go from {} to {}", from.as_str(), to.as_str())
+        );
+        self.status = to;
+        VaultEvent::log_status_changed(self.token_id.clone(), from.as_str(), to.as_str());
+    }
+
+    fn is_fully_funded(&self) -> bool {
+        (self.near_amount == U128(0) || self.near_deposited)
+            && self.token_deposit.iter().all(|token| token.is_deposited)
+    }
+
+    /// Let the NFT contract know every deposit is in
+    fn notify_funded(&self) {
+        VaultEvent::log_funding_completed(self.token_id.clone());
+        Promise::new(self.owner_id.clone()).function_call(
+            "on_vault_funded".to_string(),
+            json!({ "token_id": self.token_id }).to_string().into_bytes(),
+            0,
+            GAS_FOR_FUNDED_NOTIFY,
+        );
+    }
+
+    /// Let the NFT contract know part of the vault was released, it waits for deposits again
+    fn notify_unfunded(&self) {
+        Promise::new(self.owner_id.clone()).function_call(
+            "on_vault_unfunded".to_string(),
+            json!({ "token_id": self.token_id }).to_string().into_bytes(),
+            0,
+            GAS_FOR_FUNDED_NOTIFY,
+        );
+    }
+
+    /// Once every asset is in, let the NFT contract know and hand the FT balances over
+    /// to the strategy contract
+    fn on_funding_update(&mut self) {
+        self.sync_funding_status();
+        if !self.is_fully_funded() {
+            return;
+        }
+        self.notify_funded();
+        let strategy_contract = match self.strategy_contract.clone() {
+            Some(strategy_contract) => strategy_contract,
+            None => return,
+        };
+        if self.strategy_deposited {
+            return;
+        }
+        let token_contract_ids = self
+            .token_deposit
+            .iter()
+            .map(|token| token.token_contract_id.clone())
+            .collect();
+        let deposits = self
+            .token_deposit
+            .iter()
+            .map(|token| {
+                Promise::new(token.token_contract_id.clone()).function_call(
+                    "ft_transfer_call".to_string(),
+                    json!({
+                      "receiver_id": strategy_contract.clone(),
+                      "amount": token.token_amount,
+                      "msg": "deposit",
+                    })
+                    .to_string()
+                    .into_bytes(),
+                    1,
+                    GAS_FOR_FT_TRANSFER_CALL,
+                )
+            })
+            .reduce(|all, deposit| all.and(deposit));
+        if let Some(deposits) = deposits {
+            deposits.then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_STRATEGY_CALLBACK)
+                    .on_strategy_deposit(token_contract_ids),
+            );
+        }
+    }
+}
+
+fn is_promise_success() -> bool {
+    (0..env::promise_results_count())
+        .all(|i| matches!(env::promise_result(i), PromiseResult::Successful(_)))
+}
+
+#[near_bindgen]
+impl FungibleTokenReceiver for Contract {
+    /// Called by fungible token contract after `ft_transfer_call` was initiated by
+    /// `sender_id` of the given `amount` with the transfer message given in `msg` field.
+    /// The `amount` of tokens were already transferred to this contract account and ready to be used.
+    ///
+    /// The method must return the amount of tokens that are *not* used/accepted by this contract from the transferred
+    /// amount. Examples:
     /// - The transferred amount was `500`, the contract completely takes it and must return `0`.
     /// - The transferred amount was `500`, but this transfer call only needs `450` for the action passed in the `msg`
     ///   field, then the method must return `50`.
@@ -139,41 +2038,877 @@ impl FungibleTokenReceiver for Contract {
     /// Returns the amount of unused tokens that should be returned to sender, in a decimal string representation.
     fn ft_on_transfer(
         &mut self,
-        _sender_id: AccountId,
+        sender_id: AccountId,
         amount: U128,
-        _msg: String,
+        msg: String,
     ) -> PromiseOrValue<U128> {
         let token_contract_id = env::predecessor_account_id();
+        if !self.status.takes_deposits() {
+            return PromiseOrValue::Value(amount);
+        }
 
-        for token in &mut self.token_deposit {
-            if token.token_contract_id == token_contract_id {
-                let require_amount = token.token_amount;
-                if token.is_deposited == false
-                    && u128::from(require_amount)
-                        .checked_div(100)
-                        .unwrap()
-                        .checked_add(u128::from(
-                            u128::from(self.near_amount)
-                                .checked_div(100)
-                                .unwrap()
-                                .checked_add(u128::from(self.near_amount))
-                                .unwrap(),
-                        ))
-                        .unwrap()
-                        == u128::from(amount)
-                {
-                    Promise::new(token.token_contract_id.clone())
-              .function_call("ft_transfer".to_string(),
-              json!({ "receiver_id": self.owner_id.clone(), "amount": U128(u128::from(require_amount).checked_div(100).unwrap())}).to_string().into_bytes(),
-              1.try_into().unwrap(),
-              Gas(60_000_000_000_000));
-                    token.is_deposited = true
-                } else {
-                    return PromiseOrValue::Value(amount);
-                }
+        // the NFT contract routes deposits on behalf of whoever sent them to it
+        let (sender_id, fund_for) = match near_sdk::serde_json::from_str::<RoutedMsg>(&msg) {
+            Ok(routed) if sender_id == self.owner_id => (routed.depositor, routed.fund_for),
+            _ => (
+                sender_id,
+                near_sdk::serde_json::from_str::<FundingMsg>(&msg)
+                    .ok()
+                    .map(|funding| funding.fund_for),
+            ),
+        };
+        // a sponsor's deposit is only taken while the member it's meant for holds the token
+        let funded_for = match fund_for {
+            Some(fund_for) if self.beneficiary_id.as_ref() != Some(&fund_for) => {
+                return PromiseOrValue::Value(amount)
             }
+            funded_for => funded_for,
+        };
+
+        if self.is_wrap_near(&token_contract_id)
+            && self.near_amount != U128(0)
+            && !self.near_deposited
+        {
+            return self.deposit_wrapped_near(sender_id, amount, funded_for);
+        }
+        // another deposit of the token waits until the fee of the previous one is resolved
+        if self
+            .token_deposit
+            .iter()
+            .any(|token| token.token_contract_id == token_contract_id && token.fee_pending)
+        {
+            return PromiseOrValue::Value(amount);
         }
 
+        // tokens the vault doesn't expect go back to the sender. A top up can require a token
+        // again once it's deposited, the deposit goes to the requirement still open
+        let index = match self
+            .token_deposit
+            .iter()
+            .position(|token| token.token_contract_id == token_contract_id && !token.is_deposited)
+        {
+            Some(index) => index,
+            None => return PromiseOrValue::Value(amount),
+        };
+        let token = self.token_deposit[index].clone();
+        let split = match fee::split_deposit(amount.0, token.token_amount.0, self.fee_bps) {
+            Some(split) => split,
+            None => return PromiseOrValue::Value(amount),
+        };
+        // state is settled before the fee leaves, a re-entrant deposit finds it taken
+        self.token_deposit[index].is_deposited = true;
+        self.token_deposit[index].depositor = Some(sender_id.clone());
+        self.token_deposit[index].funded_for = funded_for.clone();
+        self.token_deposit[index].fee_pending =
+            self.forward_token_fee(token.token_contract_id.clone(), split.fee, Some(index as u32));
+        VaultEvent::log_deposit_received(
+            self.token_id.clone(),
+            Some(token.token_contract_id),
+            sender_id.clone(),
+            U128(split.principal),
+            funded_for,
+        );
+
+        self.on_funding_update();
         PromiseOrValue::Value(U128(0))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::mock::VmAction;
+    use near_sdk::serde_json::{self, Value};
+    use near_sdk::test_utils::{get_created_receipts, get_logs, VMContextBuilder};
+    use near_sdk::testing_env;
+    use std::collections::HashMap;
+
+    const FT_AMOUNT: u128 = 10_000;
+    /// FT_AMOUNT plus the default 1% fee
+    const FT_REQUIRED: u128 = 10_100;
+
+    fn account(name: &str) -> AccountId {
+        name.parse().unwrap()
+    }
+
+    const VAULT: &str = "vault_1.nft.near";
+    /// deposit amounts and fee bps the conservation tests run through
+    const AMOUNTS: [u128; 6] = [1, 7, 9_999, 10_000, 123_456_789, u64::MAX as u128];
+    const FEE_BPS: [u16; 6] = [0, 1, 50, 100, 999, 1_000];
+
+    fn set_predecessor(predecessor: &str) {
+        set_context(predecessor, 0);
+    }
+
+    fn set_context(predecessor: &str, attached_deposit: Balance) {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(account(VAULT))
+            .predecessor_account_id(account(predecessor))
+            .attached_deposit(attached_deposit)
+            .build());
+    }
+
+    /// Vault expecting `near_amount` NEAR and `ft_amount` of `ft.near`, fees to `fees.near`
+    fn vault_with(near_amount: u128, ft_amount: u128, fee_bps: u16) -> Contract {
+        set_predecessor("nft.near");
+        let mut token_deposit = vec![];
+        if ft_amount > 0 {
+            let mut ft = token("ft.near", false);
+            ft.token_amount = U128(ft_amount);
+            token_deposit.push(ft);
+        }
+        Contract::new(
+            account("nft.near"),
+            "1".to_string(),
+            U128(near_amount),
+            token_deposit,
+            None,
+            Some(account("wrap.near")),
+            Some(account("fees.near")),
+            Some(fee_bps),
+            Some(account("alice.near")),
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+    }
+
+    /// NEAR the receipts created by the last call send to each account
+    fn near_sent() -> HashMap<AccountId, u128> {
+        let mut sent = HashMap::new();
+        for receipt in get_created_receipts() {
+            for action in receipt.actions {
+                let deposit = match action {
+                    VmAction::Transfer { deposit } => deposit,
+                    VmAction::FunctionCall { deposit, .. } => deposit,
+                    _ => 0,
+                };
+                *sent.entry(receipt.receiver_id.clone()).or_insert(0) += deposit;
+            }
+        }
+        sent
+    }
+
+    /// Sum of the `amount`s of the vault events of `kind` the last call logged
+    fn logged_amount(kind: &str) -> u128 {
+        get_logs()
+            .iter()
+            .filter_map(|log| log.strip_prefix("EVENT_JSON:"))
+            .map(|log| serde_json::from_str::<Value>(log).unwrap())
+            .filter(|event| event["event"] == kind)
+            .flat_map(|event| event["data"].as_array().unwrap().clone())
+            .map(|data| data["amount"].as_str().unwrap().parse::<u128>().unwrap())
+            .sum()
+    }
+
+    /// FT contract keeping balances, it applies the `ft_transfer`s a vault schedules on it and
+    /// gives back what `ft_on_transfer` leaves unused like `ft_resolve_transfer`
+    struct MockFt {
+        contract_id: AccountId,
+        balances: HashMap<AccountId, u128>,
+    }
+
+    impl MockFt {
+        fn new(contract_id: &str, holder: &str, amount: u128) -> Self {
+            let mut balances = HashMap::new();
+            balances.insert(account(holder), amount);
+            Self {
+                contract_id: account(contract_id),
+                balances,
+            }
+        }
+
+        fn balance(&self, account_id: &str) -> u128 {
+            self.balances
+                .get(&account(account_id))
+                .copied()
+                .unwrap_or(0)
+        }
+
+        fn move_balance(&mut self, from: &AccountId, to: &AccountId, amount: u128) {
+            let from_balance = self.balances.get(from).copied().unwrap_or(0);
+            assert!(from_balance >= amount, "{} can't send {}", from, amount);
+            self.balances.insert(from.clone(), from_balance - amount);
+            *self.balances.entry(to.clone()).or_insert(0) += amount;
+        }
+
+        /// `ft_transfer_call` of `amount` from `sender_id` to the vault, returns the unused amount
+        fn transfer_call(
+            &mut self,
+            contract: &mut Contract,
+            sender_id: &str,
+            amount: u128,
+            msg: &str,
+        ) -> u128 {
+            self.move_balance(&account(sender_id), &account(VAULT), amount);
+            set_predecessor(self.contract_id.as_str());
+            let unused =
+                match contract.ft_on_transfer(account(sender_id), U128(amount), msg.to_string()) {
+                    PromiseOrValue::Value(unused) => unused.0,
+                    PromiseOrValue::Promise(_) => panic!("ft_on_transfer returned a promise"),
+                };
+            self.apply_receipts();
+            self.move_balance(&account(VAULT), &account(sender_id), unused);
+            unused
+        }
+
+        /// Run the `ft_transfer`s of the last call that are addressed to this contract
+        fn apply_receipts(&mut self) {
+            for receipt in get_created_receipts() {
+                if receipt.receiver_id != self.contract_id {
+                    continue;
+                }
+                for action in receipt.actions {
+                    if let VmAction::FunctionCall {
+                        function_name,
+                        args,
+                        ..
+                    } = action
+                    {
+                        if function_name != "ft_transfer" {
+                            continue;
+                        }
+                        let args: Value = serde_json::from_slice(&args).unwrap();
+                        let receiver_id = account(args["receiver_id"].as_str().unwrap());
+                        let amount = args["amount"].as_str().unwrap().parse().unwrap();
+                        self.move_balance(&account(VAULT), &receiver_id, amount);
+                    }
+                }
+            }
+        }
+    }
+
+    fn token(token_contract_id: &str, is_deposited: bool) -> TokenDeposit {
+        TokenDeposit {
+            token_contract_id: account(token_contract_id),
+            token_amount: U128(FT_AMOUNT),
+            is_deposited,
+            depositor: None,
+            funded_for: None,
+            fee_pending: false,
+        }
+    }
+
+    /// Vault waiting for an FT deposit of `ft.near`
+    fn vault() -> Contract {
+        set_predecessor("nft.near");
+        Contract::new(
+            account("nft.near"),
+            "1".to_string(),
+            U128(0),
+            vec![token("ft.near", false)],
+            None,
+            None,
+            None,
+            None,
+            Some(account("alice.near")),
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+    }
+
+    /// Unused amount of an `ft_transfer_call` from alice
+    fn transfer(contract: &mut Contract, amount: u128) -> u128 {
+        set_predecessor("ft.near");
+        match contract.ft_on_transfer(account("alice.near"), U128(amount), String::new()) {
+            PromiseOrValue::Value(unused) => unused.0,
+            PromiseOrValue::Promise(_) => panic!("ft_on_transfer returned a promise"),
+        }
+    }
+
+    fn resolve_fee(contract: &mut Contract, index: u32) {
+        set_predecessor("vault_1.nft.near");
+        assert!(contract.on_fee_forwarded(Some(index)));
+    }
+
+    #[test]
+    fn declared_tokens_are_deposited_after_new() {
+        let mut contract = vault();
+        assert_eq!(contract.status, VaultState::Created);
+        assert!(!contract.token_deposit[0].is_deposited);
+
+        assert_eq!(transfer(&mut contract, FT_REQUIRED), 0);
+        assert!(contract.token_deposit[0].is_deposited);
+        assert_eq!(contract.token_deposit[0].depositor, Some(account("alice.near")));
+        resolve_fee(&mut contract, 0);
+        assert_eq!(contract.status, VaultState::Funded);
+    }
+
+    #[test]
+    #[should_panic(expected = "Tokens are deposited after the vault is created")]
+    fn new_rejects_deposited_tokens() {
+        set_predecessor("nft.near");
+        Contract::new(
+            account("nft.near"),
+            "1".to_string(),
+            U128(0),
+            vec![token("ft.near", true)],
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Duplicate token contract")]
+    fn duplicate_token_contracts() {
+        set_predecessor("nft.near");
+        Contract::new(
+            account("nft.near"),
+            "1".to_string(),
+            U128(0),
+            vec![token("ft.near", false), token("ft.near", false)],
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the NFT contract can create its vault")]
+    fn only_nft_contract_creates_vault() {
+        set_predecessor("mallory.near");
+        Contract::new(
+            account("nft.near"),
+            "1".to_string(),
+            U128(0),
+            vec![token("ft.near", false)],
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+    }
+
+    #[test]
+    fn vault_records_creator() {
+        let contract = vault();
+        let metadata = serde_json::to_value(contract.vault_metadata()).unwrap();
+        assert_eq!(metadata["created_by"], "nft.near");
+    }
+
+    #[test]
+    fn repeated_transfer_is_refunded() {
+        let mut contract = vault();
+        assert_eq!(transfer(&mut contract, FT_REQUIRED), 0);
+        resolve_fee(&mut contract, 0);
+        assert_eq!(transfer(&mut contract, FT_REQUIRED), FT_REQUIRED);
+        assert!(contract.is_fully_funded());
+    }
+
+    #[test]
+    #[should_panic(expected = "Duplicate token contract")]
+    fn top_up_rejects_token_awaiting_deposit() {
+        let mut contract = vault();
+        set_predecessor("nft.near");
+        contract.top_up(U128(0), vec![token("ft.near", false)]);
+    }
+
+    #[test]
+    fn deposit_waits_for_pending_fee() {
+        let mut contract = vault();
+        transfer(&mut contract, FT_REQUIRED);
+        assert!(contract.token_deposit[0].fee_pending);
+        set_predecessor("nft.near");
+        contract.top_up(U128(0), vec![token("ft.near", false)]);
+
+        // the second deposit of the token bounces until the first one's fee is resolved
+        assert_eq!(transfer(&mut contract, FT_REQUIRED), FT_REQUIRED);
+        assert!(!contract.token_deposit[1].is_deposited);
+        resolve_fee(&mut contract, 0);
+        assert_eq!(transfer(&mut contract, FT_REQUIRED), 0);
+        assert!(contract.token_deposit[1].is_deposited);
+    }
+
+    /// Upgrade between membership tiers that both require `ft.near`
+    #[test]
+    fn top_up_requires_deposited_token_again() {
+        let mut contract = vault();
+        transfer(&mut contract, FT_REQUIRED);
+        resolve_fee(&mut contract, 0);
+        assert_eq!(contract.status, VaultState::Funded);
+
+        set_predecessor("nft.near");
+        contract.top_up(U128(0), vec![token("ft.near", false)]);
+        assert_eq!(contract.status, VaultState::PartiallyFunded);
+        assert_eq!(contract.get_status().tokens[1].required, U128(FT_REQUIRED));
+
+        assert_eq!(transfer(&mut contract, FT_REQUIRED), 0);
+        assert!(contract.token_deposit[1].is_deposited);
+        resolve_fee(&mut contract, 1);
+        assert_eq!(contract.status, VaultState::Funded);
+        assert_eq!(
+            contract.held_assets(),
+            vec![(account("ft.near"), 2 * FT_AMOUNT)]
+        );
+    }
+
+    #[test]
+    fn pending_fee_is_not_surplus() {
+        let mut contract = vault();
+        transfer(&mut contract, FT_REQUIRED);
+        assert_eq!(contract.held_amount(&account("ft.near")), FT_REQUIRED);
+        resolve_fee(&mut contract, 0);
+        assert_eq!(contract.held_amount(&account("ft.near")), FT_AMOUNT);
+    }
+
+    #[test]
+    fn routed_deposit_keeps_depositor() {
+        let mut contract = vault();
+        set_predecessor("ft.near");
+        let msg = json!({ "depositor": "bob.near" }).to_string();
+        contract.ft_on_transfer(account("nft.near"), U128(FT_REQUIRED), msg);
+        assert_eq!(contract.token_deposit[0].depositor, Some(account("bob.near")));
+    }
+
+    #[test]
+    #[should_panic(expected = "Vault is locked up")]
+    fn release_waits_for_lockup() {
+        set_predecessor("nft.near");
+        let mut contract = Contract::new(
+            account("nft.near"),
+            "1".to_string(),
+            U128(0),
+            vec![token("ft.near", false)],
+            None,
+            None,
+            None,
+            None,
+            Some(account("alice.near")),
+            None,
+            None,
+            Some(U64(1_000)),
+            None,
+            None,
+        );
+        transfer(&mut contract, FT_REQUIRED);
+        resolve_fee(&mut contract, 0);
+        assert_eq!(contract.get_lockup_remaining(), U64(1_000));
+        set_predecessor("nft.near");
+        contract.release(account("alice.near"), Some(0));
+    }
+
+    #[test]
+    fn release_needs_risk_approval() {
+        set_predecessor("nft.near");
+        let mut contract = Contract::new(
+            account("nft.near"),
+            "1".to_string(),
+            U128(0),
+            vec![token("ft.near", false)],
+            None,
+            None,
+            None,
+            None,
+            Some(account("alice.near")),
+            None,
+            None,
+            None,
+            Some(account("risk.near")),
+            Some(U128(0)),
+        );
+        transfer(&mut contract, FT_REQUIRED);
+        resolve_fee(&mut contract, 0);
+        set_predecessor("nft.near");
+        assert!(matches!(
+            contract.release(account("alice.near"), Some(0)),
+            PromiseOrValue::Value(false)
+        ));
+        assert_eq!(contract.status, VaultState::Funded);
+        assert!(contract.get_pending_release().unwrap().nft_approved);
+
+        set_predecessor("risk.near");
+        contract.approve_release(account("alice.near"), Some(0));
+        assert_eq!(contract.status, VaultState::Releasing);
+        assert!(contract.pending_release.is_none());
+    }
+
+    #[test]
+    fn near_over_deposit_is_refunded() {
+        let mut contract = vault_with(FT_AMOUNT, 0, 100);
+        set_context("alice.near", FT_REQUIRED + 1);
+        contract.deposit_near();
+        assert!(contract.near_deposited);
+        assert_eq!(contract.get_funding()[0].amount, U128(FT_AMOUNT));
+        assert_eq!(near_sent().get(&account("alice.near")), Some(&1));
+    }
+
+    #[test]
+    #[should_panic(expected = "Attach at least 10100 yN for the NEAR deposit")]
+    fn near_under_deposit_panics() {
+        let mut contract = vault_with(FT_AMOUNT, 0, 100);
+        set_context("alice.near", FT_REQUIRED - 1);
+        contract.deposit_near();
+    }
+
+    #[test]
+    fn routed_msg_only_from_nft_contract() {
+        let mut contract = vault();
+        set_predecessor("ft.near");
+        let msg = json!({ "depositor": "bob.near" }).to_string();
+        contract.ft_on_transfer(account("alice.near"), U128(FT_REQUIRED), msg);
+        assert_eq!(contract.token_deposit[0].depositor, Some(account("alice.near")));
+    }
+
+    #[test]
+    fn ft_exact_deposit_is_taken() {
+        let mut contract = vault_with(0, FT_AMOUNT, 100);
+        let mut ft = MockFt::new("ft.near", "alice.near", FT_REQUIRED);
+        assert_eq!(
+            ft.transfer_call(&mut contract, "alice.near", FT_REQUIRED, ""),
+            0
+        );
+        assert!(contract.token_deposit[0].is_deposited);
+        assert!(contract.token_deposit[0].fee_pending);
+        assert_eq!(ft.balance("fees.near"), FT_REQUIRED - FT_AMOUNT);
+        assert_eq!(ft.balance(VAULT), FT_AMOUNT);
+        assert_eq!(contract.status, VaultState::Funded);
+    }
+
+    #[test]
+    fn ft_under_and_over_deposits_are_returned() {
+        let mut contract = vault_with(0, FT_AMOUNT, 100);
+        let mut ft = MockFt::new("ft.near", "alice.near", FT_REQUIRED + 1);
+        for amount in [FT_AMOUNT, FT_REQUIRED - 1, FT_REQUIRED + 1] {
+            assert_eq!(
+                ft.transfer_call(&mut contract, "alice.near", amount, ""),
+                amount
+            );
+            assert!(!contract.token_deposit[0].is_deposited);
+        }
+        assert_eq!(ft.balance("alice.near"), FT_REQUIRED + 1);
+        assert_eq!(contract.status, VaultState::Created);
+    }
+
+    #[test]
+    fn ft_without_fee_is_taken_as_is() {
+        let mut contract = vault_with(0, FT_AMOUNT, 0);
+        let mut ft = MockFt::new("ft.near", "alice.near", FT_AMOUNT);
+        assert_eq!(
+            ft.transfer_call(&mut contract, "alice.near", FT_AMOUNT, ""),
+            0
+        );
+        assert!(contract.token_deposit[0].is_deposited);
+        assert!(!contract.token_deposit[0].fee_pending);
+        assert_eq!(ft.balance("fees.near"), 0);
+        assert_eq!(ft.balance(VAULT), FT_AMOUNT);
+    }
+
+    #[test]
+    fn unexpected_token_is_returned() {
+        let mut contract = vault_with(0, FT_AMOUNT, 100);
+        let mut other = MockFt::new("other.near", "alice.near", FT_REQUIRED);
+        assert_eq!(
+            other.transfer_call(&mut contract, "alice.near", FT_REQUIRED, ""),
+            FT_REQUIRED
+        );
+        assert!(!contract.token_deposit[0].is_deposited);
+    }
+
+    #[test]
+    fn deposit_for_other_beneficiary_is_returned() {
+        let mut contract = vault_with(0, FT_AMOUNT, 100);
+        let mut ft = MockFt::new("ft.near", "bob.near", FT_REQUIRED);
+        let msg = json!({ "fund_for": "carol.near" }).to_string();
+        assert_eq!(
+            ft.transfer_call(&mut contract, "bob.near", FT_REQUIRED, &msg),
+            FT_REQUIRED
+        );
+
+        let msg = json!({ "fund_for": "alice.near" }).to_string();
+        assert_eq!(
+            ft.transfer_call(&mut contract, "bob.near", FT_REQUIRED, &msg),
+            0
+        );
+        assert_eq!(
+            contract.token_deposit[0].funded_for,
+            Some(account("alice.near"))
+        );
+    }
+
+    #[test]
+    fn wrapped_near_stands_in_for_near() {
+        let mut contract = vault_with(FT_AMOUNT, 0, 100);
+        let mut wnear = MockFt::new("wrap.near", "alice.near", FT_REQUIRED + 1);
+        assert_eq!(
+            wnear.transfer_call(&mut contract, "alice.near", FT_REQUIRED + 1, ""),
+            FT_REQUIRED + 1
+        );
+        assert!(!contract.near_deposited);
+        assert_eq!(
+            wnear.transfer_call(&mut contract, "alice.near", FT_REQUIRED, ""),
+            0
+        );
+        assert!(contract.near_deposited && contract.near_wrapped);
+        assert_eq!(wnear.balance(VAULT), FT_AMOUNT);
+        assert_eq!(contract.held_amount(&account("wrap.near")), FT_REQUIRED);
+    }
+
+    #[test]
+    fn cancelled_vault_returns_deposits() {
+        let mut contract = vault_with(0, FT_AMOUNT, 100);
+        set_predecessor("nft.near");
+        contract.cancel();
+        let mut ft = MockFt::new("ft.near", "alice.near", FT_REQUIRED);
+        assert_eq!(
+            ft.transfer_call(&mut contract, "alice.near", FT_REQUIRED, ""),
+            FT_REQUIRED
+        );
+    }
+
+    #[test]
+    fn near_exact_deposit_sends_only_the_fee() {
+        let mut contract = vault_with(FT_AMOUNT, 0, 100);
+        set_context("alice.near", FT_REQUIRED);
+        contract.deposit_near();
+        assert!(contract.near_deposited);
+        let sent = near_sent();
+        assert_eq!(
+            sent.get(&account("fees.near")),
+            Some(&(FT_REQUIRED - FT_AMOUNT))
+        );
+        assert_eq!(sent.get(&account("alice.near")), None);
+    }
+
+    #[test]
+    fn near_without_fee_is_taken_as_is() {
+        let mut contract = vault_with(FT_AMOUNT, 0, 0);
+        set_context("alice.near", FT_AMOUNT);
+        contract.deposit_near();
+        assert!(contract.near_deposited);
+        assert_eq!(logged_amount("fee_collected"), 0);
+        assert_eq!(near_sent().get(&account("fees.near")), None);
+    }
+
+    #[test]
+    fn nft_contract_releases_near_to_holder() {
+        let mut contract = vault_with(FT_AMOUNT, 0, 0);
+        set_context("alice.near", FT_AMOUNT);
+        contract.deposit_near();
+        set_predecessor("nft.near");
+        contract.release_near(account("alice.near"));
+        assert!(!contract.near_deposited);
+        assert_eq!(near_sent().get(&account("alice.near")), Some(&FT_AMOUNT));
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized")]
+    fn holder_cannot_release_part_of_the_vault_directly() {
+        let mut contract = vault_with(FT_AMOUNT, 0, 0);
+        set_context("alice.near", FT_AMOUNT);
+        contract.deposit_near();
+        set_predecessor("alice.near");
+        contract.release_near(account("alice.near"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the token holder can receive part of the vault")]
+    fn part_of_the_vault_only_goes_to_holder() {
+        let mut contract = vault_with(FT_AMOUNT, 0, 0);
+        set_context("alice.near", FT_AMOUNT);
+        contract.deposit_near();
+        set_predecessor("nft.near");
+        contract.release_near(account("bob.near"));
+    }
+
+    /// Run a callback of the vault with the results of the promises it waited for
+    fn set_callback(promise_results: Vec<PromiseResult>) {
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(account(VAULT))
+                .predecessor_account_id(account(VAULT))
+                .build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            promise_results,
+        );
+    }
+
+    #[test]
+    fn assets_released_one_at_a_time_keep_the_vault() {
+        let mut contract = vault_with(FT_AMOUNT, FT_AMOUNT, 100);
+        set_context("alice.near", FT_REQUIRED);
+        contract.deposit_near();
+        transfer(&mut contract, FT_REQUIRED);
+        resolve_fee(&mut contract, 0);
+        assert_eq!(contract.status, VaultState::Funded);
+
+        set_predecessor("nft.near");
+        contract.release_near(account("alice.near"));
+        assert!(contract.release_pending);
+        set_callback(vec![]);
+        contract.on_release_complete();
+        assert!(!contract.release_pending);
+        assert_eq!(contract.status, VaultState::PartiallyFunded);
+
+        set_predecessor("nft.near");
+        contract.release_asset(account("alice.near"), account("ft.near"));
+        set_callback(vec![
+            PromiseResult::Successful(b"{}".to_vec()),
+            PromiseResult::Successful(format!("\"{}\"", FT_AMOUNT).into_bytes()),
+        ]);
+        contract.on_storage_checked(account("alice.near"), account("ft.near"), U128(FT_AMOUNT), None);
+        set_callback(vec![PromiseResult::Successful(vec![])]);
+        contract.on_token_released(
+            ReleasedAsset {
+                token_contract_id: Some(account("ft.near")),
+                receiver_id: account("alice.near"),
+                amount: U128(FT_AMOUNT),
+                balance: Some(U128(FT_AMOUNT)),
+            },
+            None,
+        );
+        assert!(!contract.token_deposit[0].is_deposited);
+        set_callback(vec![]);
+        contract.on_release_complete();
+        assert!(!contract.release_pending);
+        assert_eq!(contract.status, VaultState::Created);
+        assert!(get_created_receipts()
+            .iter()
+            .flat_map(|receipt| &receipt.actions)
+            .all(|action| !matches!(action, VmAction::DeleteAccount { .. })));
+
+        // the holder can fund it again
+        assert_eq!(transfer(&mut contract, FT_REQUIRED), 0);
+        assert!(contract.token_deposit[0].is_deposited);
+    }
+
+    #[test]
+    #[should_panic(expected = "Vault doesn't take deposits")]
+    fn near_deposit_is_taken_once() {
+        let mut contract = vault_with(FT_AMOUNT, 0, 100);
+        set_context("alice.near", FT_REQUIRED);
+        contract.deposit_near();
+        set_context("bob.near", FT_REQUIRED);
+        contract.deposit_near();
+    }
+
+    /// Whatever the amount and fee, an FT sender loses exactly what the vault holds plus the
+    /// fee, an amount off by one is returned in full
+    #[test]
+    fn ft_deposits_conserve_principal_and_fee() {
+        for amount in AMOUNTS {
+            for fee_bps in FEE_BPS {
+                let required = fee::amount_with_fee(amount, fee_bps);
+                let mut contract = vault_with(0, amount, fee_bps);
+                let mut ft = MockFt::new("ft.near", "alice.near", required + 1);
+                assert_eq!(
+                    ft.transfer_call(&mut contract, "alice.near", required + 1, ""),
+                    required + 1
+                );
+                if required > 1 {
+                    assert_eq!(
+                        ft.transfer_call(&mut contract, "alice.near", required - 1, ""),
+                        required - 1
+                    );
+                }
+                assert_eq!(
+                    ft.transfer_call(&mut contract, "alice.near", required, ""),
+                    0
+                );
+
+                let fee = ft.balance("fees.near");
+                assert_eq!(fee, fee::fee_for(amount, fee_bps));
+                assert_eq!(ft.balance(VAULT), amount);
+                assert_eq!(
+                    ft.balance("alice.near") + ft.balance(VAULT) + fee,
+                    required + 1
+                );
+                assert_eq!(logged_amount("deposit_received"), amount);
+                if fee > 0 {
+                    resolve_fee(&mut contract, 0);
+                    assert_eq!(logged_amount("fee_collected"), fee);
+                }
+                assert_eq!(contract.held_amount(&account("ft.near")), amount);
+            }
+        }
+    }
+
+    /// Whatever the amount, fee and change, the attached NEAR is exactly the principal kept,
+    /// the fee and the change refunded
+    #[test]
+    fn near_deposits_conserve_principal_and_fee() {
+        for amount in AMOUNTS {
+            for fee_bps in FEE_BPS {
+                for change in [0, 1, FT_AMOUNT] {
+                    let required = fee::amount_with_fee(amount, fee_bps);
+                    let mut contract = vault_with(amount, 0, fee_bps);
+                    set_context("alice.near", required + change);
+                    contract.deposit_near();
+
+                    let sent = near_sent();
+                    let fee = sent.get(&account("fees.near")).copied().unwrap_or(0);
+                    let refunded = sent.get(&account("alice.near")).copied().unwrap_or(0);
+                    assert_eq!(fee, fee::fee_for(amount, fee_bps));
+                    assert_eq!(refunded, change);
+                    assert_eq!(contract.near_amount.0 + fee + refunded, required + change);
+                    assert_eq!(logged_amount("deposit_received"), amount);
+                    assert_eq!(logged_amount("fee_collected"), fee);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn migrate_keeps_current_state() {
+        let mut contract = vault();
+        transfer(&mut contract, FT_REQUIRED);
+        resolve_fee(&mut contract, 0);
+        env::state_write(&contract);
+
+        let code_hash = Base58CryptoHash::from([1; 32]);
+        let migrated = Contract::migrate(Some(code_hash));
+        assert_eq!(migrated.status, VaultState::Funded);
+        assert!(migrated.token_deposit[0].is_deposited);
+        assert_eq!(migrated.created_by, Some(account("nft.near")));
+        assert_eq!(migrated.code_hash, Some(code_hash));
+    }
+
+    /// State of a vault created before upgrades, with the NEAR deposited
+    #[test]
+    fn migrate_baseline_layout() {
+        let state = (
+            account("nft.near"),
+            "1".to_string(),
+            U128(1_000),
+            true,
+            vec![(account("ft.near"), U128(FT_AMOUNT), true)],
+        )
+            .try_to_vec()
+            .unwrap();
+        env::storage_write(b"STATE", &state);
+
+        let migrated = Contract::migrate_baseline();
+        assert_eq!(migrated.status, VaultState::PartiallyFunded);
+        assert!(migrated.near_deposited);
+        assert!(!migrated.token_deposit[0].is_deposited);
+        assert_eq!(migrated.fee_recipient, account("nft.near"));
+        assert_eq!(migrated.wrap_near_contract, None);
+    }
+}
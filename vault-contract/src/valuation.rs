@@ -0,0 +1,109 @@
+//! Basket valuation math, with prices as reported by the NEAR price oracle.
+//!
+//! The oracle prices one smallest unit of an asset at `multiplier / 10^decimals` USD,
+//! e.g. wNEAR at $3 is `30000` with 28 decimals. Values are kept in USD with
+//! `USD_DECIMALS` decimals and in yoctoNEAR. Overflowing values are left out, never wrapped.
+
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Deserialize;
+use near_sdk::AccountId;
+
+/// Decimals of the USD values, millionths of a dollar
+pub const USD_DECIMALS: u8 = 6;
+
+#[derive(Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Price {
+    pub multiplier: U128,
+    pub decimals: u8,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AssetOptionalPrice {
+    pub asset_id: AccountId,
+    pub price: Option<Price>,
+}
+
+/// Result of the oracle's `get_price_data`
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PriceData {
+    /// ns since epoch the prices were reported at
+    pub timestamp: U64,
+    pub prices: Vec<AssetOptionalPrice>,
+}
+
+impl PriceData {
+    pub fn price_of(&self, asset_id: &AccountId) -> Option<&Price> {
+        self.prices
+            .iter()
+            .find(|price| price.asset_id == *asset_id)
+            .and_then(|price| price.price.as_ref())
+    }
+}
+
+/// USD value of `amount` smallest units of an asset priced at `price`, rounded down
+pub fn usd_value(amount: u128, price: &Price) -> Option<u128> {
+    let value = amount.checked_mul(price.multiplier.0)?;
+    if price.decimals >= USD_DECIMALS {
+        Some(value / 10u128.checked_pow((price.decimals - USD_DECIMALS) as u32)?)
+    } else {
+        value.checked_mul(10u128.checked_pow((USD_DECIMALS - price.decimals) as u32)?)
+    }
+}
+
+/// yoctoNEAR worth `usd` at the wNEAR price `near_price`, rounded down
+pub fn near_value(usd: u128, near_price: &Price) -> Option<u128> {
+    if near_price.multiplier.0 == 0 {
+        return None;
+    }
+    if near_price.decimals >= USD_DECIMALS {
+        let scale = 10u128.checked_pow((near_price.decimals - USD_DECIMALS) as u32)?;
+        Some(usd.checked_mul(scale)? / near_price.multiplier.0)
+    } else {
+        let scale = 10u128.checked_pow((USD_DECIMALS - near_price.decimals) as u32)?;
+        Some(usd / near_price.multiplier.0.checked_mul(scale)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ONE_NEAR: u128 = 1_000_000_000_000_000_000_000_000;
+
+    fn price(multiplier: u128, decimals: u8) -> Price {
+        Price {
+            multiplier: U128(multiplier),
+            decimals,
+        }
+    }
+
+    #[test]
+    fn near_in_usd() {
+        // $3.0000 per NEAR
+        let near_price = price(30_000, 28);
+        assert_eq!(usd_value(10 * ONE_NEAR, &near_price), Some(30_000_000));
+        assert_eq!(near_value(30_000_000, &near_price), Some(10 * ONE_NEAR));
+    }
+
+    #[test]
+    fn stablecoin_in_usd() {
+        // USDC has 6 decimals, $1.0001
+        let usdc_price = price(10_001, 10);
+        assert_eq!(usd_value(2_000_000, &usdc_price), Some(2_000_200));
+    }
+
+    #[test]
+    fn few_price_decimals() {
+        assert_eq!(usd_value(5, &price(3, 4)), Some(1_500));
+        assert_eq!(near_value(1_500, &price(3, 4)), Some(5));
+    }
+
+    #[test]
+    fn overflow_is_none() {
+        assert_eq!(usd_value(u128::MAX, &price(2, 28)), None);
+        assert_eq!(near_value(1, &price(0, 28)), None);
+    }
+}